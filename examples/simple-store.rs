@@ -7,7 +7,7 @@
 //!
 //!     cargo run --example simple-store
 
-use std::fs;
+use std::{borrow::Cow, fs};
 
 use tempfile::Builder;
 
@@ -34,7 +34,9 @@ fn getput<'w, 's>(store: MultiStore, writer: &'w mut Writer, ids: &'s mut Vec<St
         }
     }
     for id in ids {
-        store.put(writer, &id, &Value::Blob(b"weeeeeee")).unwrap();
+        store
+            .put(writer, &id, &Value::Blob(Cow::Borrowed(b"weeeeeee")))
+            .unwrap();
     }
 }
 
@@ -87,11 +89,11 @@ fn main() {
             .put(
                 &mut writer,
                 "json",
-                &Value::Json(r#"{"foo":"bar", "number": 1}"#),
+                &Value::Json(Cow::Borrowed(r#"{"foo":"bar", "number": 1}"#)),
             )
             .unwrap();
         store
-            .put(&mut writer, "blob", &Value::Blob(b"blob"))
+            .put(&mut writer, "blob", &Value::Blob(Cow::Borrowed(b"blob")))
             .unwrap();
         writer.commit().unwrap();
     }
@@ -9,6 +9,7 @@
 // specific language governing permissions and limitations under the License.
 
 mod common;
+mod impl_any;
 #[cfg(feature = "lmdb")]
 mod impl_lmdb;
 mod impl_safe;
@@ -23,18 +24,40 @@ pub use impl_lmdb::{
     ArchMigrator as LmdbArchMigrator, DatabaseFlagsImpl as LmdbDatabaseFlags,
     DatabaseImpl as LmdbDatabase, EnvironmentBuilderImpl as Lmdb,
     EnvironmentFlagsImpl as LmdbEnvironmentFlags, EnvironmentImpl as LmdbEnvironment,
-    ErrorImpl as LmdbError, InfoImpl as LmdbInfo, IterImpl as LmdbIter,
-    RoCursorImpl as LmdbRoCursor, RoTransactionImpl as LmdbRoTransaction,
-    RwCursorImpl as LmdbRwCursor, RwTransactionImpl as LmdbRwTransaction, StatImpl as LmdbStat,
-    WriteFlagsImpl as LmdbWriteFlags,
+    ErrorImpl as LmdbError, InactiveTransactionImpl as LmdbInactiveTransaction,
+    InfoImpl as LmdbInfo, IterImpl as LmdbIter, RoCursorImpl as LmdbRoCursor,
+    RoTransactionImpl as LmdbRoTransaction, RwCursorImpl as LmdbRwCursor,
+    RwTransactionImpl as LmdbRwTransaction, SharedRoTransactionImpl as LmdbSharedRoTransaction,
+    StatImpl as LmdbStat, WriteFlagsImpl as LmdbWriteFlags,
 };
 
 pub use impl_safe::{
     DatabaseFlagsImpl as SafeModeDatabaseFlags, DatabaseImpl as SafeModeDatabase,
     EnvironmentBuilderImpl as SafeMode, EnvironmentFlagsImpl as SafeModeEnvironmentFlags,
-    EnvironmentImpl as SafeModeEnvironment, ErrorImpl as SafeModeError, InfoImpl as SafeModeInfo,
+    EnvironmentImpl as SafeModeEnvironment, ErrorImpl as SafeModeError,
+    InactiveTransactionImpl as SafeModeInactiveTransaction, InfoImpl as SafeModeInfo,
     IterImpl as SafeModeIter, RoCursorImpl as SafeModeRoCursor,
     RoTransactionImpl as SafeModeRoTransaction, RwCursorImpl as SafeModeRwCursor,
     RwTransactionImpl as SafeModeRwTransaction, StatImpl as SafeModeStat,
     WriteFlagsImpl as SafeModeWriteFlags,
 };
+
+pub use impl_any::{
+    DatabaseFlagsImpl as AnyDatabaseFlags, DatabaseImpl as AnyDatabase,
+    EnvironmentBuilderImpl as Any, EnvironmentFlagsImpl as AnyEnvironmentFlags,
+    EnvironmentImpl as AnyEnvironment, ErrorImpl as AnyError,
+    InactiveTransactionImpl as AnyInactiveTransaction, InfoImpl as AnyInfo, IterImpl as AnyIter,
+    RoCursorImpl as AnyRoCursor, RoTransactionImpl as AnyRoTransaction,
+    RwCursorImpl as AnyRwCursor, RwTransactionImpl as AnyRwTransaction,
+    SharedRoTransactionImpl as AnySharedRoTransaction, StatImpl as AnyStat,
+    WriteFlagsImpl as AnyWriteFlags,
+};
+
+/// A store of an `AnyEnvironment`, whichever backend it was opened with.
+pub type AnyStore = crate::SingleStore<AnyDatabase>;
+
+/// A read transaction of an `AnyEnvironment`.
+pub type AnyReader<'t> = crate::Reader<AnyRoTransaction<'t>>;
+
+/// A write transaction of an `AnyEnvironment`.
+pub type AnyWriter<'t> = crate::Writer<AnyRwTransaction<'t>>;
@@ -9,6 +9,8 @@
 // specific language governing permissions and limitations under the License.
 #![allow(non_camel_case_types)]
 
+use std::cmp::Ordering;
+
 pub enum EnvironmentFlags {
     FIXED_MAP,
     NO_SUB_DIR,
@@ -42,3 +44,26 @@ pub enum WriteFlags {
     APPEND,
     APPEND_DUP,
 }
+
+/// Compare keys as a database orders them: those of a database with integer keys as the
+/// native-endian unsigned integers they hold, and others byte by byte.
+pub(crate) fn cmp_keys(a: &[u8], b: &[u8], integer_key: bool) -> Ordering {
+    if integer_key && cfg!(target_endian = "little") {
+        a.len()
+            .cmp(&b.len())
+            .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+    } else {
+        a.cmp(b)
+    }
+}
+
+/// Whether the pairs are in strictly ascending order of key, or of key then value if the
+/// database has duplicate keys, see `BackendRwTransaction::put_sorted`.
+pub(crate) fn is_sorted(pairs: &[(&[u8], &[u8])], dup_sort: bool, integer_key: bool) -> bool {
+    pairs
+        .windows(2)
+        .all(|w| match cmp_keys(w[0].0, w[1].0, integer_key) {
+            Ordering::Equal => dup_sort && w[0].1 < w[1].1,
+            ordering => ordering == Ordering::Less,
+        })
+}
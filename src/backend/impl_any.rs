@@ -0,0 +1,89 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! A backend whose storage is picked at runtime rather than at compile time: every type
+//! here is an enum over the types of the concrete backends, and forwards to whichever one
+//! it wraps.
+
+// Forwards to the backend wrapped by `$value`, optionally wrapping the result (or the `Ok`
+// value of the result, whose error is converted) into the same variant of `$wrap`.
+macro_rules! dispatch {
+    ($value:expr, $inner:ident => $body:expr, Ok($wrap:ident)) => {
+        match $value {
+            Self::SafeMode($inner) => $body.map($wrap::SafeMode).map_err(ErrorImpl::from),
+            #[cfg(feature = "lmdb")]
+            Self::Lmdb($inner) => $body.map($wrap::Lmdb).map_err(ErrorImpl::from),
+        }
+    };
+    ($value:expr, $inner:ident => $body:expr, $wrap:ident) => {
+        match $value {
+            Self::SafeMode($inner) => $wrap::SafeMode($body),
+            #[cfg(feature = "lmdb")]
+            Self::Lmdb($inner) => $wrap::Lmdb($body),
+        }
+    };
+    ($value:expr, $inner:ident => $body:expr) => {
+        match $value {
+            Self::SafeMode($inner) => $body,
+            #[cfg(feature = "lmdb")]
+            Self::Lmdb($inner) => $body,
+        }
+    };
+}
+
+// Like `dispatch!`, for a transaction and a database, which must come from the same backend.
+macro_rules! dispatch_db {
+    ($value:expr, $db:expr, ($inner:ident, $inner_db:ident) => $body:expr, Ok($wrap:ident)) => {
+        #[allow(unreachable_patterns)]
+        match ($value, $db) {
+            (Self::SafeMode($inner), DatabaseImpl::SafeMode($inner_db)) => {
+                $body.map($wrap::SafeMode).map_err(ErrorImpl::from)
+            }
+            #[cfg(feature = "lmdb")]
+            (Self::Lmdb($inner), DatabaseImpl::Lmdb($inner_db)) => {
+                $body.map($wrap::Lmdb).map_err(ErrorImpl::from)
+            }
+            _ => Err(ErrorImpl::ForeignDatabase),
+        }
+    };
+    ($value:expr, $db:expr, ($inner:ident, $inner_db:ident) => $body:expr) => {
+        #[allow(unreachable_patterns)]
+        match ($value, $db) {
+            (Self::SafeMode($inner), DatabaseImpl::SafeMode($inner_db)) => {
+                $body.map_err(ErrorImpl::from)
+            }
+            #[cfg(feature = "lmdb")]
+            (Self::Lmdb($inner), DatabaseImpl::Lmdb($inner_db)) => $body.map_err(ErrorImpl::from),
+            _ => Err(ErrorImpl::ForeignDatabase),
+        }
+    };
+}
+
+mod cursor;
+mod database;
+mod environment;
+mod error;
+mod flags;
+mod info;
+mod iter;
+mod stat;
+mod transaction;
+
+pub use cursor::{RoCursorImpl, RwCursorImpl};
+pub use database::DatabaseImpl;
+pub use environment::{EnvironmentBuilderImpl, EnvironmentImpl};
+pub use error::ErrorImpl;
+pub use flags::{DatabaseFlagsImpl, EnvironmentFlagsImpl, WriteFlagsImpl};
+pub use info::InfoImpl;
+pub use iter::{IterDupImpl, IterImpl};
+pub use stat::StatImpl;
+pub use transaction::{
+    InactiveTransactionImpl, RoTransactionImpl, RwTransactionImpl, SharedRoTransactionImpl,
+};
@@ -0,0 +1,108 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use super::{IterDupImpl, IterImpl};
+#[cfg(feature = "lmdb")]
+use crate::backend::impl_lmdb;
+use crate::backend::{
+    impl_safe,
+    traits::{BackendRoCursor, BackendRwCursor},
+};
+
+#[derive(Debug)]
+pub enum RoCursorImpl<'c> {
+    SafeMode(impl_safe::RoCursorImpl<'c>),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::RoCursorImpl<'c>),
+}
+
+impl<'c> BackendRoCursor<'c> for RoCursorImpl<'c> {
+    type Iter = IterImpl<'c>;
+
+    fn get_key_value<K>(self, key: K, value: &[u8]) -> bool
+    where
+        K: AsRef<[u8]> + 'c,
+    {
+        dispatch!(self, cursor => cursor.get_key_value(key, value))
+    }
+
+    fn into_iter(self) -> Self::Iter {
+        dispatch!(self, cursor => cursor.into_iter(), IterImpl)
+    }
+
+    fn into_iter_from<K>(self, key: K) -> Self::Iter
+    where
+        K: AsRef<[u8]> + 'c,
+    {
+        dispatch!(self, cursor => cursor.into_iter_from(key), IterImpl)
+    }
+
+    fn into_iter_dup_of<K>(self, key: K) -> Self::Iter
+    where
+        K: AsRef<[u8]> + 'c,
+    {
+        dispatch!(self, cursor => cursor.into_iter_dup_of(key), IterImpl)
+    }
+
+    fn into_iter_prev(self) -> Self::Iter {
+        dispatch!(self, cursor => cursor.into_iter_prev(), IterImpl)
+    }
+}
+
+#[derive(Debug)]
+pub enum RwCursorImpl<'c> {
+    SafeMode(impl_safe::RwCursorImpl<'c>),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::RwCursorImpl<'c>),
+}
+
+impl<'c> BackendRoCursor<'c> for RwCursorImpl<'c> {
+    type Iter = IterImpl<'c>;
+
+    fn get_key_value<K>(self, key: K, value: &[u8]) -> bool
+    where
+        K: AsRef<[u8]> + 'c,
+    {
+        dispatch!(self, cursor => cursor.get_key_value(key, value))
+    }
+
+    fn into_iter(self) -> Self::Iter {
+        dispatch!(self, cursor => cursor.into_iter(), IterImpl)
+    }
+
+    fn into_iter_from<K>(self, key: K) -> Self::Iter
+    where
+        K: AsRef<[u8]> + 'c,
+    {
+        dispatch!(self, cursor => cursor.into_iter_from(key), IterImpl)
+    }
+
+    fn into_iter_dup_of<K>(self, key: K) -> Self::Iter
+    where
+        K: AsRef<[u8]> + 'c,
+    {
+        dispatch!(self, cursor => cursor.into_iter_dup_of(key), IterImpl)
+    }
+
+    fn into_iter_prev(self) -> Self::Iter {
+        dispatch!(self, cursor => cursor.into_iter_prev(), IterImpl)
+    }
+}
+
+impl<'c> BackendRwCursor<'c> for RwCursorImpl<'c> {
+    type Iter = IterDupImpl<'c>;
+
+    fn into_iter_prev_dup_from<K>(self, key: K) -> Self::Iter
+    where
+        K: AsRef<[u8]> + 'c,
+    {
+        dispatch!(self, cursor => cursor.into_iter_prev_dup_from(key), IterDupImpl)
+    }
+}
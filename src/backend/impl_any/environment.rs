@@ -0,0 +1,208 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::path::{Path, PathBuf};
+
+use super::{
+    DatabaseFlagsImpl, DatabaseImpl, EnvironmentFlagsImpl, ErrorImpl, InfoImpl, RoTransactionImpl,
+    RwTransactionImpl, SharedRoTransactionImpl, StatImpl,
+};
+#[cfg(feature = "lmdb")]
+use crate::backend::impl_lmdb;
+use crate::backend::{
+    impl_safe,
+    traits::{BackendEnvironment, BackendEnvironmentBuilder},
+};
+use crate::env::Key;
+
+/// A builder for either backend. `new()` picks LMDB when the `lmdb` feature is enabled and
+/// SafeMode otherwise, while `lmdb()` and `safe_mode()` pick one explicitly, e.g. from a
+/// configuration read at runtime.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum EnvironmentBuilderImpl {
+    SafeMode(impl_safe::EnvironmentBuilderImpl),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::EnvironmentBuilderImpl),
+}
+
+impl EnvironmentBuilderImpl {
+    #[cfg(feature = "lmdb")]
+    pub fn lmdb() -> EnvironmentBuilderImpl {
+        EnvironmentBuilderImpl::Lmdb(BackendEnvironmentBuilder::new())
+    }
+
+    pub fn safe_mode() -> EnvironmentBuilderImpl {
+        EnvironmentBuilderImpl::SafeMode(BackendEnvironmentBuilder::new())
+    }
+}
+
+impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
+    type Environment = EnvironmentImpl;
+    type Error = ErrorImpl;
+    type Flags = EnvironmentFlagsImpl;
+
+    #[cfg(feature = "lmdb")]
+    fn new() -> EnvironmentBuilderImpl {
+        EnvironmentBuilderImpl::lmdb()
+    }
+
+    #[cfg(not(feature = "lmdb"))]
+    fn new() -> EnvironmentBuilderImpl {
+        EnvironmentBuilderImpl::safe_mode()
+    }
+
+    fn set_flags<T>(&mut self, flags: T) -> &mut Self
+    where
+        T: Into<Self::Flags>,
+    {
+        let flags = flags.into();
+        match self {
+            EnvironmentBuilderImpl::SafeMode(builder) => {
+                builder.set_flags(flags.to_backend::<impl_safe::EnvironmentFlagsImpl>());
+            }
+            #[cfg(feature = "lmdb")]
+            EnvironmentBuilderImpl::Lmdb(builder) => {
+                builder.set_flags(flags.to_backend::<impl_lmdb::EnvironmentFlagsImpl>());
+            }
+        }
+        self
+    }
+
+    fn set_max_readers(&mut self, max_readers: u32) -> &mut Self {
+        dispatch!(self, builder => {
+            builder.set_max_readers(max_readers);
+        });
+        self
+    }
+
+    fn set_max_dbs(&mut self, max_dbs: u32) -> &mut Self {
+        dispatch!(self, builder => {
+            builder.set_max_dbs(max_dbs);
+        });
+        self
+    }
+
+    fn set_map_size(&mut self, size: usize) -> &mut Self {
+        dispatch!(self, builder => {
+            builder.set_map_size(size);
+        });
+        self
+    }
+
+    fn set_enc_key(&mut self, key: Key) -> &mut Self {
+        dispatch!(self, builder => {
+            builder.set_enc_key(key);
+        });
+        self
+    }
+
+    fn set_make_dir_if_needed(&mut self, make_dir_if_needed: bool) -> &mut Self {
+        dispatch!(self, builder => {
+            builder.set_make_dir_if_needed(make_dir_if_needed);
+        });
+        self
+    }
+
+    fn set_discard_if_corrupted(&mut self, discard_if_corrupted: bool) -> &mut Self {
+        dispatch!(self, builder => {
+            builder.set_discard_if_corrupted(discard_if_corrupted);
+        });
+        self
+    }
+
+    fn set_no_tls(&mut self, no_tls: bool) -> &mut Self {
+        dispatch!(self, builder => {
+            builder.set_no_tls(no_tls);
+        });
+        self
+    }
+
+    fn open(&self, path: &Path) -> Result<Self::Environment, Self::Error> {
+        dispatch!(self, builder => builder.open(path), Ok(EnvironmentImpl))
+    }
+}
+
+#[derive(Debug)]
+pub enum EnvironmentImpl {
+    SafeMode(impl_safe::EnvironmentImpl),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::EnvironmentImpl),
+}
+
+impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
+    type Database = DatabaseImpl;
+    type Error = ErrorImpl;
+    type Flags = DatabaseFlagsImpl;
+    type Info = InfoImpl;
+    type RoTransaction = RoTransactionImpl<'e>;
+    type RwTransaction = RwTransactionImpl<'e>;
+    type SharedRoTransaction = SharedRoTransactionImpl<'e>;
+    type Stat = StatImpl;
+
+    fn get_dbs(&self) -> Result<Vec<Option<String>>, Self::Error> {
+        dispatch!(self, env => env.get_dbs().map_err(ErrorImpl::from))
+    }
+
+    fn open_db(&self, name: Option<&str>) -> Result<Self::Database, Self::Error> {
+        dispatch!(self, env => env.open_db(name), Ok(DatabaseImpl))
+    }
+
+    fn create_db(
+        &self,
+        name: Option<&str>,
+        flags: Self::Flags,
+    ) -> Result<Self::Database, Self::Error> {
+        dispatch!(self, env => env.create_db(name, flags.to_backend()), Ok(DatabaseImpl))
+    }
+
+    fn begin_ro_txn(&'e self) -> Result<Self::RoTransaction, Self::Error> {
+        dispatch!(self, env => env.begin_ro_txn(), Ok(RoTransactionImpl))
+    }
+
+    fn begin_rw_txn(&'e self) -> Result<Self::RwTransaction, Self::Error> {
+        dispatch!(self, env => env.begin_rw_txn(), Ok(RwTransactionImpl))
+    }
+
+    fn begin_shared_ro_txn(&'e self) -> Result<Self::SharedRoTransaction, Self::Error> {
+        dispatch!(self, env => env.begin_shared_ro_txn(), Ok(SharedRoTransactionImpl))
+    }
+
+    fn sync(&self, force: bool) -> Result<(), Self::Error> {
+        dispatch!(self, env => env.sync(force).map_err(ErrorImpl::from))
+    }
+
+    fn stat(&self) -> Result<Self::Stat, Self::Error> {
+        dispatch!(self, env => env.stat(), Ok(StatImpl))
+    }
+
+    fn info(&self) -> Result<Self::Info, Self::Error> {
+        dispatch!(self, env => env.info(), Ok(InfoImpl))
+    }
+
+    fn version(&self) -> &str {
+        dispatch!(self, env => env.version())
+    }
+
+    fn freelist(&self) -> Result<usize, Self::Error> {
+        dispatch!(self, env => env.freelist().map_err(ErrorImpl::from))
+    }
+
+    fn load_ratio(&self) -> Result<Option<f32>, Self::Error> {
+        dispatch!(self, env => env.load_ratio().map_err(ErrorImpl::from))
+    }
+
+    fn set_map_size(&self, size: usize) -> Result<(), Self::Error> {
+        dispatch!(self, env => env.set_map_size(size).map_err(ErrorImpl::from))
+    }
+
+    fn get_files_on_disk(&self) -> Vec<PathBuf> {
+        dispatch!(self, env => env.get_files_on_disk())
+    }
+}
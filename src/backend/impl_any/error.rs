@@ -0,0 +1,64 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::fmt;
+
+#[cfg(feature = "lmdb")]
+use crate::backend::impl_lmdb;
+use crate::{
+    backend::{impl_safe, traits::BackendError},
+    error::StoreError,
+};
+
+#[derive(Debug)]
+pub enum ErrorImpl {
+    SafeMode(impl_safe::ErrorImpl),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::ErrorImpl),
+    /// A database of one backend was used with a transaction of the other one.
+    ForeignDatabase,
+}
+
+impl BackendError for ErrorImpl {}
+
+impl fmt::Display for ErrorImpl {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorImpl::SafeMode(e) => e.fmt(fmt),
+            #[cfg(feature = "lmdb")]
+            ErrorImpl::Lmdb(e) => e.fmt(fmt),
+            ErrorImpl::ForeignDatabase => write!(fmt, "ForeignDatabase"),
+        }
+    }
+}
+
+impl Into<StoreError> for ErrorImpl {
+    fn into(self) -> StoreError {
+        match self {
+            ErrorImpl::SafeMode(e) => e.into(),
+            #[cfg(feature = "lmdb")]
+            ErrorImpl::Lmdb(e) => e.into(),
+            ErrorImpl::ForeignDatabase => StoreError::ForeignStore,
+        }
+    }
+}
+
+impl From<impl_safe::ErrorImpl> for ErrorImpl {
+    fn from(e: impl_safe::ErrorImpl) -> ErrorImpl {
+        ErrorImpl::SafeMode(e)
+    }
+}
+
+#[cfg(feature = "lmdb")]
+impl From<impl_lmdb::ErrorImpl> for ErrorImpl {
+    fn from(e: impl_lmdb::ErrorImpl) -> ErrorImpl {
+        ErrorImpl::Lmdb(e)
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use bitflags::bitflags;
+
+use crate::backend::{
+    common::{DatabaseFlags, EnvironmentFlags, WriteFlags},
+    traits::{BackendDatabaseFlags, BackendEnvironmentFlags, BackendFlags, BackendWriteFlags},
+};
+
+// The flags of this backend are set before knowing which backend they're for, so they
+// hold every common flag, and are only converted into the flags of the wrapped backend
+// when they're used. Flags which that backend doesn't support behave as if they were set
+// on it directly.
+
+bitflags! {
+    #[derive(Default)]
+    pub struct EnvironmentFlagsImpl: u32 {
+        const NIL = 0b0000_0000_0000;
+        const FIXED_MAP = 0b0000_0000_0001;
+        const NO_SUB_DIR = 0b0000_0000_0010;
+        const WRITE_MAP = 0b0000_0000_0100;
+        const READ_ONLY = 0b0000_0000_1000;
+        const NO_META_SYNC = 0b0000_0001_0000;
+        const NO_SYNC = 0b0000_0010_0000;
+        const MAP_ASYNC = 0b0000_0100_0000;
+        const NO_TLS = 0b0000_1000_0000;
+        const NO_LOCK = 0b0001_0000_0000;
+        const NO_READAHEAD = 0b0010_0000_0000;
+        const NO_MEM_INIT = 0b0100_0000_0000;
+    }
+}
+
+impl EnvironmentFlagsImpl {
+    pub(crate) fn to_backend<F>(self) -> F
+    where
+        F: BackendEnvironmentFlags,
+    {
+        let mut flags = F::empty();
+        if self.contains(Self::FIXED_MAP) {
+            flags.set(EnvironmentFlags::FIXED_MAP, true);
+        }
+        if self.contains(Self::NO_SUB_DIR) {
+            flags.set(EnvironmentFlags::NO_SUB_DIR, true);
+        }
+        if self.contains(Self::WRITE_MAP) {
+            flags.set(EnvironmentFlags::WRITE_MAP, true);
+        }
+        if self.contains(Self::READ_ONLY) {
+            flags.set(EnvironmentFlags::READ_ONLY, true);
+        }
+        if self.contains(Self::NO_META_SYNC) {
+            flags.set(EnvironmentFlags::NO_META_SYNC, true);
+        }
+        if self.contains(Self::NO_SYNC) {
+            flags.set(EnvironmentFlags::NO_SYNC, true);
+        }
+        if self.contains(Self::MAP_ASYNC) {
+            flags.set(EnvironmentFlags::MAP_ASYNC, true);
+        }
+        if self.contains(Self::NO_TLS) {
+            flags.set(EnvironmentFlags::NO_TLS, true);
+        }
+        if self.contains(Self::NO_LOCK) {
+            flags.set(EnvironmentFlags::NO_LOCK, true);
+        }
+        if self.contains(Self::NO_READAHEAD) {
+            flags.set(EnvironmentFlags::NO_READAHEAD, true);
+        }
+        if self.contains(Self::NO_MEM_INIT) {
+            flags.set(EnvironmentFlags::NO_MEM_INIT, true);
+        }
+        flags
+    }
+}
+
+impl BackendFlags for EnvironmentFlagsImpl {
+    fn empty() -> EnvironmentFlagsImpl {
+        EnvironmentFlagsImpl::empty()
+    }
+}
+
+impl BackendEnvironmentFlags for EnvironmentFlagsImpl {
+    fn set(&mut self, flag: EnvironmentFlags, value: bool) {
+        self.set(flag.into(), value)
+    }
+}
+
+impl Into<EnvironmentFlagsImpl> for EnvironmentFlags {
+    fn into(self) -> EnvironmentFlagsImpl {
+        match self {
+            EnvironmentFlags::FIXED_MAP => EnvironmentFlagsImpl::FIXED_MAP,
+            EnvironmentFlags::NO_SUB_DIR => EnvironmentFlagsImpl::NO_SUB_DIR,
+            EnvironmentFlags::WRITE_MAP => EnvironmentFlagsImpl::WRITE_MAP,
+            EnvironmentFlags::READ_ONLY => EnvironmentFlagsImpl::READ_ONLY,
+            EnvironmentFlags::NO_META_SYNC => EnvironmentFlagsImpl::NO_META_SYNC,
+            EnvironmentFlags::NO_SYNC => EnvironmentFlagsImpl::NO_SYNC,
+            EnvironmentFlags::MAP_ASYNC => EnvironmentFlagsImpl::MAP_ASYNC,
+            EnvironmentFlags::NO_TLS => EnvironmentFlagsImpl::NO_TLS,
+            EnvironmentFlags::NO_LOCK => EnvironmentFlagsImpl::NO_LOCK,
+            EnvironmentFlags::NO_READAHEAD => EnvironmentFlagsImpl::NO_READAHEAD,
+            EnvironmentFlags::NO_MEM_INIT => EnvironmentFlagsImpl::NO_MEM_INIT,
+        }
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct DatabaseFlagsImpl: u32 {
+        const NIL = 0b0000_0000;
+        const REVERSE_KEY = 0b0000_0001;
+        #[cfg(feature = "db-dup-sort")]
+        const DUP_SORT = 0b0000_0010;
+        #[cfg(feature = "db-dup-sort")]
+        const DUP_FIXED = 0b0000_0100;
+        #[cfg(feature = "db-int-key")]
+        const INTEGER_KEY = 0b0000_1000;
+        const INTEGER_DUP = 0b0001_0000;
+        const REVERSE_DUP = 0b0010_0000;
+    }
+}
+
+impl DatabaseFlagsImpl {
+    pub(crate) fn to_backend<F>(self) -> F
+    where
+        F: BackendDatabaseFlags,
+    {
+        let mut flags = F::empty();
+        if self.contains(Self::REVERSE_KEY) {
+            flags.set(DatabaseFlags::REVERSE_KEY, true);
+        }
+        #[cfg(feature = "db-dup-sort")]
+        if self.contains(Self::DUP_SORT) {
+            flags.set(DatabaseFlags::DUP_SORT, true);
+        }
+        #[cfg(feature = "db-dup-sort")]
+        if self.contains(Self::DUP_FIXED) {
+            flags.set(DatabaseFlags::DUP_FIXED, true);
+        }
+        #[cfg(feature = "db-int-key")]
+        if self.contains(Self::INTEGER_KEY) {
+            flags.set(DatabaseFlags::INTEGER_KEY, true);
+        }
+        if self.contains(Self::INTEGER_DUP) {
+            flags.set(DatabaseFlags::INTEGER_DUP, true);
+        }
+        if self.contains(Self::REVERSE_DUP) {
+            flags.set(DatabaseFlags::REVERSE_DUP, true);
+        }
+        flags
+    }
+
+    pub(crate) fn from_backend<F>(flags: &F) -> DatabaseFlagsImpl
+    where
+        F: BackendDatabaseFlags,
+    {
+        let mut result = DatabaseFlagsImpl::empty();
+        result.set(
+            Self::REVERSE_KEY,
+            flags.contains(DatabaseFlags::REVERSE_KEY),
+        );
+        #[cfg(feature = "db-dup-sort")]
+        result.set(Self::DUP_SORT, flags.contains(DatabaseFlags::DUP_SORT));
+        #[cfg(feature = "db-dup-sort")]
+        result.set(Self::DUP_FIXED, flags.contains(DatabaseFlags::DUP_FIXED));
+        #[cfg(feature = "db-int-key")]
+        result.set(
+            Self::INTEGER_KEY,
+            flags.contains(DatabaseFlags::INTEGER_KEY),
+        );
+        result.set(
+            Self::INTEGER_DUP,
+            flags.contains(DatabaseFlags::INTEGER_DUP),
+        );
+        result.set(
+            Self::REVERSE_DUP,
+            flags.contains(DatabaseFlags::REVERSE_DUP),
+        );
+        result
+    }
+}
+
+impl BackendFlags for DatabaseFlagsImpl {
+    fn empty() -> DatabaseFlagsImpl {
+        DatabaseFlagsImpl::empty()
+    }
+}
+
+impl BackendDatabaseFlags for DatabaseFlagsImpl {
+    fn set(&mut self, flag: DatabaseFlags, value: bool) {
+        self.set(flag.into(), value)
+    }
+
+    fn contains(&self, flag: DatabaseFlags) -> bool {
+        self.contains(flag.into())
+    }
+}
+
+impl Into<DatabaseFlagsImpl> for DatabaseFlags {
+    fn into(self) -> DatabaseFlagsImpl {
+        match self {
+            DatabaseFlags::REVERSE_KEY => DatabaseFlagsImpl::REVERSE_KEY,
+            #[cfg(feature = "db-dup-sort")]
+            DatabaseFlags::DUP_SORT => DatabaseFlagsImpl::DUP_SORT,
+            #[cfg(feature = "db-dup-sort")]
+            DatabaseFlags::DUP_FIXED => DatabaseFlagsImpl::DUP_FIXED,
+            #[cfg(feature = "db-int-key")]
+            DatabaseFlags::INTEGER_KEY => DatabaseFlagsImpl::INTEGER_KEY,
+            DatabaseFlags::INTEGER_DUP => DatabaseFlagsImpl::INTEGER_DUP,
+            DatabaseFlags::REVERSE_DUP => DatabaseFlagsImpl::REVERSE_DUP,
+        }
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct WriteFlagsImpl: u32 {
+        const NIL = 0b0000_0000;
+        const NO_OVERWRITE = 0b0000_0001;
+        const NO_DUP_DATA = 0b0000_0010;
+        const CURRENT = 0b0000_0100;
+        const APPEND = 0b0000_1000;
+        const APPEND_DUP = 0b0001_0000;
+    }
+}
+
+impl WriteFlagsImpl {
+    pub(crate) fn to_backend<F>(self) -> F
+    where
+        F: BackendWriteFlags,
+    {
+        let mut flags = F::empty();
+        if self.contains(Self::NO_OVERWRITE) {
+            flags.set(WriteFlags::NO_OVERWRITE, true);
+        }
+        if self.contains(Self::NO_DUP_DATA) {
+            flags.set(WriteFlags::NO_DUP_DATA, true);
+        }
+        if self.contains(Self::CURRENT) {
+            flags.set(WriteFlags::CURRENT, true);
+        }
+        if self.contains(Self::APPEND) {
+            flags.set(WriteFlags::APPEND, true);
+        }
+        if self.contains(Self::APPEND_DUP) {
+            flags.set(WriteFlags::APPEND_DUP, true);
+        }
+        flags
+    }
+}
+
+impl BackendFlags for WriteFlagsImpl {
+    fn empty() -> WriteFlagsImpl {
+        WriteFlagsImpl::empty()
+    }
+}
+
+impl BackendWriteFlags for WriteFlagsImpl {
+    fn set(&mut self, flag: WriteFlags, value: bool) {
+        self.set(flag.into(), value)
+    }
+}
+
+impl Into<WriteFlagsImpl> for WriteFlags {
+    fn into(self) -> WriteFlagsImpl {
+        match self {
+            WriteFlags::NO_OVERWRITE => WriteFlagsImpl::NO_OVERWRITE,
+            WriteFlags::NO_DUP_DATA => WriteFlagsImpl::NO_DUP_DATA,
+            WriteFlags::CURRENT => WriteFlagsImpl::CURRENT,
+            WriteFlags::APPEND => WriteFlagsImpl::APPEND,
+            WriteFlags::APPEND_DUP => WriteFlagsImpl::APPEND_DUP,
+        }
+    }
+}
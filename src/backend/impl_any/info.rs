@@ -0,0 +1,41 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+#[cfg(feature = "lmdb")]
+use crate::backend::impl_lmdb;
+use crate::backend::{impl_safe, traits::BackendInfo};
+
+pub enum InfoImpl {
+    SafeMode(impl_safe::InfoImpl),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::InfoImpl),
+}
+
+impl BackendInfo for InfoImpl {
+    fn map_size(&self) -> usize {
+        dispatch!(self, info => info.map_size())
+    }
+
+    fn last_pgno(&self) -> usize {
+        dispatch!(self, info => info.last_pgno())
+    }
+
+    fn last_txnid(&self) -> usize {
+        dispatch!(self, info => info.last_txnid())
+    }
+
+    fn max_readers(&self) -> usize {
+        dispatch!(self, info => info.max_readers())
+    }
+
+    fn num_readers(&self) -> usize {
+        dispatch!(self, info => info.num_readers())
+    }
+}
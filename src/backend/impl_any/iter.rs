@@ -0,0 +1,65 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use super::ErrorImpl;
+#[cfg(feature = "lmdb")]
+use crate::backend::impl_lmdb;
+use crate::backend::{
+    impl_safe,
+    traits::{BackendDupIter, BackendIter},
+};
+
+pub enum IterImpl<'i> {
+    SafeMode(impl_safe::IterImpl<'i>),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::IterImpl<'i, lmdb::RoCursor<'i>>),
+    // The values of one key, yielded by an `IterDupImpl`.
+    #[cfg(feature = "lmdb")]
+    LmdbDup(impl_lmdb::ProxyIterImpl<'i>),
+}
+
+impl<'i> BackendIter<'i> for IterImpl<'i> {
+    type Error = ErrorImpl;
+
+    #[allow(clippy::type_complexity)]
+    fn next(&mut self) -> Option<Result<(&'i [u8], &'i [u8]), Self::Error>> {
+        match self {
+            IterImpl::SafeMode(iter) => iter.next().map(|r| r.map_err(ErrorImpl::from)),
+            #[cfg(feature = "lmdb")]
+            IterImpl::Lmdb(iter) => iter.next().map(|r| r.map_err(ErrorImpl::from)),
+            #[cfg(feature = "lmdb")]
+            IterImpl::LmdbDup(iter) => iter.next().map(|r| r.map_err(ErrorImpl::from)),
+        }
+    }
+}
+
+pub enum IterDupImpl<'i> {
+    SafeMode(impl_safe::IterDupImpl<'i>),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::IterDupImpl<'i, lmdb::RoCursor<'i>>),
+}
+
+impl<'i> BackendDupIter<'i> for IterDupImpl<'i> {
+    type Error = ErrorImpl;
+    type Iter = IterImpl<'i>;
+
+    #[allow(clippy::type_complexity)]
+    fn next(&mut self) -> Option<Result<Self::Iter, Self::Error>> {
+        match self {
+            IterDupImpl::SafeMode(iter) => iter
+                .next()
+                .map(|r| r.map(IterImpl::SafeMode).map_err(ErrorImpl::from)),
+            #[cfg(feature = "lmdb")]
+            IterDupImpl::Lmdb(iter) => iter
+                .next()
+                .map(|r| r.map(IterImpl::LmdbDup).map_err(ErrorImpl::from)),
+        }
+    }
+}
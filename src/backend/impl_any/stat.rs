@@ -0,0 +1,45 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+#[cfg(feature = "lmdb")]
+use crate::backend::impl_lmdb;
+use crate::backend::{impl_safe, traits::BackendStat};
+
+pub enum StatImpl {
+    SafeMode(impl_safe::StatImpl),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::StatImpl),
+}
+
+impl BackendStat for StatImpl {
+    fn page_size(&self) -> usize {
+        dispatch!(self, stat => stat.page_size())
+    }
+
+    fn depth(&self) -> usize {
+        dispatch!(self, stat => stat.depth())
+    }
+
+    fn branch_pages(&self) -> usize {
+        dispatch!(self, stat => stat.branch_pages())
+    }
+
+    fn leaf_pages(&self) -> usize {
+        dispatch!(self, stat => stat.leaf_pages())
+    }
+
+    fn overflow_pages(&self) -> usize {
+        dispatch!(self, stat => stat.overflow_pages())
+    }
+
+    fn entries(&self) -> usize {
+        dispatch!(self, stat => stat.entries())
+    }
+}
@@ -0,0 +1,270 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use super::{
+    DatabaseFlagsImpl, DatabaseImpl, ErrorImpl, RoCursorImpl, RwCursorImpl, StatImpl,
+    WriteFlagsImpl,
+};
+#[cfg(feature = "lmdb")]
+use crate::backend::impl_lmdb;
+use crate::backend::{
+    impl_safe,
+    traits::{
+        BackendInactiveTransaction, BackendRoCursorTransaction, BackendRoTransaction,
+        BackendRwCursorTransaction, BackendRwCursorType, BackendRwDupPrevCursorTransaction,
+        BackendRwTransaction,
+    },
+};
+
+#[derive(Debug)]
+pub enum RoTransactionImpl<'t> {
+    SafeMode(impl_safe::RoTransactionImpl<'t>),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::RoTransactionImpl<'t>),
+}
+
+impl<'t> BackendRoTransaction for RoTransactionImpl<'t> {
+    type Database = DatabaseImpl;
+    type DatabaseFlags = DatabaseFlagsImpl;
+    type Error = ErrorImpl;
+    type Stat = StatImpl;
+    type Inactive = InactiveTransactionImpl<'t>;
+
+    fn get(&self, db: &Self::Database, key: &[u8]) -> Result<&[u8], Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.get(db, key))
+    }
+
+    fn abort(self) {
+        dispatch!(self, txn => txn.abort())
+    }
+
+    fn reset(self) -> Self::Inactive {
+        dispatch!(self, txn => txn.reset(), InactiveTransactionImpl)
+    }
+
+    fn stat(&self, db: &Self::Database) -> Result<Self::Stat, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.stat(db), Ok(StatImpl))
+    }
+
+    fn db_flags(&self, db: &Self::Database) -> Result<Self::DatabaseFlags, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn
+            .db_flags(db)
+            .map(|flags| DatabaseFlagsImpl::from_backend(&flags)))
+    }
+}
+
+#[derive(Debug)]
+pub enum InactiveTransactionImpl<'t> {
+    SafeMode(impl_safe::InactiveTransactionImpl<'t>),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::InactiveTransactionImpl<'t>),
+}
+
+impl<'t> BackendInactiveTransaction for InactiveTransactionImpl<'t> {
+    type Error = ErrorImpl;
+    type RoTransaction = RoTransactionImpl<'t>;
+
+    fn renew(self) -> Result<Self::RoTransaction, Self::Error> {
+        dispatch!(self, txn => txn.renew(), Ok(RoTransactionImpl))
+    }
+}
+
+impl<'t> BackendRoCursorTransaction<'t> for RoTransactionImpl<'t> {
+    type RoCursor = RoCursorImpl<'t>;
+    type RwCursor = RwCursorImpl<'t>;
+
+    fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.open_ro_cursor(db), Ok(RoCursorImpl))
+    }
+
+    fn open_ro_dup_cursor(&'t self, db: &Self::Database) -> Result<Self::RwCursor, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.open_ro_dup_cursor(db), Ok(RwCursorImpl))
+    }
+}
+
+#[derive(Debug)]
+pub enum SharedRoTransactionImpl<'t> {
+    SafeMode(impl_safe::RoTransactionImpl<'t>),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::SharedRoTransactionImpl<'t>),
+}
+
+impl<'t> BackendRoTransaction for SharedRoTransactionImpl<'t> {
+    type Database = DatabaseImpl;
+    type DatabaseFlags = DatabaseFlagsImpl;
+    type Error = ErrorImpl;
+    type Stat = StatImpl;
+    type Inactive = SharedInactiveTransactionImpl<'t>;
+
+    fn get(&self, db: &Self::Database, key: &[u8]) -> Result<&[u8], Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.get(db, key))
+    }
+
+    fn abort(self) {
+        dispatch!(self, txn => txn.abort())
+    }
+
+    fn reset(self) -> Self::Inactive {
+        dispatch!(self, txn => txn.reset(), SharedInactiveTransactionImpl)
+    }
+
+    fn stat(&self, db: &Self::Database) -> Result<Self::Stat, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.stat(db), Ok(StatImpl))
+    }
+
+    fn db_flags(&self, db: &Self::Database) -> Result<Self::DatabaseFlags, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn
+            .db_flags(db)
+            .map(|flags| DatabaseFlagsImpl::from_backend(&flags)))
+    }
+}
+
+impl<'t> BackendRoCursorTransaction<'t> for SharedRoTransactionImpl<'t> {
+    type RoCursor = RoCursorImpl<'t>;
+    type RwCursor = RwCursorImpl<'t>;
+
+    fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.open_ro_cursor(db), Ok(RoCursorImpl))
+    }
+
+    fn open_ro_dup_cursor(&'t self, db: &Self::Database) -> Result<Self::RwCursor, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.open_ro_dup_cursor(db), Ok(RwCursorImpl))
+    }
+}
+
+#[derive(Debug)]
+pub enum SharedInactiveTransactionImpl<'t> {
+    SafeMode(impl_safe::InactiveTransactionImpl<'t>),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::SharedInactiveTransactionImpl<'t>),
+}
+
+impl<'t> BackendInactiveTransaction for SharedInactiveTransactionImpl<'t> {
+    type Error = ErrorImpl;
+    type RoTransaction = SharedRoTransactionImpl<'t>;
+
+    fn renew(self) -> Result<Self::RoTransaction, Self::Error> {
+        dispatch!(self, txn => txn.renew(), Ok(SharedRoTransactionImpl))
+    }
+}
+
+#[derive(Debug)]
+pub enum RwTransactionImpl<'t> {
+    SafeMode(impl_safe::RwTransactionImpl<'t>),
+    #[cfg(feature = "lmdb")]
+    Lmdb(impl_lmdb::RwTransactionImpl<'t>),
+}
+
+impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
+    type Database = DatabaseImpl;
+    type DatabaseFlags = DatabaseFlagsImpl;
+    type Error = ErrorImpl;
+    type Flags = WriteFlagsImpl;
+    type Stat = StatImpl;
+
+    fn get(&self, db: &Self::Database, key: &[u8]) -> Result<&[u8], Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.get(db, key))
+    }
+
+    fn put(
+        &mut self,
+        db: &Self::Database,
+        key: &[u8],
+        value: &[u8],
+        flags: Self::Flags,
+    ) -> Result<(), Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.put(db, key, value, flags.to_backend()))
+    }
+
+    #[cfg(not(feature = "db-dup-sort"))]
+    fn del(&mut self, db: &Self::Database, key: &[u8]) -> Result<(), Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.del(db, key))
+    }
+
+    #[cfg(feature = "db-dup-sort")]
+    fn del(
+        &mut self,
+        db: &Self::Database,
+        key: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<(), Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.del(db, key, value))
+    }
+
+    fn put_many(
+        &mut self,
+        db: &Self::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.put_many(db, pairs))
+    }
+
+    fn put_sorted(
+        &mut self,
+        db: &Self::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.put_sorted(db, pairs))
+    }
+
+    fn range_keys(
+        &self,
+        db: &Self::Database,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Vec<Vec<u8>>, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.range_keys(db, start, end))
+    }
+
+    fn clear_db(&mut self, db: &Self::Database) -> Result<(), Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.clear_db(db))
+    }
+
+    fn commit(self) -> Result<(), Self::Error> {
+        dispatch!(self, txn => txn.commit().map_err(ErrorImpl::from))
+    }
+
+    fn abort(self) {
+        dispatch!(self, txn => txn.abort())
+    }
+
+    fn stat(&self, db: &Self::Database) -> Result<Self::Stat, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.stat(db), Ok(StatImpl))
+    }
+
+    fn db_flags(&self, db: &Self::Database) -> Result<Self::DatabaseFlags, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn
+            .db_flags(db)
+            .map(|flags| DatabaseFlagsImpl::from_backend(&flags)))
+    }
+}
+
+impl<'t> BackendRwCursorTransaction<'t> for RwTransactionImpl<'t> {
+    type RoCursor = RoCursorImpl<'t>;
+    type RwCursor = RwCursorImpl<'t>;
+
+    fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.open_ro_cursor(db), Ok(RoCursorImpl))
+    }
+
+    fn open_ro_dup_cursor(&'t self, db: &Self::Database) -> Result<Self::RwCursor, Self::Error> {
+        dispatch_db!(self, db, (txn, db) => txn.open_ro_dup_cursor(db), Ok(RwCursorImpl))
+    }
+}
+
+pub enum BackendRwCursorFamily {}
+
+impl<'t> BackendRwCursorType<'t> for BackendRwCursorFamily {
+    type Type = RwCursorImpl<'t>;
+}
+
+impl BackendRwDupPrevCursorTransaction for RwTransactionImpl<'_> {
+    type RwCursor = BackendRwCursorFamily;
+}
@@ -17,6 +17,7 @@ mod error;
 mod flags;
 mod info;
 mod iter;
+mod lock;
 mod stat;
 mod transaction;
 
@@ -29,6 +30,9 @@ pub use environment::{EnvironmentBuilderImpl, EnvironmentImpl};
 pub use error::ErrorImpl;
 pub use flags::{DatabaseFlagsImpl, EnvironmentFlagsImpl, WriteFlagsImpl};
 pub use info::InfoImpl;
-pub use iter::{IterDupImpl, IterImpl};
+pub use iter::{IterDupImpl, IterImpl, ProxyIterImpl};
 pub use stat::StatImpl;
-pub use transaction::{RoTransactionImpl, RwTransactionImpl};
+pub use transaction::{
+    InactiveTransactionImpl, RoTransactionImpl, RwTransactionImpl, SharedInactiveTransactionImpl,
+    SharedRoTransactionImpl,
+};
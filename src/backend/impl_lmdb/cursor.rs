@@ -10,24 +10,32 @@
 
 use lmdb::Cursor;
 
-use super::{IterDupImpl, IterImpl};
+use super::{
+    lock::{Locked, TxnLock},
+    IterDupImpl, IterImpl,
+};
 use crate::backend::traits::{BackendRoCursor, BackendRwCursor};
 
 #[derive(Debug)]
-pub struct RoCursorImpl<'c>(pub(crate) lmdb::RoCursor<'c>);
+pub struct RoCursorImpl<'c>(pub(crate) Locked<'c, lmdb::RoCursor<'c>>);
+
+impl<'c> RoCursorImpl<'c> {
+    pub(crate) fn new(cursor: lmdb::RoCursor<'c>, lock: TxnLock<'c>) -> RoCursorImpl<'c> {
+        RoCursorImpl(Locked::new(cursor, lock))
+    }
+}
 
 impl<'c> BackendRoCursor<'c> for RoCursorImpl<'c> {
     type Iter = IterImpl<'c, lmdb::RoCursor<'c>>;
 
-    fn get_key_value<K>(self, key: K, value: &crate::value::Value) -> bool
+    fn get_key_value<K>(self, key: K, value: &[u8]) -> bool
     where
         K: AsRef<[u8]> + 'c,
     {
-        match self.0.get(
-            Some(key.as_ref()),
-            Some(&value.to_bytes().unwrap()),
-            lmdb::MDB_GET_BOTH,
-        ) {
+        let lock = self.0.lock();
+        let _held = lock.hold();
+        let cursor = self.0.into_inner();
+        match cursor.get(Some(key.as_ref()), Some(value), lmdb::MDB_GET_BOTH) {
             Err(e) => false,
             Ok((Some(k), _)) => true,
             Ok((None, _)) => true,
@@ -63,20 +71,25 @@ impl<'c> BackendRoCursor<'c> for RoCursorImpl<'c> {
 }
 
 #[derive(Debug)]
-pub struct RwCursorImpl<'c>(pub(crate) lmdb::RoCursor<'c>);
+pub struct RwCursorImpl<'c>(pub(crate) Locked<'c, lmdb::RoCursor<'c>>);
+
+impl<'c> RwCursorImpl<'c> {
+    pub(crate) fn new(cursor: lmdb::RoCursor<'c>, lock: TxnLock<'c>) -> RwCursorImpl<'c> {
+        RwCursorImpl(Locked::new(cursor, lock))
+    }
+}
 
 impl<'c> BackendRoCursor<'c> for RwCursorImpl<'c> {
     type Iter = IterImpl<'c, lmdb::RoCursor<'c>>;
 
-    fn get_key_value<K>(self, key: K, value: &crate::value::Value) -> bool
+    fn get_key_value<K>(self, key: K, value: &[u8]) -> bool
     where
         K: AsRef<[u8]> + 'c,
     {
-        match self.0.get(
-            Some(key.as_ref()),
-            Some(&value.to_bytes().unwrap()),
-            lmdb::MDB_GET_BOTH,
-        ) {
+        let lock = self.0.lock();
+        let _held = lock.hold();
+        let cursor = self.0.into_inner();
+        match cursor.get(Some(key.as_ref()), Some(value), lmdb::MDB_GET_BOTH) {
             Err(e) => false,
             Ok((Some(k), _)) => true,
             Ok((None, _)) => false,
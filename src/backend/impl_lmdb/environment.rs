@@ -17,7 +17,7 @@ use lmdb::Error as LmdbError;
 
 use super::{
     DatabaseFlagsImpl, DatabaseImpl, EnvironmentFlagsImpl, ErrorImpl, InfoImpl, RoTransactionImpl,
-    RwTransactionImpl, StatImpl,
+    RwTransactionImpl, SharedRoTransactionImpl, StatImpl,
 };
 use crate::backend::traits::{
     BackendEnvironment, BackendEnvironmentBuilder, BackendInfo, BackendIter, BackendRoCursor,
@@ -33,6 +33,8 @@ pub struct EnvironmentBuilderImpl {
     env_lock_type: EnvironmentLockType,
     env_db_type: EnvironmentDefaultDbType,
     make_dir_if_needed: bool,
+    flags: lmdb::EnvironmentFlags,
+    no_tls: bool,
 }
 
 impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
@@ -47,6 +49,8 @@ impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
             env_lock_type: EnvironmentLockType::Lockfile,
             env_db_type: EnvironmentDefaultDbType::SingleDatabase,
             make_dir_if_needed: false,
+            flags: lmdb::EnvironmentFlags::empty(),
+            no_tls: false,
         }
     }
 
@@ -55,13 +59,14 @@ impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
         T: Into<Self::Flags>,
     {
         let flags = flags.into();
-        if flags.0 == lmdb::EnvironmentFlags::NO_SUB_DIR {
+        if flags.0.contains(lmdb::EnvironmentFlags::NO_SUB_DIR) {
             self.env_path_type = EnvironmentPathType::NoSubDir;
         }
-        if flags.0 == lmdb::EnvironmentFlags::NO_LOCK {
+        if flags.0.contains(lmdb::EnvironmentFlags::NO_LOCK) {
             self.env_lock_type = EnvironmentLockType::NoLockfile;
         }
-        self.builder.set_flags(flags.0);
+        self.flags = flags.0;
+        self.builder.set_flags(self.lmdb_flags());
         self
     }
 
@@ -99,6 +104,12 @@ impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
         unimplemented!();
     }
 
+    fn set_no_tls(&mut self, no_tls: bool) -> &mut Self {
+        self.no_tls = no_tls;
+        self.builder.set_flags(self.lmdb_flags());
+        self
+    }
+
     fn open(&self, path: &Path) -> Result<Self::Environment, Self::Error> {
         match self.env_path_type {
             EnvironmentPathType::NoSubDir => {
@@ -125,12 +136,25 @@ impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
                     self.env_path_type,
                     self.env_lock_type,
                     self.env_db_type,
+                    self.lmdb_flags().contains(lmdb::EnvironmentFlags::NO_TLS),
                     lmdbenv,
                 )
             })
     }
 }
 
+impl EnvironmentBuilderImpl {
+    // The flags to open the environment with: `set_no_tls` adds to those of `set_flags`
+    // rather than being replaced by them.
+    fn lmdb_flags(&self) -> lmdb::EnvironmentFlags {
+        if self.no_tls {
+            self.flags | lmdb::EnvironmentFlags::NO_TLS
+        } else {
+            self.flags
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum EnvironmentPathType {
     SubDir,
@@ -155,6 +179,7 @@ pub struct EnvironmentImpl {
     env_path_type: EnvironmentPathType,
     env_lock_type: EnvironmentLockType,
     env_db_type: EnvironmentDefaultDbType,
+    no_tls: bool,
     lmdbenv: lmdb::Environment,
 }
 
@@ -164,6 +189,7 @@ impl EnvironmentImpl {
         env_path_type: EnvironmentPathType,
         env_lock_type: EnvironmentLockType,
         env_db_type: EnvironmentDefaultDbType,
+        no_tls: bool,
         lmdbenv: lmdb::Environment,
     ) -> Result<EnvironmentImpl, ErrorImpl> {
         Ok(EnvironmentImpl {
@@ -171,6 +197,7 @@ impl EnvironmentImpl {
             env_path_type,
             env_lock_type,
             env_db_type,
+            no_tls,
             lmdbenv,
         })
     }
@@ -183,6 +210,7 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
     type Info = InfoImpl;
     type RoTransaction = RoTransactionImpl<'e>;
     type RwTransaction = RwTransactionImpl<'e>;
+    type SharedRoTransaction = SharedRoTransactionImpl<'e>;
     type Stat = StatImpl;
 
     fn get_dbs(&self) -> Result<Vec<Option<String>>, Self::Error> {
@@ -243,6 +271,14 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
             .map_err(ErrorImpl::LmdbError)
     }
 
+    fn begin_shared_ro_txn(&'e self) -> Result<Self::SharedRoTransaction, Self::Error> {
+        // Without NO_TLS, a read transaction is bound to the thread that began it.
+        if !self.no_tls {
+            return Err(ErrorImpl::NoTlsRequired);
+        }
+        self.begin_ro_txn().map(SharedRoTransactionImpl::new)
+    }
+
     fn sync(&self, force: bool) -> Result<(), Self::Error> {
         self.lmdbenv.sync(force).map_err(ErrorImpl::LmdbError)
     }
@@ -289,10 +325,14 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
         let mut store = vec![];
 
         if self.env_path_type == EnvironmentPathType::NoSubDir {
-            // The option NO_SUB_DIR could change the default directory layout; therefore this should
-            // probably return the path used to create environment, along with the custom lockfile
-            // when available.
-            unimplemented!();
+            // The path is the data file itself, and LMDB names the lockfile after it.
+            store.push(self.path.clone());
+            if self.env_lock_type == EnvironmentLockType::Lockfile {
+                let mut lock_filename = self.path.clone().into_os_string();
+                lock_filename.push("-lock");
+                store.push(lock_filename.into());
+            }
+            return store;
         }
 
         let mut db_filename = self.path.clone();
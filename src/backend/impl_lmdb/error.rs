@@ -17,6 +17,8 @@ pub enum ErrorImpl {
     LmdbError(lmdb::Error),
     UnsuitableEnvironmentPath(PathBuf),
     IoError(io::Error),
+    NoTlsRequired,
+    KeysNotSorted,
 }
 
 impl BackendError for ErrorImpl {}
@@ -27,6 +29,8 @@ impl fmt::Display for ErrorImpl {
             ErrorImpl::LmdbError(e) => e.fmt(fmt),
             ErrorImpl::UnsuitableEnvironmentPath(_) => write!(fmt, "UnsuitableEnvironmentPath"),
             ErrorImpl::IoError(e) => e.fmt(fmt),
+            ErrorImpl::NoTlsRequired => write!(fmt, "NoTlsRequired"),
+            ErrorImpl::KeysNotSorted => write!(fmt, "KeysNotSorted"),
         }
     }
 }
@@ -46,6 +50,8 @@ impl Into<StoreError> for ErrorImpl {
                 StoreError::UnsuitableEnvironmentPath(path)
             }
             ErrorImpl::IoError(error) => StoreError::IoError(error),
+            ErrorImpl::NoTlsRequired => StoreError::NoTlsRequired,
+            ErrorImpl::KeysNotSorted => StoreError::KeysNotSorted,
         }
     }
 }
@@ -8,7 +8,10 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use super::ErrorImpl;
+use super::{
+    lock::{Locked, TxnLock},
+    ErrorImpl,
+};
 use crate::backend::traits::{BackendDupIter, BackendIter, BackendRoCursor};
 use lmdb::Cursor;
 
@@ -17,18 +20,22 @@ pub struct IterImpl<'i, C> {
     // of an iterator. In other words, cursors must not be dropped while an
     // iterator built from it is alive. Unfortunately, the LMDB crate API does
     // not express this through the type system, so we must enforce it somehow.
-    #[allow(dead_code)]
-    cursor: C,
-    iter: lmdb::Iter<'i>,
+    // The iterator goes first in the pair, so it's dropped before the cursor.
+    inner: Locked<'i, (lmdb::Iter<'i>, C)>,
 }
 
 impl<'i, C> IterImpl<'i, C> {
     pub(crate) fn new(
-        mut cursor: C,
+        cursor: Locked<'i, C>,
         to_iter: impl FnOnce(&mut C) -> lmdb::Iter<'i>,
     ) -> IterImpl<'i, C> {
+        let lock = cursor.lock();
+        let _held = lock.hold();
+        let mut cursor = cursor.into_inner();
         let iter = to_iter(&mut cursor);
-        IterImpl { cursor, iter }
+        IterImpl {
+            inner: Locked::new((iter, cursor), lock),
+        }
     }
 }
 
@@ -37,7 +44,8 @@ impl<'i, C> BackendIter<'i> for IterImpl<'i, C> {
 
     #[allow(clippy::type_complexity)]
     fn next(&mut self) -> Option<Result<(&'i [u8], &'i [u8]), Self::Error>> {
-        self.iter.next().map(|e| e.map_err(ErrorImpl::LmdbError))
+        let _held = self.inner.lock().hold();
+        self.inner.0.next().map(|e| e.map_err(ErrorImpl::LmdbError))
     }
 }
 
@@ -45,13 +53,13 @@ pub struct ProxyIterImpl<'i> {
     // Here we do not keep the cursor, because we are in a sub-iterator (the iterator of values on a duplicate key)
     // and the lmdb cursor is kept by the higher level iterator.
     // becareful to keep the higher level iterator (the iterator of keys) alive as long as you are iterating on this iterator.
-    #[allow(dead_code)]
     iter: lmdb::Iter<'i>,
+    lock: TxnLock<'i>,
 }
 
 impl<'i> ProxyIterImpl<'i> {
-    pub(crate) fn new_from_lmdb_iter(iter: lmdb::Iter<'i>) -> ProxyIterImpl<'i> {
-        ProxyIterImpl { iter }
+    pub(crate) fn new_from_lmdb_iter(iter: lmdb::Iter<'i>, lock: TxnLock<'i>) -> ProxyIterImpl<'i> {
+        ProxyIterImpl { iter, lock }
     }
 }
 
@@ -60,6 +68,7 @@ impl<'i> BackendIter<'i> for ProxyIterImpl<'i> {
 
     #[allow(clippy::type_complexity)]
     fn next(&mut self) -> Option<Result<(&'i [u8], &'i [u8]), Self::Error>> {
+        let _held = self.lock.hold();
         self.iter.next().map(|e| e.map_err(ErrorImpl::LmdbError))
     }
 }
@@ -69,18 +78,22 @@ pub struct IterDupImpl<'i, C> {
     // of an iterator. In other words, cursors must not be dropped while an
     // iterator built from it is alive. Unfortunately, the LMDB crate API does
     // not express this through the type system, so we must enforce it somehow.
-    #[allow(dead_code)]
-    cursor: C,
-    iter: lmdb::IterPrevDup<'i>,
+    // The iterator goes first in the pair, so it's dropped before the cursor.
+    inner: Locked<'i, (lmdb::IterPrevDup<'i>, C)>,
 }
 
 impl<'i, C> IterDupImpl<'i, C> {
     pub(crate) fn new(
-        mut cursor: C,
+        cursor: Locked<'i, C>,
         to_iter: impl FnOnce(&mut C) -> lmdb::IterPrevDup<'i>,
     ) -> IterDupImpl<'i, C> {
+        let lock = cursor.lock();
+        let _held = lock.hold();
+        let mut cursor = cursor.into_inner();
         let iter = to_iter(&mut cursor);
-        IterDupImpl { cursor, iter }
+        IterDupImpl {
+            inner: Locked::new((iter, cursor), lock),
+        }
     }
 }
 
@@ -90,10 +103,12 @@ impl<'i, C> BackendDupIter<'i> for IterDupImpl<'i, C> {
 
     #[allow(clippy::type_complexity)]
     fn next(&mut self) -> Option<Result<Self::Iter, Self::Error>> {
-        let next = self.iter.next();
+        let lock = self.inner.lock();
+        let _held = lock.hold();
+        let next = self.inner.0.next();
         match next {
             None => None,
-            Some(n) => Some(Ok(ProxyIterImpl::new_from_lmdb_iter(n))),
+            Some(n) => Some(Ok(ProxyIterImpl::new_from_lmdb_iter(n, lock))),
         }
     }
 }
@@ -0,0 +1,77 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// The lock which serializes the uses of a `SharedRoTransactionImpl`, held by the cursors
+/// opened in it and their iterators. Those of other transactions have none.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct TxnLock<'t>(pub(crate) Option<&'t Mutex<()>>);
+
+impl<'t> TxnLock<'t> {
+    pub(crate) fn hold(&self) -> Option<MutexGuard<'t, ()>> {
+        self.0
+            .map(|lock| lock.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// A cursor, or a cursor and its iterator, which is dropped while holding the lock of its
+/// transaction, since closing a cursor goes through the transaction too.
+#[derive(Debug)]
+pub(crate) struct Locked<'t, T> {
+    value: ManuallyDrop<T>,
+    lock: TxnLock<'t>,
+}
+
+impl<'t, T> Locked<'t, T> {
+    pub(crate) fn new(value: T, lock: TxnLock<'t>) -> Locked<'t, T> {
+        Locked {
+            value: ManuallyDrop::new(value),
+            lock,
+        }
+    }
+
+    pub(crate) fn lock(&self) -> TxnLock<'t> {
+        self.lock
+    }
+
+    /// Takes the value out, leaving it to the caller to drop it while holding the lock.
+    pub(crate) fn into_inner(self) -> T {
+        let mut this = ManuallyDrop::new(self);
+        // Safety: `this` is never dropped, so the value is only taken once.
+        unsafe { ManuallyDrop::take(&mut this.value) }
+    }
+}
+
+impl<'t, T> Deref for Locked<'t, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<'t, T> DerefMut for Locked<'t, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<'t, T> Drop for Locked<'t, T> {
+    fn drop(&mut self) {
+        let _held = self.lock.hold();
+        // Safety: the value isn't used after this.
+        unsafe { ManuallyDrop::drop(&mut self.value) }
+    }
+}
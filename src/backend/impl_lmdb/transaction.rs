@@ -8,21 +8,35 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use lmdb::Transaction;
+use std::{cmp::Ordering, sync::Mutex};
 
-use super::{DatabaseImpl, ErrorImpl, RoCursorImpl, RwCursorImpl, StatImpl, WriteFlagsImpl};
-use crate::backend::traits::{
-    BackendRoCursorTransaction, BackendRoTransaction, BackendRwCursorTransaction,
-    BackendRwCursorType, BackendRwDupPrevCursorTransaction, BackendRwTransaction,
+use lmdb::{Cursor, Transaction};
+
+use super::{
+    lock::TxnLock, DatabaseFlagsImpl, DatabaseImpl, ErrorImpl, RoCursorImpl, RwCursorImpl,
+    StatImpl, WriteFlagsImpl,
+};
+use crate::backend::{
+    common::{cmp_keys, is_sorted},
+    traits::{
+        BackendInactiveTransaction, BackendRoCursorTransaction, BackendRoTransaction,
+        BackendRwCursorTransaction, BackendRwCursorType, BackendRwDupPrevCursorTransaction,
+        BackendRwTransaction,
+    },
 };
 
 #[derive(Debug)]
 pub struct RoTransactionImpl<'t>(pub(crate) lmdb::RoTransaction<'t>);
 
+#[derive(Debug)]
+pub struct InactiveTransactionImpl<'t>(pub(crate) lmdb::InactiveTransaction<'t>);
+
 impl<'t> BackendRoTransaction for RoTransactionImpl<'t> {
     type Database = DatabaseImpl;
+    type DatabaseFlags = DatabaseFlagsImpl;
     type Error = ErrorImpl;
     type Stat = StatImpl;
+    type Inactive = InactiveTransactionImpl<'t>;
 
     fn get(&self, db: &Self::Database, key: &[u8]) -> Result<&[u8], Self::Error> {
         self.0.get(db.0, &key).map_err(ErrorImpl::LmdbError)
@@ -32,12 +46,134 @@ impl<'t> BackendRoTransaction for RoTransactionImpl<'t> {
         self.0.abort()
     }
 
+    fn reset(self) -> Self::Inactive {
+        InactiveTransactionImpl(self.0.reset())
+    }
+
     fn stat(&self, db: &Self::Database) -> Result<Self::Stat, Self::Error> {
         self.0
             .stat(db.0)
             .map(StatImpl)
             .map_err(ErrorImpl::LmdbError)
     }
+
+    fn db_flags(&self, db: &Self::Database) -> Result<Self::DatabaseFlags, Self::Error> {
+        self.0
+            .db_flags(db.0)
+            .map(DatabaseFlagsImpl)
+            .map_err(ErrorImpl::LmdbError)
+    }
+}
+
+impl<'t> BackendInactiveTransaction for InactiveTransactionImpl<'t> {
+    type Error = ErrorImpl;
+    type RoTransaction = RoTransactionImpl<'t>;
+
+    fn renew(self) -> Result<Self::RoTransaction, Self::Error> {
+        self.0
+            .renew()
+            .map(RoTransactionImpl)
+            .map_err(ErrorImpl::LmdbError)
+    }
+}
+
+/// A read transaction of an environment opened with `NO_TLS`, whose transactions aren't
+/// bound to threads, so it can be used from several threads at once.
+#[derive(Debug)]
+pub struct SharedRoTransactionImpl<'t> {
+    txn: RoTransactionImpl<'t>,
+    // LMDB lets a `NO_TLS` read transaction move between threads, but its uses must be
+    // serialized, since reading through it updates it (e.g. when it first uses a named
+    // database, or the page buffers of an encrypted environment). Every call on it holds
+    // this lock, as do the cursors opened in it and their iterators while they step,
+    // which keeps a thread from blocking on a cursor of its own.
+    lock: Mutex<()>,
+}
+
+// Safety: with `NO_TLS`, LMDB allows read transactions to be used from any thread, as
+// long as their uses are serialized, which `lock` does.
+unsafe impl<'t> Send for SharedRoTransactionImpl<'t> {}
+unsafe impl<'t> Sync for SharedRoTransactionImpl<'t> {}
+
+impl<'t> SharedRoTransactionImpl<'t> {
+    pub(crate) fn new(txn: RoTransactionImpl<'t>) -> SharedRoTransactionImpl<'t> {
+        SharedRoTransactionImpl {
+            txn,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn lock(&self) -> TxnLock<'_> {
+        TxnLock(Some(&self.lock))
+    }
+}
+
+impl<'t> BackendRoTransaction for SharedRoTransactionImpl<'t> {
+    type Database = DatabaseImpl;
+    type DatabaseFlags = DatabaseFlagsImpl;
+    type Error = ErrorImpl;
+    type Stat = StatImpl;
+    type Inactive = SharedInactiveTransactionImpl<'t>;
+
+    fn get(&self, db: &Self::Database, key: &[u8]) -> Result<&[u8], Self::Error> {
+        let _held = self.lock().hold();
+        self.txn.get(db, key)
+    }
+
+    fn abort(self) {
+        self.txn.abort()
+    }
+
+    fn reset(self) -> Self::Inactive {
+        SharedInactiveTransactionImpl(self.txn.reset())
+    }
+
+    fn stat(&self, db: &Self::Database) -> Result<Self::Stat, Self::Error> {
+        let _held = self.lock().hold();
+        self.txn.stat(db)
+    }
+
+    fn db_flags(&self, db: &Self::Database) -> Result<Self::DatabaseFlags, Self::Error> {
+        let _held = self.lock().hold();
+        self.txn.db_flags(db)
+    }
+}
+
+impl<'t> BackendRoCursorTransaction<'t> for SharedRoTransactionImpl<'t> {
+    type RoCursor = RoCursorImpl<'t>;
+    type RwCursor = RwCursorImpl<'t>;
+
+    fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error> {
+        let lock = self.lock();
+        let _held = lock.hold();
+        self.txn
+            .0
+            .open_ro_cursor(db.0)
+            .map(|cursor| RoCursorImpl::new(cursor, lock))
+            .map_err(ErrorImpl::LmdbError)
+    }
+
+    fn open_ro_dup_cursor(&'t self, db: &Self::Database) -> Result<Self::RwCursor, Self::Error> {
+        let lock = self.lock();
+        let _held = lock.hold();
+        self.txn
+            .0
+            .open_ro_cursor(db.0)
+            .map(|cursor| RwCursorImpl::new(cursor, lock))
+            .map_err(ErrorImpl::LmdbError)
+    }
+}
+
+#[derive(Debug)]
+pub struct SharedInactiveTransactionImpl<'t>(InactiveTransactionImpl<'t>);
+
+impl<'t> BackendInactiveTransaction for SharedInactiveTransactionImpl<'t> {
+    type Error = ErrorImpl;
+    type RoTransaction = SharedRoTransactionImpl<'t>;
+
+    fn renew(self) -> Result<Self::RoTransaction, Self::Error> {
+        self.0.renew().map(SharedRoTransactionImpl::new)
+    }
 }
 
 impl<'t> BackendRoCursorTransaction<'t> for RoTransactionImpl<'t> {
@@ -47,14 +183,14 @@ impl<'t> BackendRoCursorTransaction<'t> for RoTransactionImpl<'t> {
     fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error> {
         self.0
             .open_ro_cursor(db.0)
-            .map(RoCursorImpl)
+            .map(|cursor| RoCursorImpl::new(cursor, TxnLock::default()))
             .map_err(ErrorImpl::LmdbError)
     }
 
     fn open_ro_dup_cursor(&'t self, db: &Self::Database) -> Result<Self::RwCursor, Self::Error> {
         self.0
             .open_ro_cursor(db.0)
-            .map(RwCursorImpl)
+            .map(|cursor| RwCursorImpl::new(cursor, TxnLock::default()))
             .map_err(ErrorImpl::LmdbError)
     }
 }
@@ -66,14 +202,14 @@ impl<'t> BackendRwCursorTransaction<'t> for RwTransactionImpl<'t> {
     fn open_ro_cursor(&'t self, db: &Self::Database) -> Result<Self::RoCursor, Self::Error> {
         self.0
             .open_ro_cursor(db.0)
-            .map(RoCursorImpl)
+            .map(|cursor| RoCursorImpl::new(cursor, TxnLock::default()))
             .map_err(ErrorImpl::LmdbError)
     }
 
     fn open_ro_dup_cursor(&'t self, db: &Self::Database) -> Result<Self::RwCursor, Self::Error> {
         self.0
             .open_ro_cursor(db.0)
-            .map(RwCursorImpl)
+            .map(|cursor| RwCursorImpl::new(cursor, TxnLock::default()))
             .map_err(ErrorImpl::LmdbError)
     }
 }
@@ -83,6 +219,7 @@ pub struct RwTransactionImpl<'t>(pub(crate) lmdb::RwTransaction<'t>);
 
 impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
     type Database = DatabaseImpl;
+    type DatabaseFlags = DatabaseFlagsImpl;
     type Error = ErrorImpl;
     type Flags = WriteFlagsImpl;
     type Stat = StatImpl;
@@ -99,6 +236,13 @@ impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
             .map_err(ErrorImpl::LmdbError)
     }
 
+    fn db_flags(&self, db: &Self::Database) -> Result<Self::DatabaseFlags, Self::Error> {
+        self.0
+            .db_flags(db.0)
+            .map(DatabaseFlagsImpl)
+            .map_err(ErrorImpl::LmdbError)
+    }
+
     fn put(
         &mut self,
         db: &Self::Database,
@@ -126,6 +270,80 @@ impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
         self.0.del(db.0, &key, value).map_err(ErrorImpl::LmdbError)
     }
 
+    fn put_many(
+        &mut self,
+        db: &Self::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), Self::Error> {
+        for &(key, value) in pairs {
+            self.0
+                .put(db.0, &key, &value, lmdb::WriteFlags::empty())
+                .map_err(ErrorImpl::LmdbError)?;
+        }
+        Ok(())
+    }
+
+    fn put_sorted(
+        &mut self,
+        db: &Self::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), Self::Error> {
+        let flags = self.db_flags(db)?.0;
+        let dup_sort = flags.contains(lmdb::DatabaseFlags::DUP_SORT);
+        let integer_key = flags.contains(lmdb::DatabaseFlags::INTEGER_KEY);
+        if !is_sorted(pairs, dup_sort, integer_key) {
+            return Err(ErrorImpl::KeysNotSorted);
+        }
+        // Since the pairs are sorted, only the first one can fail to come after the last
+        // key, in which case nothing is put.
+        let mut last = None;
+        for &(key, value) in pairs {
+            let flags = if last == Some(key) {
+                lmdb::WriteFlags::APPEND_DUP
+            } else {
+                lmdb::WriteFlags::APPEND
+            };
+            self.0.put(db.0, &key, &value, flags).map_err(|e| match e {
+                lmdb::Error::KeyExist => ErrorImpl::KeysNotSorted,
+                e => ErrorImpl::LmdbError(e),
+            })?;
+            last = Some(key);
+        }
+        Ok(())
+    }
+
+    fn range_keys(
+        &self,
+        db: &Self::Database,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Vec<Vec<u8>>, Self::Error> {
+        let integer_key = self
+            .db_flags(db)?
+            .0
+            .contains(lmdb::DatabaseFlags::INTEGER_KEY);
+        let mut cursor = self.0.open_ro_cursor(db.0).map_err(ErrorImpl::LmdbError)?;
+        // See `RoCursorImpl::into_iter` for why this doesn't call `iter_start`.
+        let iter = match start {
+            Some(start) => cursor.iter_from(start),
+            None => cursor.iter(),
+        };
+        let mut keys: Vec<Vec<u8>> = vec![];
+        for result in iter {
+            let (key, _) = result.map_err(ErrorImpl::LmdbError)?;
+            if end.map_or(false, |end| {
+                cmp_keys(key, end, integer_key) != Ordering::Less
+            }) {
+                break;
+            }
+            // Keys with duplicate values come up once per value.
+            if keys.last().map_or(true, |last| last.as_slice() != key) {
+                keys.push(key.to_vec());
+            }
+        }
+        Ok(keys)
+    }
+
     fn clear_db(&mut self, db: &Self::Database) -> Result<(), Self::Error> {
         self.0.clear_db(db.0).map_err(ErrorImpl::LmdbError)
     }
@@ -25,6 +25,6 @@ pub use environment::{EnvironmentBuilderImpl, EnvironmentImpl};
 pub use error::ErrorImpl;
 pub use flags::{DatabaseFlagsImpl, EnvironmentFlagsImpl, WriteFlagsImpl};
 pub use info::InfoImpl;
-pub use iter::{ IterImpl, IterDupImpl };
+pub use iter::{IterDupImpl, IterImpl};
 pub use stat::StatImpl;
-pub use transaction::{RoTransactionImpl, RwTransactionImpl};
+pub use transaction::{InactiveTransactionImpl, RoTransactionImpl, RwTransactionImpl};
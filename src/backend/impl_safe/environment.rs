@@ -97,14 +97,28 @@ impl<'b> BackendEnvironmentBuilder<'b> for EnvironmentBuilderImpl {
         self
     }
 
+    fn set_no_tls(&mut self, no_tls: bool) -> &mut Self {
+        // Read transactions are never tied to threads here, so this changes nothing.
+        self.flags.set(EnvironmentFlagsImpl::NO_TLS, no_tls);
+        self
+    }
+
     fn open(&self, path: &Path) -> Result<Self::Environment, Self::Error> {
-        // Technically NO_SUB_DIR should change these checks here, but they're both currently
-        // unimplemented with this storage backend.
-        if !path.is_dir() {
+        // With NO_SUB_DIR, the path is the data file itself, which is created on the first
+        // commit, and the directory holding it is made if needed instead.
+        let dir = if self.flags.contains(EnvironmentFlagsImpl::NO_SUB_DIR) {
+            if path.is_dir() {
+                return Err(ErrorImpl::UnsuitableEnvironmentPath(path.into()));
+            }
+            path.parent().unwrap_or_else(|| Path::new(""))
+        } else {
+            path
+        };
+        if !dir.is_dir() {
             if !self.make_dir_if_needed {
                 return Err(ErrorImpl::UnsuitableEnvironmentPath(path.into()));
             }
-            fs::create_dir_all(path)?;
+            fs::create_dir_all(dir)?;
         }
         let mut env = EnvironmentImpl::new(
             path,
@@ -142,6 +156,7 @@ impl<'a> From<&'a mut EnvironmentDbs> for EnvironmentDbsRefMut<'a> {
 #[derive(Debug)]
 pub struct EnvironmentImpl {
     path: PathBuf,
+    flags: EnvironmentFlagsImpl,
     max_dbs: usize,
     dbs: RwLock<EnvironmentDbs>,
     ro_txns: Arc<()>,
@@ -184,7 +199,7 @@ impl EnvironmentImpl {
         max_dbs: Option<usize>,
         map_size: Option<usize>,
     ) -> Result<EnvironmentImpl, ErrorImpl> {
-        if !flags.is_empty() {
+        if !(flags - EnvironmentFlagsImpl::NO_SUB_DIR - EnvironmentFlagsImpl::NO_TLS).is_empty() {
             warn!("Ignoring `flags={:?}`", flags);
         }
         if let Some(max_readers) = max_readers {
@@ -196,6 +211,7 @@ impl EnvironmentImpl {
 
         Ok(EnvironmentImpl {
             path: path.to_path_buf(),
+            flags,
             max_dbs: max_dbs.unwrap_or(std::usize::MAX),
             dbs: RwLock::new(EnvironmentDbs {
                 arena: DatabaseArena::new(),
@@ -206,11 +222,16 @@ impl EnvironmentImpl {
         })
     }
 
+    fn db_filename(&self) -> Cow<'_, Path> {
+        if self.flags.contains(EnvironmentFlagsImpl::NO_SUB_DIR) {
+            Cow::from(&self.path)
+        } else {
+            Cow::from(self.path.join(DEFAULT_DB_FILENAME))
+        }
+    }
+
     pub(crate) fn read_from_disk(&mut self, discard_if_corrupted: bool) -> Result<(), ErrorImpl> {
-        let mut path = Cow::from(&self.path);
-        if fs::metadata(&path)?.is_dir() {
-            path.to_mut().push(DEFAULT_DB_FILENAME);
-        };
+        let path = self.db_filename();
         if fs::metadata(&path).is_err() {
             return Ok(());
         };
@@ -220,11 +241,7 @@ impl EnvironmentImpl {
     }
 
     pub(crate) fn write_to_disk(&self) -> Result<(), ErrorImpl> {
-        let mut path = Cow::from(&self.path);
-        if fs::metadata(&path)?.is_dir() {
-            path.to_mut().push(DEFAULT_DB_FILENAME);
-        };
-        fs::write(&path, self.serialize()?)?;
+        fs::write(self.db_filename(), self.serialize()?)?;
         Ok(())
     }
 
@@ -244,6 +261,7 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
     type Info = InfoImpl;
     type RoTransaction = RoTransactionImpl<'e>;
     type RwTransaction = RwTransactionImpl<'e>;
+    type SharedRoTransaction = RoTransactionImpl<'e>;
     type Stat = StatImpl;
 
     fn get_dbs(&self) -> Result<Vec<Option<String>>, Self::Error> {
@@ -293,6 +311,11 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
         RwTransactionImpl::new(self, self.rw_txns.clone())
     }
 
+    fn begin_shared_ro_txn(&'e self) -> Result<Self::SharedRoTransaction, Self::Error> {
+        // Read transactions only hold snapshots, which are immutable and reference-counted.
+        self.begin_ro_txn()
+    }
+
     fn sync(&self, force: bool) -> Result<(), Self::Error> {
         warn!("Ignoring `force={}`", force);
         self.write_to_disk()
@@ -324,11 +347,9 @@ impl<'e> BackendEnvironment<'e> for EnvironmentImpl {
     }
 
     fn get_files_on_disk(&self) -> Vec<PathBuf> {
-        // Technically NO_SUB_DIR and NO_LOCK should change this output, but
-        // they're both currently unimplemented with this storage backend.
-        let mut db_filename = self.path.clone();
-        db_filename.push(DEFAULT_DB_FILENAME);
-        vec![db_filename]
+        // Technically NO_LOCK should change this output, but it's currently unimplemented
+        // with this storage backend.
+        vec![self.db_filename().into_owned()]
     }
 
     fn version(&self) -> &str {
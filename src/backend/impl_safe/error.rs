@@ -17,6 +17,7 @@ use crate::{backend::traits::BackendError, error::StoreError};
 #[derive(Debug)]
 pub enum ErrorImpl {
     KeyValuePairNotFound,
+    KeysNotSorted,
     EnvPoisonError,
     DbsFull,
     DbsIllegalOpen,
@@ -33,6 +34,7 @@ impl fmt::Display for ErrorImpl {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ErrorImpl::KeyValuePairNotFound => write!(fmt, "KeyValuePairNotFound (safe mode)"),
+            ErrorImpl::KeysNotSorted => write!(fmt, "KeysNotSorted (safe mode)"),
             ErrorImpl::EnvPoisonError => write!(fmt, "EnvPoisonError (safe mode)"),
             ErrorImpl::DbsFull => write!(fmt, "DbsFull (safe mode)"),
             ErrorImpl::DbsIllegalOpen => write!(fmt, "DbIllegalOpen (safe mode)"),
@@ -55,6 +57,7 @@ impl Into<StoreError> for ErrorImpl {
         // unimplemented yet, but they should be in the future.
         match self {
             ErrorImpl::KeyValuePairNotFound => StoreError::KeyValuePairNotFound,
+            ErrorImpl::KeysNotSorted => StoreError::KeysNotSorted,
             ErrorImpl::BincodeError(_) => StoreError::FileInvalid,
             ErrorImpl::DbsFull => StoreError::DbsFull,
             ErrorImpl::UnsuitableEnvironmentPath(path) => {
@@ -20,6 +20,8 @@ bitflags! {
     #[derive(Default, Serialize, Deserialize)]
     pub struct EnvironmentFlagsImpl: u32 {
         const NIL = 0b0000_0000;
+        const NO_SUB_DIR = 0b0000_0001;
+        const NO_TLS = 0b0000_0010;
     }
 }
 
@@ -39,13 +41,13 @@ impl Into<EnvironmentFlagsImpl> for EnvironmentFlags {
     fn into(self) -> EnvironmentFlagsImpl {
         match self {
             EnvironmentFlags::FIXED_MAP => unimplemented!(),
-            EnvironmentFlags::NO_SUB_DIR => unimplemented!(),
+            EnvironmentFlags::NO_SUB_DIR => EnvironmentFlagsImpl::NO_SUB_DIR,
             EnvironmentFlags::WRITE_MAP => unimplemented!(),
             EnvironmentFlags::READ_ONLY => unimplemented!(),
             EnvironmentFlags::NO_META_SYNC => unimplemented!(),
             EnvironmentFlags::NO_SYNC => unimplemented!(),
             EnvironmentFlags::MAP_ASYNC => unimplemented!(),
-            EnvironmentFlags::NO_TLS => unimplemented!(),
+            EnvironmentFlags::NO_TLS => EnvironmentFlagsImpl::NO_TLS,
             EnvironmentFlags::NO_LOCK => unimplemented!(),
             EnvironmentFlags::NO_READAHEAD => unimplemented!(),
             EnvironmentFlags::NO_MEM_INIT => unimplemented!(),
@@ -74,6 +76,17 @@ impl BackendDatabaseFlags for DatabaseFlagsImpl {
     fn set(&mut self, flag: DatabaseFlags, value: bool) {
         self.set(flag.into(), value)
     }
+
+    fn contains(&self, flag: DatabaseFlags) -> bool {
+        match flag {
+            #[cfg(feature = "db-dup-sort")]
+            DatabaseFlags::DUP_SORT => self.contains(DatabaseFlagsImpl::DUP_SORT),
+            #[cfg(feature = "db-int-key")]
+            DatabaseFlags::INTEGER_KEY => self.contains(DatabaseFlagsImpl::INTEGER_KEY),
+            // Databases can't be opened with the other flags.
+            _ => false,
+        }
+    }
 }
 
 impl Into<DatabaseFlagsImpl> for DatabaseFlags {
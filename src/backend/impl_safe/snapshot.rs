@@ -9,16 +9,67 @@
 // specific language governing permissions and limitations under the License.
 
 use std::{
+    cmp::Ordering,
     collections::{BTreeMap, BTreeSet},
+    iter,
+    ops::Bound,
     sync::Arc,
 };
 
 use serde_derive::{Deserialize, Serialize};
 
 use super::DatabaseFlagsImpl;
+use crate::backend::common::{cmp_keys, is_sorted};
 
 type Key = Box<[u8]>;
 type Value = Box<[u8]>;
+type Bounds<'k> = (Bound<&'k [u8]>, Bound<&'k [u8]>);
+
+// The bounds of the keys from `start`, included, up to `end`, excluded, unless there are no
+// such keys, since `BTreeMap::range` panics if the start comes after the end.
+fn bounds<'k>(start: Option<&'k [u8]>, end: Option<&'k [u8]>) -> Option<Bounds<'k>> {
+    match (start, end) {
+        (Some(start), Some(end)) if start >= end => None,
+        _ => Some((
+            start.map_or(Bound::Unbounded, Bound::Included),
+            end.map_or(Bound::Unbounded, Bound::Excluded),
+        )),
+    }
+}
+
+// The entries of `map` from `start`, included, up to `end`, excluded. The map orders keys
+// byte by byte, so all of its entries are looked at if it holds integer keys.
+fn range<'m, V>(
+    map: &'m BTreeMap<Key, V>,
+    start: Option<&'m [u8]>,
+    end: Option<&'m [u8]>,
+    integer_key: bool,
+) -> Box<dyn Iterator<Item = (&'m Key, &'m V)> + 'm> {
+    if integer_key {
+        Box::new(map.iter().filter(move |(key, _)| {
+            start.map_or(true, |start| cmp_keys(key, start, true) != Ordering::Less)
+                && end.map_or(true, |end| cmp_keys(key, end, true) == Ordering::Less)
+        }))
+    } else {
+        Box::new(
+            bounds(start, end)
+                .into_iter()
+                .flat_map(move |bounds| map.range::<[u8], _>(bounds)),
+        )
+    }
+}
+
+// The last of some keys, in ascending order, as a database orders them.
+fn last<'k, I>(mut keys: I, integer_key: bool) -> Option<&'k Key>
+where
+    I: DoubleEndedIterator<Item = &'k Key>,
+{
+    if integer_key {
+        keys.max_by(|a, b| cmp_keys(a, b, true))
+    } else {
+        keys.next_back()
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Snapshot {
@@ -41,6 +92,16 @@ impl Snapshot {
         &self.flags
     }
 
+    #[cfg(feature = "db-int-key")]
+    fn integer_key(&self) -> bool {
+        self.flags.contains(DatabaseFlagsImpl::INTEGER_KEY)
+    }
+
+    #[cfg(not(feature = "db-int-key"))]
+    fn integer_key(&self) -> bool {
+        false
+    }
+
     pub(crate) fn clear(&mut self) {
         self.map = Default::default();
     }
@@ -67,6 +128,39 @@ impl Snapshot {
             .iter()
             .map(|(key, value)| (key.as_ref(), value.as_ref()))
     }
+
+    // Whether `key` comes after the last key, if any.
+    fn is_after_last(&self, key: &[u8]) -> bool {
+        let integer_key = self.integer_key();
+        last(self.map.keys(), integer_key).map_or(true, |last| {
+            cmp_keys(last, key, integer_key) == Ordering::Less
+        })
+    }
+
+    /// Merge pairs in strictly ascending order of key, which come after the last key, in
+    /// bulk, see `BackendRwTransaction::put_sorted`.
+    pub(crate) fn append(&mut self, pairs: &[(&[u8], &[u8])]) -> Option<()> {
+        let after_last = pairs
+            .first()
+            .map_or(true, |&(key, _)| self.is_after_last(key));
+        if !after_last || !is_sorted(pairs, false, self.integer_key()) {
+            return None;
+        }
+        // Building a map from sorted pairs, and appending it to one whose keys all come
+        // before, both take linear time.
+        let mut tail: BTreeMap<Key, Value> = pairs
+            .iter()
+            .map(|&(key, value)| (Box::from(key), Box::from(value)))
+            .collect();
+        Arc::make_mut(&mut self.map).append(&mut tail);
+        Some(())
+    }
+
+    pub(crate) fn range_keys(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Vec<Vec<u8>> {
+        range(&self.map, start, end, self.integer_key())
+            .map(|(key, _)| key.to_vec())
+            .collect()
+    }
 }
 
 #[cfg(feature = "db-dup-sort")]
@@ -110,6 +204,52 @@ impl Snapshot {
             .iter()
             .map(|(key, values)| (key.as_ref(), values.iter().map(|value| value.as_ref())))
     }
+
+    // Whether `key` comes after the last key whose values weren't all deleted, if any.
+    fn is_after_last(&self, key: &[u8]) -> bool {
+        let integer_key = self.integer_key();
+        let keys = self
+            .map
+            .iter()
+            .filter(|(_, values)| !values.is_empty())
+            .map(|(key, _)| key);
+        last(keys, integer_key).map_or(true, |last| {
+            cmp_keys(last, key, integer_key) == Ordering::Less
+        })
+    }
+
+    /// Merge pairs in strictly ascending order of key, or of key then value if `dup_sort`,
+    /// which come after the last key, in bulk, see `BackendRwTransaction::put_sorted`.
+    pub(crate) fn append(&mut self, pairs: &[(&[u8], &[u8])], dup_sort: bool) -> Option<()> {
+        let after_last = pairs
+            .first()
+            .map_or(true, |&(key, _)| self.is_after_last(key));
+        if !after_last || !is_sorted(pairs, dup_sort, self.integer_key()) {
+            return None;
+        }
+        let mut groups: Vec<(Key, BTreeSet<Value>)> = vec![];
+        for &(key, value) in pairs {
+            match groups.last_mut() {
+                Some((last, values)) if last.as_ref() == key => {
+                    values.insert(Box::from(value));
+                }
+                _ => groups.push((Box::from(key), iter::once(Box::from(value)).collect())),
+            }
+        }
+        // Building a map from sorted pairs, and appending it to one whose keys all come
+        // before, both take linear time.
+        let mut tail: BTreeMap<Key, BTreeSet<Value>> = groups.into_iter().collect();
+        Arc::make_mut(&mut self.map).append(&mut tail);
+        Some(())
+    }
+
+    /// The keys within the bounds, leaving out those whose values were all deleted.
+    pub(crate) fn range_keys(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> Vec<Vec<u8>> {
+        range(&self.map, start, end, self.integer_key())
+            .filter(|(_, values)| !values.is_empty())
+            .map(|(key, _)| key.to_vec())
+            .collect()
+    }
 }
 
 #[cfg(feature = "db-dup-sort")]
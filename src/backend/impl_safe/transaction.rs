@@ -12,12 +12,13 @@
 use std::{collections::HashMap, sync::Arc};
 
 use super::{
-    snapshot::Snapshot, DatabaseImpl, EnvironmentImpl, ErrorImpl, RoCursorImpl, RwCursorImpl,
-    StatImpl, WriteFlagsImpl,
+    snapshot::Snapshot, DatabaseFlagsImpl, DatabaseImpl, EnvironmentImpl, ErrorImpl, RoCursorImpl,
+    RwCursorImpl, StatImpl, WriteFlagsImpl,
 };
 use crate::backend::traits::{
-    BackendRoCursorTransaction, BackendRoTransaction, BackendRwCursorTransaction,
-    BackendRwCursorType, BackendRwDupPrevCursorTransaction, BackendRwTransaction,
+    BackendEnvironment, BackendInactiveTransaction, BackendRoCursorTransaction,
+    BackendRoTransaction, BackendRwCursorTransaction, BackendRwCursorType,
+    BackendRwDupPrevCursorTransaction, BackendRwTransaction,
 };
 
 #[derive(Debug)]
@@ -48,8 +49,10 @@ impl<'t> RoTransactionImpl<'t> {
 
 impl<'t> BackendRoTransaction for RoTransactionImpl<'t> {
     type Database = DatabaseImpl;
+    type DatabaseFlags = DatabaseFlagsImpl;
     type Error = ErrorImpl;
     type Stat = StatImpl;
+    type Inactive = InactiveTransactionImpl<'t>;
 
     fn get(&self, db: &Self::Database, key: &[u8]) -> Result<&[u8], Self::Error> {
         let snapshot = self.snapshots.get(db).ok_or(ErrorImpl::DbIsForeignError)?;
@@ -60,9 +63,18 @@ impl<'t> BackendRoTransaction for RoTransactionImpl<'t> {
         // noop
     }
 
+    fn reset(self) -> Self::Inactive {
+        InactiveTransactionImpl { env: self.env }
+    }
+
     fn stat(&self, _db: &Self::Database) -> Result<Self::Stat, Self::Error> {
         unimplemented!()
     }
+
+    fn db_flags(&self, db: &Self::Database) -> Result<Self::DatabaseFlags, Self::Error> {
+        let snapshot = self.snapshots.get(db).ok_or(ErrorImpl::DbIsForeignError)?;
+        Ok(*snapshot.flags())
+    }
 }
 
 impl<'t> BackendRoCursorTransaction<'t> for RoTransactionImpl<'t> {
@@ -79,6 +91,22 @@ impl<'t> BackendRoCursorTransaction<'t> for RoTransactionImpl<'t> {
     }
 }
 
+/// A read transaction which doesn't hold any snapshots, nor count as a reader of the
+/// environment, until it's renewed.
+#[derive(Debug)]
+pub struct InactiveTransactionImpl<'t> {
+    env: &'t EnvironmentImpl,
+}
+
+impl<'t> BackendInactiveTransaction for InactiveTransactionImpl<'t> {
+    type Error = ErrorImpl;
+    type RoTransaction = RoTransactionImpl<'t>;
+
+    fn renew(self) -> Result<Self::RoTransaction, Self::Error> {
+        self.env.begin_ro_txn()
+    }
+}
+
 #[derive(Debug)]
 pub struct RwTransactionImpl<'t> {
     env: &'t EnvironmentImpl,
@@ -107,6 +135,7 @@ impl<'t> RwTransactionImpl<'t> {
 
 impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
     type Database = DatabaseImpl;
+    type DatabaseFlags = DatabaseFlagsImpl;
     type Error = ErrorImpl;
     type Flags = WriteFlagsImpl;
     type Stat = StatImpl;
@@ -120,6 +149,11 @@ impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
         unimplemented!()
     }
 
+    fn db_flags(&self, db: &Self::Database) -> Result<Self::DatabaseFlags, Self::Error> {
+        let snapshot = self.snapshots.get(db).ok_or(ErrorImpl::DbIsForeignError)?;
+        Ok(*snapshot.flags())
+    }
+
     #[cfg(not(feature = "db-dup-sort"))]
     fn put(
         &mut self,
@@ -188,6 +222,84 @@ impl<'t> BackendRwTransaction for RwTransactionImpl<'t> {
         deleted.ok_or(ErrorImpl::KeyValuePairNotFound)
     }
 
+    #[cfg(not(feature = "db-dup-sort"))]
+    fn put_many(
+        &mut self,
+        db: &Self::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), Self::Error> {
+        let snapshot = self
+            .snapshots
+            .get_mut(db)
+            .ok_or(ErrorImpl::DbIsForeignError)?;
+        for &(key, value) in pairs {
+            snapshot.put(key, value);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "db-dup-sort")]
+    fn put_many(
+        &mut self,
+        db: &Self::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), Self::Error> {
+        use super::DatabaseFlagsImpl;
+        let snapshot = self
+            .snapshots
+            .get_mut(db)
+            .ok_or(ErrorImpl::DbIsForeignError)?;
+        let dup_sort = snapshot.flags().contains(DatabaseFlagsImpl::DUP_SORT);
+        for &(key, value) in pairs {
+            if dup_sort {
+                snapshot.put_dup(key, value);
+            } else {
+                snapshot.put(key, value);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "db-dup-sort"))]
+    fn put_sorted(
+        &mut self,
+        db: &Self::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), Self::Error> {
+        let snapshot = self
+            .snapshots
+            .get_mut(db)
+            .ok_or(ErrorImpl::DbIsForeignError)?;
+        snapshot.append(pairs).ok_or(ErrorImpl::KeysNotSorted)
+    }
+
+    #[cfg(feature = "db-dup-sort")]
+    fn put_sorted(
+        &mut self,
+        db: &Self::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), Self::Error> {
+        use super::DatabaseFlagsImpl;
+        let snapshot = self
+            .snapshots
+            .get_mut(db)
+            .ok_or(ErrorImpl::DbIsForeignError)?;
+        let dup_sort = snapshot.flags().contains(DatabaseFlagsImpl::DUP_SORT);
+        snapshot
+            .append(pairs, dup_sort)
+            .ok_or(ErrorImpl::KeysNotSorted)
+    }
+
+    fn range_keys(
+        &self,
+        db: &Self::Database,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Vec<Vec<u8>>, Self::Error> {
+        let snapshot = self.snapshots.get(db).ok_or(ErrorImpl::DbIsForeignError)?;
+        Ok(snapshot.range_keys(start, end))
+    }
+
     fn clear_db(&mut self, db: &Self::Database) -> Result<(), Self::Error> {
         let snapshot = self
             .snapshots
@@ -21,7 +21,7 @@ use crate::{
 
 pub trait BackendError: Debug + Display + Into<StoreError> {}
 
-pub trait BackendDatabase: Debug + Eq + PartialEq + Copy + Clone {}
+pub trait BackendDatabase: Debug + Eq + PartialEq + Copy + Clone + Send + Sync + 'static {}
 
 pub trait BackendFlags: Debug + Eq + PartialEq + Copy + Clone + Default {
     fn empty() -> Self;
@@ -33,6 +33,8 @@ pub trait BackendEnvironmentFlags: BackendFlags {
 
 pub trait BackendDatabaseFlags: BackendFlags {
     fn set(&mut self, flag: DatabaseFlags, value: bool);
+
+    fn contains(&self, flag: DatabaseFlags) -> bool;
 }
 
 pub trait BackendWriteFlags: BackendFlags {
@@ -88,6 +90,10 @@ pub trait BackendEnvironmentBuilder<'b>: Debug + Eq + PartialEq + Copy + Clone {
 
     fn set_discard_if_corrupted(&mut self, discard_if_corrupted: bool) -> &mut Self;
 
+    /// Whether read transactions are tied to their own objects instead of to the thread
+    /// that began them, so that a thread can have several of them at once.
+    fn set_no_tls(&mut self, no_tls: bool) -> &mut Self;
+
     fn open(&self, path: &Path) -> Result<Self::Environment, Self::Error>;
 }
 
@@ -99,6 +105,9 @@ pub trait BackendEnvironment<'e>: Debug {
     type Info: BackendInfo;
     type RoTransaction: BackendRoCursorTransaction<'e, Database = Self::Database>;
     type RwTransaction: BackendRwCursorTransaction<'e, Database = Self::Database>;
+    type SharedRoTransaction: BackendRoCursorTransaction<'e, Database = Self::Database>
+        + Send
+        + Sync;
 
     fn get_dbs(&self) -> Result<Vec<Option<String>>, Self::Error>;
 
@@ -114,6 +123,9 @@ pub trait BackendEnvironment<'e>: Debug {
 
     fn begin_rw_txn(&'e self) -> Result<Self::RwTransaction, Self::Error>;
 
+    /// Begin a read transaction which can be used from several threads at once.
+    fn begin_shared_ro_txn(&'e self) -> Result<Self::SharedRoTransaction, Self::Error>;
+
     fn sync(&self, force: bool) -> Result<(), Self::Error>;
 
     fn stat(&self) -> Result<Self::Stat, Self::Error>;
@@ -134,18 +146,35 @@ pub trait BackendEnvironment<'e>: Debug {
 pub trait BackendRoTransaction: Debug {
     type Error: BackendError;
     type Database: BackendDatabase;
+    type DatabaseFlags: BackendDatabaseFlags;
     type Stat: BackendStat;
+    type Inactive: BackendInactiveTransaction<RoTransaction = Self>;
 
     fn get(&self, db: &Self::Database, key: &[u8]) -> Result<&[u8], Self::Error>;
 
     fn abort(self);
 
+    /// Release the transaction's snapshot, but keep what's needed to cheaply begin another
+    /// one with `BackendInactiveTransaction::renew`.
+    fn reset(self) -> Self::Inactive;
+
     fn stat(&self, db: &Self::Database) -> Result<Self::Stat, Self::Error>;
+
+    fn db_flags(&self, db: &Self::Database) -> Result<Self::DatabaseFlags, Self::Error>;
+}
+
+pub trait BackendInactiveTransaction: Debug + Sized {
+    type Error: BackendError;
+    type RoTransaction: BackendRoTransaction<Inactive = Self>;
+
+    /// Begin a read transaction of the latest committed data again.
+    fn renew(self) -> Result<Self::RoTransaction, Self::Error>;
 }
 
 pub trait BackendRwTransaction: Debug {
     type Error: BackendError;
     type Database: BackendDatabase;
+    type DatabaseFlags: BackendDatabaseFlags;
     type Flags: BackendWriteFlags;
     type Stat: BackendStat;
 
@@ -170,6 +199,31 @@ pub trait BackendRwTransaction: Debug {
         value: Option<&[u8]>,
     ) -> Result<(), Self::Error>;
 
+    /// Put each pair, in order, as `put` does without flags, looking the database up
+    /// once for all of them.
+    fn put_many(
+        &mut self,
+        db: &Self::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), Self::Error>;
+
+    /// Put pairs in strictly ascending byte order of key, or of key then value in a
+    /// database with duplicate keys, whose keys all come after those already in the
+    /// database, faster than one at a time. Nothing is put if they don't.
+    fn put_sorted(
+        &mut self,
+        db: &Self::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), Self::Error>;
+
+    /// The keys from `start`, included, up to `end`, excluded, in order.
+    fn range_keys(
+        &self,
+        db: &Self::Database,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<Vec<Vec<u8>>, Self::Error>;
+
     fn clear_db(&mut self, db: &Self::Database) -> Result<(), Self::Error>;
 
     fn commit(self) -> Result<(), Self::Error>;
@@ -177,6 +231,8 @@ pub trait BackendRwTransaction: Debug {
     fn abort(self);
 
     fn stat(&self, db: &Self::Database) -> Result<Self::Stat, Self::Error>;
+
+    fn db_flags(&self, db: &Self::Database) -> Result<Self::DatabaseFlags, Self::Error>;
 }
 
 pub trait BackendRoCursorTransaction<'t>: BackendRoTransaction {
@@ -212,7 +268,7 @@ pub trait BackendRwDupPrevCursorTransaction: BackendRwTransaction {
 pub trait BackendRoCursor<'c>: Debug {
     type Iter: BackendIter<'c>;
 
-    fn get_key_value<K>(self, key: K, value: &crate::value::Value) -> bool
+    fn get_key_value<K>(self, key: K, value: &[u8]) -> bool
     where
         K: AsRef<[u8]> + 'c;
 
@@ -14,7 +14,7 @@
 //! the number of key/value pairs to create via the `-n <number>` flag
 //! (for which the default value is 50).
 
-use std::{env::args, fs, fs::File, io::Read, path::Path};
+use std::{borrow::Cow, env::args, fs, fs::File, io::Read, path::Path};
 
 use rkv::{
     backend::{BackendEnvironmentBuilder, Lmdb},
@@ -98,7 +98,7 @@ fn main() {
         random.read_exact(&mut value[0..value_len]).unwrap();
 
         store
-            .put(&mut writer, key, &Value::Blob(&value))
+            .put(&mut writer, key, &Value::Blob(Cow::Borrowed(&value)))
             .expect("wrote");
     }
 
@@ -0,0 +1,541 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! A command-line utility to inspect and edit rkv environments, of either backend.
+//!
+//! ```text
+//! rkv list-dbs ENV
+//! rkv stat ENV
+//! rkv get ENV STORE KEY [-x]
+//! rkv put ENV STORE KEY VALUE [-x] [-o]
+//! rkv del ENV STORE KEY [VALUE] [-x] [-o]
+//! rkv dump ENV [-s STORE]...
+//! rkv load ENV
+//! rkv diff ENV OTHER-ENV [-s STORE]...
+//! ```
+//!
+//! Each `ENV` is a path, optionally preceded by `-b BACKEND`, `lmdb` when built with the
+//! `lmdb` feature and `safe` otherwise, and by `-k KEY-FILE` for encrypted environments.
+//! Key files hold the 32 bytes of the key, either as-is or in hex.
+//!
+//! `STORE` is the name of a store, or an empty string for the default one. `KEY` is
+//! taken as UTF-8, or as hex with `-x`, or as a number for integer-key stores. Values
+//! are printed, and `VALUE`s given, as in dumps, e.g. `{"str":"foo"}`, see `rkv::dump`.
+//! Values of dup-sort stores opened with `ValueEncoding::Ordered` are given with `-o`.
+//!
+//! `dump` writes the given stores, or all of them, to stdout, and `load` reads a dump
+//! from stdin, creating the environment if need be. `diff` prints the entries that
+//! differ between two environments, prefixed with `+` if only the other one holds
+//! them, `-` if only the first one does, or `~` if their values differ, and exits with
+//! status 1 if there are any. `get` and `del` exit with status 1 if the key isn't found.
+
+use std::{
+    env::args,
+    error::Error,
+    fs,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    process, str,
+};
+
+#[cfg(feature = "lmdb")]
+use rkv::backend::{BackendInfo, BackendStat, Lmdb};
+use rkv::{
+    backend::{BackendDatabaseFlags, BackendEnvironmentBuilder, SafeMode},
+    config::read_key_file,
+    dump::{format_value, parse_value},
+    Difference, Readable, Rkv, Store, StoreError, StoreOptions, Value, ValueEncoding,
+};
+
+#[cfg(any(feature = "db-dup-sort", feature = "db-int-key"))]
+use rkv::DatabaseFlags;
+
+const USAGE: &str = "usage:
+    rkv list-dbs ENV
+    rkv stat ENV
+    rkv get ENV STORE KEY [-x]
+    rkv put ENV STORE KEY VALUE [-x] [-o]
+    rkv del ENV STORE KEY [VALUE] [-x] [-o]
+    rkv dump ENV [-s STORE]...
+    rkv load ENV
+    rkv diff ENV OTHER-ENV [-s STORE]...
+where ENV is [-b BACKEND] [-k KEY-FILE] PATH";
+
+// Commands open every store of an environment, which may have more than rkv's default.
+const MAX_DBS: u32 = 256;
+
+#[derive(Debug, Copy, Clone)]
+enum Backend {
+    #[cfg(feature = "lmdb")]
+    Lmdb,
+    Safe,
+}
+
+impl Backend {
+    fn parse(name: Option<String>) -> Result<Backend, Box<dyn Error>> {
+        match name.as_deref() {
+            #[cfg(feature = "lmdb")]
+            Some("lmdb") => Ok(Backend::Lmdb),
+            Some("safe") => Ok(Backend::Safe),
+            Some(name) => Err(format!("unknown backend: {}", name).into()),
+            None => Err("-b must be followed by a backend".into()),
+        }
+    }
+}
+
+impl Default for Backend {
+    #[cfg(feature = "lmdb")]
+    fn default() -> Backend {
+        Backend::Lmdb
+    }
+
+    #[cfg(not(feature = "lmdb"))]
+    fn default() -> Backend {
+        Backend::Safe
+    }
+}
+
+/// An environment to open, as given on the command line.
+#[derive(Debug, Default)]
+struct Env {
+    backend: Backend,
+    key: Option<[u8; 32]>,
+    path: PathBuf,
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    envs: Vec<Env>,
+    stores: Vec<Option<String>>,
+    hex: bool,
+    ordered: bool,
+    positional: Vec<String>,
+}
+
+impl Args {
+    /// Parse the args of a command, whose first `envs` positional args are environments.
+    fn parse<I>(mut args: I, envs: usize) -> Result<Args, Box<dyn Error>>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut parsed = Args::default();
+        let mut env = Env::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-b" => env.backend = Backend::parse(args.next())?,
+                "-k" => env.key = Some(read_key(args.next())?),
+                "-s" => {
+                    let name = args.next().ok_or("-s must be followed by a store")?;
+                    parsed.stores.push(store(&name));
+                }
+                "-x" => parsed.hex = true,
+                "-o" => parsed.ordered = true,
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    return Err(format!("arg {} not recognized", arg).into())
+                }
+                _ if parsed.envs.len() < envs => {
+                    env.path = PathBuf::from(arg);
+                    parsed.envs.push(env);
+                    env = Env::default();
+                }
+                _ => parsed.positional.push(arg),
+            }
+        }
+        if parsed.envs.len() < envs {
+            return Err(USAGE.into());
+        }
+        Ok(parsed)
+    }
+
+    fn env(&self) -> &Env {
+        &self.envs[0]
+    }
+
+    /// The stores given with `-s`, or else all of the environment's, in order.
+    fn stores(&self, mut all: Vec<Option<String>>) -> Vec<Option<String>> {
+        if !self.stores.is_empty() {
+            return self.stores.clone();
+        }
+        all.sort();
+        all
+    }
+
+    fn encoding(&self) -> ValueEncoding {
+        if self.ordered {
+            ValueEncoding::Ordered
+        } else {
+            ValueEncoding::Bincode
+        }
+    }
+
+    /// The positional args after the environments, of which there must be between
+    /// `min` and `max`.
+    fn positional(&self, min: usize, max: usize) -> Result<&[String], Box<dyn Error>> {
+        if self.positional.len() < min || self.positional.len() > max {
+            return Err(USAGE.into());
+        }
+        Ok(&self.positional)
+    }
+}
+
+fn read_key(path: Option<String>) -> Result<[u8; 32], Box<dyn Error>> {
+    let path = path.ok_or("-k must be followed by a key file")?;
+    Ok(read_key_file(Path::new(&path))?)
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(format!("invalid hex: {}", hex).into());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| e.into()))
+        .collect()
+}
+
+fn store(name: &str) -> Option<String> {
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_owned())
+    }
+}
+
+fn printable(bytes: &[u8]) -> String {
+    match str::from_utf8(bytes) {
+        Ok(s) if !s.chars().any(char::is_control) => format!("{:?}", s),
+        _ => bytes.iter().fold("0x".to_owned(), |mut hex, b| {
+            hex.push_str(&format!("{:02x}", b));
+            hex
+        }),
+    }
+}
+
+fn store_name(name: Option<&str>) -> String {
+    name.map_or("(default)".to_owned(), |name| printable(name.as_bytes()))
+}
+
+#[cfg(feature = "db-dup-sort")]
+fn is_dup_sort<F: BackendDatabaseFlags>(flags: &F) -> bool {
+    flags.contains(DatabaseFlags::DUP_SORT)
+}
+
+#[cfg(not(feature = "db-dup-sort"))]
+fn is_dup_sort<F: BackendDatabaseFlags>(_flags: &F) -> bool {
+    false
+}
+
+#[cfg(feature = "db-int-key")]
+fn is_integer_key<F: BackendDatabaseFlags>(flags: &F) -> bool {
+    flags.contains(DatabaseFlags::INTEGER_KEY)
+}
+
+#[cfg(not(feature = "db-int-key"))]
+fn is_integer_key<F: BackendDatabaseFlags>(_flags: &F) -> bool {
+    false
+}
+
+fn flag_names<F: BackendDatabaseFlags>(flags: &F) -> String {
+    let mut names = String::new();
+    if is_dup_sort(flags) {
+        names.push_str(" dup-sort");
+    }
+    if is_integer_key(flags) {
+        names.push_str(" integer-key");
+    }
+    names
+}
+
+/// The bytes of a key given on the command line, for a store with the given flags.
+fn key<F>(key: &str, hex: bool, flags: &F) -> Result<Vec<u8>, Box<dyn Error>>
+where
+    F: BackendDatabaseFlags,
+{
+    if is_integer_key(flags) {
+        Ok(key.parse::<u32>()?.to_ne_bytes().to_vec())
+    } else if hex {
+        from_hex(key)
+    } else {
+        Ok(key.as_bytes().to_vec())
+    }
+}
+
+fn open<'e, B>(env: &Env) -> Result<Rkv<B::Environment>, StoreError>
+where
+    B: BackendEnvironmentBuilder<'e>,
+{
+    let mut builder = B::new();
+    builder.set_max_dbs(MAX_DBS);
+    if let Some(key) = env.key {
+        builder.set_enc_key(key);
+    }
+    Rkv::from_builder(&env.path, builder)
+}
+
+// Environments of different backends have different types, whose transactions only
+// borrow them for as long as the environments live, so commands are written once for
+// each backend by this.
+macro_rules! with_env {
+    ($env:expr, |$rkv:ident| $body:expr) => {
+        match $env.backend {
+            Backend::Safe => {
+                let $rkv = open::<SafeMode>($env)?;
+                $body
+            }
+            #[cfg(feature = "lmdb")]
+            Backend::Lmdb => {
+                let $rkv = open::<Lmdb>($env)?;
+                $body
+            }
+        }
+    };
+}
+
+fn list_dbs(args: Args) -> Result<bool, Box<dyn Error>> {
+    args.positional(0, 0)?;
+    with_env!(args.env(), |rkv| {
+        let names = args.stores(rkv.get_dbs()?);
+        let mut stores = vec![];
+        for name in &names {
+            stores.push(rkv.open_single(name.as_deref(), StoreOptions::default())?);
+        }
+        let reader = rkv.read()?;
+        for (name, store) in names.iter().zip(&stores) {
+            let flags = reader.db_flags(&store.database())?;
+            println!("{}{}", store_name(name.as_deref()), flag_names(&flags));
+        }
+    });
+    Ok(true)
+}
+
+fn stat(args: Args) -> Result<bool, Box<dyn Error>> {
+    args.positional(0, 0)?;
+    let env = args.env();
+    with_env!(env, |rkv| {
+        let names = args.stores(rkv.get_dbs()?);
+        let mut stores = vec![];
+        for name in &names {
+            stores.push(rkv.open_single(name.as_deref(), StoreOptions::default())?);
+        }
+        let reader = rkv.read()?;
+        for (name, store) in names.iter().zip(&stores) {
+            let flags = reader.db_flags(&store.database())?;
+            let entries = store.iter_start(&reader)?.count();
+            let name = store_name(name.as_deref());
+            println!("{}{}: {} entries", name, flag_names(&flags), entries);
+        }
+        // Only LMDB keeps statistics about its environments.
+        #[cfg(feature = "lmdb")]
+        if let Backend::Lmdb = env.backend {
+            let (stat, info) = (rkv.stat()?, rkv.info()?);
+            println!("page size: {}", stat.page_size());
+            println!("depth: {}", stat.depth());
+            println!("branch pages: {}", stat.branch_pages());
+            println!("leaf pages: {}", stat.leaf_pages());
+            println!("overflow pages: {}", stat.overflow_pages());
+            println!("map size: {}", info.map_size());
+            println!("last page number: {}", info.last_pgno());
+            println!("last transaction id: {}", info.last_txnid());
+            println!("readers: {}/{}", info.num_readers(), info.max_readers());
+        }
+    });
+    Ok(true)
+}
+
+fn get(args: Args) -> Result<bool, Box<dyn Error>> {
+    let positional = args.positional(2, 2)?;
+    let name = store(&positional[0]);
+    let mut found = false;
+    with_env!(args.env(), |rkv| {
+        let store = rkv.open_single(name.as_deref(), StoreOptions::default())?;
+        let reader = rkv.read()?;
+        let key = key(
+            &positional[1],
+            args.hex,
+            &reader.db_flags(&store.database())?,
+        )?;
+        // Cursors visit each value of a key in dup-sort stores, so this finds them all.
+        for result in store.iter_from(&reader, &key)? {
+            match result? {
+                (k, Value::Blob(bytes)) if k == &key[..] => println!("{}", format_value(&bytes)),
+                _ => break,
+            }
+            found = true;
+        }
+    });
+    if !found {
+        eprintln!("key not found");
+    }
+    Ok(found)
+}
+
+fn put(args: Args) -> Result<bool, Box<dyn Error>> {
+    let positional = args.positional(3, 3)?;
+    let name = store(&positional[0]);
+    let value = parse_value(&positional[2])?;
+    with_env!(args.env(), |rkv| {
+        let store = rkv
+            .open_single(name.as_deref(), StoreOptions::default())
+            .or_else(|_| rkv.open_single(name.as_deref(), StoreOptions::create()))?;
+        let flags = rkv.read()?.db_flags(&store.database())?;
+        let key = key(&positional[1], args.hex, &flags)?;
+        let value = Value::from(&value);
+        #[cfg(feature = "db-dup-sort")]
+        if is_dup_sort(&flags) {
+            let opts = StoreOptions::default().with_encoding(args.encoding());
+            let store = rkv.open_multi(name.as_deref(), opts)?;
+            let mut writer = rkv.write()?;
+            store.put(&mut writer, &key, &value)?;
+            writer.commit()?;
+            return Ok(true);
+        }
+        if args.ordered {
+            return Err("only dup-sort stores hold ordered values".into());
+        }
+        let mut writer = rkv.write()?;
+        store.put(&mut writer, &key, &value)?;
+        writer.commit()?;
+    });
+    Ok(true)
+}
+
+fn del(args: Args) -> Result<bool, Box<dyn Error>> {
+    let positional = args.positional(2, 3)?;
+    let name = store(&positional[0]);
+    let value = positional.get(2).map(|v| parse_value(v)).transpose()?;
+    let mut found = true;
+    with_env!(args.env(), |rkv| {
+        let store = rkv.open_single(name.as_deref(), StoreOptions::default())?;
+        let flags = rkv.read()?.db_flags(&store.database())?;
+        let key = key(&positional[1], args.hex, &flags)?;
+        let result = match &value {
+            None => {
+                let mut writer = rkv.write()?;
+                store
+                    .delete(&mut writer, &key)
+                    .and_then(|_| writer.commit())
+            }
+            #[cfg(feature = "db-dup-sort")]
+            Some(value) if is_dup_sort(&flags) => {
+                let opts = StoreOptions::default().with_encoding(args.encoding());
+                let store = rkv.open_multi(name.as_deref(), opts)?;
+                let mut writer = rkv.write()?;
+                store
+                    .delete(&mut writer, &key, &Value::from(value))
+                    .and_then(|_| writer.commit())
+            }
+            Some(_) => return Err("only the values of dup-sort stores can be deleted".into()),
+        };
+        match result {
+            Err(StoreError::KeyValuePairNotFound) => found = false,
+            result => result?,
+        }
+    });
+    if !found {
+        eprintln!("key not found");
+    }
+    Ok(found)
+}
+
+fn dump(args: Args) -> Result<bool, Box<dyn Error>> {
+    args.positional(0, 0)?;
+    with_env!(args.env(), |rkv| {
+        let names = args.stores(rkv.get_dbs()?);
+        for name in &names {
+            rkv.open_single(name.as_deref(), StoreOptions::default())?;
+        }
+        let names: Vec<_> = names.iter().map(|name| name.as_deref()).collect();
+        rkv.dump(&rkv.read()?, &names, io::stdout().lock())?;
+    });
+    Ok(true)
+}
+
+fn load(args: Args) -> Result<bool, Box<dyn Error>> {
+    args.positional(0, 0)?;
+    fs::create_dir_all(&args.env().path)?;
+    with_env!(args.env(), |rkv| {
+        rkv.load(BufReader::new(io::stdin().lock()))?;
+    });
+    Ok(true)
+}
+
+fn print_difference(store: Option<&str>, difference: &Difference) {
+    let store = store_name(store);
+    let key = printable(difference.key());
+    match difference {
+        Difference::Added { value, .. } => println!("+ {} {} {:?}", store, key, value),
+        Difference::Removed { value, .. } => println!("- {} {} {:?}", store, key, value),
+        Difference::Changed { old, new, .. } => {
+            println!("~ {} {} {:?} -> {:?}", store, key, old, new)
+        }
+    }
+}
+
+fn diff(args: Args) -> Result<bool, Box<dyn Error>> {
+    args.positional(0, 0)?;
+    let mut differ = false;
+    with_env!(&args.envs[0], |a| with_env!(&args.envs[1], |b| {
+        let (names_a, names_b) = (a.get_dbs()?, b.get_dbs()?);
+        let mut names = args.stores.clone();
+        if names.is_empty() {
+            names = names_a.iter().chain(&names_b).cloned().collect();
+            names.sort();
+            names.dedup();
+        }
+        let mut stores = vec![];
+        for name in &names {
+            match (names_a.contains(name), names_b.contains(name)) {
+                (true, true) => stores.push(name.as_deref()),
+                (false, false) => {
+                    return Err(format!("no store {}", store_name(name.as_deref())).into())
+                }
+                (true, false) => println!("- {}", store_name(name.as_deref())),
+                (false, true) => println!("+ {}", store_name(name.as_deref())),
+            }
+            differ |= !names_a.contains(name) || !names_b.contains(name);
+        }
+        for name in &stores {
+            a.open_single(*name, StoreOptions::default())?;
+            b.open_single(*name, StoreOptions::default())?;
+        }
+        let (reader, other_reader) = (a.read()?, b.read()?);
+        for result in a.diff(&reader, &b, &other_reader, &stores)? {
+            let (store, difference) = result?;
+            print_difference(store, &difference);
+            differ = true;
+        }
+    }));
+    Ok(!differ)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = args();
+
+    // The first arg is the name of the program, which we can ignore.
+    args.next();
+
+    let command = args.next();
+    let succeeded = match command.as_deref() {
+        Some("list-dbs") => list_dbs(Args::parse(args, 1)?)?,
+        Some("stat") => stat(Args::parse(args, 1)?)?,
+        Some("get") => get(Args::parse(args, 1)?)?,
+        Some("put") => put(Args::parse(args, 1)?)?,
+        Some("del") => del(Args::parse(args, 1)?)?,
+        Some("dump") => dump(Args::parse(args, 1)?)?,
+        Some("load") => load(Args::parse(args, 1)?)?,
+        Some("diff") => diff(Args::parse(args, 2)?)?,
+        Some(command) => return Err(format!("unknown command: {}\n{}", command, USAGE).into()),
+        None => return Err(USAGE.into()),
+    };
+    if !succeeded {
+        process::exit(1);
+    }
+    Ok(())
+}
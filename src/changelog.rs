@@ -0,0 +1,395 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! A persistent log of the mutations made by committed write transactions, for
+//! replicating an environment into others, see `Rkv::enable_change_log`.
+//!
+//! The log lives in an internal database of the environment, named `LOG_NAME`, which
+//! counts towards its maximum number of databases. Each write transaction that changes
+//! some store appends a `ChangeSet` to it, within the transaction itself, numbered by a
+//! sequence number one greater than the last one's. Sequence numbers are never reused,
+//! even once the sets they number are truncated or compacted.
+//!
+//! Mutations hold keys and values as their stores encoded them, so that replaying them
+//! reproduces the stores exactly, whatever their types.
+
+use std::{cmp, marker::PhantomData};
+
+use serde_derive::{Deserialize, Serialize};
+
+#[cfg(feature = "db-dup-sort")]
+use crate::backend::{BackendDatabaseFlags, DatabaseFlags};
+use crate::{
+    backend::{BackendFlags, BackendIter, BackendRoCursor, BackendRwTransaction},
+    error::{DataError, StoreError},
+    readwrite::{Readable, Writer},
+    value::Value,
+};
+
+/// The name of the internal database holding the change log.
+pub const LOG_NAME: &str = "__rkv_change_log";
+
+// The log's metadata are held next to its sets, under keys that aren't 8 bytes long.
+const NEXT_KEY: &[u8] = b"next";
+const TRUNCATED_KEY: &[u8] = b"truncated";
+const APPLIED_KEY: &[u8] = b"applied";
+
+/// A mutation of a store, named as it was opened, or `None` for the default store.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub enum Mutation {
+    Put {
+        store: Option<String>,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    /// A tombstone for a key, or for one of its values in a dup-sort store.
+    Delete {
+        store: Option<String>,
+        key: Vec<u8>,
+        value: Option<Vec<u8>>,
+    },
+    Clear {
+        store: Option<String>,
+    },
+}
+
+impl Mutation {
+    pub fn store(&self) -> Option<&str> {
+        match self {
+            Mutation::Put { store, .. }
+            | Mutation::Delete { store, .. }
+            | Mutation::Clear { store } => store.as_deref(),
+        }
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        match self {
+            Mutation::Put { key, .. } | Mutation::Delete { key, .. } => Some(key),
+            Mutation::Clear { .. } => None,
+        }
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        match self {
+            Mutation::Put { value, .. } => Some(value),
+            Mutation::Delete { value, .. } => value.as_deref(),
+            Mutation::Clear { .. } => None,
+        }
+    }
+
+    /// Whether replaying `self` after `earlier` undoes whatever `earlier` did. Unless
+    /// their store is a dup-sort one, a put overwrites whatever was under its key.
+    fn undoes(&self, earlier: &Mutation, dup_sort: bool) -> bool {
+        if self.store() != earlier.store() {
+            return false;
+        }
+        match self {
+            Mutation::Clear { .. } => true,
+            Mutation::Put { key, .. } if !dup_sort => earlier.key() == Some(key),
+            Mutation::Delete {
+                key, value: None, ..
+            } => earlier.key() == Some(key),
+            _ => {
+                earlier.key() == self.key()
+                    && earlier.value().is_some()
+                    && earlier.value() == self.value()
+            }
+        }
+    }
+}
+
+/// The mutations made by a committed write transaction, in the order they were made.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ChangeSet {
+    pub seq: u64,
+    pub mutations: Vec<Mutation>,
+}
+
+fn seq_key(seq: u64) -> [u8; 8] {
+    seq.to_be_bytes()
+}
+
+fn read_u64(bytes: &[u8]) -> Result<u64, StoreError> {
+    let mut buf = [0; 8];
+    if bytes.len() != buf.len() {
+        return Err(StoreError::DatabaseCorrupted);
+    }
+    buf.copy_from_slice(bytes);
+    Ok(u64::from_be_bytes(buf))
+}
+
+fn decode_set(key: &[u8], bytes: &[u8]) -> Result<ChangeSet, StoreError> {
+    Ok(ChangeSet {
+        seq: read_u64(key)?,
+        mutations: bincode::deserialize(bytes).map_err(|_| StoreError::DatabaseCorrupted)?,
+    })
+}
+
+/// Append a set of mutations to the log, within the transaction that made them. This
+/// is called as the transaction commits, so it writes to it directly rather than
+/// through a `Writer`, whose writes would be recorded in turn.
+pub(crate) fn append<T>(
+    txn: &mut T,
+    db: &T::Database,
+    mutations: &[Mutation],
+) -> Result<(), StoreError>
+where
+    T: BackendRwTransaction,
+{
+    let seq = match txn.get(db, NEXT_KEY).map_err(|e| e.into()) {
+        Ok(bytes) => read_u64(bytes)?,
+        Err(StoreError::KeyValuePairNotFound) => 1,
+        Err(e) => return Err(e),
+    };
+    let bytes = bincode::serialize(mutations).map_err(DataError::from)?;
+    txn.put(db, &seq_key(seq), &bytes, T::Flags::empty())
+        .map_err(|e| e.into())?;
+    txn.put(db, NEXT_KEY, &(seq + 1).to_be_bytes(), T::Flags::empty())
+        .map_err(|e| e.into())
+}
+
+/// Iterate over the sets numbered after `seq`, or fail if some of them were truncated.
+pub(crate) fn changes_since<'r, R, I, C>(
+    reader: &'r R,
+    db: &R::Database,
+    seq: u64,
+) -> Result<Changes<'r, I>, StoreError>
+where
+    R: Readable<'r, RoCursor = C>,
+    I: BackendIter<'r>,
+    C: BackendRoCursor<'r, Iter = I>,
+{
+    let truncated = match reader.get(db, &TRUNCATED_KEY)? {
+        Some(Value::Blob(bytes)) => read_u64(&bytes)?,
+        Some(_) => return Err(StoreError::DatabaseCorrupted),
+        None => 0,
+    };
+    if seq < truncated {
+        return Err(StoreError::ChangesTruncated(truncated));
+    }
+    let cursor = reader.open_ro_cursor(db)?;
+    Ok(Changes {
+        iter: cursor.into_iter_from(seq_key(seq.saturating_add(1))),
+        phantom: PhantomData,
+    })
+}
+
+/// Iterates over the sets of a change log from some sequence number on, see
+/// `Rkv::changes_since`.
+pub struct Changes<'i, I> {
+    iter: I,
+    phantom: PhantomData<&'i ()>,
+}
+
+impl<'i, I> Iterator for Changes<'i, I>
+where
+    I: BackendIter<'i>,
+{
+    type Item = Result<ChangeSet, StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.iter.next()? {
+                Ok((key, _)) if key.len() != 8 => continue,
+                Ok((key, bytes)) => return Some(decode_set(key, bytes)),
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+}
+
+fn get_u64<T>(writer: &Writer<T>, db: &T::Database, k: &[u8]) -> Result<u64, StoreError>
+where
+    T: BackendRwTransaction,
+{
+    writer.get_bytes(db, &k)?.map_or(Ok(0), read_u64)
+}
+
+fn put_u64<T>(writer: &mut Writer<T>, db: &T::Database, k: &[u8], n: u64) -> Result<(), StoreError>
+where
+    T: BackendRwTransaction,
+{
+    writer.put(db, &k, &n.to_be_bytes(), T::Flags::empty())
+}
+
+#[cfg(not(feature = "db-dup-sort"))]
+fn delete<T>(
+    writer: &mut Writer<T>,
+    db: &T::Database,
+    k: &[u8],
+    _v: Option<&[u8]>,
+) -> Result<(), StoreError>
+where
+    T: BackendRwTransaction,
+{
+    match writer.delete(db, &k) {
+        Err(StoreError::KeyValuePairNotFound) => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(feature = "db-dup-sort")]
+fn delete<T>(
+    writer: &mut Writer<T>,
+    db: &T::Database,
+    k: &[u8],
+    v: Option<&[u8]>,
+) -> Result<(), StoreError>
+where
+    T: BackendRwTransaction,
+{
+    match writer.delete(db, &k, v) {
+        Err(StoreError::KeyValuePairNotFound) => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(not(feature = "db-dup-sort"))]
+fn is_dup_sort<T>(_writer: &Writer<T>, _db: Option<T::Database>) -> Result<bool, StoreError>
+where
+    T: BackendRwTransaction,
+{
+    Ok(false)
+}
+
+/// Whether a store is a dup-sort one. Those the environment hasn't opened are taken
+/// to be, which only leaves out the mutations undone either way.
+#[cfg(feature = "db-dup-sort")]
+fn is_dup_sort<T>(writer: &Writer<T>, db: Option<T::Database>) -> Result<bool, StoreError>
+where
+    T: BackendRwTransaction,
+{
+    match db {
+        Some(db) => Ok(writer.db_flags(&db)?.contains(DatabaseFlags::DUP_SORT)),
+        None => Ok(true),
+    }
+}
+
+/// The sets numbered up to `seq`, which are to be truncated or compacted.
+fn sets_through<T>(
+    writer: &Writer<T>,
+    db: &T::Database,
+    seq: u64,
+) -> Result<Vec<ChangeSet>, StoreError>
+where
+    T: BackendRwTransaction,
+{
+    let first = get_u64(writer, db, TRUNCATED_KEY)? + 1;
+    let last = cmp::min(seq, get_u64(writer, db, NEXT_KEY)?.saturating_sub(1));
+    let mut sets = vec![];
+    for seq in first..=last {
+        if let Some(bytes) = writer.get_bytes(db, &seq_key(seq))? {
+            sets.push(decode_set(&seq_key(seq), bytes)?);
+        }
+    }
+    Ok(sets)
+}
+
+/// Delete the sets numbered up to `seq`, returning how many there were.
+pub(crate) fn truncate<T>(
+    writer: &mut Writer<T>,
+    db: &T::Database,
+    seq: u64,
+) -> Result<usize, StoreError>
+where
+    T: BackendRwTransaction,
+{
+    let sets = sets_through(writer, db, seq)?;
+    for set in &sets {
+        delete(writer, db, &seq_key(set.seq), None)?;
+    }
+    if get_u64(writer, db, TRUNCATED_KEY)? < seq {
+        put_u64(writer, db, TRUNCATED_KEY, seq)?;
+    }
+    Ok(sets.len())
+}
+
+/// Merge the sets numbered up to `seq` into one, numbered as the last of them, leaving
+/// out the mutations that later ones undo. Replaying the merged set in place of any
+/// suffix of the sets it merges leaves stores in the same state, so peers that already
+/// replayed some of them can still catch up. Returns how many sets were merged.
+/// `named` looks up the stores the mutations name, to tell which are dup-sort ones.
+pub(crate) fn compact<T, F>(
+    writer: &mut Writer<T>,
+    db: &T::Database,
+    seq: u64,
+    named: F,
+) -> Result<usize, StoreError>
+where
+    T: BackendRwTransaction,
+    F: Fn(Option<&str>) -> Option<T::Database>,
+{
+    let sets = sets_through(writer, db, seq)?;
+    let last = match sets.last() {
+        Some(set) => set.seq,
+        None => return Ok(0),
+    };
+    let mutations: Vec<&Mutation> = sets.iter().flat_map(|set| &set.mutations).collect();
+    let mut dup_sort: Vec<(Option<&str>, bool)> = Vec::new();
+    for mutation in &mutations {
+        let store = mutation.store();
+        if dup_sort.iter().all(|(s, _)| *s != store) {
+            dup_sort.push((store, is_dup_sort(writer, named(store))?));
+        }
+    }
+    let dup_sort = |store: Option<&str>| dup_sort.iter().any(|(s, d)| *s == store && *d);
+    let merged: Vec<&Mutation> = mutations
+        .iter()
+        .enumerate()
+        .filter(|(i, mutation)| {
+            !mutations[i + 1..]
+                .iter()
+                .any(|later| later.undoes(mutation, dup_sort(mutation.store())))
+        })
+        .map(|(_, mutation)| *mutation)
+        .collect();
+    for set in &sets {
+        delete(writer, db, &seq_key(set.seq), None)?;
+    }
+    let bytes = bincode::serialize(&merged).map_err(DataError::from)?;
+    writer.put(db, &seq_key(last), &bytes, T::Flags::empty())?;
+    Ok(sets.len())
+}
+
+/// The sequence number of the last set replayed into an environment, held in its log.
+pub(crate) fn applied<T>(writer: &Writer<T>, db: &T::Database) -> Result<u64, StoreError>
+where
+    T: BackendRwTransaction,
+{
+    get_u64(writer, db, APPLIED_KEY)
+}
+
+pub(crate) fn set_applied<T>(
+    writer: &mut Writer<T>,
+    db: &T::Database,
+    seq: u64,
+) -> Result<(), StoreError>
+where
+    T: BackendRwTransaction,
+{
+    put_u64(writer, db, APPLIED_KEY, seq)
+}
+
+/// Replay a mutation into its store. Deleting a missing key or value is a no-op, so
+/// that replaying a mutation twice has the same effect as replaying it once.
+pub(crate) fn replay<T>(
+    writer: &mut Writer<T>,
+    db: &T::Database,
+    mutation: &Mutation,
+) -> Result<(), StoreError>
+where
+    T: BackendRwTransaction,
+{
+    match mutation {
+        Mutation::Put { key, value, .. } => writer.put(db, key, value, T::Flags::empty()),
+        Mutation::Delete { key, value, .. } => delete(writer, db, key, value.as_deref()),
+        Mutation::Clear { .. } => writer.clear(db),
+    }
+}
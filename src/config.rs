@@ -0,0 +1,363 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Environments configured by a URL, so that their backend and settings can be changed
+//! without changing code, e.g.
+//!
+//! ```text
+//! rkv+lmdb:///data/profile?max_dbs=16&map_size=1GiB&mkdir=1
+//! rkv+safe:///data/profile?discard_if_corrupted=1
+//! ```
+//!
+//! The scheme picks the backend: `rkv+lmdb` or `rkv+safe`. The path must be absolute, and
+//! is percent-decoded. These parameters are recognized, and any others are reported as
+//! unknown:
+//!
+//! - `max_dbs`, `max_readers`: numbers.
+//! - `map_size`: a number of bytes, optionally followed by `KiB`, `MiB`, `GiB` or `TiB`.
+//! - `mkdir`, `discard_if_corrupted`: `1`/`true` or `0`/`false`.
+//! - `flags`: comma-separated environment flags, written as in `EnvironmentFlags` but in
+//!   lowercase, e.g. `no_sub_dir,no_tls`.
+//! - `key_file`: the path of a file holding the key of an encrypted environment, see
+//!   [read_key_file](fn.read_key_file.html).
+//!
+//! Parameters that aren't given keep the defaults of `Rkv::new`. Those which the backend
+//! doesn't support are reported as invalid, rather than ignored: `discard_if_corrupted`
+//! for LMDB, and `key_file` and flags other than `no_sub_dir` and `no_tls` for SafeMode.
+
+use std::{
+    collections::HashSet,
+    convert::TryFrom,
+    fs,
+    path::{Path, PathBuf},
+    str::{self, FromStr},
+};
+
+use url::Url;
+
+use crate::{
+    backend::{Any, AnyEnvironmentFlags, BackendEnvironmentBuilder, BackendEnvironmentFlags},
+    env::{Key, DEFAULT_MAX_DBS},
+    error::StoreError,
+    EnvironmentFlags,
+};
+
+/// The path and builder of an environment, parsed from its URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentConfig {
+    pub path: PathBuf,
+    pub builder: Any,
+}
+
+impl EnvironmentConfig {
+    pub fn from_url(url: &str) -> Result<EnvironmentConfig, StoreError> {
+        let url = Url::parse(url).map_err(|e| invalid(format!("{}", e)))?;
+        let mut builder = match url.scheme() {
+            #[cfg(feature = "lmdb")]
+            "rkv+lmdb" => Any::lmdb(),
+            #[cfg(not(feature = "lmdb"))]
+            "rkv+lmdb" => return Err(invalid("the lmdb backend isn't enabled")),
+            "rkv+safe" => Any::safe_mode(),
+            scheme => return Err(invalid(format!("unknown scheme {}", scheme))),
+        };
+        let lmdb = url.scheme() == "rkv+lmdb";
+        if url.has_host() {
+            return Err(invalid("urls can't name a host"));
+        }
+        let path = match url.to_file_path() {
+            Ok(ref path) if path.is_absolute() => path.clone(),
+            _ => return Err(invalid("the path must be absolute")),
+        };
+
+        builder.set_max_dbs(DEFAULT_MAX_DBS);
+        let mut seen = HashSet::new();
+        let mut unknown = vec![];
+        for (name, value) in url.query_pairs() {
+            if !seen.insert(name.clone()) {
+                return Err(invalid(format!("{} is given more than once", name)));
+            }
+            match &*name {
+                "max_dbs" => {
+                    builder.set_max_dbs(parse(&name, &value)?);
+                }
+                "max_readers" => {
+                    builder.set_max_readers(parse(&name, &value)?);
+                }
+                "map_size" => {
+                    builder.set_map_size(parse_size(&value).ok_or_else(|| bad(&name, &value))?);
+                }
+                "mkdir" => {
+                    builder.set_make_dir_if_needed(parse_bool(&name, &value)?);
+                }
+                "discard_if_corrupted" if lmdb => {
+                    return Err(invalid("lmdb doesn't support discard_if_corrupted"));
+                }
+                "discard_if_corrupted" => {
+                    builder.set_discard_if_corrupted(parse_bool(&name, &value)?);
+                }
+                "flags" => {
+                    builder.set_flags(parse_flags(&value, lmdb)?);
+                }
+                "key_file" if !lmdb => {
+                    return Err(invalid("safe mode doesn't support key_file"));
+                }
+                "key_file" => {
+                    builder.set_enc_key(read_key_file(Path::new(&*value))?);
+                }
+                _ => unknown.push(name.into_owned()),
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(StoreError::UrlParametersUnknown(unknown));
+        }
+
+        Ok(EnvironmentConfig { path, builder })
+    }
+}
+
+impl FromStr for EnvironmentConfig {
+    type Err = StoreError;
+
+    fn from_str(url: &str) -> Result<EnvironmentConfig, StoreError> {
+        EnvironmentConfig::from_url(url)
+    }
+}
+
+/// Read the key of an encrypted environment from a file, which holds its 32 bytes either
+/// as-is or in hex.
+pub fn read_key_file(path: &Path) -> Result<Key, StoreError> {
+    let bytes = fs::read(path)?;
+    let bytes = match str::from_utf8(&bytes) {
+        Ok(hex) if hex.trim().len() == 64 => from_hex(hex.trim()),
+        _ => Some(bytes),
+    };
+    let mut key = [0; 32];
+    match bytes {
+        Some(bytes) if bytes.len() == key.len() => {
+            key.copy_from_slice(&bytes);
+            Ok(key)
+        }
+        _ => Err(StoreError::KeyFileInvalid(path.into())),
+    }
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn invalid<S: Into<String>>(reason: S) -> StoreError {
+    StoreError::UrlInvalid(reason.into())
+}
+
+fn bad(name: &str, value: &str) -> StoreError {
+    invalid(format!("invalid {}: {}", name, value))
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, StoreError> {
+    value.parse().map_err(|_| bad(name, value))
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, StoreError> {
+    match value {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => Err(bad(name, value)),
+    }
+}
+
+fn parse_size(value: &str) -> Option<usize> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let shift = match unit {
+        "" => 0,
+        "KiB" => 10,
+        "MiB" => 20,
+        "GiB" => 30,
+        "TiB" => 40,
+        _ => return None,
+    };
+    let number: u64 = number.parse().ok()?;
+    let size = number.checked_mul(1 << shift)?;
+    usize::try_from(size).ok()
+}
+
+fn parse_flags(value: &str, lmdb: bool) -> Result<AnyEnvironmentFlags, StoreError> {
+    let mut flags = AnyEnvironmentFlags::empty();
+    for name in value.split(',').filter(|name| !name.is_empty()) {
+        let flag = match name {
+            "fixed_map" => EnvironmentFlags::FIXED_MAP,
+            "no_sub_dir" => EnvironmentFlags::NO_SUB_DIR,
+            "write_map" => EnvironmentFlags::WRITE_MAP,
+            "read_only" => EnvironmentFlags::READ_ONLY,
+            "no_meta_sync" => EnvironmentFlags::NO_META_SYNC,
+            "no_sync" => EnvironmentFlags::NO_SYNC,
+            "map_async" => EnvironmentFlags::MAP_ASYNC,
+            "no_tls" => EnvironmentFlags::NO_TLS,
+            "no_lock" => EnvironmentFlags::NO_LOCK,
+            "no_readahead" => EnvironmentFlags::NO_READAHEAD,
+            "no_mem_init" => EnvironmentFlags::NO_MEM_INIT,
+            _ => return Err(bad("flags", name)),
+        };
+        if !lmdb && name != "no_sub_dir" && name != "no_tls" {
+            return Err(invalid(format!("safe mode doesn't support {}", name)));
+        }
+        BackendEnvironmentFlags::set(&mut flags, flag, true);
+    }
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use tempfile::Builder;
+
+    use crate::backend::{SafeMode, SafeModeEnvironmentFlags};
+
+    fn safe_mode(path: &str, configure: impl FnOnce(&mut Any)) -> EnvironmentConfig {
+        let mut builder = Any::safe_mode();
+        builder.set_max_dbs(DEFAULT_MAX_DBS);
+        configure(&mut builder);
+        EnvironmentConfig {
+            path: path.into(),
+            builder,
+        }
+    }
+
+    fn reason(url: &str) -> String {
+        match EnvironmentConfig::from_url(url) {
+            Err(StoreError::UrlInvalid(reason)) => reason,
+            result => panic!("expected an invalid url, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_from_url() {
+        assert_eq!(
+            EnvironmentConfig::from_url("rkv+safe:///data/profile").expect("parsed"),
+            safe_mode("/data/profile", |_| ())
+        );
+        assert_eq!(
+            "rkv+safe:///data/my%20profile?max_dbs=16&map_size=1GiB&mkdir=1&flags=no_sub_dir"
+                .parse::<EnvironmentConfig>()
+                .expect("parsed"),
+            safe_mode("/data/my profile", |builder| {
+                builder
+                    .set_max_dbs(16)
+                    .set_map_size(1 << 30)
+                    .set_make_dir_if_needed(true)
+                    .set_flags(EnvironmentFlags::NO_SUB_DIR);
+            })
+        );
+        assert_eq!(
+            EnvironmentConfig::from_url(
+                "rkv+safe:///data?max_readers=4&discard_if_corrupted=true&map_size=4096"
+            )
+            .expect("parsed"),
+            safe_mode("/data", |builder| {
+                builder
+                    .set_max_readers(4)
+                    .set_discard_if_corrupted(true)
+                    .set_map_size(4096);
+            })
+        );
+
+        // The flags of the wrapped builder are set as if they were given to it directly.
+        let mut expected = SafeMode::new();
+        expected
+            .set_max_dbs(DEFAULT_MAX_DBS)
+            .set_flags(SafeModeEnvironmentFlags::NO_SUB_DIR | SafeModeEnvironmentFlags::NO_TLS);
+        assert_eq!(
+            EnvironmentConfig::from_url("rkv+safe:///data?flags=no_sub_dir,no_tls")
+                .expect("parsed")
+                .builder,
+            Any::SafeMode(expected)
+        );
+    }
+
+    #[test]
+    fn test_from_url_invalid() {
+        assert_eq!(reason("rkv+sqlite:///data"), "unknown scheme rkv+sqlite");
+        assert_eq!(reason("rkv+safe://host/data"), "urls can't name a host");
+        assert_eq!(reason("rkv+safe:data"), "the path must be absolute");
+        assert_eq!(
+            reason("rkv+safe:///data?max_dbs=lots"),
+            "invalid max_dbs: lots"
+        );
+        assert_eq!(
+            reason("rkv+safe:///data?map_size=1GB"),
+            "invalid map_size: 1GB"
+        );
+        assert_eq!(reason("rkv+safe:///data?mkdir=yes"), "invalid mkdir: yes");
+        assert_eq!(
+            reason("rkv+safe:///data?flags=no_fsync"),
+            "invalid flags: no_fsync"
+        );
+        assert_eq!(
+            reason("rkv+safe:///data?mkdir=1&mkdir=0"),
+            "mkdir is given more than once"
+        );
+        assert_eq!(
+            reason("rkv+safe:///data?flags=no_sub_dir,no_sync"),
+            "safe mode doesn't support no_sync"
+        );
+        assert_eq!(
+            reason("rkv+safe:///data?key_file=/data/key"),
+            "safe mode doesn't support key_file"
+        );
+        #[cfg(feature = "lmdb")]
+        assert_eq!(
+            reason("rkv+lmdb:///data?discard_if_corrupted=1"),
+            "lmdb doesn't support discard_if_corrupted"
+        );
+
+        match EnvironmentConfig::from_url("rkv+safe:///data?max_db=16&mkdir=1&mapsize=1GiB") {
+            Err(StoreError::UrlParametersUnknown(names)) => {
+                assert_eq!(names, vec!["max_db", "mapsize"])
+            }
+            result => panic!("expected unknown parameters, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_read_key_file() {
+        let root = Builder::new()
+            .prefix("test_read_key_file")
+            .tempdir()
+            .expect("tempdir");
+        let key: Key = [7; 32];
+
+        let raw = root.path().join("raw");
+        fs::write(&raw, key).expect("written");
+        assert_eq!(read_key_file(&raw).expect("read"), key);
+
+        let hex = root.path().join("hex");
+        let mut file = fs::File::create(&hex).expect("created");
+        writeln!(file, "{}", "07".repeat(32)).expect("written");
+        assert_eq!(read_key_file(&hex).expect("read"), key);
+
+        let short = root.path().join("short");
+        fs::write(&short, &key[..16]).expect("written");
+        match read_key_file(&short) {
+            Err(StoreError::KeyFileInvalid(path)) => assert_eq!(path, short),
+            result => panic!("expected an invalid key file, got {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! The differences between the stores of two environments, see `Rkv::diff`.
+//!
+//! Stores are walked in key order with a cursor on each side, so diffing them takes a
+//! single pass and doesn't hold either in memory. Entries are compared as they're
+//! encoded, which doesn't depend on the backend, so environments of different backends
+//! can be compared, e.g. to check what a `Migrator` did.
+//!
+//! A key whose single value differs between the two stores is `Changed`. Keys with
+//! several values, in dup-sort stores, are compared value by value, so a value only one
+//! side holds is `Added` or `Removed`.
+
+use std::{borrow::Cow, collections::VecDeque};
+
+use crate::{backend::BackendIter, error::StoreError, value::Value};
+
+/// A difference between an entry of a store and the other environment's.
+#[derive(Debug, PartialEq)]
+pub enum Difference<'r> {
+    /// An entry only the other environment holds.
+    Added { key: &'r [u8], value: Value<'r> },
+    /// An entry only this environment holds.
+    Removed { key: &'r [u8], value: Value<'r> },
+    Changed {
+        key: &'r [u8],
+        old: Value<'r>,
+        new: Value<'r>,
+    },
+}
+
+impl<'r> Difference<'r> {
+    pub fn key(&self) -> &'r [u8] {
+        match self {
+            Difference::Added { key, .. }
+            | Difference::Removed { key, .. }
+            | Difference::Changed { key, .. } => key,
+        }
+    }
+}
+
+/// Decode a value as a tagged value where it parses as one, which values that were
+/// compressed, or written as blobs by single stores, may not.
+fn decode(bytes: &[u8]) -> Value<'_> {
+    Value::from_tagged_slice(bytes).unwrap_or(Value::Blob(Cow::Borrowed(bytes)))
+}
+
+/// One side of a diff, which reads ahead by one entry to group the values of a key.
+struct Side<'r, I> {
+    iter: I,
+    next: Option<(&'r [u8], &'r [u8])>,
+    done: bool,
+}
+
+impl<'r, I> Side<'r, I>
+where
+    I: BackendIter<'r>,
+{
+    fn new(iter: I) -> Side<'r, I> {
+        Side {
+            iter,
+            next: None,
+            done: false,
+        }
+    }
+
+    fn key(&mut self) -> Result<Option<&'r [u8]>, StoreError> {
+        if self.next.is_none() && !self.done {
+            match self.iter.next() {
+                Some(result) => self.next = Some(result.map_err(|e| e.into())?),
+                None => self.done = true,
+            }
+        }
+        Ok(self.next.map(|(key, _)| key))
+    }
+
+    fn values(&mut self, key: &[u8]) -> Result<Vec<&'r [u8]>, StoreError> {
+        let mut values = vec![];
+        while self.key()? == Some(key) {
+            values.push(self.next.take().unwrap().1);
+        }
+        Ok(values)
+    }
+}
+
+/// Iterates over the differences between pairs of stores, along with the name of the
+/// store they're in.
+pub struct Diff<'r, 's, I, J> {
+    stores: VecDeque<(Option<&'s str>, Side<'r, I>, Side<'r, J>)>,
+    pending: VecDeque<(Option<&'s str>, Difference<'r>)>,
+}
+
+impl<'r, 's, I, J> Diff<'r, 's, I, J>
+where
+    I: BackendIter<'r>,
+    J: BackendIter<'r>,
+{
+    pub(crate) fn new() -> Diff<'r, 's, I, J> {
+        Diff {
+            stores: VecDeque::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, name: Option<&'s str>, ours: I, theirs: J) {
+        self.stores
+            .push_back((name, Side::new(ours), Side::new(theirs)));
+    }
+
+    /// Compare the values of the next key of the first store left, returning whether
+    /// there was one.
+    fn step(&mut self) -> Result<bool, StoreError> {
+        let (name, ours, theirs) = match self.stores.front_mut() {
+            Some(store) => store,
+            None => return Ok(false),
+        };
+        let key = match (ours.key()?, theirs.key()?) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(a), None) => a,
+            (None, Some(b)) => b,
+            (None, None) => {
+                self.stores.pop_front();
+                return Ok(true);
+            }
+        };
+        let (old, new) = (ours.values(key)?, theirs.values(key)?);
+        let name = *name;
+        match (&old[..], &new[..]) {
+            ([old], [new]) => {
+                if old != new {
+                    let (old, new) = (decode(old), decode(new));
+                    self.pending
+                        .push_back((name, Difference::Changed { key, old, new }));
+                }
+            }
+            _ => {
+                for value in old.iter().filter(|value| !new.contains(value)) {
+                    let value = decode(value);
+                    self.pending
+                        .push_back((name, Difference::Removed { key, value }));
+                }
+                for value in new.iter().filter(|value| !old.contains(value)) {
+                    let value = decode(value);
+                    self.pending
+                        .push_back((name, Difference::Added { key, value }));
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<'r, 's, I, J> Iterator for Diff<'r, 's, I, J>
+where
+    I: BackendIter<'r>,
+    J: BackendIter<'r>,
+{
+    type Item = Result<(Option<&'s str>, Difference<'r>), StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(difference) = self.pending.pop_front() {
+                return Some(Ok(difference));
+            }
+            match self.step() {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
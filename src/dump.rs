@@ -0,0 +1,481 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! A text format for the contents of stores, which doesn't depend on the backend or the
+//! platform, see `Rkv::dump` and `Rkv::load`.
+//!
+//! A dump is made of lines, each holding a JSON object. The first one is a header with
+//! the version of the format:
+//!
+//! ```text
+//! {"header":{"version":1}}
+//! ```
+//!
+//! Then each store has a line naming it, `null` for the default store, along with the
+//! flags it was created with, followed by a line per entry, in key order. Dup-sort
+//! stores have an entry per value.
+//!
+//! ```text
+//! {"store":{"name":"people","dup_sort":false,"integer_key":false}}
+//! {"entry":{"key":{"str":"alice"},"value":{"i64":42}}}
+//! ```
+//!
+//! Keys are tagged with how they're written: `str` for UTF-8 keys, `u32` and `u64` for
+//! the keys of integer-key stores, which are held in native byte order, and `hex` for
+//! any other.
+//!
+//! Values are tagged with their `Type`: `bool`, `u64`, `i64`, `f64`, `instant`, `uuid`,
+//! `str`, `json`, `blob`, `array` and `map` hold what they'd be expected to, with UUIDs
+//! hyphenated, JSON documents as strings and blobs in hex, while `i128`, `u128`,
+//! `decimal` and `timestamp` are written as decimal strings, which JSON numbers can't
+//! all be. Entries whose value was written with `Encoding::Ordered` have
+//! `"ordered":true`. Values that don't decode to a `Value` that encodes back to the same
+//! bytes are `raw`, in hex: compressed values, blobs held untagged by single stores, the
+//! values of typed stores, and the records of stores that build upon others. Loading a
+//! dump thus writes back the exact bytes it was taken from.
+//!
+//! Readers reject the versions they don't know. The version only changes for dumps that
+//! older readers would misread.
+
+use std::{
+    collections::BTreeMap,
+    convert::TryInto,
+    io::{BufRead, Write},
+    str::FromStr,
+};
+
+use rust_decimal::Decimal;
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[cfg(any(feature = "db-dup-sort", feature = "db-int-key"))]
+use crate::backend::DatabaseFlags;
+use crate::{
+    backend::BackendDatabaseFlags,
+    error::{DataError, StoreError},
+    value::{Encoding, OwnedValue, Value},
+};
+
+/// The version of the format written by `Rkv::dump`.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    Header { version: u32 },
+    Store(StoreRecord),
+    Entry(EntryRecord),
+}
+
+/// A store, as named in a dump, along with the flags it was created with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct StoreRecord {
+    name: Option<String>,
+    dup_sort: bool,
+    integer_key: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct EntryRecord {
+    key: KeyRecord,
+    value: ValueRecord,
+    #[serde(default, skip_serializing_if = "is_false")]
+    ordered: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum KeyRecord {
+    Str(String),
+    U32(u32),
+    U64(u64),
+    Hex(String),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ValueRecord {
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    Instant(i64),
+    Uuid(String),
+    Str(String),
+    Json(String),
+    Blob(String),
+    Array(Vec<ValueRecord>),
+    Map(BTreeMap<String, ValueRecord>),
+    I128(String),
+    U128(String),
+    Decimal(String),
+    Timestamp(String),
+    Raw(String),
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(format!("invalid hex: {:?}", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("invalid hex: {:?}", hex))
+        })
+        .collect()
+}
+
+fn parse<T: FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("invalid number: {:?}", s))
+}
+
+impl StoreRecord {
+    #[cfg_attr(
+        not(any(feature = "db-dup-sort", feature = "db-int-key")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn new<F>(name: Option<&str>, flags: &F) -> StoreRecord
+    where
+        F: BackendDatabaseFlags,
+    {
+        #[cfg(feature = "db-dup-sort")]
+        let dup_sort = flags.contains(DatabaseFlags::DUP_SORT);
+        #[cfg(not(feature = "db-dup-sort"))]
+        let dup_sort = false;
+        #[cfg(feature = "db-int-key")]
+        let integer_key = flags.contains(DatabaseFlags::INTEGER_KEY);
+        #[cfg(not(feature = "db-int-key"))]
+        let integer_key = false;
+        StoreRecord {
+            name: name.map(str::to_owned),
+            dup_sort,
+            integer_key,
+        }
+    }
+
+    /// The flags to create the store with, unless this build doesn't support them.
+    fn flags<F>(&self) -> Result<F, String>
+    where
+        F: BackendDatabaseFlags,
+    {
+        #[allow(unused_mut)]
+        let mut flags = F::empty();
+        if self.dup_sort {
+            #[cfg(feature = "db-dup-sort")]
+            flags.set(DatabaseFlags::DUP_SORT, true);
+            #[cfg(not(feature = "db-dup-sort"))]
+            return Err("dup-sort stores aren't supported".to_owned());
+        }
+        if self.integer_key {
+            #[cfg(feature = "db-int-key")]
+            flags.set(DatabaseFlags::INTEGER_KEY, true);
+            #[cfg(not(feature = "db-int-key"))]
+            return Err("integer-key stores aren't supported".to_owned());
+        }
+        Ok(flags)
+    }
+}
+
+impl KeyRecord {
+    fn new(key: &[u8], integer_key: bool) -> KeyRecord {
+        match (integer_key, key.len()) {
+            (true, 4) => KeyRecord::U32(u32::from_ne_bytes(key.try_into().unwrap())),
+            (true, 8) => KeyRecord::U64(u64::from_ne_bytes(key.try_into().unwrap())),
+            _ => match std::str::from_utf8(key) {
+                Ok(s) if !integer_key => KeyRecord::Str(s.to_owned()),
+                _ => KeyRecord::Hex(to_hex(key)),
+            },
+        }
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        match self {
+            KeyRecord::Str(s) => Ok(s.as_bytes().to_vec()),
+            KeyRecord::U32(n) => Ok(n.to_ne_bytes().to_vec()),
+            KeyRecord::U64(n) => Ok(n.to_ne_bytes().to_vec()),
+            KeyRecord::Hex(hex) => from_hex(hex),
+        }
+    }
+}
+
+impl ValueRecord {
+    fn new(value: &OwnedValue) -> ValueRecord {
+        match value {
+            OwnedValue::Bool(v) => ValueRecord::Bool(*v),
+            OwnedValue::U64(v) => ValueRecord::U64(*v),
+            OwnedValue::I64(v) => ValueRecord::I64(*v),
+            OwnedValue::F64(v) => ValueRecord::F64(*v),
+            OwnedValue::Instant(v) => ValueRecord::Instant(*v),
+            OwnedValue::Uuid(v) => ValueRecord::Uuid(v.to_string()),
+            OwnedValue::Str(v) => ValueRecord::Str(v.clone()),
+            OwnedValue::Json(v) => ValueRecord::Json(v.clone()),
+            OwnedValue::Blob(v) => ValueRecord::Blob(to_hex(v)),
+            OwnedValue::Array(v) => ValueRecord::Array(v.iter().map(ValueRecord::new).collect()),
+            OwnedValue::Map(v) => ValueRecord::Map(
+                v.iter()
+                    .map(|(k, v)| (k.clone(), ValueRecord::new(v)))
+                    .collect(),
+            ),
+            OwnedValue::I128(v) => ValueRecord::I128(v.to_string()),
+            OwnedValue::U128(v) => ValueRecord::U128(v.to_string()),
+            OwnedValue::Decimal(v) => ValueRecord::Decimal(v.to_string()),
+            OwnedValue::Timestamp(v) => ValueRecord::Timestamp(v.to_string()),
+        }
+    }
+
+    /// The record of the bytes of a stored value, and whether it was written with
+    /// `Encoding::Ordered`.
+    fn from_bytes(bytes: &[u8]) -> (ValueRecord, bool) {
+        if let Ok(value) = Value::from_tagged_slice(bytes) {
+            let record = ValueRecord::new(&OwnedValue::from(&value));
+            for (encoding, ordered) in [(Encoding::Bincode, false), (Encoding::Ordered, true)] {
+                // Non-finite floats have no JSON number to be written as.
+                if record.is_finite() && record.to_bytes(encoding).ok().as_deref() == Some(bytes) {
+                    return (record, ordered);
+                }
+            }
+        }
+        (ValueRecord::Raw(to_hex(bytes)), false)
+    }
+
+    fn is_finite(&self) -> bool {
+        match self {
+            ValueRecord::F64(v) => v.is_finite(),
+            ValueRecord::Array(v) => v.iter().all(ValueRecord::is_finite),
+            ValueRecord::Map(v) => v.values().all(ValueRecord::is_finite),
+            _ => true,
+        }
+    }
+
+    fn to_value(&self) -> Result<OwnedValue, String> {
+        Ok(match self {
+            ValueRecord::Bool(v) => OwnedValue::Bool(*v),
+            ValueRecord::U64(v) => OwnedValue::U64(*v),
+            ValueRecord::I64(v) => OwnedValue::I64(*v),
+            ValueRecord::F64(v) => OwnedValue::F64(*v),
+            ValueRecord::Instant(v) => OwnedValue::Instant(*v),
+            ValueRecord::Uuid(v) => {
+                OwnedValue::Uuid(Uuid::parse_str(v).map_err(|_| format!("invalid uuid: {:?}", v))?)
+            }
+            ValueRecord::Str(v) => OwnedValue::Str(v.clone()),
+            ValueRecord::Json(v) => OwnedValue::Json(v.clone()),
+            ValueRecord::Blob(v) => OwnedValue::Blob(from_hex(v)?),
+            ValueRecord::Array(v) => OwnedValue::Array(
+                v.iter()
+                    .map(ValueRecord::to_value)
+                    .collect::<Result<_, _>>()?,
+            ),
+            ValueRecord::Map(v) => OwnedValue::Map(
+                v.iter()
+                    .map(|(k, v)| Ok((k.clone(), v.to_value()?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            ValueRecord::I128(v) => OwnedValue::I128(parse(v)?),
+            ValueRecord::U128(v) => OwnedValue::U128(parse(v)?),
+            ValueRecord::Decimal(v) => OwnedValue::Decimal(
+                Decimal::from_str(v).map_err(|_| format!("invalid decimal: {:?}", v))?,
+            ),
+            ValueRecord::Timestamp(v) => OwnedValue::Timestamp(parse(v)?),
+            ValueRecord::Raw(_) => return Err("raw values aren't typed".to_owned()),
+        })
+    }
+
+    fn to_bytes(&self, encoding: Encoding) -> Result<Vec<u8>, String> {
+        match self {
+            ValueRecord::Raw(hex) => from_hex(hex),
+            _ => Value::from(&self.to_value()?)
+                .encode(encoding)
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// The bytes of a stored value, as dumps write them, e.g. `{"i64":42}`.
+pub fn format_value(bytes: &[u8]) -> String {
+    let (record, _) = ValueRecord::from_bytes(bytes);
+    serde_json::to_string(&record).expect("serialized")
+}
+
+/// A value written as dumps write them. Raw values aren't, as they have no type.
+pub fn parse_value(json: &str) -> Result<OwnedValue, DataError> {
+    let record: ValueRecord = serde_json::from_str(json)?;
+    record.to_value().map_err(DataError::InvalidValue)
+}
+
+fn write_record<W>(out: &mut W, record: &Record) -> Result<(), StoreError>
+where
+    W: Write,
+{
+    serde_json::to_writer(&mut *out, record).map_err(|e| StoreError::IoError(e.into()))?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+pub(crate) fn write_header<W>(out: &mut W) -> Result<(), StoreError>
+where
+    W: Write,
+{
+    write_record(out, &Record::Header { version: VERSION })
+}
+
+pub(crate) fn write_store<W>(out: &mut W, store: &StoreRecord) -> Result<(), StoreError>
+where
+    W: Write,
+{
+    write_record(out, &Record::Store(store.clone()))
+}
+
+pub(crate) fn write_entry<W>(
+    out: &mut W,
+    store: &StoreRecord,
+    key: &[u8],
+    value: &[u8],
+) -> Result<(), StoreError>
+where
+    W: Write,
+{
+    let (value, ordered) = ValueRecord::from_bytes(value);
+    let entry = EntryRecord {
+        key: KeyRecord::new(key, store.integer_key),
+        value,
+        ordered,
+    };
+    write_record(out, &Record::Entry(entry))
+}
+
+/// A store of a dump, along with the flags to create it with, and its entries as they
+/// were encoded.
+pub(crate) struct DumpedStore<F> {
+    pub(crate) name: Option<String>,
+    pub(crate) flags: F,
+    pub(crate) entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// The stores of a dump. Nothing is returned unless the whole dump is well-formed.
+pub(crate) fn read<F, R>(input: R) -> Result<Vec<DumpedStore<F>>, StoreError>
+where
+    F: BackendDatabaseFlags,
+    R: BufRead,
+{
+    let mut stores: Vec<DumpedStore<F>> = vec![];
+    let mut lines = input.lines().enumerate();
+    let invalid = |line: usize, reason: String| StoreError::DumpInvalid {
+        line: line + 1,
+        reason,
+    };
+    match lines.next() {
+        Some((i, line)) => match serde_json::from_str(&line?) {
+            Ok(Record::Header { version: VERSION }) => (),
+            Ok(Record::Header { version }) => {
+                return Err(StoreError::DumpVersionUnsupported(version))
+            }
+            Ok(_) => return Err(invalid(i, "expected a header".to_owned())),
+            Err(e) => return Err(invalid(i, e.to_string())),
+        },
+        None => return Err(invalid(0, "expected a header".to_owned())),
+    }
+    for (i, line) in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line).map_err(|e| invalid(i, e.to_string()))? {
+            Record::Header { .. } => return Err(invalid(i, "unexpected header".to_owned())),
+            Record::Store(store) => stores.push(DumpedStore {
+                flags: store.flags().map_err(|e| invalid(i, e))?,
+                name: store.name,
+                entries: vec![],
+            }),
+            Record::Entry(entry) => {
+                let store = stores
+                    .last_mut()
+                    .ok_or_else(|| invalid(i, "entry outside of a store".to_owned()))?;
+                let encoding = if entry.ordered {
+                    Encoding::Ordered
+                } else {
+                    Encoding::Bincode
+                };
+                let key = entry.key.to_bytes().map_err(|e| invalid(i, e))?;
+                let value = entry.value.to_bytes(encoding).map_err(|e| invalid(i, e))?;
+                store.entries.push((key, value));
+            }
+        }
+    }
+    Ok(stores)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use ordered_float::OrderedFloat;
+
+    use super::*;
+
+    #[test]
+    fn test_value_records() {
+        let values = vec![
+            Value::Bool(true),
+            Value::I64(-1),
+            Value::F64(OrderedFloat(-0.0)),
+            Value::Str("foo"),
+            Value::Json(Cow::Borrowed(r#"{"a":[1,2]}"#)),
+            Value::Blob(Cow::Borrowed(&[0, 255])),
+            Value::Array(vec![Value::U64(1), Value::Str("x")]),
+            Value::I128(i128::MIN),
+            Value::Decimal(Decimal::new(150, 2)),
+        ];
+        for value in &values {
+            for (encoding, ordered) in [(Encoding::Bincode, false), (Encoding::Ordered, true)] {
+                let bytes = value.encode(encoding).expect("encoded");
+                let (record, was_ordered) = ValueRecord::from_bytes(&bytes);
+                // Blobs are stored untagged, unless they're ordered.
+                if let (Value::Blob(_), Encoding::Bincode) = (value, encoding) {
+                    assert!(matches!(record, ValueRecord::Raw(_)));
+                } else {
+                    assert!(!matches!(record, ValueRecord::Raw(_)), "{:?}", value);
+                    assert_eq!(was_ordered, ordered);
+                }
+                let json = serde_json::to_string(&record).expect("serialized");
+                let record: ValueRecord = serde_json::from_str(&json).expect("deserialized");
+                assert_eq!(record.to_bytes(encoding).expect("encoded"), bytes);
+            }
+        }
+
+        // Bytes that aren't a tagged value, such as a compressed one, are kept as-is.
+        let (record, _) = ValueRecord::from_bytes(&[0x80, 1, 2]);
+        assert_eq!(record, ValueRecord::Raw("800102".to_owned()));
+        let (record, _) =
+            ValueRecord::from_bytes(&Value::F64(OrderedFloat(f64::NAN)).to_bytes().unwrap());
+        assert!(matches!(record, ValueRecord::Raw(_)));
+    }
+
+    #[test]
+    fn test_key_records() {
+        for (key, integer_key) in [
+            (&b"foo"[..], false),
+            (&[0xff, 0][..], false),
+            (&[1, 0, 0, 0][..], true),
+        ] {
+            let record = KeyRecord::new(key, integer_key);
+            assert_eq!(record.to_bytes().expect("decoded"), key);
+        }
+        assert_eq!(KeyRecord::new(&1u32.to_ne_bytes(), true), KeyRecord::U32(1));
+        assert!(from_hex("0g").is_err());
+    }
+}
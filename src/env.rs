@@ -9,27 +9,56 @@
 // specific language governing permissions and limitations under the License.
 
 use std::{
+    any::Any,
     fs,
+    io::{BufRead, Write},
     os::raw::c_uint,
     path::{Path, PathBuf},
+    sync::{Arc, RwLock},
 };
 
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The key of an encrypted environment, see `BackendEnvironmentBuilder::set_enc_key`.
 pub type Key = [u8; 32];
 
+/// The databases of stores opened by `Rkv::open_for_write`, by name.
+type Opened<'n, D> = Vec<(Option<&'n str>, D)>;
+
 #[cfg(any(feature = "db-dup-sort", feature = "db-int-key"))]
 use crate::backend::{BackendDatabaseFlags, DatabaseFlags};
 use crate::{
     backend::{
-        BackendEnvironment, BackendEnvironmentBuilder, BackendRoCursorTransaction,
-        BackendRwCursorTransaction, SafeModeError,
+        AnyEnvironment, BackendEnvironment, BackendEnvironmentBuilder, BackendFlags, BackendInfo,
+        BackendIter, BackendRoCursor, BackendRoCursorTransaction, BackendRoTransaction,
+        BackendRwCursorTransaction, BackendRwTransaction, SafeModeError,
     },
+    changelog::{self, ChangeSet, Changes, LOG_NAME},
+    config::EnvironmentConfig,
+    diff::Diff,
+    dump::{self, StoreRecord},
     error::{CloseError, StoreError},
-    readwrite::{Reader, Writer},
-    store::{single::SingleStore, CloseOptions, Options as StoreOptions},
+    feed::{Change, Feed, Subscription},
+    group_commit::{self, GroupCommitter},
+    readwrite::{Readable, Reader, SharedReader, Writer},
+    snapshot::{Keypair, PublicKey, SignedSnapshot, StoreContents},
+    store::{
+        blob::BlobStore,
+        lww::LwwStore,
+        merkle::{MerkleStore, MERKLE_PREFIX},
+        single::SingleStore,
+        typed::TypedStore,
+        CloseOptions, Options as StoreOptions, Store, COMPRESSION_LZ4, COMPRESSION_NAME,
+    },
+    value::Compression,
 };
 
 #[cfg(feature = "db-dup-sort")]
-use crate::store::multi::MultiStore;
+use crate::store::{
+    multi::MultiStore,
+    orset::{OrSetStore, TOMBSTONES_PREFIX},
+    typed::TypedMultiStore,
+};
 
 #[cfg(feature = "db-int-key")]
 use crate::store::integer::IntegerStore;
@@ -46,6 +75,8 @@ pub static DEFAULT_MAX_DBS: c_uint = 10;
 pub struct Rkv<E> {
     _path: PathBuf,
     env: E,
+    // A `Feed` of the environment's database type, which can't be named here.
+    feed: Arc<dyn Any + Send + Sync>,
 }
 
 /// Static methods.
@@ -107,10 +138,21 @@ where
         Ok(Rkv {
             _path: path.into(),
             env: builder.open(path).map_err(|e| e.into())?,
+            feed: Arc::new(Feed::<E::Database>::new()),
         })
     }
 }
 
+impl Rkv<AnyEnvironment> {
+    /// Return a new Rkv environment of the backend and with the settings given by `url`,
+    /// e.g. `rkv+lmdb:///data/profile?max_dbs=16&map_size=1GiB&mkdir=1`. See the
+    /// [config](config/index.html) module for the format of the URL.
+    pub fn open_url(url: &str) -> Result<Rkv<AnyEnvironment>, StoreError> {
+        let config = EnvironmentConfig::from_url(url)?;
+        Rkv::from_builder(&config.path, config.builder)
+    }
+}
+
 /// Store creation methods.
 impl<'e, E> Rkv<E>
 where
@@ -125,14 +167,16 @@ where
     /// Note: that create=true cannot be called concurrently with other operations so if
     /// you are sure that the database exists, call this with create=false.
     pub fn open_single<'s, T>(
-        &self,
+        &'e self,
         name: T,
         opts: StoreOptions<E::Flags>,
     ) -> Result<SingleStore<E::Database>, StoreError>
     where
         T: Into<Option<&'s str>>,
     {
-        self.open(name, opts).map(SingleStore::new)
+        let compression = opts.compression;
+        self.open_compressed(name.into(), opts)
+            .map(|db| SingleStore::new(db, compression))
     }
 
     /// Create or Open an existing database in (Integer -> Single Value) mode.
@@ -140,7 +184,7 @@ where
     /// you are sure that the database exists, call this with create=false.
     #[cfg(feature = "db-int-key")]
     pub fn open_integer<'s, T, K>(
-        &self,
+        &'e self,
         name: T,
         mut opts: StoreOptions<E::Flags>,
     ) -> Result<IntegerStore<E::Database, K>, StoreError>
@@ -149,7 +193,9 @@ where
         T: Into<Option<&'s str>>,
     {
         opts.flags.set(DatabaseFlags::INTEGER_KEY, true);
-        self.open(name, opts).map(IntegerStore::new)
+        let compression = opts.compression;
+        self.open_compressed(name.into(), opts)
+            .map(|db| IntegerStore::new(db, compression))
     }
 
     /// Create or Open an existing database in (&[u8] -> Multiple Values) mode.
@@ -157,7 +203,7 @@ where
     /// you are sure that the database exists, call this with create=false.
     #[cfg(feature = "db-dup-sort")]
     pub fn open_multi<'s, T>(
-        &self,
+        &'e self,
         name: T,
         mut opts: StoreOptions<E::Flags>,
     ) -> Result<MultiStore<E::Database>, StoreError>
@@ -165,7 +211,9 @@ where
         T: Into<Option<&'s str>>,
     {
         opts.flags.set(DatabaseFlags::DUP_SORT, true);
-        self.open(name, opts).map(MultiStore::new)
+        let (encoding, compression) = (opts.encoding, opts.compression);
+        self.open_compressed(name.into(), opts)
+            .map(|db| MultiStore::with_encoding(db, encoding, compression))
     }
 
     /// Create or Open an existing database in (Integer -> Multiple Values) mode.
@@ -173,7 +221,7 @@ where
     /// you are sure that the database exists, call this with create=false.
     #[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
     pub fn open_multi_integer<'s, T, K>(
-        &self,
+        &'e self,
         name: T,
         mut opts: StoreOptions<E::Flags>,
     ) -> Result<MultiIntegerStore<E::Database, K>, StoreError>
@@ -183,16 +231,206 @@ where
     {
         opts.flags.set(DatabaseFlags::INTEGER_KEY, true);
         opts.flags.set(DatabaseFlags::DUP_SORT, true);
-        self.open(name, opts).map(MultiIntegerStore::new)
+        let (encoding, compression) = (opts.encoding, opts.compression);
+        self.open_compressed(name.into(), opts)
+            .map(|db| MultiIntegerStore::new(db, encoding, compression))
+    }
+
+    /// Create or Open an existing database in (&[u8] -> Single serde Value) mode. Values
+    /// are written with the given schema `version`, and reading a value written with
+    /// another version fails with `DataError::UnexpectedVersion`.
+    /// Note: that create=true cannot be called concurrently with other operations so if
+    /// you are sure that the database exists, call this with create=false.
+    pub fn open_typed<'s, T, K, V>(
+        &'e self,
+        name: T,
+        opts: StoreOptions<E::Flags>,
+        version: u8,
+    ) -> Result<TypedStore<E::Database, K, V>, StoreError>
+    where
+        K: AsRef<[u8]>,
+        V: Serialize + DeserializeOwned,
+        T: Into<Option<&'s str>>,
+    {
+        let compression = opts.compression;
+        self.open_compressed(name.into(), opts)
+            .map(|db| TypedStore::new(db, version, compression))
+    }
+
+    /// Create or Open an existing database in (&[u8] -> Multiple serde Values) mode.
+    /// Values are written with the given schema `version`, see `open_typed`.
+    /// Note: that create=true cannot be called concurrently with other operations so if
+    /// you are sure that the database exists, call this with create=false.
+    #[cfg(feature = "db-dup-sort")]
+    pub fn open_typed_multi<'s, T, K, V>(
+        &'e self,
+        name: T,
+        mut opts: StoreOptions<E::Flags>,
+        version: u8,
+    ) -> Result<TypedMultiStore<E::Database, K, V>, StoreError>
+    where
+        K: AsRef<[u8]>,
+        V: Serialize + DeserializeOwned,
+        T: Into<Option<&'s str>>,
+    {
+        opts.flags.set(DatabaseFlags::DUP_SORT, true);
+        let compression = opts.compression;
+        self.open_compressed(name.into(), opts)
+            .map(|db| TypedMultiStore::new(db, version, compression))
+    }
+
+    /// Create or Open an existing database in (&[u8] -> chunked blob) mode, for blobs
+    /// that are streamed in and out rather than held in memory, see `BlobStore`. Values
+    /// aren't compressed, regardless of `opts.compression`.
+    /// Note: that create=true cannot be called concurrently with other operations so if
+    /// you are sure that the database exists, call this with create=false.
+    pub fn open_blobs<'s, T>(
+        &self,
+        name: T,
+        opts: StoreOptions<E::Flags>,
+    ) -> Result<BlobStore<E::Database>, StoreError>
+    where
+        T: Into<Option<&'s str>>,
+    {
+        self.open(name, opts).map(BlobStore::new)
+    }
+
+    /// Create or Open an existing database in (&[u8] -> Single Value) mode, along with
+    /// a Merkle tree of its contents down to `depth`, see `MerkleStore`. The tree is held
+    /// in a database of its own, named after the store's, along with the depth it was
+    /// created with, and opening it with another one fails with
+    /// `StoreError::MerkleDepthMismatch`. This needs a write transaction, so it can't be
+    /// called while one is active.
+    /// Note: that create=true cannot be called concurrently with other operations so if
+    /// you are sure that the database exists, call this with create=false.
+    pub fn open_merkle<'s, T, W>(
+        &'e self,
+        name: T,
+        opts: StoreOptions<E::Flags>,
+        depth: u8,
+    ) -> Result<MerkleStore<E::Database>, StoreError>
+    where
+        T: Into<Option<&'s str>>,
+        E: BackendEnvironment<'e, RwTransaction = W>,
+        W: BackendRwCursorTransaction<'e, Database = E::Database>,
+    {
+        let name = name.into();
+        let tree_name = format!("{}{}", MERKLE_PREFIX, name.unwrap_or_default());
+        let tree_opts = StoreOptions {
+            flags: E::Flags::empty(),
+            ..opts
+        };
+        let compression = opts.compression;
+        let db = self.open_compressed(name, opts)?;
+        let tree = self.open(tree_name.as_str(), tree_opts)?;
+        let store = MerkleStore::new(db, tree, depth, compression);
+        let mut writer = self.write()?;
+        if store.check_depth(&mut writer)? {
+            writer.commit()?;
+        }
+        Ok(store)
+    }
+
+    /// Create or Open an existing database of last-writer-wins registers, see the
+    /// `store::lww` module.
+    pub fn open_lww<'s, T>(
+        &'e self,
+        name: T,
+        opts: StoreOptions<E::Flags>,
+    ) -> Result<LwwStore<E::Database>, StoreError>
+    where
+        T: Into<Option<&'s str>>,
+    {
+        let compression = opts.compression;
+        self.open_compressed(name.into(), opts)
+            .map(|db| LwwStore::new(db, compression))
+    }
+
+    /// Create or Open an existing database of observed-remove sets, see the
+    /// `store::orset` module. Its tombstones are held in a database of their own, named
+    /// after it.
+    #[cfg(feature = "db-dup-sort")]
+    pub fn open_or_set<'s, T>(
+        &'e self,
+        name: T,
+        mut opts: StoreOptions<E::Flags>,
+    ) -> Result<OrSetStore<E::Database>, StoreError>
+    where
+        T: Into<Option<&'s str>>,
+    {
+        let name = name.into();
+        let tombstones_name = format!("{}{}", TOMBSTONES_PREFIX, name.unwrap_or_default());
+        let tombstones_opts = StoreOptions {
+            flags: E::Flags::empty(),
+            ..opts
+        };
+        opts.flags.set(DatabaseFlags::DUP_SORT, true);
+        let compression = opts.compression;
+        let db = self.open_compressed(name, opts)?;
+        let tombstones = self.open(tombstones_name.as_str(), tombstones_opts)?;
+        Ok(OrSetStore::new(db, tombstones, compression))
     }
 
-    fn open<'s, T>(&self, name: T, opts: StoreOptions<E::Flags>) -> Result<E::Database, StoreError>
+    /// Open a store like `open`, then check that it's opened with the compression it was
+    /// created with. Stores that compress their values are recorded as such, in a
+    /// database of their own, the first time they're opened, which fails if they already
+    /// hold values then, since those weren't compressed. This needs a write transaction,
+    /// so compressed stores can't be opened for the first time while one is active.
+    fn open_compressed(
+        &'e self,
+        name: Option<&str>,
+        opts: StoreOptions<E::Flags>,
+    ) -> Result<E::Database, StoreError> {
+        let compressed = opts.compression != Compression::None;
+        let db = self.open(name, opts)?;
+        let meta_opts = StoreOptions {
+            create: compressed,
+            ..StoreOptions::default()
+        };
+        let meta = match self.open(COMPRESSION_NAME, meta_opts) {
+            Ok(meta) => meta,
+            // The database is only created once a store compresses its values.
+            Err(_) if !compressed => return Ok(db),
+            Err(e) => return Err(e),
+        };
+        let key = name.unwrap_or_default().as_bytes();
+        let recorded = {
+            let txn = self.env.begin_ro_txn().map_err(|e| e.into())?;
+            match txn.get(&meta, key).map_err(|e| e.into()) {
+                Ok(_) => true,
+                Err(StoreError::KeyValuePairNotFound) => false,
+                Err(e) => return Err(e),
+            }
+        };
+        match (compressed, recorded) {
+            (false, true) => Err(StoreError::CompressionMismatch),
+            (true, false) => {
+                let mut txn = self.env.begin_rw_txn().map_err(|e| e.into())?;
+                let keys = txn.range_keys(&db, None, None).map_err(|e| e.into())?;
+                if !keys.is_empty() {
+                    return Err(StoreError::CompressionMismatch);
+                }
+                txn.put(&meta, key, &[COMPRESSION_LZ4], BackendFlags::empty())
+                    .map_err(|e| e.into())?;
+                txn.commit().map_err(|e| e.into())?;
+                Ok(db)
+            }
+            _ => Ok(db),
+        }
+    }
+
+    pub(crate) fn open<'s, T>(
+        &self,
+        name: T,
+        opts: StoreOptions<E::Flags>,
+    ) -> Result<E::Database, StoreError>
     where
         T: Into<Option<&'s str>>,
     {
-        if opts.create {
+        let name = name.into();
+        let db = if opts.create {
             self.env
-                .create_db(name.into(), opts.flags)
+                .create_db(name, opts.flags)
                 .map_err(|e| match e.into() {
                     #[cfg(feature = "lmdb")]
                     StoreError::LmdbError(lmdb::Error::BadRslot) => {
@@ -204,7 +442,7 @@ where
                     e => e,
                 })
         } else {
-            self.env.open_db(name.into()).map_err(|e| match e.into() {
+            self.env.open_db(name).map_err(|e| match e.into() {
                 #[cfg(feature = "lmdb")]
                 StoreError::LmdbError(lmdb::Error::BadRslot) => {
                     StoreError::open_during_transaction()
@@ -214,7 +452,9 @@ where
                 }
                 e => e,
             })
-        }
+        }?;
+        self.feed().name(db, name);
+        Ok(db)
     }
 }
 
@@ -226,12 +466,34 @@ where
     /// Create a read transaction.  There can be multiple concurrent readers for an
     /// environment, up to the maximum specified by LMDB (default 126), and you can open
     /// readers while a write transaction is active.
+    /// With LMDB, a thread can only have one reader at a time, and fails to create another
+    /// with `StoreError::ReadTransactionAlreadyExists`, unless the environment was opened
+    /// with `set_no_tls(true)`. See `Reader` for what each reader sees.
     pub fn read<T>(&'e self) -> Result<Reader<T>, StoreError>
     where
         E: BackendEnvironment<'e, RoTransaction = T>,
         T: BackendRoCursorTransaction<'e, Database = E::Database>,
     {
-        Ok(Reader::new(self.env.begin_ro_txn().map_err(|e| e.into())?))
+        let txn = self.env.begin_ro_txn().map_err(|e| match e.into() {
+            #[cfg(feature = "lmdb")]
+            StoreError::LmdbError(lmdb::Error::BadRslot) => {
+                StoreError::read_transaction_already_exists()
+            }
+            e => e,
+        })?;
+        Ok(Reader::new(txn))
+    }
+
+    /// Create a read transaction which can be cloned and used from several threads at once,
+    /// see `SharedReader`. With LMDB, this fails with `StoreError::NoTlsRequired` unless
+    /// the environment was opened with `set_no_tls(true)`.
+    pub fn read_shared<T>(&'e self) -> Result<SharedReader<T>, StoreError>
+    where
+        E: BackendEnvironment<'e, SharedRoTransaction = T>,
+        T: BackendRoCursorTransaction<'e, Database = E::Database>,
+    {
+        let txn = self.env.begin_shared_ro_txn().map_err(|e| e.into())?;
+        Ok(SharedReader::new(txn))
     }
 
     /// Create a write transaction.  There can be only one write transaction active at any
@@ -242,7 +504,432 @@ where
         E: BackendEnvironment<'e, RwTransaction = T>,
         T: BackendRwCursorTransaction<'e, Database = E::Database>,
     {
-        Ok(Writer::new(self.env.begin_rw_txn().map_err(|e| e.into())?))
+        let txn = self.env.begin_rw_txn().map_err(|e| e.into())?;
+        let recorder = self.feed().recorder();
+        Ok(Writer::new(
+            txn,
+            recorder.map(|r| Box::new(r) as Box<dyn Any + Send + Sync>),
+        ))
+    }
+
+    /// Subscribe to the changes made to a store by committed write transactions. The
+    /// returned receiver gets a batch of changes for each transaction that changed a key
+    /// for which `filter` returns true, see the `feed` module for ordering guarantees.
+    pub fn subscribe<S, F>(&self, store: &S, filter: F) -> Subscription<E::Database>
+    where
+        S: Store<Database = E::Database>,
+        F: Fn(&Change<E::Database>) -> bool + Send + 'static,
+    {
+        self.feed().subscribe(store.database(), filter)
+    }
+
+    /// Enable the change log, so that every write transaction committed from now on logs
+    /// its mutations, see the `changelog` module. The log is kept across restarts, but
+    /// this must be called again to keep appending to it.
+    /// Note: this opens, or creates, the log's database, so it cannot be called
+    /// concurrently with other operations.
+    pub fn enable_change_log(&self) -> Result<(), StoreError> {
+        let db = self.open(LOG_NAME, StoreOptions::create())?;
+        self.feed().enable_log(db);
+        Ok(())
+    }
+
+    /// Iterate over the change sets committed after the one numbered `seq`, or all of
+    /// them if it's 0. Fails with `StoreError::ChangesTruncated` if some of them were
+    /// truncated, in which case a replica that's this far behind needs a full copy.
+    pub fn changes_since<'r, R, I, C>(
+        &self,
+        reader: &'r R,
+        seq: u64,
+    ) -> Result<Changes<'r, I>, StoreError>
+    where
+        R: Readable<'r, Database = E::Database, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        let db = self.feed().log().ok_or(StoreError::ChangeLogDisabled)?;
+        changelog::changes_since(reader, &db, seq)
+    }
+
+    /// Delete the change sets numbered up to `seq`, returning how many there were.
+    pub fn truncate_changes<T>(&'e self, seq: u64) -> Result<usize, StoreError>
+    where
+        E: BackendEnvironment<'e, RwTransaction = T>,
+        T: BackendRwCursorTransaction<'e, Database = E::Database>,
+    {
+        let db = self.feed().log().ok_or(StoreError::ChangeLogDisabled)?;
+        let mut writer = self.write()?;
+        let truncated = changelog::truncate(&mut writer, &db, seq)?;
+        writer.commit()?;
+        Ok(truncated)
+    }
+
+    /// Merge the change sets numbered up to `seq` into one, leaving out the mutations
+    /// undone by later ones, and return how many there were. Unlike truncating them,
+    /// this lets replicas that are behind `seq` catch up.
+    pub fn compact_changes<T>(&'e self, seq: u64) -> Result<usize, StoreError>
+    where
+        E: BackendEnvironment<'e, RwTransaction = T>,
+        T: BackendRwCursorTransaction<'e, Database = E::Database>,
+    {
+        let db = self.feed().log().ok_or(StoreError::ChangeLogDisabled)?;
+        let mut writer = self.write()?;
+        let merged = changelog::compact(&mut writer, &db, seq, |name| self.feed().named(name))?;
+        writer.commit()?;
+        Ok(merged)
+    }
+
+    /// Replay change sets from another environment's log into this one, within a single
+    /// write transaction, and return the number of the last one replayed. Stores are
+    /// opened by name, and created as single stores if they don't exist, so dup-sort or
+    /// integer stores should be created beforehand. The number of the last set replayed
+    /// is kept, and sets up to it are skipped, so replaying sets again is harmless as
+    /// long as they all come from the same log.
+    pub fn apply_changes<I, T>(&'e self, sets: I) -> Result<u64, StoreError>
+    where
+        I: IntoIterator<Item = ChangeSet>,
+        E: BackendEnvironment<'e, RwTransaction = T>,
+        T: BackendRwCursorTransaction<'e, Database = E::Database>,
+    {
+        let sets: Vec<ChangeSet> = sets.into_iter().collect();
+        let log = self.open(LOG_NAME, StoreOptions::create())?;
+        let names = sets.iter().flat_map(|set| &set.mutations);
+        let dbs = self.open_for_write(names.map(|m| (m.store(), E::Flags::empty())))?;
+
+        let mut writer = self.write()?;
+        let mut applied = changelog::applied(&writer, &log)?;
+        for set in &sets {
+            if set.seq <= applied {
+                continue;
+            }
+            for mutation in &set.mutations {
+                changelog::replay(&mut writer, opened(&dbs, mutation.store()), mutation)?;
+            }
+            applied = set.seq;
+        }
+        changelog::set_applied(&mut writer, &log, applied)?;
+        writer.commit()?;
+        Ok(applied)
+    }
+
+    /// Iterate over the differences between some stores of this environment and the
+    /// stores of the same names of another one, possibly of another backend, see the
+    /// `diff` module. Stores must have been opened by both environments.
+    pub fn diff<'r, 's, 'o, O, R, I, C, RO, IO, CO>(
+        &self,
+        reader: &'r R,
+        other: &Rkv<O>,
+        other_reader: &'r RO,
+        stores: &[Option<&'s str>],
+    ) -> Result<Diff<'r, 's, I, IO>, StoreError>
+    where
+        O: BackendEnvironment<'o>,
+        R: Readable<'r, Database = E::Database, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        RO: Readable<'r, Database = O::Database, RoCursor = CO>,
+        IO: BackendIter<'r>,
+        CO: BackendRoCursor<'r, Iter = IO>,
+    {
+        let mut diff = Diff::new();
+        for name in stores {
+            let ours = self.feed().named(*name).ok_or(StoreError::ForeignStore)?;
+            let theirs = other.feed().named(*name).ok_or(StoreError::ForeignStore)?;
+            diff.push(
+                *name,
+                reader.open_ro_cursor(&ours)?.into_iter(),
+                other_reader.open_ro_cursor(&theirs)?.into_iter(),
+            );
+        }
+        Ok(diff)
+    }
+
+    /// Write the contents of some stores to `out`, in a format that other environments,
+    /// of any backend, can load, see the `dump` module. Stores must have been opened by
+    /// this environment, and are written in the given order.
+    pub fn dump<'r, R, I, C, W>(
+        &self,
+        reader: &'r R,
+        stores: &[Option<&str>],
+        mut out: W,
+    ) -> Result<(), StoreError>
+    where
+        R: Readable<'r, Database = E::Database, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        W: Write,
+    {
+        dump::write_header(&mut out)?;
+        for name in stores {
+            let db = self.feed().named(*name).ok_or(StoreError::ForeignStore)?;
+            let store = StoreRecord::new(*name, &reader.db_flags(&db)?);
+            dump::write_store(&mut out, &store)?;
+            let mut iter = reader.open_ro_cursor(&db)?.into_iter();
+            while let Some(result) = iter.next() {
+                let (key, value) = result.map_err(|e| e.into())?;
+                dump::write_entry(&mut out, &store, key, value)?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Load a dump written by `Rkv::dump`, returning the stores it held. Each of them is
+    /// created with the flags it was dumped with if it doesn't exist, and cleared, then
+    /// filled with its entries, within a single write transaction. Nothing is written
+    /// unless the whole dump is well-formed.
+    pub fn load<T, R>(&'e self, input: R) -> Result<Vec<Option<String>>, StoreError>
+    where
+        E: BackendEnvironment<'e, RwTransaction = T>,
+        T: BackendRwCursorTransaction<'e, Database = E::Database>,
+        R: BufRead,
+    {
+        let stores = dump::read::<E::Flags, _>(input)?;
+        let dbs = self.open_for_write(stores.iter().map(|s| (s.name.as_deref(), s.flags)))?;
+        let mut writer = self.write()?;
+        for store in &stores {
+            refill(
+                &mut writer,
+                opened(&dbs, store.name.as_deref()),
+                &store.entries,
+            )?;
+        }
+        writer.commit()?;
+        Ok(stores.into_iter().map(|store| store.name).collect())
+    }
+
+    /// Export the contents of some stores as a snapshot signed with `keypair`, which
+    /// other environments can import once they verified it, see the `snapshot` module.
+    /// Stores are named in the snapshot as they were opened. Only a store's own
+    /// database is exported, and not those kept next to it, such as the tree of a
+    /// `MerkleStore` or the tombstones of an `OrSetStore`.
+    pub fn export_snapshot<'r, R, I, C>(
+        &self,
+        reader: &'r R,
+        stores: &[&dyn Store<Database = E::Database>],
+        keypair: &Keypair,
+    ) -> Result<SignedSnapshot, StoreError>
+    where
+        R: Readable<'r, Database = E::Database, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        let mut contents = vec![];
+        for store in stores {
+            let db = store.database();
+            let name = self.feed().name_of(db).ok_or(StoreError::ForeignStore)?;
+            let mut iter = reader.open_ro_cursor(&db)?.into_iter();
+            let mut entries = vec![];
+            while let Some(result) = iter.next() {
+                let (key, value) = result.map_err(|e| e.into())?;
+                entries.push((key.to_vec(), value.to_vec()));
+            }
+            contents.push(StoreContents { name, entries });
+        }
+        SignedSnapshot::sign(&contents, keypair)
+    }
+
+    /// Import a snapshot exported by another environment, once it verified that it was
+    /// signed by the holder of `public_key`. Nothing is written unless it was. Each of
+    /// its stores is cleared, then filled with its entries, within a single write
+    /// transaction. Stores are opened by name, and created as single stores if they
+    /// don't exist, so dup-sort or integer stores should be created beforehand.
+    pub fn import_snapshot<T>(
+        &'e self,
+        snapshot: &SignedSnapshot,
+        public_key: &PublicKey,
+    ) -> Result<(), StoreError>
+    where
+        E: BackendEnvironment<'e, RwTransaction = T>,
+        T: BackendRwCursorTransaction<'e, Database = E::Database>,
+    {
+        let stores = snapshot.verify(public_key)?;
+        let names = stores
+            .iter()
+            .map(|s| (s.name.as_deref(), E::Flags::empty()));
+        let dbs = self.open_for_write(names)?;
+        let mut writer = self.write()?;
+        for store in &stores {
+            refill(
+                &mut writer,
+                opened(&dbs, store.name.as_deref()),
+                &store.entries,
+            )?;
+        }
+        writer.commit()
+    }
+
+    /// Open the stores of some names, creating those that don't exist with the flags
+    /// they come with, so that a write transaction can then fill them. Databases can't
+    /// be opened within one, so they're opened upfront. Each name is opened once.
+    fn open_for_write<'n, N>(&self, names: N) -> Result<Opened<'n, E::Database>, StoreError>
+    where
+        N: IntoIterator<Item = (Option<&'n str>, E::Flags)>,
+    {
+        let mut dbs: Opened<E::Database> = vec![];
+        for (name, flags) in names {
+            if dbs.iter().all(|(n, _)| *n != name) {
+                let db = self.open(name, StoreOptions::default()).or_else(|_| {
+                    let opts = StoreOptions {
+                        flags,
+                        ..StoreOptions::create()
+                    };
+                    self.open(name, opts)
+                })?;
+                dbs.push((name, db));
+            }
+        }
+        Ok(dbs)
+    }
+
+    fn feed(&self) -> Arc<Feed<E::Database>> {
+        self.feed
+            .clone()
+            .downcast()
+            .unwrap_or_else(|_| unreachable!("feed of another database type"))
+    }
+}
+
+/// The database of a store opened by `Rkv::open_for_write`.
+fn opened<'d, D>(dbs: &'d [(Option<&str>, D)], name: Option<&str>) -> &'d D {
+    dbs.iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, db)| db)
+        .unwrap_or_else(|| unreachable!("store not opened for writing"))
+}
+
+/// Clear a store, then fill it with entries.
+fn refill<T>(
+    writer: &mut Writer<T>,
+    db: &T::Database,
+    entries: &[(Vec<u8>, Vec<u8>)],
+) -> Result<(), StoreError>
+where
+    T: BackendRwTransaction,
+{
+    writer.clear(db)?;
+    for (key, value) in entries {
+        writer.put(db, key, value, T::Flags::empty())?;
+    }
+    Ok(())
+}
+
+/// How `Rkv::transact_with_retry` retries write transactions which failed for reasons that
+/// retrying can fix.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RetryOptions {
+    /// The maximum number of retries, after the first attempt.
+    pub attempts: usize,
+    /// The factor to grow the map by before retrying a transaction that filled it, or
+    /// `None` to not retry those.
+    pub map_growth: Option<f64>,
+}
+
+impl Default for RetryOptions {
+    fn default() -> RetryOptions {
+        RetryOptions {
+            attempts: 3,
+            map_growth: Some(2.0),
+        }
+    }
+}
+
+/// Closure-based transactions.
+impl<'e, E> Rkv<E>
+where
+    E: BackendEnvironment<'e>,
+{
+    /// Run `f` in a write transaction, which is committed if it returns `Ok`, and aborted
+    /// if it returns `Err`. Errors of the transaction itself are converted into `X`.
+    pub fn transact<T, F, R, X>(&'e self, f: F) -> Result<R, X>
+    where
+        E: BackendEnvironment<'e, RwTransaction = T>,
+        T: BackendRwCursorTransaction<'e, Database = E::Database>,
+        F: FnOnce(&mut Writer<T>) -> Result<R, X>,
+        X: From<StoreError>,
+    {
+        let mut writer = self.write()?;
+        match f(&mut writer) {
+            Ok(value) => {
+                writer.commit()?;
+                Ok(value)
+            }
+            Err(e) => {
+                writer.abort();
+                Err(e)
+            }
+        }
+    }
+
+    /// Like `transact`, but runs `f` again in a new transaction when the last one failed
+    /// with `StoreError::MapFull`, after growing the map, or because another process
+    /// resized it (`LmdbError::MapResized`), after adopting the new size.
+    /// Note: as with `set_map_size`, there mustn't be any other active transactions in
+    /// this process when the map is resized.
+    pub fn transact_with_retry<T, F, R>(
+        &'e self,
+        options: RetryOptions,
+        mut f: F,
+    ) -> Result<R, StoreError>
+    where
+        E: BackendEnvironment<'e, RwTransaction = T>,
+        T: BackendRwCursorTransaction<'e, Database = E::Database>,
+        F: FnMut(&mut Writer<T>) -> Result<R, StoreError>,
+    {
+        let mut attempts = 0;
+        loop {
+            let result = self.transact(&mut f);
+            match (result, options.map_growth) {
+                (Err(StoreError::MapFull), Some(growth)) if attempts < options.attempts => {
+                    let size = self.info()?.map_size();
+                    self.set_map_size((size as f64 * growth) as usize)?;
+                }
+                (Err(ref e), _) if attempts < options.attempts && is_map_resized(e) => {
+                    self.set_map_size(0)?;
+                }
+                (result, _) => return result,
+            }
+            attempts += 1;
+        }
+    }
+
+    /// Run `f` in a read transaction, which is aborted after it returns.
+    pub fn view<T, F, R, X>(&'e self, f: F) -> Result<R, X>
+    where
+        E: BackendEnvironment<'e, RoTransaction = T>,
+        T: BackendRoCursorTransaction<'e, Database = E::Database>,
+        F: FnOnce(&Reader<T>) -> Result<R, X>,
+        X: From<StoreError>,
+    {
+        let reader = self.read()?;
+        let result = f(&reader);
+        reader.abort();
+        result
+    }
+}
+
+#[cfg(feature = "lmdb")]
+fn is_map_resized(e: &StoreError) -> bool {
+    matches!(e, StoreError::LmdbError(lmdb::Error::MapResized))
+}
+
+#[cfg(not(feature = "lmdb"))]
+fn is_map_resized(_e: &StoreError) -> bool {
+    false
+}
+
+/// Group commit.
+impl<E> Rkv<E>
+where
+    E: for<'e> BackendEnvironment<'e> + Send + Sync + 'static,
+{
+    /// Start a committer thread which runs the closures submitted to it in as few write
+    /// transactions as it can, see the `group_commit` module.
+    pub fn group_commit(
+        rkv: Arc<RwLock<Rkv<E>>>,
+        options: group_commit::Options,
+    ) -> GroupCommitter<E> {
+        GroupCommitter::spawn(rkv, options)
     }
 }
 
@@ -327,6 +1014,12 @@ where
         self.env.set_map_size(size).map_err(Into::into)
     }
 
+    /// The files the environment is kept in, the data file first.
+    #[cfg(feature = "lmdb")]
+    pub(crate) fn files_on_disk(&self) -> Vec<PathBuf> {
+        self.env.get_files_on_disk()
+    }
+
     /// Closes this environment and optionally deletes all its files from disk. Doesn't
     /// delete the folder used when opening the environment.
     pub fn close(self, options: CloseOptions) -> Result<(), CloseError> {
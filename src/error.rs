@@ -23,6 +23,9 @@ pub enum DataError {
     #[error("unexpected type tag: expected {expected}, got {actual}")]
     UnexpectedType { expected: Type, actual: Type },
 
+    #[error("unexpected schema version: expected {expected}, got {actual}")]
+    UnexpectedVersion { expected: u8, actual: u8 },
+
     #[error("empty data; expected tag")]
     Empty,
 
@@ -37,6 +40,31 @@ pub enum DataError {
 
     #[error("invalid uuid bytes")]
     InvalidUuid,
+
+    #[error("invalid length for type {value_type}: expected {expected} bytes, got {actual}")]
+    InvalidLength {
+        value_type: Type,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("compressed {0} value; only blobs, JSON and serde values are compressed")]
+    Compressed(Type),
+
+    #[error("couldn't decompress value: {0}")]
+    DecompressionError(#[from] lz4_flex::block::DecompressError),
+
+    #[error("invalid json: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+
+    #[error("invalid json path: {0:?}")]
+    InvalidJsonPath(String),
+
+    #[error("invalid value: {0}")]
+    InvalidValue(String),
+
+    #[error("arrays and maps nested deeper than {0} levels")]
+    TooDeeplyNested(usize),
 }
 
 #[derive(Debug, Error)]
@@ -53,6 +81,9 @@ pub enum StoreError {
     #[error("unsupported size of key/DB name/data")]
     KeyValuePairBadSize,
 
+    #[error("keys aren't sorted, or don't come after those already in the store")]
+    KeysNotSorted,
+
     #[error("file is not a valid database")]
     FileInvalid,
 
@@ -86,6 +117,54 @@ pub enum StoreError {
 
     #[error("attempted to open DB during transaction in thread {0:?}")]
     OpenAttemptedDuringTransaction(ThreadId),
+
+    #[error("shared read transactions need an environment opened with NO_TLS")]
+    NoTlsRequired,
+
+    #[error("the group committer stopped before committing the submission")]
+    GroupCommitStopped,
+
+    #[error("the state of a group commit submission is poisoned")]
+    GroupCommitPoisoned,
+
+    #[error("the lock of the environment is poisoned")]
+    EnvironmentPoisoned,
+
+    #[error("change log isn't enabled")]
+    ChangeLogDisabled,
+
+    #[error("changes up to sequence number {0} were truncated")]
+    ChangesTruncated(u64),
+
+    #[error("store wasn't opened by this environment")]
+    ForeignStore,
+
+    #[error("unexpected merkle tree depth: expected {expected}, got {actual}")]
+    MerkleDepthMismatch { expected: u8, actual: u8 },
+
+    #[error("the store was created with another compression than it's opened with")]
+    CompressionMismatch,
+
+    #[error("snapshot is malformed or doesn't match its hash")]
+    SnapshotInvalid,
+
+    #[error("snapshot signature doesn't verify")]
+    SnapshotSignatureInvalid,
+
+    #[error("dump is malformed at line {line}: {reason}")]
+    DumpInvalid { line: usize, reason: String },
+
+    #[error("unsupported dump version: {0}")]
+    DumpVersionUnsupported(u32),
+
+    #[error("invalid environment url: {0}")]
+    UrlInvalid(String),
+
+    #[error("unknown environment url parameters: {0:?}")]
+    UrlParametersUnknown(Vec<String>),
+
+    #[error("key file {0:?} doesn't hold a 32-byte key")]
+    KeyFileInvalid(PathBuf),
 }
 
 impl StoreError {
@@ -115,6 +194,9 @@ pub enum CloseError {
     #[error("close attempted while an environment not known to the manager is still open")]
     UnknownEnvironmentStillOpen,
 
+    #[error("some environments couldn't be closed: {0:?}")]
+    EnvironmentsNotClosed(Vec<(PathBuf, CloseError)>),
+
     #[error("I/O error: {0:?}")]
     IoError(#[from] io::Error),
 }
@@ -141,6 +223,9 @@ pub enum MigrateError {
 
     #[error("destination is not empty")]
     DestinationNotEmpty,
+
+    #[error("destination store {0:?} doesn't match its source")]
+    VerificationFailed(Option<String>),
 }
 
 impl<T> From<sync::PoisonError<T>> for MigrateError {
@@ -0,0 +1,314 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! A feed of the changes made by committed write transactions, see `Rkv::subscribe`.
+//!
+//! Writers record their puts, deletes and clears as they're made, and hand them to the
+//! feed when they're committed. Aborted writers, and writers that fail to commit, never
+//! reach the feed. Each subscriber receives one batch per committed transaction that
+//! changed its store, holding the changes that passed its filter:
+//!
+//! * batches are received in the order their transactions were committed;
+//! * changes within a batch are in the order they were made within their transaction;
+//! * a batch is sent once its transaction is committed, so a reader opened after
+//!   receiving it sees its changes. The transaction is only durable by then if the
+//!   environment syncs its commits, which it doesn't with `NO_SYNC`, `MAP_ASYNC` or,
+//!   for the last commits, `NO_META_SYNC`.
+//!
+//! Writers only record changes if there was a subscriber when they were created, so a
+//! new subscription starts with the first transaction created after it. Subscriptions
+//! are dropped by dropping their receiver.
+//!
+//! Writers take a ticket just before committing, while LMDB still holds the write lock
+//! of their transaction, so tickets are taken in commit order, and batches are sent in
+//! ticket order. Neither committing nor running the filters holds a lock of the feed, so
+//! filters may subscribe, but they mustn't commit writers of their own.
+
+use std::{
+    collections::BTreeSet,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use log::warn;
+
+use crate::{
+    backend::{BackendDatabase, BackendRwTransaction},
+    changelog::{self, Mutation},
+    error::StoreError,
+};
+
+/// The kind of change made to a key.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Op {
+    Put,
+    Delete,
+    /// The whole store was cleared, in which case the key is empty.
+    Clear,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Change<D> {
+    pub db: D,
+    pub key: Vec<u8>,
+    pub op: Op,
+}
+
+/// Receives a batch of changes for each committed transaction, see `Rkv::subscribe`.
+pub type Subscription<D> = Receiver<Vec<Change<D>>>;
+
+type Filter<D> = Box<dyn Fn(&Change<D>) -> bool + Send>;
+
+struct Subscriber<D> {
+    db: D,
+    filter: Filter<D>,
+    sender: Sender<Vec<Change<D>>>,
+}
+
+// Subscribers are shared with the writer sending them a batch, which doesn't lock the
+// list of subscribers while doing so.
+type SharedSubscriber<D> = Arc<Mutex<Subscriber<D>>>;
+
+// The tickets taken by writers, in the order they're committed, and those whose writers
+// are done sending their batches, or failed to commit.
+#[derive(Default)]
+struct Tickets {
+    issued: u64,
+    next: u64,
+    done: BTreeSet<u64>,
+}
+
+// The feed's state stays consistent when a thread panics while holding one of its locks,
+// since every change to it is made at once, so a poisoned lock is used all the same.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Where the changes made by an environment's writers go: to its subscribers, and to
+/// its change log if it's enabled, see the `changelog` module.
+pub(crate) struct Feed<D> {
+    subscribers: Mutex<Vec<SharedSubscriber<D>>>,
+    tickets: Mutex<Tickets>,
+    turn: Condvar,
+    // The names the environment's databases were opened by, which the log refers to
+    // them by.
+    names: Mutex<Vec<(D, Option<String>)>>,
+    log: Mutex<Option<D>>,
+}
+
+impl<D> Feed<D>
+where
+    D: BackendDatabase,
+{
+    pub(crate) fn new() -> Feed<D> {
+        Feed {
+            subscribers: Mutex::new(vec![]),
+            tickets: Mutex::new(Tickets::default()),
+            turn: Condvar::new(),
+            names: Mutex::new(vec![]),
+            log: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn subscribe<F>(&self, db: D, filter: F) -> Subscription<D>
+    where
+        F: Fn(&Change<D>) -> bool + Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        lock(&self.subscribers).push(Arc::new(Mutex::new(Subscriber {
+            db,
+            filter: Box::new(filter),
+            sender,
+        })));
+        receiver
+    }
+
+    pub(crate) fn name(&self, db: D, name: Option<&str>) {
+        let mut names = lock(&self.names);
+        if !names.iter().any(|(d, _)| *d == db) {
+            names.push((db, name.map(str::to_owned)));
+        }
+    }
+
+    /// The name a database was opened by, if it was opened by the environment.
+    pub(crate) fn name_of(&self, db: D) -> Option<Option<String>> {
+        let names = lock(&self.names);
+        names
+            .iter()
+            .find(|(d, _)| *d == db)
+            .map(|(_, name)| name.clone())
+    }
+
+    /// The database opened by a name, if the environment opened one by it.
+    pub(crate) fn named(&self, name: Option<&str>) -> Option<D> {
+        let names = lock(&self.names);
+        names
+            .iter()
+            .find(|(_, n)| n.as_deref() == name)
+            .map(|(db, _)| *db)
+    }
+
+    pub(crate) fn enable_log(&self, db: D) {
+        *lock(&self.log) = Some(db);
+    }
+
+    pub(crate) fn log(&self) -> Option<D> {
+        *lock(&self.log)
+    }
+
+    /// Start recording the changes of a new writer, unless they'd go nowhere.
+    pub(crate) fn recorder(self: &Arc<Self>) -> Option<Recorder<D>> {
+        let log = self.log();
+        if log.is_none() && lock(&self.subscribers).is_empty() {
+            return None;
+        }
+        Some(Recorder {
+            feed: self.clone(),
+            log,
+            changes: vec![],
+        })
+    }
+
+    fn ticket(&self) -> Ticket<'_, D> {
+        let mut tickets = lock(&self.tickets);
+        let number = tickets.issued;
+        tickets.issued += 1;
+        Ticket { feed: self, number }
+    }
+}
+
+// A writer's place in the order batches are sent in, which lets the next writers send
+// theirs once it's dropped.
+struct Ticket<'f, D> {
+    feed: &'f Feed<D>,
+    number: u64,
+}
+
+impl<'f, D> Ticket<'f, D> {
+    // Block until the writers which took earlier tickets are done.
+    fn wait(&self) {
+        let mut tickets = lock(&self.feed.tickets);
+        while tickets.next != self.number {
+            tickets = self
+                .feed
+                .turn
+                .wait(tickets)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+impl<'f, D> Drop for Ticket<'f, D> {
+    fn drop(&mut self) {
+        let mut guard = lock(&self.feed.tickets);
+        let tickets = &mut *guard;
+        tickets.done.insert(self.number);
+        while tickets.done.remove(&tickets.next) {
+            tickets.next += 1;
+        }
+        self.feed.turn.notify_all();
+    }
+}
+
+/// Records the changes made by a writer, along with their values if they're logged.
+pub(crate) struct Recorder<D> {
+    feed: Arc<Feed<D>>,
+    log: Option<D>,
+    changes: Vec<(Change<D>, Option<Vec<u8>>)>,
+}
+
+impl<D> Recorder<D>
+where
+    D: BackendDatabase,
+{
+    pub(crate) fn record(&mut self, db: D, key: &[u8], op: Op, value: Option<&[u8]>) {
+        if self.log == Some(db) {
+            return;
+        }
+        let value = self.log.and(value).map(<[u8]>::to_vec);
+        let change = Change {
+            db,
+            key: key.to_vec(),
+            op,
+        };
+        self.changes.push((change, value));
+    }
+
+    fn mutations(&self) -> Vec<Mutation> {
+        let names = lock(&self.feed.names);
+        let mut mutations = vec![];
+        for (change, value) in &self.changes {
+            let store = match names.iter().find(|(db, _)| *db == change.db) {
+                Some((_, name)) => name.clone(),
+                None => {
+                    warn!(
+                        "Not logging a change to an unnamed database: {:?}",
+                        change.db
+                    );
+                    continue;
+                }
+            };
+            let key = change.key.clone();
+            let value = value.clone();
+            mutations.push(match change.op {
+                Op::Put => Mutation::Put {
+                    store,
+                    key,
+                    value: value.unwrap_or_default(),
+                },
+                Op::Delete => Mutation::Delete { store, key, value },
+                Op::Clear => Mutation::Clear { store },
+            });
+        }
+        mutations
+    }
+
+    /// Log the changes within the writer's transaction, commit it and, if that
+    /// succeeds, send them to the subscribers, once the writers committed before it are
+    /// done sending theirs.
+    pub(crate) fn commit<T>(self, mut txn: T) -> Result<(), StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        if let Some(log) = self.log {
+            let mutations = self.mutations();
+            if !mutations.is_empty() {
+                changelog::append(&mut txn, &log, &mutations)?;
+            }
+        }
+        let ticket = self.feed.ticket();
+        txn.commit().map_err(|e| e.into())?;
+        ticket.wait();
+        let subscribers = lock(&self.feed.subscribers).clone();
+        let mut dropped = vec![];
+        for shared in subscribers {
+            let subscriber = lock(&shared);
+            let batch: Vec<_> = self
+                .changes
+                .iter()
+                .map(|(change, _)| change)
+                .filter(|change| change.db == subscriber.db && (subscriber.filter)(change))
+                .cloned()
+                .collect();
+            // A subscriber is dropped once its receiver is.
+            if !batch.is_empty() && subscriber.sender.send(batch).is_err() {
+                drop(subscriber);
+                dropped.push(shared);
+            }
+        }
+        if !dropped.is_empty() {
+            lock(&self.feed.subscribers)
+                .retain(|shared| !dropped.iter().any(|d| Arc::ptr_eq(d, shared)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Group commit: many small writes from concurrent callers, coalesced into fewer write
+//! transactions, so that they share the cost of committing, see `Rkv::group_commit`.
+//!
+//! Callers submit closures which make changes through a `Writer`, and get a `Pending`
+//! result for each. A committer thread runs the closures submitted within an interval
+//! of the first one in a single transaction:
+//!
+//! * closures are run in the order they were submitted;
+//! * if a closure fails, the transaction is aborted, the failure is the result of that
+//!   submission, and the rest of the batch is run again in a new transaction;
+//! * if the transaction fails to commit, each closure of the batch is run again in a
+//!   transaction of its own, so that each gets the error it caused.
+//!
+//! Closures may thus be called more than once, and should only change the writer.
+//! Results are available once the transaction holding their changes is committed.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    slice,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Condvar, Mutex, RwLock,
+    },
+    task::{Context, Poll, Waker},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{backend::BackendEnvironment, error::StoreError, readwrite::Writer, Rkv};
+
+/// The writer that submitted closures get, for an environment of type `E`.
+pub type GroupWriter<'e, E> = Writer<<E as BackendEnvironment<'e>>::RwTransaction>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// How long to wait for more submissions after the first one of a batch.
+    pub interval: Duration,
+    /// The largest number of submissions in a batch, which is committed as soon as it's
+    /// this large.
+    pub max_batch: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            interval: Duration::from_millis(10),
+            max_batch: 1000,
+        }
+    }
+}
+
+/// A submission whose result is unknown until its batch is committed.
+trait Job<E>: Send
+where
+    E: for<'e> BackendEnvironment<'e>,
+{
+    fn run<'e>(&mut self, writer: &mut GroupWriter<'e, E>) -> Result<(), StoreError>;
+
+    fn complete(self: Box<Self>, result: Result<(), StoreError>);
+}
+
+struct Submission<F, R> {
+    f: F,
+    value: Option<R>,
+    shared: Arc<Shared<R>>,
+}
+
+impl<E, F, R> Job<E> for Submission<F, R>
+where
+    E: for<'e> BackendEnvironment<'e>,
+    F: for<'e> FnMut(&mut GroupWriter<'e, E>) -> Result<R, StoreError> + Send,
+    R: Send,
+{
+    fn run<'e>(&mut self, writer: &mut GroupWriter<'e, E>) -> Result<(), StoreError> {
+        self.value = Some((self.f)(writer)?);
+        Ok(())
+    }
+
+    fn complete(mut self: Box<Self>, result: Result<(), StoreError>) {
+        let result = result.map(|()| self.value.take().expect("value of a successful run"));
+        self.shared.set(result);
+    }
+}
+
+impl<F, R> Drop for Submission<F, R> {
+    fn drop(&mut self) {
+        // Only does something if the committer stopped before completing the submission,
+        // e.g. because a closure panicked.
+        self.shared.set(Err(StoreError::GroupCommitStopped));
+    }
+}
+
+struct State<R> {
+    result: Option<Result<R, StoreError>>,
+    done: bool,
+    waker: Option<Waker>,
+}
+
+struct Shared<R> {
+    state: Mutex<State<R>>,
+    ready: Condvar,
+}
+
+impl<R> Shared<R> {
+    fn new() -> Shared<R> {
+        Shared {
+            state: Mutex::new(State {
+                result: None,
+                done: false,
+                waker: None,
+            }),
+            ready: Condvar::new(),
+        }
+    }
+
+    // Sets the result, unless it was already set.
+    fn set(&self, result: Result<R, StoreError>) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if state.done {
+            return;
+        }
+        state.result = Some(result);
+        state.done = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.ready.notify_all();
+    }
+}
+
+/// The result of a submission, once its changes are committed. It can be waited for, or
+/// awaited as a `Future`.
+pub struct Pending<R> {
+    shared: Arc<Shared<R>>,
+}
+
+impl<R> Pending<R> {
+    /// Block until the result is available.
+    pub fn wait(self) -> Result<R, StoreError> {
+        let poisoned = |_| StoreError::GroupCommitPoisoned;
+        let mut state = self.shared.state.lock().map_err(poisoned)?;
+        while !state.done {
+            state = self.shared.ready.wait(state).map_err(poisoned)?;
+        }
+        state
+            .result
+            .take()
+            .unwrap_or(Err(StoreError::GroupCommitStopped))
+    }
+}
+
+impl<R> Future for Pending<R> {
+    type Output = Result<R, StoreError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut state = match self.shared.state.lock() {
+            Ok(state) => state,
+            Err(_) => return Poll::Ready(Err(StoreError::GroupCommitPoisoned)),
+        };
+        if state.done {
+            Poll::Ready(
+                state
+                    .result
+                    .take()
+                    .unwrap_or(Err(StoreError::GroupCommitStopped)),
+            )
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+type Sent<E> = Box<dyn Job<E>>;
+
+/// Coalesces the changes submitted to it into as few write transactions as possible, see
+/// the module documentation. Dropping it commits the pending submissions, and stops its
+/// committer thread.
+pub struct GroupCommitter<E>
+where
+    E: for<'e> BackendEnvironment<'e>,
+{
+    sender: Mutex<Option<Sender<Sent<E>>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<E> GroupCommitter<E>
+where
+    E: for<'e> BackendEnvironment<'e> + Send + Sync + 'static,
+{
+    pub(crate) fn spawn(rkv: Arc<RwLock<Rkv<E>>>, options: Options) -> GroupCommitter<E> {
+        let (sender, receiver) = channel();
+        let thread = thread::spawn(move || commit_batches(&rkv, &receiver, options));
+        GroupCommitter {
+            sender: Mutex::new(Some(sender)),
+            thread: Some(thread),
+        }
+    }
+
+    /// Submit a closure to run in the write transaction of an upcoming batch. It may be
+    /// run more than once, see the module documentation.
+    pub fn submit<F, R>(&self, f: F) -> Pending<R>
+    where
+        F: for<'e> FnMut(&mut GroupWriter<'e, E>) -> Result<R, StoreError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let shared = Arc::new(Shared::new());
+        let submission = Box::new(Submission {
+            f,
+            value: None,
+            shared: shared.clone(),
+        });
+        // If the committer stopped, the submission is dropped, and fails.
+        if let Ok(sender) = self.sender.lock() {
+            if let Some(sender) = sender.as_ref() {
+                let _ = sender.send(submission);
+            }
+        }
+        Pending { shared }
+    }
+}
+
+impl<E> Drop for GroupCommitter<E>
+where
+    E: for<'e> BackendEnvironment<'e>,
+{
+    fn drop(&mut self) {
+        if let Ok(mut sender) = self.sender.lock() {
+            sender.take();
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn commit_batches<E>(rkv: &RwLock<Rkv<E>>, receiver: &Receiver<Sent<E>>, options: Options)
+where
+    E: for<'e> BackendEnvironment<'e>,
+{
+    // Stops once the committer is dropped, and all the submissions are committed.
+    while let Ok(first) = receiver.recv() {
+        let mut jobs = vec![first];
+        let deadline = Instant::now() + options.interval;
+        while jobs.len() < options.max_batch {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match receiver.recv_timeout(timeout) {
+                Ok(job) => jobs.push(job),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        match rkv.read() {
+            Ok(rkv) => commit_batch(&rkv, jobs),
+            Err(_) => {
+                for job in jobs {
+                    job.complete(Err(StoreError::EnvironmentPoisoned));
+                }
+            }
+        }
+    }
+}
+
+fn commit_batch<E>(rkv: &Rkv<E>, mut jobs: Vec<Sent<E>>)
+where
+    E: for<'e> BackendEnvironment<'e>,
+{
+    while !jobs.is_empty() {
+        match run(rkv, &mut jobs) {
+            Ok(()) => {
+                for job in jobs.drain(..) {
+                    job.complete(Ok(()));
+                }
+            }
+            Err((Some(index), e)) => jobs.remove(index).complete(Err(e)),
+            Err((None, e)) if jobs.len() == 1 => jobs.remove(0).complete(Err(e)),
+            Err((None, _)) => {
+                for mut job in jobs.drain(..) {
+                    let result = run(rkv, slice::from_mut(&mut job)).map_err(|(_, e)| e);
+                    job.complete(result);
+                }
+            }
+        }
+    }
+}
+
+// Runs the jobs in one transaction, which is committed if they all succeed. Otherwise,
+// returns the error, along with the index of the job that failed, unless it was the
+// transaction itself.
+fn run<E>(rkv: &Rkv<E>, jobs: &mut [Sent<E>]) -> Result<(), (Option<usize>, StoreError)>
+where
+    E: for<'e> BackendEnvironment<'e>,
+{
+    let mut writer = rkv.write().map_err(|e| (None, e))?;
+    for (index, job) in jobs.iter_mut().enumerate() {
+        if let Err(e) = job.run(&mut writer) {
+            writer.abort();
+            return Err((Some(index), e));
+        }
+    }
+    writer.commit().map_err(|e| (None, e))
+}
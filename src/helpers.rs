@@ -9,18 +9,37 @@
 // specific language governing permissions and limitations under the License.
 
 use std::{
+    borrow::Cow,
     io,
     path::{Path, PathBuf},
 };
 
 use url::Url;
 
-use crate::{error::StoreError, value::Value};
+use crate::{
+    error::StoreError,
+    value::{Compression, Encoding, Value},
+};
 
-pub(crate) fn read_transform(value: Result<&[u8], StoreError>) -> Result<Value, StoreError> {
-    match value {
-        Ok(bytes) => Value::from_tagged_slice(bytes).map_err(StoreError::DataError),
-        Err(e) => Err(e),
+/// Stores hand back the bytes they hold as a `Value::Blob`, decompressed if the store
+/// compresses them, unless they were opened with an encoding that is decoded on read.
+/// `Encoding::Bincode` values aren't, as untagged blobs could be mistaken for them.
+pub(crate) fn read_transform(
+    value: Result<&[u8], StoreError>,
+    encoding: Encoding,
+    compression: Compression,
+) -> Result<Value, StoreError> {
+    match (value, encoding) {
+        (Ok(bytes), Encoding::Bincode) if compression != Compression::None => {
+            Value::decompress(bytes)
+                .map(Value::Blob)
+                .map_err(StoreError::DataError)
+        }
+        (Ok(bytes), Encoding::Bincode) => Ok(Value::Blob(Cow::Borrowed(bytes))),
+        (Ok(bytes), Encoding::Ordered) => {
+            Value::from_tagged_slice(bytes).map_err(StoreError::DataError)
+        }
+        (Err(e), _) => Err(e),
     }
 }
 
@@ -0,0 +1,186 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Hybrid logical clocks, which timestamp the writes to the CRDT stores, `LwwStore` and
+//! `OrSetStore`.
+//!
+//! A timestamp is the wall clock time in milliseconds, a logical counter which orders
+//! the timestamps taken within the same millisecond, and the id of the replica that took
+//! it, which orders the timestamps of concurrent writes. Each replica must have its own
+//! id, and should `observe` the timestamps of the entries it merges from others, so that
+//! its later writes are ordered after them even if its wall clock is behind theirs.
+
+use std::{
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::error::StoreError;
+
+pub type ReplicaId = u64;
+
+/// The length of an encoded `Timestamp`.
+pub const TIMESTAMP_LEN: usize = 20;
+
+#[derive(Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
+pub struct Timestamp {
+    pub wall: u64,
+    pub logical: u32,
+    pub replica: ReplicaId,
+}
+
+impl Timestamp {
+    /// Encode the timestamp such that encoded timestamps sort as they compare.
+    pub fn to_bytes(&self) -> [u8; TIMESTAMP_LEN] {
+        let mut bytes = [0; TIMESTAMP_LEN];
+        bytes[..8].copy_from_slice(&self.wall.to_be_bytes());
+        bytes[8..12].copy_from_slice(&self.logical.to_be_bytes());
+        bytes[12..].copy_from_slice(&self.replica.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Timestamp, StoreError> {
+        if bytes.len() != TIMESTAMP_LEN {
+            return Err(StoreError::DatabaseCorrupted);
+        }
+        let mut wall = [0; 8];
+        let mut logical = [0; 4];
+        let mut replica = [0; 8];
+        wall.copy_from_slice(&bytes[..8]);
+        logical.copy_from_slice(&bytes[8..12]);
+        replica.copy_from_slice(&bytes[12..]);
+        Ok(Timestamp {
+            wall: u64::from_be_bytes(wall),
+            logical: u32::from_be_bytes(logical),
+            replica: u64::from_be_bytes(replica),
+        })
+    }
+}
+
+/// Hands out the timestamps of a replica's writes, each greater than those it handed out
+/// or observed before.
+#[derive(Debug)]
+pub struct Clock {
+    replica: ReplicaId,
+    last: Mutex<(u64, u32)>,
+}
+
+impl Clock {
+    pub fn new(replica: ReplicaId) -> Clock {
+        Clock {
+            replica,
+            last: Mutex::new((0, 0)),
+        }
+    }
+
+    pub fn replica(&self) -> ReplicaId {
+        self.replica
+    }
+
+    pub fn now(&self) -> Timestamp {
+        let mut last = self.last.lock().unwrap();
+        let wall = physical_now();
+        *last = if wall > last.0 {
+            (wall, 0)
+        } else {
+            tick(last.0, last.1)
+        };
+        Timestamp {
+            wall: last.0,
+            logical: last.1,
+            replica: self.replica,
+        }
+    }
+
+    /// Move the clock past a timestamp taken by another replica.
+    pub fn observe(&self, remote: &Timestamp) {
+        let mut last = self.last.lock().unwrap();
+        let wall = physical_now();
+        let remote = (remote.wall, remote.logical);
+        if wall > last.0 && wall > remote.0 {
+            *last = (wall, 0);
+        } else if remote > *last {
+            *last = tick(remote.0, remote.1);
+        } else {
+            *last = tick(last.0, last.1);
+        }
+    }
+}
+
+fn physical_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+// Counters only overflow if a replica was handed a forged timestamp, in which case the
+// clock moves on to the next millisecond.
+fn tick(wall: u64, logical: u32) -> (u64, u32) {
+    match logical.checked_add(1) {
+        Some(logical) => (wall, logical),
+        None => (wall + 1, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_is_monotonic() {
+        let clock = Clock::new(1);
+        let mut last = clock.now();
+        for _ in 0..1000 {
+            let now = clock.now();
+            assert!(now > last);
+            assert_eq!(now.replica, 1);
+            last = now;
+        }
+    }
+
+    #[test]
+    fn test_clock_observe() {
+        let clock = Clock::new(1);
+        let remote = Timestamp {
+            wall: physical_now() + 60_000,
+            logical: 7,
+            replica: 2,
+        };
+        clock.observe(&remote);
+        let now = clock.now();
+        assert_eq!((now.wall, now.logical), (remote.wall, 9));
+
+        let forged = Timestamp {
+            wall: remote.wall,
+            logical: u32::MAX,
+            replica: 2,
+        };
+        clock.observe(&forged);
+        assert!(clock.now() > forged);
+    }
+
+    #[test]
+    fn test_timestamp_bytes_sort() {
+        let a = Timestamp {
+            wall: 1,
+            logical: u32::MAX,
+            replica: 9,
+        };
+        let b = Timestamp {
+            wall: 2,
+            logical: 0,
+            replica: 1,
+        };
+        assert!(a < b);
+        assert!(a.to_bytes() < b.to_bytes());
+        assert_eq!(Timestamp::from_bytes(&b.to_bytes()).expect("decoded"), b);
+        assert!(Timestamp::from_bytes(&[0; 3]).is_err());
+    }
+}
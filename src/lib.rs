@@ -47,7 +47,7 @@
 //! ```
 //! use rkv::{Manager, Rkv, SingleStore, Value, StoreOptions};
 //! use rkv::backend::{SafeMode, SafeModeEnvironment};
-//! use std::fs;
+//! use std::{borrow::Cow, fs};
 //! use tempfile::Builder;
 //!
 //! // First determine the path to the environment, which is represented on disk as a
@@ -88,8 +88,8 @@
 //!     store.put(&mut writer, "instant", &Value::Instant(1528318073700)).unwrap();
 //!     store.put(&mut writer, "boolean", &Value::Bool(true)).unwrap();
 //!     store.put(&mut writer, "string", &Value::Str("Héllo, wörld!")).unwrap();
-//!     store.put(&mut writer, "json", &Value::Json(r#"{"foo":"bar", "number": 1}"#)).unwrap();
-//!     store.put(&mut writer, "blob", &Value::Blob(b"blob")).unwrap();
+//!     store.put(&mut writer, "json", &Value::Json(Cow::Borrowed(r#"{"foo":"bar", "number": 1}"#))).unwrap();
+//!     store.put(&mut writer, "blob", &Value::Blob(Cow::Borrowed(b"blob"))).unwrap();
 //!
 //!     // You must commit a write transaction before the writer goes out of scope, or the
 //!     // transaction will abort and the data won't persist.
@@ -209,23 +209,39 @@ mod manager;
 mod readwrite;
 
 pub mod backend;
+pub mod changelog;
+pub mod config;
+pub mod diff;
+pub mod dump;
+pub mod feed;
+pub mod group_commit;
+pub mod hlc;
 #[cfg(feature = "lmdb")]
 pub mod migrator;
+pub mod snapshot;
 pub mod store;
 pub mod value;
 
 pub use backend::{DatabaseFlags, EnvironmentFlags, WriteFlags};
-pub use env::Rkv;
+pub use changelog::{ChangeSet, Mutation};
+pub use config::EnvironmentConfig;
+pub use diff::{Diff, Difference};
+pub use env::{Key, RetryOptions, Rkv};
 pub use error::{DataError, MigrateError, StoreError};
+pub use feed::{Change, Op as ChangeOp, Subscription};
 pub use manager::Manager;
 #[cfg(feature = "lmdb")]
 pub use migrator::Migrator;
-pub use readwrite::{Readable, Reader, Writer};
-pub use store::{keys::EncodableKey, single::SingleStore, CloseOptions, Options as StoreOptions};
-pub use value::{OwnedValue, Value};
+pub use readwrite::{InactiveReader, Readable, Reader, SharedReader, Writer};
+pub use snapshot::SignedSnapshot;
+pub use store::{
+    blob::BlobStore, keys::EncodableKey, lww::LwwStore, merkle::MerkleStore, single::SingleStore,
+    typed::TypedStore, CloseOptions, KeyRange, Options as StoreOptions, Store,
+};
+pub use value::{Compression as ValueCompression, Encoding as ValueEncoding, OwnedValue, Value};
 
 #[cfg(feature = "db-dup-sort")]
-pub use store::multi::MultiStore;
+pub use store::{multi::MultiStore, orset::OrSetStore, typed::TypedMultiStore};
 
 #[cfg(feature = "db-int-key")]
 pub use store::integer::IntegerStore;
@@ -10,10 +10,11 @@
 
 use std::{
     collections::{btree_map::Entry, BTreeMap},
+    mem,
     os::raw::c_uint,
     path::{Path, PathBuf},
     result,
-    sync::{Arc, RwLock},
+    sync::{Arc, PoisonError, RwLock},
 };
 
 use lazy_static::lazy_static;
@@ -21,7 +22,7 @@ use lazy_static::lazy_static;
 #[cfg(feature = "lmdb")]
 use crate::backend::LmdbEnvironment;
 use crate::{
-    backend::{BackendEnvironment, BackendEnvironmentBuilder, SafeModeEnvironment},
+    backend::{AnyEnvironment, BackendEnvironment, BackendEnvironmentBuilder, SafeModeEnvironment},
     error::{CloseError, StoreError},
     helpers::canonicalize_path,
     store::CloseOptions,
@@ -42,6 +43,10 @@ lazy_static! {
         RwLock::new(Manager::new());
 }
 
+lazy_static! {
+    static ref MANAGER_ANY: RwLock<Manager<AnyEnvironment>> = RwLock::new(Manager::new());
+}
+
 /// A process is only permitted to have one open handle to each Rkv environment. This
 /// manager exists to enforce that constraint: don't open environments directly.
 ///
@@ -175,6 +180,59 @@ where
             }
         }
     }
+
+    /// Return the paths of the open environments, along with the number of references to
+    /// each. The manager holds one of them, so an environment which nobody else refers to
+    /// has a count of 1.
+    pub fn paths(&self) -> Vec<(PathBuf, usize)> {
+        self.environments
+            .iter()
+            .map(|(path, env)| (path.clone(), Arc::strong_count(env)))
+            .collect()
+    }
+
+    /// Tries to close every environment, carrying on past those that fail. Those which
+    /// other users still refer to, or whose lock is poisoned, are left open and tracked.
+    /// Every failure is returned in a `CloseError::EnvironmentsNotClosed`, along with the
+    /// path of its environment.
+    pub fn close_all(&mut self, options: CloseOptions) -> CloseResult<()> {
+        let mut failures = vec![];
+        for (path, env) in mem::take(&mut self.environments) {
+            let env = if Arc::strong_count(&env) > 1 {
+                Err((env, CloseError::EnvironmentStillOpen))
+            } else if env.is_poisoned() {
+                Err((env, CloseError::ManagerPoisonError))
+            } else {
+                Arc::try_unwrap(env).map_err(|env| (env, CloseError::UnknownEnvironmentStillOpen))
+            };
+            match env {
+                Ok(env) => {
+                    // Nobody else can lock it, and it isn't poisoned.
+                    let env = env.into_inner().unwrap_or_else(PoisonError::into_inner);
+                    if let Err(e) = env.close(options) {
+                        failures.push((path, e));
+                    }
+                }
+                Err((env, e)) => {
+                    self.environments.insert(path.clone(), env);
+                    failures.push((path, e));
+                }
+            }
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(CloseError::EnvironmentsNotClosed(failures))
+        }
+    }
+
+    /// Flushes every environment to disk, as `Rkv::sync(true)` does.
+    pub fn flush_all(&self) -> Result<()> {
+        for env in self.environments.values() {
+            env.read()?.sync(true)?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(feature = "lmdb")]
@@ -190,18 +248,27 @@ impl Manager<SafeModeEnvironment> {
     }
 }
 
+/// Environments of either backend can be managed together by this manager, as long as
+/// they're opened through `backend::Any`.
+impl Manager<AnyEnvironment> {
+    pub fn singleton() -> &'static RwLock<Manager<AnyEnvironment>> {
+        &MANAGER_ANY
+    }
+}
+
 #[allow(unused_imports)]
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::*;
 
-    use std::fs;
+    use std::{fs, thread};
 
     use tempfile::Builder;
 
     #[cfg(feature = "lmdb")]
     use backend::Lmdb;
+    use backend::SafeMode;
 
     /// Test that one can mutate managed Rkv instances in surprising ways.
     #[cfg(feature = "lmdb")]
@@ -245,4 +312,86 @@ mod tests {
             .expect("success");
         assert!(!Arc::ptr_eq(&path2_arc, &arc));
     }
+
+    /// Test that the manager enumerates, flushes and closes all its environments.
+    #[test]
+    fn test_close_all_safe() {
+        let mut manager = Manager::<SafeModeEnvironment>::new();
+
+        let root = Builder::new()
+            .prefix("test_close_all_safe")
+            .tempdir()
+            .expect("tempdir");
+        let path1 = root.path().join("1");
+        let path2 = root.path().join("2");
+        fs::create_dir_all(&path1).expect("dir created");
+        fs::create_dir_all(&path2).expect("dir created");
+        let path1 = canonicalize_path(path1.as_path()).expect("canonical");
+        let path2 = canonicalize_path(path2.as_path()).expect("canonical");
+
+        let path3 = root.path().join("3");
+        fs::create_dir_all(&path3).expect("dir created");
+        let path3 = canonicalize_path(path3.as_path()).expect("canonical");
+
+        let arc1 = manager
+            .get_or_create(path1.as_path(), Rkv::new::<SafeMode>)
+            .expect("created");
+        manager
+            .get_or_create(path2.as_path(), Rkv::new::<SafeMode>)
+            .expect("created");
+        assert_eq!(
+            manager.paths(),
+            vec![(path1.clone(), 2), (path2.clone(), 1)]
+        );
+
+        {
+            let k = arc1.read().expect("guard");
+            let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+            let mut writer = k.write().expect("writer");
+            sk.put(&mut writer, "foo", &Value::I64(1234))
+                .expect("wrote");
+            writer.commit().expect("committed");
+        }
+        fs::remove_file(path1.join("data.safe.bin")).expect("removed");
+        manager.flush_all().expect("flushed");
+        assert!(path1.join("data.safe.bin").is_file());
+        assert!(path2.join("data.safe.bin").is_file());
+
+        // A panic while holding an environment's lock poisons it.
+        let arc3 = manager
+            .get_or_create(path3.as_path(), Rkv::new::<SafeMode>)
+            .expect("created");
+        thread::spawn(move || {
+            let _guard = arc3.write().expect("guard");
+            panic!("poisoning the lock");
+        })
+        .join()
+        .expect_err("panicked");
+
+        // Environments which are still referenced, or poisoned, are left open and
+        // reported, and the others are closed regardless.
+        match manager.close_all(CloseOptions::delete_files_on_disk()) {
+            Err(CloseError::EnvironmentsNotClosed(failures)) => {
+                assert_eq!(failures.len(), 2);
+                assert_eq!(failures[0].0, path1);
+                assert!(matches!(failures[0].1, CloseError::EnvironmentStillOpen));
+                assert_eq!(failures[1].0, path3);
+                assert!(matches!(failures[1].1, CloseError::ManagerPoisonError));
+            }
+            result => panic!("expected environments not closed, got {:?}", result),
+        }
+        assert_eq!(
+            manager.paths(),
+            vec![(path1.clone(), 2), (path3.clone(), 1)]
+        );
+        assert!(!path2.join("data.safe.bin").exists());
+
+        drop(arc1);
+        match manager.close_all(CloseOptions::delete_files_on_disk()) {
+            Err(CloseError::EnvironmentsNotClosed(failures)) => assert_eq!(failures.len(), 1),
+            result => panic!("expected environments not closed, got {:?}", result),
+        }
+        assert_eq!(manager.paths(), vec![(path3.clone(), 1)]);
+        assert!(!path1.join("data.safe.bin").exists());
+    }
 }
@@ -16,12 +16,16 @@
 //! see `arch_migrator` if this is needed. However, this utility is ultimately intended to
 //! handle all possible migrations.
 //!
-//! The destination environment should be empty of data, otherwise an error is returned.
+//! By default, the destination environment should be empty of data, otherwise an error is
+//! returned. Migrations can be tuned with `MigrateOptions` instead, to keep or replace the
+//! destination's data, report progress, pick and rename stores, and verify the result.
 //!
-//! There are 3 versions of the migration methods:
+//! There are 4 versions of the migration methods:
 //! * `migrate_<src>_to_<dst>`, where `<src>` and `<dst>` are the source and destination
 //!   environment types. You're responsive with opening both these environments, handling
 //!   all errors, and performing any cleanup if necessary.
+//! * `migrate_<src>_to_<dst>_with_options`, which is the same as the above, but migrates
+//!   as told by a `MigrateOptions`.
 //! * `open_and_migrate_<src>_to_<dst>`, which is similar the the above, but automatically
 //!   attempts to open the source environment and delete all of its supporting files if
 //!   there's no other environment open at that path. You're still responsible with
@@ -31,30 +35,212 @@
 //!   (corrupted), unavailable (path not accessible or incompatible with configuration),
 //!   or empty (database has no records).
 //!
-//! The tool currently has these limitations:
+//! Databases are migrated with their `DatabaseFlags::DUP_SORT` and
+//! `DatabaseFlags::INTEGER_KEY` flags, which both backends support, so every store type
+//! can be migrated: all the values of a key are carried over, and integer keys keep
+//! their encoding and the ordering it implies. Entries are copied as they're encoded,
+//! so compressed values, and values of any `ValueEncoding`, are migrated as is.
 //!
-//! 1. It doesn't support migration from environments created with
-//!    `EnvironmentFlags::NO_SUB_DIR`. To migrate such an environment, create a temporary
-//!    directory, copy the environment's data files in the temporary directory, then
-//!    migrate the temporary directory as the source environment.
-//! 2. It doesn't support migration from databases created with DatabaseFlags::DUP_SORT`
-//!    (with or without `DatabaseFlags::DUP_FIXED`) nor with `DatabaseFlags::INTEGER_KEY`.
-//!    This effectively means that migration is limited to `SingleStore`s.
-//! 3. It doesn't allow for existing data in the destination environment, which means that
-//!    it cannot overwrite nor append data.
+//! Environments created with `EnvironmentFlags::NO_SUB_DIR` can be migrated too: the
+//! `open_and_migrate_<src>_to_<dst>` methods open the source with that flag when they're
+//! given the path of a file rather than of a directory.
 
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+#[cfg(any(feature = "db-dup-sort", feature = "db-int-key"))]
+use crate::backend::DatabaseFlags;
 use crate::{
-    backend::{LmdbEnvironment, SafeModeEnvironment},
+    backend::{
+        BackendDatabaseFlags, BackendEnvironmentBuilder, BackendFlags, BackendInfo, BackendIter,
+        BackendRoCursor, Lmdb, LmdbEnvironment, SafeModeEnvironment,
+    },
+    env::{Key, DEFAULT_MAX_DBS},
     error::MigrateError,
-    Rkv, StoreOptions,
+    CloseOptions, EnvironmentFlags, Manager, Readable, Rkv, StoreError, StoreOptions,
 };
 
 pub use crate::backend::{LmdbArchMigrateError, LmdbArchMigrateResult, LmdbArchMigrator};
 
+/// The flags of a database of the destination environment, as the source database was
+/// created with.
+#[allow(unused_variables)]
+fn migrate_flags<S, D>(src: &S) -> D
+where
+    S: BackendDatabaseFlags,
+    D: BackendDatabaseFlags,
+{
+    #[allow(unused_mut)]
+    let mut dst = D::empty();
+    #[cfg(feature = "db-dup-sort")]
+    dst.set(
+        DatabaseFlags::DUP_SORT,
+        src.contains(DatabaseFlags::DUP_SORT),
+    );
+    #[cfg(feature = "db-int-key")]
+    dst.set(
+        DatabaseFlags::INTEGER_KEY,
+        src.contains(DatabaseFlags::INTEGER_KEY),
+    );
+    dst
+}
+
+/// How often progress is reported, in entries.
+const PROGRESS_INTERVAL: usize = 1024;
+
+/// Resolves a key's value from the name of the destination store, the key, the
+/// destination's value and the source's, as they're encoded.
+pub type Resolver<'a> = Box<dyn Fn(Option<&str>, &[u8], &[u8], &[u8]) -> Vec<u8> + 'a>;
+
+type Report<'a> = Box<dyn Fn(&Progress) + 'a>;
+
+/// What to do with the keys of a store that the destination environment already holds.
+pub enum Existing<'a> {
+    /// Don't migrate into a destination environment that holds any data, and return
+    /// `MigrateError::DestinationNotEmpty` instead.
+    Fail,
+    /// Replace the destination's values of a key with the source's.
+    Overwrite,
+    /// Keep the destination's values of a key, and skip the source's.
+    Skip,
+    /// Keep the value the resolver returns for a key both hold. Values of dup-sort stores
+    /// aren't resolved, but added to the destination's.
+    Merge(Resolver<'a>),
+}
+
+/// How far a migration went, as reported to a `MigrateOptions::with_progress` callback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress<'s> {
+    /// The name of the source store being migrated.
+    pub store: Option<&'s str>,
+    /// The number of entries of the store migrated so far.
+    pub entries: usize,
+    /// Whether all of the store's entries were migrated.
+    pub done: bool,
+    /// The number of stores migrated before this one, and the number of stores to migrate.
+    pub stores_done: usize,
+    pub stores: usize,
+}
+
+/// Options for `Migrator::migrate_<src>_to_<dst>_with_options`. By default, all the
+/// stores are migrated, into an empty destination environment, without verification.
+pub struct MigrateOptions<'a> {
+    existing: Existing<'a>,
+    stores: Option<BTreeMap<Option<String>, Option<String>>>,
+    progress: Option<Report<'a>>,
+    verify: bool,
+}
+
+impl<'a> Default for MigrateOptions<'a> {
+    fn default() -> MigrateOptions<'a> {
+        MigrateOptions {
+            existing: Existing::Fail,
+            stores: None,
+            progress: None,
+            verify: false,
+        }
+    }
+}
+
+impl<'a> MigrateOptions<'a> {
+    /// What to do with the destination's data. Stores it already holds are migrated into
+    /// as they are, with the flags they were created with.
+    pub fn with_existing(mut self, existing: Existing<'a>) -> MigrateOptions<'a> {
+        self.existing = existing;
+        self
+    }
+
+    /// Migrate the given store, as named in the source environment. Once a store is given,
+    /// only the stores given are migrated.
+    pub fn with_store<'s, T>(self, name: T) -> MigrateOptions<'a>
+    where
+        T: Into<Option<&'s str>>,
+    {
+        let name = name.into();
+        self.with_renamed_store(name, name)
+    }
+
+    /// Migrate the given store into a store named `to` in the destination environment.
+    /// Once a store is given, only the stores given are migrated.
+    pub fn with_renamed_store<'s, T, U>(mut self, from: T, to: U) -> MigrateOptions<'a>
+    where
+        T: Into<Option<&'s str>>,
+        U: Into<Option<&'s str>>,
+    {
+        let from = from.into().map(str::to_owned);
+        let to = to.into().map(str::to_owned);
+        self.stores
+            .get_or_insert_with(BTreeMap::new)
+            .insert(from, to);
+        self
+    }
+
+    /// Report progress to `progress`, every so many entries and once each store is done.
+    pub fn with_progress<F>(mut self, progress: F) -> MigrateOptions<'a>
+    where
+        F: Fn(&Progress) + 'a,
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Check, once the stores are migrated, that each destination store holds as many
+    /// entries as its source store, with the same content hash, or else return
+    /// `MigrateError::VerificationFailed`. This only holds if the destination stores
+    /// didn't hold other keys, or the source's were skipped or merged.
+    pub fn with_verification(mut self, verify: bool) -> MigrateOptions<'a> {
+        self.verify = verify;
+        self
+    }
+
+    /// The names of the stores to migrate, and of the stores to migrate them into.
+    fn stores(&self, names: Vec<Option<String>>) -> Vec<(Option<String>, Option<String>)> {
+        match &self.stores {
+            None => names.into_iter().map(|name| (name.clone(), name)).collect(),
+            Some(stores) => names
+                .into_iter()
+                .filter_map(|name| stores.get(&name).map(|to| (name, to.clone())))
+                .collect(),
+        }
+    }
+
+    fn report(&self, progress: Progress) {
+        if let Some(report) = &self.progress {
+            report(&progress);
+        }
+    }
+}
+
+/// The number of entries of a database and the SHA-256 hash of them, in order.
+fn content<'r, R, I, C>(reader: &'r R, db: &R::Database) -> Result<(usize, [u8; 32]), StoreError>
+where
+    R: Readable<'r, RoCursor = C>,
+    I: BackendIter<'r>,
+    C: BackendRoCursor<'r, Iter = I>,
+{
+    let mut hasher = Sha256::new();
+    let mut count = 0;
+    let mut iter = reader.open_ro_cursor(db)?.into_iter();
+    while let Some(result) = iter.next() {
+        let (key, value) = result.map_err(|e| e.into())?;
+        hasher.update((key.len() as u64).to_be_bytes());
+        hasher.update(key);
+        hasher.update((value.len() as u64).to_be_bytes());
+        hasher.update(value);
+        count += 1;
+    }
+    Ok((count, hasher.finalize().into()))
+}
+
 // FIXME: should parametrize this instead.
 
 macro_rules! fn_migrator {
-    ($name:tt, $src_env:ty, $dst_env:ty) => {
+    ($name:tt, $with_options:tt, $src_env:ty, $dst_env:ty) => {
         /// Migrate all data in all of databases from the source environment to the destination
         /// environment. This includes all key/value pairs in the main database that aren't
         /// metadata about subdatabases and all key/value pairs in all subdatabases.
@@ -68,24 +254,112 @@ macro_rules! fn_migrator {
             S: std::ops::Deref<Target = Rkv<$src_env>>,
             D: std::ops::Deref<Target = Rkv<$dst_env>>,
         {
-            let src_dbs = src_env.get_dbs().unwrap();
-            if src_dbs.is_empty() {
+            Migrator::$with_options(src_env, dst_env, &MigrateOptions::default())
+        }
+
+        /// Same as the `migrate_x_to_y` migration method above, but migrates as told by
+        /// `options`. Each store is migrated within a write transaction of its own.
+        pub fn $with_options<S, D>(
+            src_env: S,
+            dst_env: D,
+            options: &MigrateOptions,
+        ) -> Result<(), MigrateError>
+        where
+            S: std::ops::Deref<Target = Rkv<$src_env>>,
+            D: std::ops::Deref<Target = Rkv<$dst_env>>,
+        {
+            let stores = options.stores(src_env.get_dbs()?);
+            if stores.is_empty() {
                 return Err(MigrateError::SourceEmpty);
             }
-            let dst_dbs = dst_env.get_dbs().unwrap();
-            if !dst_dbs.is_empty() {
-                return Err(MigrateError::DestinationNotEmpty);
+            if let Existing::Fail = options.existing {
+                if !dst_env.get_dbs()?.is_empty() {
+                    return Err(MigrateError::DestinationNotEmpty);
+                }
             }
-            for name in src_dbs {
-                let src_store = src_env.open_single(name.as_deref(), StoreOptions::default())?;
-                let dst_store = dst_env.open_single(name.as_deref(), StoreOptions::create())?;
+            let mut dbs = vec![];
+            for (stores_done, (from, to)) in stores.iter().enumerate() {
+                let src_db = src_env.open(from.as_deref(), StoreOptions::default())?;
                 let reader = src_env.read()?;
+                let dst_db = dst_env
+                    .open(to.as_deref(), StoreOptions::default())
+                    .or_else(|_| {
+                        let opts = StoreOptions {
+                            flags: migrate_flags(&reader.db_flags(&src_db)?),
+                            ..StoreOptions::create()
+                        };
+                        dst_env.open(to.as_deref(), opts)
+                    })?;
+                let mut progress = Progress {
+                    store: from.as_deref(),
+                    entries: 0,
+                    done: false,
+                    stores_done,
+                    stores: stores.len(),
+                };
+
                 let mut writer = dst_env.write()?;
-                let mut iter = src_store.iter_start(&reader)?;
-                while let Some(Ok((key, value))) = iter.next() {
-                    dst_store.put(&mut writer, key, &value).expect("wrote");
+                #[cfg(feature = "db-dup-sort")]
+                let dup_sort = BackendDatabaseFlags::contains(
+                    &writer.db_flags(&dst_db)?,
+                    DatabaseFlags::DUP_SORT,
+                );
+                #[cfg(not(feature = "db-dup-sort"))]
+                let dup_sort = false;
+                // The key whose values are being migrated, and whether they're skipped.
+                let mut current: Option<(Vec<u8>, bool)> = None;
+                let mut iter = reader.open_ro_cursor(&src_db)?.into_iter();
+                while let Some(result) = iter.next() {
+                    let (key, value) = result.map_err(Into::<StoreError>::into)?;
+                    let first = current.as_ref().map_or(true, |(k, _)| &k[..] != key);
+                    if first {
+                        let skip = match options.existing {
+                            Existing::Skip => writer.get_bytes(&dst_db, &key)?.is_some(),
+                            _ => false,
+                        };
+                        current = Some((key.to_vec(), skip));
+                        #[cfg(feature = "db-dup-sort")]
+                        if dup_sort && !skip {
+                            if let Existing::Overwrite = options.existing {
+                                match writer.delete(&dst_db, &key, None) {
+                                    Err(StoreError::KeyValuePairNotFound) => (),
+                                    result => result?,
+                                }
+                            }
+                        }
+                    }
+                    match (
+                        &options.existing,
+                        current.as_ref().map_or(false, |(_, skip)| *skip),
+                    ) {
+                        (_, true) => (),
+                        (Existing::Merge(resolve), false) if !dup_sort => {
+                            let merged = writer
+                                .get_bytes(&dst_db, &key)?
+                                .map(|existing| resolve(to.as_deref(), key, existing, value));
+                            let value = merged.as_deref().unwrap_or(value);
+                            writer.put(&dst_db, &key, value, BackendFlags::empty())?;
+                        }
+                        _ => writer.put(&dst_db, &key, value, BackendFlags::empty())?,
+                    }
+                    progress.entries += 1;
+                    if progress.entries % PROGRESS_INTERVAL == 0 {
+                        options.report(progress);
+                    }
                 }
                 writer.commit()?;
+                progress.done = true;
+                options.report(progress);
+                dbs.push((src_db, dst_db));
+            }
+
+            if options.verify {
+                let (reader, dst_reader) = (src_env.read()?, dst_env.read()?);
+                for ((src_db, dst_db), (_, to)) in dbs.iter().zip(&stores) {
+                    if content(&reader, src_db)? != content(&dst_reader, dst_db)? {
+                        return Err(MigrateError::VerificationFailed(to.clone()));
+                    }
+                }
             }
             Ok(())
         }
@@ -93,8 +367,9 @@ macro_rules! fn_migrator {
 
     (open $migrate:tt, $name:tt, $builder:tt, $src_env:ty, $dst_env:ty) => {
         /// Same as the the `migrate_x_to_y` migration method above, but automatically attempts
-        /// to open the source environment. Finally, deletes all of its supporting files if
-        /// there's no other environment open at that path and the migration succeeded.
+        /// to open the source environment, with `EnvironmentFlags::NO_SUB_DIR` if `path` is a
+        /// file. Finally, deletes all of its supporting files if there's no other environment
+        /// open at that path and the migration succeeded.
         pub fn $name<F, D>(path: &std::path::Path, build: F, dst_env: D) -> Result<(), MigrateError>
         where
             F: FnOnce(crate::backend::$builder) -> crate::backend::$builder,
@@ -105,6 +380,9 @@ macro_rules! fn_migrator {
             let mut manager = crate::Manager::<$src_env>::singleton().write()?;
             let mut builder = Rkv::<$src_env>::environment_builder::<$builder>();
             builder.set_max_dbs(crate::env::DEFAULT_MAX_DBS);
+            if path.is_file() {
+                builder.set_flags(EnvironmentFlags::NO_SUB_DIR);
+            }
             builder = build(builder);
 
             let src_env =
@@ -165,7 +443,7 @@ macro_rules! fns_migrator {
     };
     ($name:tt, $src:tt, $dst:tt) => {
         paste::item! {
-            fn_migrator!($name, [<$src:camel Environment>], [<$dst:camel Environment>]);
+            fn_migrator!($name, [<$name _with_options>], [<$src:camel Environment>], [<$dst:camel Environment>]);
             fn_migrator!(open $name, [<open_and_ $name>], [<$src:camel>], [<$src:camel Environment>], [<$dst:camel Environment>]);
             fn_migrator!(easy [<open_and_ $name>], [<easy_ $name>], [<$src:camel Environment>], [<$dst:camel Environment>]);
         }
@@ -176,4 +454,79 @@ pub struct Migrator;
 
 impl Migrator {
     fns_migrator!(lmdb, safe_mode);
+    fns_migrator!(migrate_lmdb_to_lmdb, lmdb, lmdb);
+
+    /// Migrate the LMDB environment at `path`, encrypted with `src_key` if any, into one
+    /// encrypted with `dst_key` if any, which replaces it at the same path. This encrypts a
+    /// plaintext environment, decrypts an encrypted one, or changes its key.
+    ///
+    /// The environment is migrated into a new one, in a `<path>.rekey` directory next to
+    /// it, then verified. Only then is the source's data file replaced by the new one,
+    /// atomically, so the source is left as it was if anything fails. The environment
+    /// mustn't be open elsewhere, otherwise `CloseError::EnvironmentStillOpen` is returned.
+    pub fn rekey_lmdb(
+        path: &Path,
+        src_key: Option<Key>,
+        dst_key: Option<Key>,
+    ) -> Result<(), MigrateError> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".rekey");
+        let tmp = PathBuf::from(tmp);
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp).map_err(StoreError::from)?;
+        }
+
+        let mut builder = Rkv::environment_builder::<Lmdb>();
+        builder.set_max_dbs(DEFAULT_MAX_DBS);
+        if path.is_file() {
+            builder.set_flags(EnvironmentFlags::NO_SUB_DIR);
+        }
+        if let Some(key) = src_key {
+            builder.set_enc_key(key);
+        }
+        // The manager is only locked to look the environment up and to close it, so that
+        // other environments can be opened and closed while this one is copied.
+        let src_env = Manager::<LmdbEnvironment>::singleton()
+            .write()?
+            .get_or_create_from_builder(path, builder, Rkv::from_builder::<Lmdb>)?;
+        let src_files = src_env.read()?.files_on_disk();
+        let result = Migrator::rekey_into(&*src_env.read()?, &tmp, dst_key);
+
+        drop(src_env);
+        let result = result.and_then(|dst_files| {
+            Manager::<LmdbEnvironment>::singleton()
+                .write()?
+                .try_close(path, CloseOptions::default())?;
+            fs::rename(&dst_files[0], &src_files[0]).map_err(StoreError::from)?;
+            // The source's lockfile, if any, doesn't belong to the new data file.
+            for file in src_files[1..].iter().filter(|file| file.exists()) {
+                fs::remove_file(file).map_err(StoreError::from)?;
+            }
+            Ok(())
+        });
+        // Failing to clean up doesn't hide why rekeying failed.
+        let cleaned = fs::remove_dir_all(&tmp).map_err(StoreError::from);
+        result.and(cleaned.map_err(MigrateError::from))
+    }
+
+    /// Migrate `src_env` into a new environment at `path`, encrypted with `key` if any,
+    /// returning the files of the new environment once it's verified and closed.
+    fn rekey_into(
+        src_env: &Rkv<LmdbEnvironment>,
+        path: &Path,
+        key: Option<Key>,
+    ) -> Result<Vec<PathBuf>, MigrateError> {
+        let mut builder = Rkv::environment_builder::<Lmdb>();
+        builder.set_max_dbs(DEFAULT_MAX_DBS.max(src_env.get_dbs()?.len() as u32));
+        builder.set_map_size(src_env.info()?.map_size());
+        builder.set_make_dir_if_needed(true);
+        if let Some(key) = key {
+            builder.set_enc_key(key);
+        }
+        let dst_env = Rkv::from_builder(path, builder)?;
+        let options = MigrateOptions::default().with_verification(true);
+        Migrator::migrate_lmdb_to_lmdb_with_options(src_env, &dst_env, &options)?;
+        dst_env.sync(true)?;
+        Ok(dst_env.files_on_disk())
+    }
 }
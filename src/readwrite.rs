@@ -8,21 +8,51 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
+use std::{any::Any, sync::Arc};
+
 use crate::{
     backend::{
-        BackendDatabase, BackendRoCursor, BackendRoCursorTransaction, BackendRoTransaction,
-        BackendRwCursor, BackendRwCursorTransaction, BackendRwTransaction, BackendStat,
+        BackendDatabase, BackendDatabaseFlags, BackendInactiveTransaction, BackendRoCursor,
+        BackendRoCursorTransaction, BackendRoTransaction, BackendRwCursor,
+        BackendRwCursorTransaction, BackendRwTransaction, BackendStat,
     },
     error::StoreError,
+    feed::{Op, Recorder},
     helpers::read_transform,
-    value::Value,
+    store::KeyRange,
+    value::{Compression, Encoding, Value},
 };
 
+/// A read transaction, which sees the data as it was committed when the transaction began,
+/// whatever is committed while it's active.
+///
+/// With LMDB, a thread can only have one active reader, unless the environment was opened
+/// with `set_no_tls(true)`; a reader also keeps the pages of its snapshot from being reused,
+/// so long-lived readers make the map grow. With SafeMode, a reader holds its own copy of
+/// each store's contents at the time it began, and a thread can have any number of them.
 pub struct Reader<T>(T);
-pub struct Writer<T>(T);
+
+/// A read transaction which can be cloned, and sent to or shared with other threads, e.g.
+/// the workers of a thread pool, all of which read the same snapshot. It ends when the last
+/// clone is dropped.
+///
+/// It doesn't own the environment: like `Reader`, it borrows the `Rkv` it was created from,
+/// so the threads using it must be scoped, or the `Rkv` must outlive them, e.g. by being
+/// `'static`. With LMDB, the environment must have been opened with `NO_TLS`, and the
+/// threads take turns using the transaction, one call or iterator step at a time.
+pub struct SharedReader<T>(Arc<T>);
+
+/// A read transaction whose snapshot was released by `Reader::reset`, which can be renewed
+/// into a reader of the latest committed data without allocating a new transaction.
+pub struct InactiveReader<T>(T);
+/// A write transaction, along with a `Recorder` of its changes if they're to be
+/// subscribed to or logged. The recorder's type names the transaction's database type,
+/// which would make writers invariant over their transaction's lifetime, so it's erased.
+pub struct Writer<T>(T, Option<Box<dyn Any + Send + Sync>>);
 
 pub trait Readable<'r> {
     type Database: BackendDatabase;
+    type DatabaseFlags: BackendDatabaseFlags;
     type RoCursor: BackendRoCursor<'r>;
     type RwCursor: BackendRwCursor<'r>;
     type Stat: BackendStat;
@@ -36,6 +66,9 @@ pub trait Readable<'r> {
     fn open_ro_dup_cursor(&'r self, db: &Self::Database) -> Result<Self::RwCursor, StoreError>;
 
     fn stat(&'r self, db: &Self::Database) -> Result<Self::Stat, StoreError>;
+
+    /// The flags a database was created with.
+    fn db_flags(&'r self, db: &Self::Database) -> Result<Self::DatabaseFlags, StoreError>;
 }
 
 impl<'r, T> Readable<'r> for Reader<T>
@@ -43,6 +76,7 @@ where
     T: BackendRoCursorTransaction<'r>,
 {
     type Database = T::Database;
+    type DatabaseFlags = T::DatabaseFlags;
     type RoCursor = T::RoCursor;
     type RwCursor = T::RwCursor;
     type Stat = T::Stat;
@@ -52,7 +86,7 @@ where
         K: AsRef<[u8]>,
     {
         let bytes = self.0.get(db, k.as_ref()).map_err(|e| e.into());
-        match read_transform(bytes).map(Some) {
+        match read_transform(bytes, Encoding::Bincode, Compression::None).map(Some) {
             Err(StoreError::KeyValuePairNotFound) => Ok(None),
             result => result,
         }
@@ -69,6 +103,10 @@ where
     fn stat(&'r self, db: &Self::Database) -> Result<Self::Stat, StoreError> {
         self.0.stat(db).map_err(|e| e.into())
     }
+
+    fn db_flags(&'r self, db: &Self::Database) -> Result<Self::DatabaseFlags, StoreError> {
+        self.0.db_flags(db).map_err(|e| e.into())
+    }
 }
 
 impl<T> Reader<T> {
@@ -77,6 +115,56 @@ impl<T> Reader<T> {
     }
 }
 
+impl<'r, T> Readable<'r> for SharedReader<T>
+where
+    T: BackendRoCursorTransaction<'r>,
+{
+    type Database = T::Database;
+    type DatabaseFlags = T::DatabaseFlags;
+    type RoCursor = T::RoCursor;
+    type RwCursor = T::RwCursor;
+    type Stat = T::Stat;
+
+    fn get<K>(&'r self, db: &T::Database, k: &K) -> Result<Option<Value<'r>>, StoreError>
+    where
+        K: AsRef<[u8]>,
+    {
+        let bytes = self.0.get(db, k.as_ref()).map_err(|e| e.into());
+        match read_transform(bytes, Encoding::Bincode, Compression::None).map(Some) {
+            Err(StoreError::KeyValuePairNotFound) => Ok(None),
+            result => result,
+        }
+    }
+
+    fn open_ro_cursor(&'r self, db: &T::Database) -> Result<T::RoCursor, StoreError> {
+        self.0.open_ro_cursor(db).map_err(|e| e.into())
+    }
+
+    fn open_ro_dup_cursor(&'r self, db: &T::Database) -> Result<T::RwCursor, StoreError> {
+        self.0.open_ro_dup_cursor(db).map_err(|e| e.into())
+    }
+
+    fn stat(&'r self, db: &Self::Database) -> Result<Self::Stat, StoreError> {
+        self.0.stat(db).map_err(|e| e.into())
+    }
+
+    fn db_flags(&'r self, db: &Self::Database) -> Result<Self::DatabaseFlags, StoreError> {
+        self.0.db_flags(db).map_err(|e| e.into())
+    }
+}
+
+impl<T> SharedReader<T> {
+    pub(crate) fn new(txn: T) -> SharedReader<T> {
+        SharedReader(Arc::new(txn))
+    }
+}
+
+impl<T> Clone for SharedReader<T> {
+    fn clone(&self) -> SharedReader<T> {
+        SharedReader(self.0.clone())
+    }
+}
+
 impl<T> Reader<T>
 where
     T: BackendRoTransaction,
@@ -84,6 +172,22 @@ where
     pub fn abort(self) {
         self.0.abort();
     }
+
+    /// Release this reader's snapshot, e.g. between the batches of a long-running job, so
+    /// that it doesn't hold onto data which has since been changed.
+    pub fn reset(self) -> InactiveReader<T::Inactive> {
+        InactiveReader(self.0.reset())
+    }
+}
+
+impl<T> InactiveReader<T>
+where
+    T: BackendInactiveTransaction,
+{
+    /// Begin reading again, from a snapshot of the data committed by now.
+    pub fn renew(self) -> Result<Reader<T::RoTransaction>, StoreError> {
+        self.0.renew().map(Reader).map_err(|e| e.into())
+    }
 }
 
 impl<'r, T> Readable<'r> for Writer<T>
@@ -91,6 +195,7 @@ where
     T: BackendRwCursorTransaction<'r>,
 {
     type Database = T::Database;
+    type DatabaseFlags = T::DatabaseFlags;
     type RoCursor = T::RoCursor;
     type RwCursor = T::RwCursor;
     type Stat = T::Stat;
@@ -100,7 +205,7 @@ where
         K: AsRef<[u8]>,
     {
         let bytes = self.0.get(db, k.as_ref()).map_err(|e| e.into());
-        match read_transform(bytes).map(Some) {
+        match read_transform(bytes, Encoding::Bincode, Compression::None).map(Some) {
             Err(StoreError::KeyValuePairNotFound) => Ok(None),
             result => result,
         }
@@ -117,11 +222,15 @@ where
     fn stat(&'r self, db: &Self::Database) -> Result<Self::Stat, StoreError> {
         self.0.stat(db).map_err(|e| e.into())
     }
+
+    fn db_flags(&'r self, db: &Self::Database) -> Result<Self::DatabaseFlags, StoreError> {
+        self.0.db_flags(db).map_err(|e| e.into())
+    }
 }
 
 impl<T> Writer<T> {
-    pub(crate) fn new(txn: T) -> Writer<T> {
-        Writer(txn)
+    pub(crate) fn new(txn: T, recorder: Option<Box<dyn Any + Send + Sync>>) -> Writer<T> {
+        Writer(txn, recorder)
     }
 }
 
@@ -130,27 +239,59 @@ where
     T: BackendRwTransaction,
 {
     pub fn commit(self) -> Result<(), StoreError> {
-        self.0.commit().map_err(|e| e.into())
+        let Writer(txn, recorder) = self;
+        match recorder.and_then(|r| r.downcast::<Recorder<T::Database>>().ok()) {
+            Some(recorder) => recorder.commit(txn),
+            None => txn.commit().map_err(|e| e.into()),
+        }
+    }
+
+    fn record(&mut self, db: &T::Database, k: &[u8], op: Op, v: Option<&[u8]>) {
+        let recorder = self
+            .1
+            .as_mut()
+            .and_then(|r| r.downcast_mut::<Recorder<T::Database>>());
+        if let Some(recorder) = recorder {
+            recorder.record(*db, k, op, v);
+        }
     }
 
     pub fn abort(self) {
         self.0.abort();
     }
 
+    /// Get the bytes held under a key without opening a cursor, so that, unlike
+    /// `Readable::get`, the writer can still be written to afterwards.
+    pub(crate) fn get_bytes<K>(&self, db: &T::Database, k: &K) -> Result<Option<&[u8]>, StoreError>
+    where
+        K: AsRef<[u8]>,
+    {
+        match self.0.get(db, k.as_ref()).map_err(|e| e.into()) {
+            Err(StoreError::KeyValuePairNotFound) => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    /// The flags a database was created with, like `Readable::db_flags` but for
+    /// writers that don't open cursors.
+    pub(crate) fn db_flags(&self, db: &T::Database) -> Result<T::DatabaseFlags, StoreError> {
+        self.0.db_flags(db).map_err(|e| e.into())
+    }
+
+    /// Put a value that a store already encoded as it holds them.
     pub(crate) fn put<K>(
         &mut self,
         db: &T::Database,
         k: &K,
-        v: &Value,
+        v: &[u8],
         flags: T::Flags,
     ) -> Result<(), StoreError>
     where
         K: AsRef<[u8]>,
     {
-        // TODO: don't allocate twice.
-        self.0
-            .put(db, k.as_ref(), &v.to_bytes()?, flags)
-            .map_err(|e| e.into())
+        self.0.put(db, k.as_ref(), v, flags).map_err(|e| e.into())?;
+        self.record(db, k.as_ref(), Op::Put, Some(v));
+        Ok(())
     }
 
     #[cfg(not(feature = "db-dup-sort"))]
@@ -158,7 +299,9 @@ where
     where
         K: AsRef<[u8]>,
     {
-        self.0.del(db, k.as_ref()).map_err(|e| e.into())
+        self.0.del(db, k.as_ref()).map_err(|e| e.into())?;
+        self.record(db, k.as_ref(), Op::Delete, None);
+        Ok(())
     }
 
     #[cfg(feature = "db-dup-sort")]
@@ -171,10 +314,62 @@ where
     where
         K: AsRef<[u8]>,
     {
-        self.0.del(db, k.as_ref(), v).map_err(|e| e.into())
+        self.0.del(db, k.as_ref(), v).map_err(|e| e.into())?;
+        self.record(db, k.as_ref(), Op::Delete, v);
+        Ok(())
+    }
+
+    /// Put values that a store already encoded as it holds them, in order, in a single
+    /// call to the backend, see `BackendRwTransaction::put_many`.
+    pub(crate) fn put_many(
+        &mut self,
+        db: &T::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), StoreError> {
+        self.0.put_many(db, pairs).map_err(|e| e.into())?;
+        for &(k, v) in pairs {
+            self.record(db, k, Op::Put, Some(v));
+        }
+        Ok(())
+    }
+
+    /// Put values that a store already encoded as it holds them, sorted, and after the
+    /// keys it holds, see `BackendRwTransaction::put_sorted`.
+    pub(crate) fn put_sorted(
+        &mut self,
+        db: &T::Database,
+        pairs: &[(&[u8], &[u8])],
+    ) -> Result<(), StoreError> {
+        self.0.put_sorted(db, pairs).map_err(|e| e.into())?;
+        for &(k, v) in pairs {
+            self.record(db, k, Op::Put, Some(v));
+        }
+        Ok(())
+    }
+
+    /// Delete the keys of `range`, along with all of their values, and return how many
+    /// there were.
+    pub(crate) fn delete_range(
+        &mut self,
+        db: &T::Database,
+        range: &KeyRange,
+    ) -> Result<usize, StoreError> {
+        let keys = self
+            .0
+            .range_keys(db, range.start.as_deref(), range.end.as_deref())
+            .map_err(|e| e.into())?;
+        for k in &keys {
+            #[cfg(not(feature = "db-dup-sort"))]
+            self.delete(db, k)?;
+            #[cfg(feature = "db-dup-sort")]
+            self.delete(db, k, None)?;
+        }
+        Ok(keys.len())
     }
 
     pub(crate) fn clear(&mut self, db: &T::Database) -> Result<(), StoreError> {
-        self.0.clear_db(db).map_err(|e| e.into())
+        self.0.clear_db(db).map_err(|e| e.into())?;
+        self.record(db, &[], Op::Clear, None);
+        Ok(())
     }
 }
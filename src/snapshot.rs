@@ -0,0 +1,142 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Signed snapshots of some of an environment's stores, which can be shipped to peers
+//! and imported once they verified where they came from, see `Rkv::export_snapshot`
+//! and `Rkv::import_snapshot`.
+//!
+//! A snapshot holds the entries of its stores as they were encoded, the SHA-256 hash of
+//! them, and an Ed25519 signature of that hash by the exporter's key. Its bytes, from
+//! `SignedSnapshot::to_bytes`, don't depend on the backend or the platform.
+
+use std::convert::TryFrom;
+
+use ed25519_dalek::{Signature, Signer, Verifier};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+
+use crate::error::{DataError, StoreError};
+
+/// What the bytes of a snapshot start with.
+pub const MAGIC: &[u8] = b"rkvsnap1";
+
+pub type Hash = [u8; 32];
+
+/// The entries of a store, named as it was opened, or `None` for the default store.
+/// Stores with duplicates have an entry per value.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StoreContents {
+    pub name: Option<String>,
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SignedSnapshot {
+    hash: Vec<u8>,
+    signature: Vec<u8>,
+    content: Vec<u8>,
+}
+
+impl SignedSnapshot {
+    pub(crate) fn sign(
+        stores: &[StoreContents],
+        keypair: &Keypair,
+    ) -> Result<SignedSnapshot, StoreError> {
+        let content = bincode::serialize(stores).map_err(DataError::from)?;
+        let hash = Sha256::digest(&content);
+        Ok(SignedSnapshot {
+            hash: hash.to_vec(),
+            signature: keypair.sign(&hash).to_bytes().to_vec(),
+            content,
+        })
+    }
+
+    /// The hash of the snapshot's content, as claimed by it until it's verified.
+    pub fn hash(&self) -> Hash {
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&self.hash);
+        hash
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(bincode::serialize(self).expect("serialized"));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SignedSnapshot, StoreError> {
+        let snapshot: SignedSnapshot = match bytes.strip_prefix(MAGIC) {
+            Some(bytes) => bincode::deserialize(bytes).map_err(|_| StoreError::SnapshotInvalid)?,
+            None => return Err(StoreError::SnapshotInvalid),
+        };
+        if snapshot.hash.len() != 32 {
+            return Err(StoreError::SnapshotInvalid);
+        }
+        Ok(snapshot)
+    }
+
+    /// Check that the snapshot's content matches its hash, and that the hash was signed
+    /// by the holder of `public_key`, then decode its stores.
+    pub fn verify(&self, public_key: &PublicKey) -> Result<Vec<StoreContents>, StoreError> {
+        if Sha256::digest(&self.content)[..] != self.hash[..] {
+            return Err(StoreError::SnapshotInvalid);
+        }
+        let signature = Signature::try_from(&self.signature[..])
+            .map_err(|_| StoreError::SnapshotSignatureInvalid)?;
+        public_key
+            .verify(&self.hash, &signature)
+            .map_err(|_| StoreError::SnapshotSignatureInvalid)?;
+        bincode::deserialize(&self.content).map_err(|_| StoreError::SnapshotInvalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keypair(seed: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[seed; 32]).expect("secret key");
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    #[test]
+    fn test_snapshot_verify() {
+        let stores = vec![StoreContents {
+            name: Some("s".to_owned()),
+            entries: vec![(b"k".to_vec(), b"v".to_vec())],
+        }];
+        let (ours, theirs) = (keypair(1), keypair(2));
+        let snapshot = SignedSnapshot::sign(&stores, &ours).expect("signed");
+        let snapshot = SignedSnapshot::from_bytes(&snapshot.to_bytes()).expect("decoded");
+        assert_eq!(snapshot.verify(&ours.public).expect("verified"), stores);
+        assert!(matches!(
+            snapshot.verify(&theirs.public),
+            Err(StoreError::SnapshotSignatureInvalid)
+        ));
+
+        let mut tampered = snapshot.clone();
+        *tampered.content.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            tampered.verify(&ours.public),
+            Err(StoreError::SnapshotInvalid)
+        ));
+        // Fixing up the hash doesn't help without the key.
+        tampered.hash = Sha256::digest(&tampered.content).to_vec();
+        assert!(matches!(
+            tampered.verify(&ours.public),
+            Err(StoreError::SnapshotSignatureInvalid)
+        ));
+
+        assert!(SignedSnapshot::from_bytes(b"not a snapshot").is_err());
+    }
+}
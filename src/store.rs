@@ -8,24 +8,47 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
+pub mod blob;
 pub mod keys;
+pub mod lww;
+pub mod merkle;
 pub mod single;
+pub mod typed;
 
 #[cfg(feature = "db-dup-sort")]
 pub mod multi;
 
+#[cfg(feature = "db-dup-sort")]
+pub mod orset;
+
 #[cfg(feature = "db-int-key")]
 pub mod integer;
 
 #[cfg(all(feature = "db-dup-sort", feature = "db-int-key"))]
 pub mod integermulti;
 
-use crate::backend::BackendDatabaseFlags;
+use crate::{
+    backend::{BackendDatabase, BackendDatabaseFlags},
+    value::{Compression, Encoding},
+};
+
+/// The name of the internal database recording which stores compress their values.
+pub const COMPRESSION_NAME: &str = "__rkv_compression";
+
+// What the database records for stores compressing their values with LZ4.
+pub(crate) const COMPRESSION_LZ4: u8 = 1;
 
 #[derive(Default, Debug, Copy, Clone)]
 pub struct Options<F> {
     pub create: bool,
     pub flags: F,
+    /// How values are laid out on disk. Only stores that keep values sorted, i.e.
+    /// `MultiStore` and `MultiIntegerStore`, honor `Encoding::Ordered`.
+    pub encoding: Encoding,
+    /// Whether values are compressed on disk. A store must always be opened with the
+    /// compression it was created with, or opening it fails with
+    /// `StoreError::CompressionMismatch`; only the threshold can change.
+    pub compression: Compression,
 }
 
 impl<F> Options<F>
@@ -36,8 +59,42 @@ where
         Options {
             create: true,
             flags: F::empty(),
+            encoding: Encoding::Bincode,
+            compression: Compression::None,
         }
     }
+
+    pub fn with_encoding(mut self, encoding: Encoding) -> Options<F> {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn with_compression(mut self, compression: Compression) -> Options<F> {
+        self.compression = compression;
+        self
+    }
+}
+
+/// A contiguous range of a store's keys: those from `start`, included, to `end`, excluded,
+/// where `None` leaves that side unbounded. See `SingleStore::partition`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KeyRange {
+    pub start: Option<Vec<u8>>,
+    pub end: Option<Vec<u8>>,
+}
+
+impl KeyRange {
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.start.as_deref().map_or(true, |start| key >= start)
+            && self.end.as_deref().map_or(true, |end| key < end)
+    }
+}
+
+/// A store, by way of its underlying database.
+pub trait Store {
+    type Database: BackendDatabase;
+
+    fn database(&self) -> Self::Database;
 }
 
 #[derive(Default, Debug, Copy, Clone)]
@@ -0,0 +1,590 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Stores for blobs too large to be held in memory at once. A blob is streamed in and
+//! out of its store in fixed-size chunks, each held under a key derived from the blob's
+//! id, next to a small header holding the blob's length and chunk size. Every key is
+//! prefixed by the length of the id, so that the keys of distinct blobs never collide.
+//!
+//! All of a blob's chunks are written, and deleted, within a single `Writer`, so other
+//! readers either see the whole blob or none of it. Chunks are garbage collected as
+//! part of the transaction that orphans them: deleting a blob deletes its chunks, and
+//! so does dropping a `BlobWriter` without finishing it. A blob's chunks alternate
+//! between two sets of keys, so that replacing it leaves the old chunks alone until the
+//! new blob is finished, and only then deletes them. Aborting the transaction aborts
+//! the collection along with everything else.
+
+use std::{
+    borrow::Cow,
+    cmp,
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use log::warn;
+
+use crate::{
+    backend::{BackendDatabase, BackendFlags, BackendRwTransaction},
+    error::StoreError,
+    readwrite::{Readable, Writer},
+    store::Store,
+    value::Value,
+};
+
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+const HEADER: u8 = 0;
+const CHUNK: u8 = 1;
+const CHUNK_ALT: u8 = 2;
+
+fn blob_key(id: &[u8], kind: u8, index: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(4 + id.len() + 1 + 8);
+    key.extend_from_slice(&(id.len() as u32).to_be_bytes());
+    key.extend_from_slice(id);
+    key.push(kind);
+    key.extend_from_slice(&index.to_be_bytes());
+    key
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+struct Header {
+    len: u64,
+    chunk_size: u64,
+    // Which of `CHUNK` and `CHUNK_ALT` the chunks are kept under.
+    kind: u8,
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; 17] {
+        let mut bytes = [0; 17];
+        bytes[..8].copy_from_slice(&self.len.to_be_bytes());
+        bytes[8..16].copy_from_slice(&self.chunk_size.to_be_bytes());
+        bytes[16] = self.kind;
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Header, StoreError> {
+        if bytes.len() != 17 || bytes[8..16] == [0; 8] || ![CHUNK, CHUNK_ALT].contains(&bytes[16]) {
+            return Err(StoreError::DatabaseCorrupted);
+        }
+        let mut len = [0; 8];
+        let mut chunk_size = [0; 8];
+        len.copy_from_slice(&bytes[..8]);
+        chunk_size.copy_from_slice(&bytes[8..16]);
+        Ok(Header {
+            len: u64::from_be_bytes(len),
+            chunk_size: u64::from_be_bytes(chunk_size),
+            kind: bytes[16],
+        })
+    }
+
+    fn chunks(self) -> u64 {
+        (self.len + self.chunk_size - 1) / self.chunk_size
+    }
+}
+
+fn io_error(err: StoreError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+#[cfg(not(feature = "db-dup-sort"))]
+fn delete_key<T>(writer: &mut Writer<T>, db: &T::Database, key: &[u8]) -> Result<(), StoreError>
+where
+    T: BackendRwTransaction,
+{
+    writer.delete(db, &key)
+}
+
+#[cfg(feature = "db-dup-sort")]
+fn delete_key<T>(writer: &mut Writer<T>, db: &T::Database, key: &[u8]) -> Result<(), StoreError>
+where
+    T: BackendRwTransaction,
+{
+    writer.delete(db, &key, None)
+}
+
+fn delete_chunks<T>(
+    writer: &mut Writer<T>,
+    db: &T::Database,
+    id: &[u8],
+    kind: u8,
+    chunks: u64,
+) -> Result<(), StoreError>
+where
+    T: BackendRwTransaction,
+{
+    for index in 0..chunks {
+        delete_key(writer, db, &blob_key(id, kind, index))?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct BlobStore<D> {
+    db: D,
+    chunk_size: usize,
+}
+
+impl<D> Store for BlobStore<D>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> D {
+        self.db
+    }
+}
+
+impl<D> BlobStore<D>
+where
+    D: BackendDatabase,
+{
+    pub(crate) fn new(db: D) -> BlobStore<D> {
+        BlobStore {
+            db,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Split blobs written from now on into chunks of `chunk_size` bytes. Blobs that
+    /// were already written keep their chunk size.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> BlobStore<D> {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    fn header<'r, R, K>(&self, reader: &'r R, id: K) -> Result<Option<Header>, StoreError>
+    where
+        R: Readable<'r, Database = D>,
+        K: AsRef<[u8]>,
+    {
+        match reader.get(&self.db, &blob_key(id.as_ref(), HEADER, 0))? {
+            Some(Value::Blob(bytes)) => Header::from_bytes(&bytes).map(Some),
+            Some(_) => Err(StoreError::DatabaseCorrupted),
+            None => Ok(None),
+        }
+    }
+
+    /// The length of a blob, or `None` if there's no such blob.
+    pub fn len<'r, R, K>(&self, reader: &'r R, id: K) -> Result<Option<u64>, StoreError>
+    where
+        R: Readable<'r, Database = D>,
+        K: AsRef<[u8]>,
+    {
+        Ok(self.header(reader, id)?.map(|header| header.len))
+    }
+
+    /// Open a blob for reading, or return `None` if there's no such blob. Chunks are
+    /// borrowed from the reader as they're read, so at most one is held in memory.
+    pub fn reader<'r, R, K>(
+        &self,
+        reader: &'r R,
+        id: K,
+    ) -> Result<Option<BlobReader<'r, R, D>>, StoreError>
+    where
+        R: Readable<'r, Database = D>,
+        K: AsRef<[u8]>,
+    {
+        Ok(self.header(reader, &id)?.map(|header| BlobReader {
+            reader,
+            store: *self,
+            id: id.as_ref().to_vec(),
+            header,
+            pos: 0,
+        }))
+    }
+
+    /// Start writing a blob, replacing any blob with the same id. The blob only exists,
+    /// and the one it replaces is only deleted, once `BlobWriter::finish` is called, and
+    /// it's visible to others once the writer is committed.
+    pub fn writer<'w, T, K>(
+        &self,
+        writer: &'w mut Writer<T>,
+        id: K,
+    ) -> Result<BlobWriter<'w, T, D>, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        let old = match writer.get_bytes(&self.db, &blob_key(id.as_ref(), HEADER, 0))? {
+            Some(bytes) => Some(Header::from_bytes(bytes)?),
+            None => None,
+        };
+        let kind = match old {
+            Some(Header { kind: CHUNK, .. }) => CHUNK_ALT,
+            _ => CHUNK,
+        };
+        Ok(BlobWriter {
+            writer,
+            store: *self,
+            id: id.as_ref().to_vec(),
+            old,
+            kind,
+            buf: Vec::with_capacity(self.chunk_size),
+            index: 0,
+            len: 0,
+            finished: false,
+        })
+    }
+
+    /// Delete a blob and all of its chunks, returning whether there was such a blob.
+    pub fn delete<T, K>(&self, writer: &mut Writer<T>, id: K) -> Result<bool, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        let id = id.as_ref();
+        let key = blob_key(id, HEADER, 0);
+        let header = match writer.get_bytes(&self.db, &key)? {
+            Some(bytes) => Header::from_bytes(bytes)?,
+            None => return Ok(false),
+        };
+        delete_chunks(writer, &self.db, id, header.kind, header.chunks())?;
+        delete_key(writer, &self.db, &key)?;
+        Ok(true)
+    }
+}
+
+/// Streams a blob into its store, see `BlobStore::writer`.
+pub struct BlobWriter<'w, T, D>
+where
+    T: BackendRwTransaction<Database = D>,
+{
+    writer: &'w mut Writer<T>,
+    store: BlobStore<D>,
+    id: Vec<u8>,
+    // The header of the blob being replaced, whose chunks are deleted once finished.
+    old: Option<Header>,
+    kind: u8,
+    buf: Vec<u8>,
+    index: u64,
+    len: u64,
+    finished: bool,
+}
+
+impl<'w, T, D> BlobWriter<'w, T, D>
+where
+    T: BackendRwTransaction<Database = D>,
+    D: BackendDatabase,
+{
+    fn put_chunk(&mut self) -> Result<(), StoreError> {
+        let key = blob_key(&self.id, self.kind, self.index);
+        self.writer
+            .put(&self.store.db, &key, &self.buf, T::Flags::empty())?;
+        self.index += 1;
+        self.len += self.buf.len() as u64;
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Write the last chunk and the header of the blob, and delete the chunks of the
+    /// blob it replaces, returning its length.
+    pub fn finish(mut self) -> Result<u64, StoreError> {
+        if !self.buf.is_empty() {
+            self.put_chunk()?;
+        }
+        if let Some(old) = self.old {
+            delete_chunks(
+                self.writer,
+                &self.store.db,
+                &self.id,
+                old.kind,
+                old.chunks(),
+            )?;
+        }
+        let header = Header {
+            len: self.len,
+            chunk_size: self.store.chunk_size as u64,
+            kind: self.kind,
+        };
+        let key = blob_key(&self.id, HEADER, 0);
+        self.writer
+            .put(&self.store.db, &key, &header.to_bytes(), T::Flags::empty())?;
+        self.finished = true;
+        Ok(self.len)
+    }
+}
+
+impl<'w, T, D> Drop for BlobWriter<'w, T, D>
+where
+    T: BackendRwTransaction<Database = D>,
+{
+    /// Collect the chunks of a blob that was never finished, leaving the blob it was to
+    /// replace as it was.
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let (db, id) = (&self.store.db, &self.id);
+        if let Err(e) = delete_chunks(self.writer, db, id, self.kind, self.index) {
+            warn!("Couldn't delete the chunks of an unfinished blob: {}", e);
+        }
+    }
+}
+
+impl<'w, T, D> Write for BlobWriter<'w, T, D>
+where
+    T: BackendRwTransaction<Database = D>,
+    D: BackendDatabase,
+{
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = cmp::min(data.len(), self.store.chunk_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == self.store.chunk_size {
+            self.put_chunk().map_err(io_error)?;
+        }
+        Ok(n)
+    }
+
+    /// Chunks are written as soon as they're full, and are only durable once the
+    /// writer is committed, so there's nothing to flush.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Streams a blob out of its store, see `BlobStore::reader`.
+pub struct BlobReader<'r, R, D> {
+    reader: &'r R,
+    store: BlobStore<D>,
+    id: Vec<u8>,
+    header: Header,
+    pos: u64,
+}
+
+impl<'r, R, D> BlobReader<'r, R, D>
+where
+    R: Readable<'r, Database = D>,
+    D: BackendDatabase,
+{
+    pub fn len(&self) -> u64 {
+        self.header.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.header.len == 0
+    }
+
+    fn chunk(&self, index: u64) -> Result<&'r [u8], StoreError> {
+        let key = blob_key(&self.id, self.header.kind, index);
+        match self.reader.get(&self.store.db, &key)? {
+            Some(Value::Blob(Cow::Borrowed(bytes))) => Ok(bytes),
+            _ => Err(StoreError::DatabaseCorrupted),
+        }
+    }
+}
+
+impl<'r, R, D> Read for BlobReader<'r, R, D>
+where
+    R: Readable<'r, Database = D>,
+    D: BackendDatabase,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.header.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let chunk = self
+            .chunk(self.pos / self.header.chunk_size)
+            .map_err(io_error)?;
+        let offset = (self.pos % self.header.chunk_size) as usize;
+        if offset >= chunk.len() {
+            return Err(io_error(StoreError::DatabaseCorrupted));
+        }
+        let n = cmp::min(buf.len(), chunk.len() - offset);
+        buf[..n].copy_from_slice(&chunk[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<'r, R, D> Seek for BlobReader<'r, R, D>
+where
+    R: Readable<'r, Database = D>,
+    D: BackendDatabase,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.pos = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (self.header.len, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        let pos = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.unsigned_abs())
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use tempfile::Builder;
+
+    use crate::{
+        backend::{SafeMode, SafeModeEnvironment},
+        store::single::SingleStore,
+        value::Compression,
+        Rkv, StoreOptions,
+    };
+
+    fn blob(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_blob_round_trip() {
+        let root = Builder::new()
+            .prefix("test_blob_round_trip")
+            .tempdir()
+            .expect("tempdir");
+        fs::create_dir_all(root.path()).expect("dir created");
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let blobs = k
+            .open_blobs("blobs", StoreOptions::create())
+            .expect("opened")
+            .with_chunk_size(100);
+
+        let data = blob(1050);
+        {
+            let mut writer = k.write().expect("writer");
+            let mut w = blobs.writer(&mut writer, "a").expect("blob writer");
+            for piece in data.chunks(33) {
+                w.write_all(piece).expect("wrote");
+            }
+            assert_eq!(w.finish().expect("finished"), 1050);
+            let mut w = blobs.writer(&mut writer, "empty").expect("blob writer");
+            w.flush().expect("flushed");
+            assert_eq!(w.finish().expect("finished"), 0);
+            writer.commit().expect("committed");
+        }
+
+        let reader = k.read().expect("reader");
+        assert_eq!(blobs.len(&reader, "a").expect("len"), Some(1050));
+        assert_eq!(blobs.len(&reader, "b").expect("len"), None);
+        assert!(blobs.reader(&reader, "b").expect("reader").is_none());
+
+        let mut r = blobs.reader(&reader, "a").expect("reader").expect("blob");
+        let mut read = vec![];
+        r.read_to_end(&mut read).expect("read");
+        assert_eq!(read, data);
+
+        let mut buf = [0; 20];
+        assert_eq!(r.seek(SeekFrom::Start(95)).expect("seeked"), 95);
+        r.read_exact(&mut buf).expect("read across chunks");
+        assert_eq!(&buf[..], &data[95..115]);
+        assert_eq!(r.seek(SeekFrom::End(-10)).expect("seeked"), 1040);
+        assert_eq!(r.read(&mut buf).expect("read"), 10);
+        assert_eq!(&buf[..10], &data[1040..]);
+        assert_eq!(r.read(&mut buf).expect("read"), 0);
+        assert_eq!(r.seek(SeekFrom::Current(-1050)).expect("seeked"), 0);
+        assert!(r.seek(SeekFrom::Current(-1)).is_err());
+
+        let mut empty = blobs
+            .reader(&reader, "empty")
+            .expect("reader")
+            .expect("blob");
+        assert!(empty.is_empty());
+        assert_eq!(empty.read(&mut buf).expect("read"), 0);
+    }
+
+    fn count(
+        k: &Rkv<SafeModeEnvironment>,
+        blobs: &BlobStore<crate::backend::SafeModeDatabase>,
+    ) -> usize {
+        let reader = k.read().expect("reader");
+        let store = SingleStore::new(blobs.db, Compression::None);
+        store.iter_start(&reader).expect("iter").count()
+    }
+
+    #[test]
+    fn test_blob_delete() {
+        let root = Builder::new()
+            .prefix("test_blob_delete")
+            .tempdir()
+            .expect("tempdir");
+        fs::create_dir_all(root.path()).expect("dir created");
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+        let blobs = k
+            .open_blobs("blobs", StoreOptions::create())
+            .expect("opened")
+            .with_chunk_size(10);
+
+        {
+            let mut writer = k.write().expect("writer");
+            for id in &["a", "b"] {
+                let mut w = blobs.writer(&mut writer, id).expect("blob writer");
+                w.write_all(&blob(25)).expect("wrote");
+                w.finish().expect("finished");
+            }
+            // Overwriting a blob drops its old chunks.
+            let mut w = blobs.writer(&mut writer, "b").expect("blob writer");
+            w.write_all(&blob(5)).expect("wrote");
+            w.finish().expect("finished");
+            // So does dropping an unfinished one.
+            let mut w = blobs.writer(&mut writer, "c").expect("blob writer");
+            w.write_all(&blob(25)).expect("wrote");
+            drop(w);
+            // Which leaves the blob it was to replace as it was.
+            let mut w = blobs.writer(&mut writer, "b").expect("blob writer");
+            w.write_all(&blob(25)).expect("wrote");
+            drop(w);
+            writer.commit().expect("committed");
+        }
+        assert_eq!(count(&k, &blobs), 4 + 2);
+        {
+            let reader = k.read().expect("reader");
+            let mut r = blobs.reader(&reader, "b").expect("reader").expect("blob");
+            let mut read = vec![];
+            r.read_to_end(&mut read).expect("read");
+            assert_eq!(read, blob(5));
+        }
+
+        {
+            let mut writer = k.write().expect("writer");
+            assert!(blobs.delete(&mut writer, "a").expect("deleted"));
+            assert!(!blobs.delete(&mut writer, "a").expect("deleted"));
+            // Nothing is deleted until the writer commits.
+            writer.abort();
+        }
+        assert_eq!(count(&k, &blobs), 6);
+
+        {
+            let mut writer = k.write().expect("writer");
+            assert!(blobs.delete(&mut writer, "a").expect("deleted"));
+            writer.commit().expect("committed");
+        }
+        assert_eq!(count(&k, &blobs), 2);
+        let reader = k.read().expect("reader");
+        assert_eq!(blobs.len(&reader, "a").expect("len"), None);
+        assert_eq!(blobs.len(&reader, "b").expect("len"), Some(5));
+    }
+}
@@ -15,10 +15,11 @@ use crate::{
     error::StoreError,
     readwrite::{Readable, Writer},
     store::{
-        keys::{Key, PrimitiveInt},
+        keys::{key_range, Key, PrimitiveInt},
         single::SingleStore,
+        Store,
     },
-    value::Value,
+    value::{Compression, Value},
 };
 
 type EmptyResult = Result<(), StoreError>;
@@ -29,14 +30,25 @@ pub struct IntegerStore<D, K> {
     phantom: PhantomData<K>,
 }
 
+impl<D, K> Store for IntegerStore<D, K>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> D {
+        self.inner.database()
+    }
+}
+
 impl<D, K> IntegerStore<D, K>
 where
     D: BackendDatabase,
     K: PrimitiveInt,
 {
-    pub(crate) fn new(db: D) -> IntegerStore<D, K> {
+    pub(crate) fn new(db: D, compression: Compression) -> IntegerStore<D, K> {
         IntegerStore {
-            inner: SingleStore::new(db),
+            inner: SingleStore::new(db, compression),
             phantom: PhantomData,
         }
     }
@@ -55,6 +67,34 @@ where
         self.inner.put(writer, Key::new(&k)?, v)
     }
 
+    /// Put each pair, in order, see `SingleStore::put_many`.
+    pub fn put_many<'v, T, I>(&self, writer: &mut Writer<T>, pairs: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        I: IntoIterator<Item = (K, Value<'v>)>,
+    {
+        let pairs = pairs
+            .into_iter()
+            .map(|(k, v)| Ok((Key::new(&k)?, v)))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        self.inner.put_many(writer, pairs)
+    }
+
+    /// Put pairs in strictly ascending order of key, whose keys all come after those the
+    /// store already holds, see `SingleStore::put_sorted`. Keys are ordered as integers,
+    /// rather than as the bytes they're encoded as.
+    pub fn put_sorted<'v, T, I>(&self, writer: &mut Writer<T>, pairs: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        I: IntoIterator<Item = (K, Value<'v>)>,
+    {
+        let pairs = pairs
+            .into_iter()
+            .map(|(k, v)| Ok((Key::new(&k)?, v)))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        self.inner.put_sorted(writer, pairs)
+    }
+
     pub fn delete<T>(&self, writer: &mut Writer<T>, k: K) -> EmptyResult
     where
         T: BackendRwTransaction<Database = D>,
@@ -62,6 +102,32 @@ where
         self.inner.delete(writer, Key::new(&k)?)
     }
 
+    /// Delete each key, in order, failing if one isn't there.
+    pub fn delete_many<T, I>(&self, writer: &mut Writer<T>, keys: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        I: IntoIterator<Item = K>,
+    {
+        for k in keys {
+            self.delete(writer, k)?;
+        }
+        Ok(())
+    }
+
+    /// Delete the keys from `start`, included, up to `end`, excluded, either of which can
+    /// be left out, returning how many there were.
+    pub fn delete_range<T>(
+        &self,
+        writer: &mut Writer<T>,
+        start: Option<K>,
+        end: Option<K>,
+    ) -> Result<usize, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        self.inner.delete_range(writer, &key_range(start, end)?)
+    }
+
     pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
     where
         T: BackendRwTransaction<Database = D>,
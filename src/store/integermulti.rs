@@ -18,10 +18,11 @@ use crate::{
     error::StoreError,
     readwrite::{Readable, Writer},
     store::{
-        keys::{Key, PrimitiveInt},
+        keys::{key_range, Key, PrimitiveInt},
         multi::{DIter, Iter, MultiStore},
+        Store,
     },
-    value::Value,
+    value::{Compression, Encoding, Value},
 };
 
 type EmptyResult = Result<(), StoreError>;
@@ -32,14 +33,29 @@ pub struct MultiIntegerStore<D, K> {
     phantom: PhantomData<K>,
 }
 
+impl<D, K> Store for MultiIntegerStore<D, K>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> D {
+        self.inner.database()
+    }
+}
+
 impl<D, K> MultiIntegerStore<D, K>
 where
     D: BackendDatabase,
     K: PrimitiveInt,
 {
-    pub(crate) fn new(db: D) -> MultiIntegerStore<D, K> {
+    pub(crate) fn new(
+        db: D,
+        encoding: Encoding,
+        compression: Compression,
+    ) -> MultiIntegerStore<D, K> {
         MultiIntegerStore {
-            inner: MultiStore::new(db),
+            inner: MultiStore::with_encoding(db, encoding, compression),
             phantom: PhantomData,
         }
     }
@@ -91,6 +107,36 @@ where
         self.inner.put(writer, Key::new(&k)?, v)
     }
 
+    /// Insert each pair, in order, allowing duplicate entries like `put`, see
+    /// `MultiStore::put_many`.
+    pub fn put_many<'v, T, I>(&self, writer: &mut Writer<T>, pairs: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        I: IntoIterator<Item = (K, Value<'v>)>,
+    {
+        let pairs = pairs
+            .into_iter()
+            .map(|(k, v)| Ok((Key::new(&k)?, v)))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        self.inner.put_many(writer, pairs)
+    }
+
+    /// Insert pairs in strictly ascending order of key, then of encoded value for each
+    /// key, whose keys all come after those the store already holds, see
+    /// `MultiStore::put_sorted`. Keys are ordered as integers, rather than as the bytes
+    /// they're encoded as.
+    pub fn put_sorted<'v, T, I>(&self, writer: &mut Writer<T>, pairs: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        I: IntoIterator<Item = (K, Value<'v>)>,
+    {
+        let pairs = pairs
+            .into_iter()
+            .map(|(k, v)| Ok((Key::new(&k)?, v)))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        self.inner.put_sorted(writer, pairs)
+    }
+
     pub fn put_with_flags<T>(
         &self,
         writer: &mut Writer<T>,
@@ -118,6 +164,32 @@ where
         self.inner.delete(writer, Key::new(&k)?, v)
     }
 
+    /// Delete each pair, in order, failing if one isn't there.
+    pub fn delete_many<'v, T, I>(&self, writer: &mut Writer<T>, pairs: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        I: IntoIterator<Item = (K, Value<'v>)>,
+    {
+        for (k, v) in pairs {
+            self.delete(writer, k, &v)?;
+        }
+        Ok(())
+    }
+
+    /// Delete the keys from `start`, included, up to `end`, excluded, either of which can
+    /// be left out, along with all of their values, returning how many keys there were.
+    pub fn delete_range<T>(
+        &self,
+        writer: &mut Writer<T>,
+        start: Option<K>,
+        end: Option<K>,
+    ) -> Result<usize, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        self.inner.delete_range(writer, &key_range(start, end)?)
+    }
+
     pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
     where
         T: BackendRwTransaction<Database = D>,
@@ -291,6 +363,8 @@ mod tests {
     #[cfg(feature = "lmdb")]
     #[test]
     pub fn test_dup_iter_integer() {
+        use std::borrow::Cow;
+
         let path_str = "test-env";
         let root = Builder::new().prefix(path_str).tempdir().unwrap();
 
@@ -324,36 +398,64 @@ mod tests {
 
             let mut writer = env.write().expect("writer");
             expiry_store
-                .put(&mut writer, 1, &Value::Blob(&[104u8, 101u8, 108u8, 108u8]))
+                .put(
+                    &mut writer,
+                    1,
+                    &Value::Blob(Cow::Borrowed(&[104u8, 101u8, 108u8, 108u8])),
+                )
                 .expect("write");
             expiry_store
-                .put(&mut writer, 1, &Value::Blob(&[104u8, 101u8, 108u8, 108u8]))
+                .put(
+                    &mut writer,
+                    1,
+                    &Value::Blob(Cow::Borrowed(&[104u8, 101u8, 108u8, 108u8])),
+                )
                 .expect("write");
             expiry_store
-                .put(&mut writer, 1, &Value::Blob(&[104u8, 101u8, 108u8, 107u8]))
+                .put(
+                    &mut writer,
+                    1,
+                    &Value::Blob(Cow::Borrowed(&[104u8, 101u8, 108u8, 107u8])),
+                )
                 .expect("write");
             expiry_store
-                .put(&mut writer, 2, &Value::Blob(&[2u8, 2u8, 2u8, 2u8]))
+                .put(
+                    &mut writer,
+                    2,
+                    &Value::Blob(Cow::Borrowed(&[2u8, 2u8, 2u8, 2u8])),
+                )
                 .expect("write");
             expiry_store
-                .put(&mut writer, 2, &Value::Blob(&[2u8, 2u8, 2u8, 3u8]))
+                .put(
+                    &mut writer,
+                    2,
+                    &Value::Blob(Cow::Borrowed(&[2u8, 2u8, 2u8, 3u8])),
+                )
                 .expect("write");
             expiry_store
-                .put(&mut writer, 4, &Value::Blob(&[4u8, 2u8, 2u8, 4u8]))
+                .put(
+                    &mut writer,
+                    4,
+                    &Value::Blob(Cow::Borrowed(&[4u8, 2u8, 2u8, 4u8])),
+                )
                 .expect("write");
             expiry_store
-                .put(&mut writer, 4, &Value::Blob(&[4u8, 2u8, 2u8, 3u8]))
+                .put(
+                    &mut writer,
+                    4,
+                    &Value::Blob(Cow::Borrowed(&[4u8, 2u8, 2u8, 3u8])),
+                )
                 .expect("write");
 
             let mut iter = expiry_store.get(&writer, 1).expect("firstkey");
 
             assert_eq!(
                 iter.next().expect("firstvalue").expect("ok").1,
-                Value::Blob(&[104u8, 101u8, 108u8, 107u8])
+                Value::Blob(Cow::Borrowed(&[104u8, 101u8, 108u8, 107u8]))
             );
             assert_eq!(
                 iter.next().expect("secondvalue").expect("ok").1,
-                Value::Blob(&[104u8, 101u8, 108u8, 108u8])
+                Value::Blob(Cow::Borrowed(&[104u8, 101u8, 108u8, 108u8]))
             );
             assert!(iter.next().is_none());
 
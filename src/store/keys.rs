@@ -13,7 +13,7 @@ mod primitives;
 
 use std::marker::PhantomData;
 
-use crate::error::DataError;
+use crate::{error::DataError, store::KeyRange};
 
 pub use encodables::*;
 pub use primitives::*;
@@ -44,3 +44,15 @@ where
         })
     }
 }
+
+/// The keys from `start`, included, up to `end`, excluded, as encoded, either of which
+/// can be left out.
+pub(crate) fn key_range<K>(start: Option<K>, end: Option<K>) -> Result<KeyRange, DataError>
+where
+    K: EncodableKey,
+{
+    Ok(KeyRange {
+        start: start.as_ref().map(K::to_bytes).transpose()?,
+        end: end.as_ref().map(K::to_bytes).transpose()?,
+    })
+}
@@ -0,0 +1,229 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Single stores of last-writer-wins registers, which replicas can merge into each
+//! other in any order and converge.
+//!
+//! Each key holds the value of the write with the greatest `Timestamp`, or a tombstone
+//! if that write was a delete, so that a delete isn't undone by merging an older put.
+//! Writes with equal timestamps, which only forged ones can have, are ordered by their
+//! encoded values, tombstones first. Merging is thus commutative, associative and
+//! idempotent.
+//!
+//! Values are held as encoded by the store, so replicas must open it with the same
+//! `StoreOptions::compression`. Reads hand them back as a `Value::Blob`, like the other
+//! stores'.
+
+use std::{borrow::Cow, cmp::Ordering};
+
+use crate::{
+    backend::{BackendDatabase, BackendFlags, BackendIter, BackendRoCursor, BackendRwTransaction},
+    error::StoreError,
+    hlc::{Timestamp, TIMESTAMP_LEN},
+    readwrite::{Readable, Writer},
+    store::{single::SingleStore, Store},
+    value::{Compression, Value},
+};
+
+type EmptyResult = Result<(), StoreError>;
+
+/// The last write to a key, as exchanged between replicas.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct LwwEntry {
+    pub key: Vec<u8>,
+    pub timestamp: Timestamp,
+    /// The encoded value, or `None` for a tombstone.
+    pub value: Option<Vec<u8>>,
+}
+
+// A record is the write's timestamp followed by a byte telling whether it's a tombstone,
+// then its value.
+const TOMBSTONE: u8 = 0;
+const LIVE: u8 = 1;
+
+fn encode_record(timestamp: &Timestamp, value: Option<&[u8]>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(TIMESTAMP_LEN + 1 + value.map_or(0, <[u8]>::len));
+    bytes.extend_from_slice(&timestamp.to_bytes());
+    match value {
+        Some(value) => {
+            bytes.push(LIVE);
+            bytes.extend_from_slice(value);
+        }
+        None => bytes.push(TOMBSTONE),
+    }
+    bytes
+}
+
+fn decode_record(bytes: &[u8]) -> Result<(Timestamp, Option<&[u8]>), StoreError> {
+    if bytes.len() <= TIMESTAMP_LEN {
+        return Err(StoreError::DatabaseCorrupted);
+    }
+    let timestamp = Timestamp::from_bytes(&bytes[..TIMESTAMP_LEN])?;
+    match bytes[TIMESTAMP_LEN] {
+        TOMBSTONE => Ok((timestamp, None)),
+        LIVE => Ok((timestamp, Some(&bytes[TIMESTAMP_LEN + 1..]))),
+        _ => Err(StoreError::DatabaseCorrupted),
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct LwwStore<D> {
+    inner: SingleStore<D>,
+}
+
+impl<D> Store for LwwStore<D>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> D {
+        self.inner.database()
+    }
+}
+
+impl<D> LwwStore<D>
+where
+    D: BackendDatabase,
+{
+    pub(crate) fn new(db: D, compression: Compression) -> LwwStore<D> {
+        LwwStore {
+            inner: SingleStore::new(db, compression),
+        }
+    }
+
+    /// The value of the last write to `k`, unless it was a delete.
+    pub fn get<'r, R, K>(&self, reader: &'r R, k: K) -> Result<Option<Value<'r>>, StoreError>
+    where
+        R: Readable<'r, Database = D>,
+        K: AsRef<[u8]>,
+    {
+        match reader.get(&self.inner.database(), &k)? {
+            Some(Value::Blob(Cow::Borrowed(bytes))) => match decode_record(bytes)?.1 {
+                Some(value) => self.inner.decode(value).map(Some),
+                None => Ok(None),
+            },
+            Some(_) => Err(StoreError::DatabaseCorrupted),
+            None => Ok(None),
+        }
+    }
+
+    /// The timestamp of the last write to `k`, whether or not it was a delete.
+    pub fn timestamp<'r, R, K>(&self, reader: &'r R, k: K) -> Result<Option<Timestamp>, StoreError>
+    where
+        R: Readable<'r, Database = D>,
+        K: AsRef<[u8]>,
+    {
+        match reader.get(&self.inner.database(), &k)? {
+            Some(Value::Blob(bytes)) => Ok(Some(decode_record(&bytes)?.0)),
+            Some(_) => Err(StoreError::DatabaseCorrupted),
+            None => Ok(None),
+        }
+    }
+
+    /// Write `v` to `k` at `timestamp`, unless a later write to `k` was already made or
+    /// merged. Returns whether it was written.
+    pub fn put<T, K>(
+        &self,
+        writer: &mut Writer<T>,
+        k: K,
+        v: &Value,
+        timestamp: Timestamp,
+    ) -> Result<bool, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        let bytes = self.inner.encode(v)?;
+        self.write(writer, k.as_ref(), &timestamp, Some(&bytes))
+    }
+
+    /// Leave a tombstone for `k` at `timestamp`, unless a later write to `k` was already
+    /// made or merged. Returns whether it was written.
+    pub fn delete<T, K>(
+        &self,
+        writer: &mut Writer<T>,
+        k: K,
+        timestamp: Timestamp,
+    ) -> Result<bool, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        self.write(writer, k.as_ref(), &timestamp, None)
+    }
+
+    fn write<T>(
+        &self,
+        writer: &mut Writer<T>,
+        k: &[u8],
+        timestamp: &Timestamp,
+        value: Option<&[u8]>,
+    ) -> Result<bool, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        let db = self.inner.database();
+        if let Some(bytes) = writer.get_bytes(&db, &k)? {
+            let (old_timestamp, old_value) = decode_record(bytes)?;
+            let order = timestamp
+                .cmp(&old_timestamp)
+                .then_with(|| value.cmp(&old_value));
+            if order != Ordering::Greater {
+                return Ok(false);
+            }
+        }
+        writer.put(&db, &k, &encode_record(timestamp, value), T::Flags::empty())?;
+        Ok(true)
+    }
+
+    /// The last writes to every key, tombstones included, to be merged into other
+    /// replicas.
+    pub fn entries<'r, R, I, C>(&self, reader: &'r R) -> Result<Vec<LwwEntry>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        let cursor = reader.open_ro_cursor(&self.inner.database())?;
+        let mut iter = cursor.into_iter();
+        let mut entries = vec![];
+        while let Some(result) = iter.next() {
+            let (key, bytes) = result.map_err(|e| e.into())?;
+            let (timestamp, value) = decode_record(bytes)?;
+            entries.push(LwwEntry {
+                key: key.to_vec(),
+                timestamp,
+                value: value.map(<[u8]>::to_vec),
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Merge the entries of another replica into this one, keeping the last write to
+    /// each key.
+    pub fn merge<T, I>(&self, writer: &mut Writer<T>, entries: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        I: IntoIterator<Item = LwwEntry>,
+    {
+        for entry in entries {
+            self.write(writer, &entry.key, &entry.timestamp, entry.value.as_deref())?;
+        }
+        Ok(())
+    }
+
+    pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        self.inner.clear(writer)
+    }
+}
@@ -0,0 +1,320 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Single stores that keep a Merkle tree of their contents, so that replicas can find
+//! out which key ranges they differ in without comparing every entry.
+//!
+//! Each range of keys sharing a prefix of up to `depth` bytes has a hash, which is the
+//! XOR of the SHA-256 hashes of its entries. The range with the empty prefix holds
+//! every entry, and its hash is the store's root hash. XOR-ing makes a range's hash
+//! independent of the order its entries were written in, and lets every `put` and
+//! `delete` update the tree incrementally, by one node per depth. It also means hashes
+//! aren't collision-resistant: with Wagner's generalized birthday attack, a replica can
+//! pick entries whose set hashes like another one, so trees are only fit for comparing
+//! replicas that trust each other.
+//!
+//! Two replicas compare root hashes first, then the hashes of the ranges at depth 1,
+//! and only descend into the ranges whose hashes differ, until they exchange the
+//! entries of the ranges that still differ. Ranges are prefixes of the keys themselves,
+//! so keys sharing a long common prefix end up in the same ranges until a matching
+//! depth, and a store's entries are hashed as encoded, so replicas must open it with
+//! the same `StoreOptions::compression`.
+//!
+//! The tree is held in a database of its own, next to the store's, so both count
+//! towards the environment's maximum number of databases. It's only kept up to date by
+//! writes through the `MerkleStore`. The tree also holds the depth it was created with,
+//! and opening it with another one fails with `StoreError::MerkleDepthMismatch`.
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    backend::{BackendDatabase, BackendFlags, BackendIter, BackendRoCursor, BackendRwTransaction},
+    error::StoreError,
+    readwrite::{Readable, Writer},
+    store::{
+        single::{Iter, SingleStore},
+        Store,
+    },
+    value::{Compression, Value},
+};
+
+type EmptyResult = Result<(), StoreError>;
+
+/// What the names of the databases holding trees start with, before their store's.
+pub const MERKLE_PREFIX: &str = "__rkv_merkle_";
+
+pub type Hash = [u8; 32];
+
+/// The hash of an empty range.
+pub const EMPTY_HASH: Hash = [0; 32];
+
+fn entry_hash(k: &[u8], v: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update((k.len() as u64).to_be_bytes());
+    hasher.update(k);
+    hasher.update(v);
+    hasher.finalize().into()
+}
+
+fn xor(a: &mut Hash, b: &Hash) {
+    for (a, b) in a.iter_mut().zip(b) {
+        *a ^= b;
+    }
+}
+
+fn read_hash(bytes: &[u8]) -> Result<Hash, StoreError> {
+    let mut hash = EMPTY_HASH;
+    if bytes.len() != hash.len() {
+        return Err(StoreError::DatabaseCorrupted);
+    }
+    hash.copy_from_slice(bytes);
+    Ok(hash)
+}
+
+// A node's key is its depth followed by its range's prefix, which is shorter than its
+// depth for the ranges of keys shorter than it.
+fn node_key(depth: u8, k: &[u8]) -> Vec<u8> {
+    let prefix = &k[..k.len().min(depth as usize)];
+    let mut key = Vec::with_capacity(1 + prefix.len());
+    key.push(depth);
+    key.extend_from_slice(prefix);
+    key
+}
+
+// The key the tree's depth is kept under, which is never a node's since the only node
+// of depth 0 is the root, whose key is `[0]`.
+const DEPTH_KEY: [u8; 2] = [0, 0];
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct MerkleStore<D> {
+    inner: SingleStore<D>,
+    tree: D,
+    depth: u8,
+}
+
+impl<D> Store for MerkleStore<D>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> D {
+        self.inner.database()
+    }
+}
+
+impl<D> MerkleStore<D>
+where
+    D: BackendDatabase,
+{
+    pub(crate) fn new(db: D, tree: D, depth: u8, compression: Compression) -> MerkleStore<D> {
+        MerkleStore {
+            inner: SingleStore::new(db, compression),
+            tree,
+            depth,
+        }
+    }
+
+    /// Record the store's depth in its tree if it's new, otherwise check that the tree
+    /// was created with it.
+    pub(crate) fn check_depth<T>(&self, writer: &mut Writer<T>) -> Result<bool, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        match writer.get_bytes(&self.tree, &DEPTH_KEY)? {
+            Some(&[depth]) if depth == self.depth => Ok(false),
+            Some(&[depth]) => Err(StoreError::MerkleDepthMismatch {
+                expected: self.depth,
+                actual: depth,
+            }),
+            Some(_) => Err(StoreError::DatabaseCorrupted),
+            None => {
+                writer.put(&self.tree, &DEPTH_KEY, &[self.depth], T::Flags::empty())?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// The greatest depth down to which range hashes are kept.
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn get<'r, R, K>(&self, reader: &'r R, k: K) -> Result<Option<Value<'r>>, StoreError>
+    where
+        R: Readable<'r, Database = D>,
+        K: AsRef<[u8]>,
+    {
+        self.inner.get(reader, k)
+    }
+
+    pub fn put<T, K>(&self, writer: &mut Writer<T>, k: K, v: &Value) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        let k = k.as_ref();
+        let bytes = self.inner.encode(v)?;
+        let mut delta = entry_hash(k, &bytes);
+        if let Some(old) = writer.get_bytes(&self.inner.database(), &k)? {
+            xor(&mut delta, &entry_hash(k, old));
+        }
+        writer.put(&self.inner.database(), &k, &bytes, T::Flags::empty())?;
+        self.update(writer, k, &delta)
+    }
+
+    pub fn delete<T, K>(&self, writer: &mut Writer<T>, k: K) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        let k = k.as_ref();
+        let delta = match writer.get_bytes(&self.inner.database(), &k)? {
+            Some(old) => entry_hash(k, old),
+            None => return Err(StoreError::KeyValuePairNotFound),
+        };
+        self.inner.delete(writer, k)?;
+        self.update(writer, k, &delta)
+    }
+
+    pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        self.inner.clear(writer)?;
+        writer.clear(&self.tree)?;
+        self.check_depth(writer).map(|_| ())
+    }
+
+    pub fn iter_start<'r, R, I, C>(&self, reader: &'r R) -> Result<Iter<'r, I>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        self.inner.iter_start(reader)
+    }
+
+    pub fn iter_from<'r, R, I, C, K>(&self, reader: &'r R, k: K) -> Result<Iter<'r, I>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: AsRef<[u8]> + 'r,
+    {
+        self.inner.iter_from(reader, k)
+    }
+
+    /// Toggle an entry's hash in, or out of, the hashes of the ranges holding it.
+    fn update<T>(&self, writer: &mut Writer<T>, k: &[u8], delta: &Hash) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        if *delta == EMPTY_HASH {
+            return Ok(());
+        }
+        for depth in 0..=self.depth {
+            let key = node_key(depth, k);
+            let mut hash = match writer.get_bytes(&self.tree, &key)? {
+                Some(bytes) => read_hash(bytes)?,
+                None => EMPTY_HASH,
+            };
+            xor(&mut hash, delta);
+            if hash == EMPTY_HASH {
+                delete_node(writer, &self.tree, &key)?;
+            } else {
+                writer.put(&self.tree, &key, &hash, T::Flags::empty())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The hash of the whole store, which is `EMPTY_HASH` if it's empty.
+    pub fn root_hash<'r, R>(&self, reader: &'r R) -> Result<Hash, StoreError>
+    where
+        R: Readable<'r, Database = D>,
+    {
+        match reader.get(&self.tree, &node_key(0, &[]))? {
+            Some(Value::Blob(bytes)) => read_hash(&bytes),
+            Some(_) => Err(StoreError::DatabaseCorrupted),
+            None => Ok(EMPTY_HASH),
+        }
+    }
+
+    /// The hashes of the non-empty ranges of keys sharing a prefix of `prefix_depth`
+    /// bytes, or all of a shorter key, ordered by prefix.
+    /// Fails with `StoreError::MerkleDepthMismatch` if `prefix_depth` is greater than
+    /// the store's depth.
+    pub fn range_hashes<'r, R, I, C>(
+        &self,
+        reader: &'r R,
+        prefix_depth: u8,
+    ) -> Result<Vec<(Vec<u8>, Hash)>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        self.subrange_hashes(reader, &[], prefix_depth)
+    }
+
+    /// Like `range_hashes`, but only for the ranges within the one of `prefix`, so that
+    /// replicas only descend into the ranges they differ in.
+    pub fn subrange_hashes<'r, R, I, C>(
+        &self,
+        reader: &'r R,
+        prefix: &[u8],
+        prefix_depth: u8,
+    ) -> Result<Vec<(Vec<u8>, Hash)>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        if prefix_depth > self.depth {
+            return Err(StoreError::MerkleDepthMismatch {
+                expected: self.depth,
+                actual: prefix_depth,
+            });
+        }
+        let start = node_key(prefix_depth, prefix);
+        let cursor = reader.open_ro_cursor(&self.tree)?;
+        let mut iter = cursor.into_iter_from(start.clone());
+        let mut hashes = vec![];
+        while let Some(result) = iter.next() {
+            let (key, bytes) = result.map_err(|e| e.into())?;
+            if !key.starts_with(&start) {
+                break;
+            }
+            // Skip the tree's depth, which starts like the root's key.
+            if key.len() > 1 + prefix_depth as usize {
+                continue;
+            }
+            hashes.push((key[1..].to_vec(), read_hash(bytes)?));
+        }
+        Ok(hashes)
+    }
+}
+
+#[cfg(not(feature = "db-dup-sort"))]
+fn delete_node<T>(writer: &mut Writer<T>, db: &T::Database, key: &[u8]) -> EmptyResult
+where
+    T: BackendRwTransaction,
+{
+    writer.delete(db, &key)
+}
+
+#[cfg(feature = "db-dup-sort")]
+fn delete_node<T>(writer: &mut Writer<T>, db: &T::Database, key: &[u8]) -> EmptyResult
+where
+    T: BackendRwTransaction,
+{
+    writer.delete(db, &key, None)
+}
@@ -8,7 +8,7 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use std::marker::PhantomData;
+use std::{borrow::Cow, marker::PhantomData};
 
 use crate::{
     backend::{
@@ -18,7 +18,8 @@ use crate::{
     error::StoreError,
     helpers::read_transform,
     readwrite::{Readable, Writer},
-    value::Value,
+    store::{KeyRange, Store},
+    value::{compress, Compression, Encoding, Type, Value},
 };
 
 type EmptyResult = Result<(), StoreError>;
@@ -26,10 +27,14 @@ type EmptyResult = Result<(), StoreError>;
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub struct MultiStore<D> {
     db: D,
+    encoding: Encoding,
+    compression: Compression,
 }
 
 pub struct Iter<'i, I> {
     iter: I,
+    encoding: Encoding,
+    compression: Compression,
     phantom: PhantomData<&'i ()>,
 }
 
@@ -38,12 +43,41 @@ pub struct DIter<'i, I> {
     phantom: PhantomData<&'i ()>,
 }
 
+impl<D> Store for MultiStore<D>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> D {
+        self.db
+    }
+}
+
 impl<D> MultiStore<D>
 where
     D: BackendDatabase,
 {
-    pub(crate) fn new(db: D) -> MultiStore<D> {
-        MultiStore { db }
+    pub(crate) fn with_encoding(
+        db: D,
+        encoding: Encoding,
+        compression: Compression,
+    ) -> MultiStore<D> {
+        MultiStore {
+            db,
+            encoding,
+            compression,
+        }
+    }
+
+    /// Encode a value as the store holds it.
+    pub(crate) fn encode(&self, v: &Value) -> Result<Vec<u8>, StoreError> {
+        Ok(v.encode_compressed(self.encoding, self.compression)?)
+    }
+
+    /// Decode bytes as the store holds them, see `MultiStore::get_first`.
+    pub(crate) fn decode<'r>(&self, bytes: &'r [u8]) -> Result<Value<'r>, StoreError> {
+        read_transform(Ok(bytes), self.encoding, self.compression)
     }
 
     /// Provides a cursor to all of the keys below the given key
@@ -81,6 +115,8 @@ where
 
         Ok(Iter {
             iter,
+            encoding: self.encoding,
+            compression: self.compression,
             phantom: PhantomData,
         })
     }
@@ -100,7 +136,7 @@ where
         K: AsRef<[u8]> + 'r,
     {
         let cursor = reader.open_ro_cursor(&self.db)?;
-        let res = cursor.get_key_value(k, v);
+        let res = cursor.get_key_value(k, &v.encode_compressed(self.encoding, self.compression)?);
 
         Ok(res)
     }
@@ -111,7 +147,10 @@ where
         R: Readable<'r, Database = D>,
         K: AsRef<[u8]>,
     {
-        reader.get(&self.db, &k)
+        match reader.get(&self.db, &k)? {
+            Some(Value::Blob(Cow::Borrowed(bytes))) => self.decode(bytes).map(Some),
+            result => Ok(result),
+        }
     }
 
     pub fn iter_start<'r, R, I, C>(&self, reader: &'r R) -> Result<Iter<'r, I>, StoreError>
@@ -125,6 +164,8 @@ where
 
         Ok(Iter {
             iter,
+            encoding: self.encoding,
+            compression: self.compression,
             phantom: PhantomData,
         })
     }
@@ -137,7 +178,48 @@ where
         T: BackendRwTransaction<Database = D>,
         K: AsRef<[u8]>,
     {
-        writer.put(&self.db, &k, v, T::Flags::empty())
+        let bytes = v.encode_compressed(self.encoding, self.compression)?;
+        writer.put(&self.db, &k, &bytes, T::Flags::empty())
+    }
+
+    /// Insert each pair, in order, allowing duplicate entries like `put`. The pairs are
+    /// encoded and handed to the backend at once, see `SingleStore::put_many`.
+    pub fn put_many<'v, T, K, I>(&self, writer: &mut Writer<T>, pairs: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = (K, Value<'v>)>,
+    {
+        let encoded = pairs
+            .into_iter()
+            .map(|(k, v)| Ok((k, self.encode(&v)?)))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        let pairs: Vec<(&[u8], &[u8])> = encoded
+            .iter()
+            .map(|(k, bytes)| (k.as_ref(), bytes.as_slice()))
+            .collect();
+        writer.put_many(&self.db, &pairs)
+    }
+
+    /// Insert pairs in strictly ascending order of key, then of encoded value for each
+    /// key, whose keys all come after those the store already holds, faster than
+    /// `put_many`, see `SingleStore::put_sorted`. Fails with `StoreError::KeysNotSorted`,
+    /// having inserted none of them, if they aren't.
+    pub fn put_sorted<'v, T, K, I>(&self, writer: &mut Writer<T>, pairs: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = (K, Value<'v>)>,
+    {
+        let encoded = pairs
+            .into_iter()
+            .map(|(k, v)| Ok((k, self.encode(&v)?)))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        let pairs: Vec<(&[u8], &[u8])> = encoded
+            .iter()
+            .map(|(k, bytes)| (k.as_ref(), bytes.as_slice()))
+            .collect();
+        writer.put_sorted(&self.db, &pairs)
     }
 
     pub fn put_with_flags<T, K>(
@@ -151,7 +233,8 @@ where
         T: BackendRwTransaction<Database = D>,
         K: AsRef<[u8]>,
     {
-        writer.put(&self.db, &k, v, flags)
+        let bytes = v.encode_compressed(self.encoding, self.compression)?;
+        writer.put(&self.db, &k, &bytes, flags)
     }
 
     pub fn delete_all<T, K>(&self, writer: &mut Writer<T>, k: K) -> EmptyResult
@@ -167,7 +250,68 @@ where
         T: BackendRwTransaction<Database = D>,
         K: AsRef<[u8]>,
     {
-        writer.delete(&self.db, &k, Some(&v.to_bytes()?))
+        let bytes = v.encode_compressed(self.encoding, self.compression)?;
+        writer.delete(&self.db, &k, Some(&bytes))
+    }
+
+    /// Insert bytes already encoded as a value of type `t`, such as those of a typed
+    /// store, compressing them as `put` would. The store must use `Encoding::Bincode`.
+    pub(crate) fn put_encoded<T, K>(
+        &self,
+        writer: &mut Writer<T>,
+        k: K,
+        t: Type,
+        bytes: Vec<u8>,
+    ) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        let bytes = compress(t, bytes, self.compression);
+        writer.put(&self.db, &k, &bytes, T::Flags::empty())
+    }
+
+    /// Delete the single duplicate equal to bytes encoded as a value of type `t`, see
+    /// `MultiStore::put_encoded`.
+    pub(crate) fn delete_encoded<T, K>(
+        &self,
+        writer: &mut Writer<T>,
+        k: K,
+        t: Type,
+        bytes: Vec<u8>,
+    ) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        let bytes = compress(t, bytes, self.compression);
+        writer.delete(&self.db, &k, Some(&bytes))
+    }
+
+    /// Delete each pair, in order, failing if one isn't there.
+    pub fn delete_many<'v, T, K, I>(&self, writer: &mut Writer<T>, pairs: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = (K, Value<'v>)>,
+    {
+        for (k, v) in pairs {
+            self.delete(writer, k, &v)?;
+        }
+        Ok(())
+    }
+
+    /// Delete the keys of `range`, along with all of their values, returning how many
+    /// keys there were.
+    pub fn delete_range<T>(
+        &self,
+        writer: &mut Writer<T>,
+        range: &KeyRange,
+    ) -> Result<usize, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        writer.delete_range(&self.db, range)
     }
 
     pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
@@ -187,10 +331,12 @@ where
     fn next(&mut self) -> Option<Self::Item> {
         match self.iter.next() {
             None => None,
-            Some(Ok((key, bytes))) => match read_transform(Ok(bytes)) {
-                Ok(val) => Some(Ok((key, val))),
-                Err(err) => Some(Err(err)),
-            },
+            Some(Ok((key, bytes))) => {
+                match read_transform(Ok(bytes), self.encoding, self.compression) {
+                    Ok(val) => Some(Ok((key, val))),
+                    Err(err) => Some(Err(err)),
+                }
+            }
             Some(Err(err)) => Some(Err(err.into())),
         }
     }
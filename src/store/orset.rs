@@ -0,0 +1,317 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Multi stores of observed-remove sets, which replicas can merge into each other in
+//! any order and converge.
+//!
+//! Each add of an element to a key's set is tagged with the `Timestamp` it was made at,
+//! and held as a duplicate of the key. Removing an element removes the adds of it that
+//! were observed, see `OrSetStore::observe`, so an add made concurrently on another
+//! replica survives the remove once merged. Removed adds leave a tombstone, held in a
+//! database of their own next to the store's, so that merging them again doesn't bring
+//! them back. Tombstones are never dropped.
+//!
+//! Elements are held as encoded by the store, so replicas must open it with the same
+//! `StoreOptions::compression`. Reads hand them back as a
+//! `Value::Blob`, like the other stores'. Adds are duplicates, which LMDB limits to its
+//! maximum key size, less the 20 bytes of their tag.
+
+use crate::{
+    backend::{BackendDatabase, BackendFlags, BackendIter, BackendRoCursor, BackendRwTransaction},
+    error::StoreError,
+    hlc::{Timestamp, TIMESTAMP_LEN},
+    readwrite::{Readable, Writer},
+    store::{multi::MultiStore, Store},
+    value::{Compression, Encoding, Value},
+};
+
+type EmptyResult = Result<(), StoreError>;
+
+/// What the names of the databases holding tombstones start with, before their store's.
+pub const TOMBSTONES_PREFIX: &str = "__rkv_orset_removed_";
+
+/// An add of an element to a key's set, or its tombstone, as exchanged between replicas.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct OrSetEntry {
+    pub key: Vec<u8>,
+    /// The encoded element.
+    pub element: Vec<u8>,
+    pub tag: Timestamp,
+    pub removed: bool,
+}
+
+// Adds are held as their tag followed by their element, and tombstones under their key
+// followed by their tag.
+fn encode_add(tag: &Timestamp, element: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(TIMESTAMP_LEN + element.len());
+    bytes.extend_from_slice(&tag.to_bytes());
+    bytes.extend_from_slice(element);
+    bytes
+}
+
+fn decode_add(bytes: &[u8]) -> Result<(Timestamp, &[u8]), StoreError> {
+    if bytes.len() < TIMESTAMP_LEN {
+        return Err(StoreError::DatabaseCorrupted);
+    }
+    let (tag, element) = bytes.split_at(TIMESTAMP_LEN);
+    Ok((Timestamp::from_bytes(tag)?, element))
+}
+
+fn tombstone_key(k: &[u8], tag: &Timestamp) -> Vec<u8> {
+    let mut key = Vec::with_capacity(k.len() + TIMESTAMP_LEN);
+    key.extend_from_slice(k);
+    key.extend_from_slice(&tag.to_bytes());
+    key
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct OrSetStore<D> {
+    inner: MultiStore<D>,
+    tombstones: D,
+}
+
+impl<D> Store for OrSetStore<D>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> D {
+        self.inner.database()
+    }
+}
+
+impl<D> OrSetStore<D>
+where
+    D: BackendDatabase,
+{
+    pub(crate) fn new(db: D, tombstones: D, compression: Compression) -> OrSetStore<D> {
+        OrSetStore {
+            inner: MultiStore::with_encoding(db, Encoding::Bincode, compression),
+            tombstones,
+        }
+    }
+
+    /// The adds of `k`'s set that are in effect, as tags and encoded elements.
+    fn adds<'r, R, I, C, K>(
+        &self,
+        reader: &'r R,
+        k: K,
+    ) -> Result<Vec<(Timestamp, &'r [u8])>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: AsRef<[u8]> + 'r,
+    {
+        let cursor = reader.open_ro_cursor(&self.inner.database())?;
+        let mut iter = cursor.into_iter_dup_of(k);
+        let mut adds = vec![];
+        while let Some(result) = iter.next() {
+            let (_, bytes) = result.map_err(|e| e.into())?;
+            adds.push(decode_add(bytes)?);
+        }
+        Ok(adds)
+    }
+
+    /// The elements of `k`'s set, in the order they were first added.
+    pub fn get<'r, R, I, C, K>(&self, reader: &'r R, k: K) -> Result<Vec<Value<'r>>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: AsRef<[u8]> + 'r,
+    {
+        let mut elements: Vec<&[u8]> = vec![];
+        for (_, element) in self.adds(reader, k)? {
+            if !elements.contains(&element) {
+                elements.push(element);
+            }
+        }
+        elements
+            .into_iter()
+            .map(|element| self.inner.decode(element))
+            .collect()
+    }
+
+    pub fn contains<'r, R, I, C, K>(
+        &self,
+        reader: &'r R,
+        k: K,
+        v: &Value,
+    ) -> Result<bool, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: AsRef<[u8]> + 'r,
+    {
+        Ok(!self.observe(reader, k, v)?.is_empty())
+    }
+
+    /// The tags of the adds of `v` to `k`'s set that are in effect, which `remove`
+    /// takes. Observing them through a writer includes its own adds.
+    pub fn observe<'r, R, I, C, K>(
+        &self,
+        reader: &'r R,
+        k: K,
+        v: &Value,
+    ) -> Result<Vec<Timestamp>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: AsRef<[u8]> + 'r,
+    {
+        let bytes = self.inner.encode(v)?;
+        Ok(self
+            .adds(reader, k)?
+            .into_iter()
+            .filter(|(_, element)| *element == &bytes[..])
+            .map(|(tag, _)| tag)
+            .collect())
+    }
+
+    /// Add `v` to `k`'s set, tagged with `tag`, which must not have tagged any other add.
+    pub fn add<T, K>(&self, writer: &mut Writer<T>, k: K, v: &Value, tag: Timestamp) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        let bytes = self.inner.encode(v)?;
+        self.merge_add(writer, k.as_ref(), &tag, &bytes)
+    }
+
+    /// Remove the adds of `v` to `k`'s set tagged with the `observed` tags.
+    pub fn remove<T, K>(
+        &self,
+        writer: &mut Writer<T>,
+        k: K,
+        v: &Value,
+        observed: &[Timestamp],
+    ) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        let bytes = self.inner.encode(v)?;
+        for tag in observed {
+            self.merge_remove(writer, k.as_ref(), tag, &bytes)?;
+        }
+        Ok(())
+    }
+
+    fn merge_add<T>(
+        &self,
+        writer: &mut Writer<T>,
+        k: &[u8],
+        tag: &Timestamp,
+        element: &[u8],
+    ) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        if writer
+            .get_bytes(&self.tombstones, &tombstone_key(k, tag))?
+            .is_some()
+        {
+            return Ok(());
+        }
+        writer.put(
+            &self.inner.database(),
+            &k,
+            &encode_add(tag, element),
+            T::Flags::empty(),
+        )
+    }
+
+    fn merge_remove<T>(
+        &self,
+        writer: &mut Writer<T>,
+        k: &[u8],
+        tag: &Timestamp,
+        element: &[u8],
+    ) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        let key = tombstone_key(k, tag);
+        if writer.get_bytes(&self.tombstones, &key)?.is_some() {
+            return Ok(());
+        }
+        writer.put(&self.tombstones, &key, element, T::Flags::empty())?;
+        match writer.delete(&self.inner.database(), &k, Some(&encode_add(tag, element))) {
+            Err(StoreError::KeyValuePairNotFound) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// The adds in effect and the tombstones of every key's set, to be merged into other
+    /// replicas.
+    pub fn entries<'r, R, I, C>(&self, reader: &'r R) -> Result<Vec<OrSetEntry>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        let mut entries = vec![];
+        let mut iter = reader.open_ro_cursor(&self.inner.database())?.into_iter();
+        while let Some(result) = iter.next() {
+            let (key, bytes) = result.map_err(|e| e.into())?;
+            let (tag, element) = decode_add(bytes)?;
+            entries.push(OrSetEntry {
+                key: key.to_vec(),
+                element: element.to_vec(),
+                tag,
+                removed: false,
+            });
+        }
+        let mut iter = reader.open_ro_cursor(&self.tombstones)?.into_iter();
+        while let Some(result) = iter.next() {
+            let (key, element) = result.map_err(|e| e.into())?;
+            if key.len() < TIMESTAMP_LEN {
+                return Err(StoreError::DatabaseCorrupted);
+            }
+            let (key, tag) = key.split_at(key.len() - TIMESTAMP_LEN);
+            entries.push(OrSetEntry {
+                key: key.to_vec(),
+                element: element.to_vec(),
+                tag: Timestamp::from_bytes(tag)?,
+                removed: true,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Merge the entries of another replica into this one, keeping the adds that
+    /// neither replica removed.
+    pub fn merge<T, I>(&self, writer: &mut Writer<T>, entries: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        I: IntoIterator<Item = OrSetEntry>,
+    {
+        for entry in entries {
+            if entry.removed {
+                self.merge_remove(writer, &entry.key, &entry.tag, &entry.element)?;
+            } else {
+                self.merge_add(writer, &entry.key, &entry.tag, &entry.element)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        self.inner.clear(writer)?;
+        writer.clear(&self.tombstones)
+    }
+}
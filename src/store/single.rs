@@ -8,14 +8,15 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use std::marker::PhantomData;
+use std::{borrow::Cow, marker::PhantomData};
 
 use crate::{
     backend::{BackendDatabase, BackendFlags, BackendIter, BackendRoCursor, BackendRwTransaction},
-    error::StoreError,
+    error::{DataError, StoreError},
     helpers::read_transform,
     readwrite::{Readable, Writer},
-    value::Value,
+    store::{KeyRange, Store},
+    value::{compress, json_pointer, Compression, Encoding, Type, Value},
 };
 
 type EmptyResult = Result<(), StoreError>;
@@ -23,19 +24,46 @@ type EmptyResult = Result<(), StoreError>;
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub struct SingleStore<D> {
     db: D,
+    compression: Compression,
 }
 
 pub struct Iter<'i, I> {
     iter: I,
+    compression: Compression,
     phantom: PhantomData<&'i ()>,
 }
 
+/// Iterates over the keys of a `KeyRange`, see `SingleStore::iter_range`.
+pub struct RangeIter<'i, I> {
+    iter: Iter<'i, I>,
+    end: Option<Vec<u8>>,
+}
+
+/// Iterates over the JSON values of a store whose field at a given path satisfies a
+/// predicate, see `SingleStore::filter_json`.
+pub struct JsonFilter<'i, I, F> {
+    iter: Iter<'i, I>,
+    tokens: Vec<String>,
+    predicate: F,
+}
+
+impl<D> Store for SingleStore<D>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> D {
+        self.db
+    }
+}
+
 impl<D> SingleStore<D>
 where
     D: BackendDatabase,
 {
-    pub(crate) fn new(db: D) -> SingleStore<D> {
-        SingleStore { db }
+    pub(crate) fn new(db: D, compression: Compression) -> SingleStore<D> {
+        SingleStore { db, compression }
     }
 
     pub fn get<'r, R, K>(&self, reader: &'r R, k: K) -> Result<Option<Value<'r>>, StoreError>
@@ -43,7 +71,10 @@ where
         R: Readable<'r, Database = D>,
         K: AsRef<[u8]>,
     {
-        reader.get(&self.db, &k)
+        match reader.get(&self.db, &k)? {
+            Some(Value::Blob(Cow::Borrowed(bytes))) => self.decode(bytes).map(Some),
+            value => Ok(value),
+        }
     }
 
     pub fn stat<'r, R>(&self, reader: &'r R) -> Result<R::Stat, StoreError>
@@ -53,13 +84,40 @@ where
         reader.stat(&self.db)
     }
 
+    /// Encode a value as the store holds it.
+    pub(crate) fn encode(&self, v: &Value) -> Result<Vec<u8>, StoreError> {
+        Ok(v.encode_compressed(Encoding::Bincode, self.compression)?)
+    }
+
+    /// Decode bytes as the store holds them, see `SingleStore::get`.
+    pub(crate) fn decode<'r>(&self, bytes: &'r [u8]) -> Result<Value<'r>, StoreError> {
+        read_transform(Ok(bytes), Encoding::Bincode, self.compression)
+    }
+
     // TODO: flags
     pub fn put<T, K>(&self, writer: &mut Writer<T>, k: K, v: &Value) -> EmptyResult
     where
         T: BackendRwTransaction<Database = D>,
         K: AsRef<[u8]>,
     {
-        writer.put(&self.db, &k, v, T::Flags::empty())
+        writer.put(&self.db, &k, &self.encode(v)?, T::Flags::empty())
+    }
+
+    /// Put bytes already encoded as a value of type `t`, such as those of a typed store,
+    /// compressing them as `put` would.
+    pub(crate) fn put_encoded<T, K>(
+        &self,
+        writer: &mut Writer<T>,
+        k: K,
+        t: Type,
+        bytes: Vec<u8>,
+    ) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+    {
+        let bytes = compress(t, bytes, self.compression);
+        writer.put(&self.db, &k, &bytes, T::Flags::empty())
     }
 
     #[cfg(not(feature = "db-dup-sort"))]
@@ -80,6 +138,71 @@ where
         writer.delete(&self.db, &k, None)
     }
 
+    /// Put each pair, in order. The pairs are all encoded before any is put, then handed
+    /// to the backend in a single call, so none is put if one fails to encode.
+    pub fn put_many<'v, T, K, I>(&self, writer: &mut Writer<T>, pairs: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = (K, Value<'v>)>,
+    {
+        let encoded = pairs
+            .into_iter()
+            .map(|(k, v)| Ok((k, self.encode(&v)?)))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        let pairs: Vec<(&[u8], &[u8])> = encoded
+            .iter()
+            .map(|(k, bytes)| (k.as_ref(), bytes.as_slice()))
+            .collect();
+        writer.put_many(&self.db, &pairs)
+    }
+
+    /// Put pairs in strictly ascending order of key, whose keys all come after those the
+    /// store already holds, faster than `put_many`: LMDB appends them to the end of the
+    /// database, and SafeMode merges them into it in bulk. Fails with
+    /// `StoreError::KeysNotSorted`, having put none of them, if they aren't.
+    pub fn put_sorted<'v, T, K, I>(&self, writer: &mut Writer<T>, pairs: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = (K, Value<'v>)>,
+    {
+        let encoded = pairs
+            .into_iter()
+            .map(|(k, v)| Ok((k, self.encode(&v)?)))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        let pairs: Vec<(&[u8], &[u8])> = encoded
+            .iter()
+            .map(|(k, bytes)| (k.as_ref(), bytes.as_slice()))
+            .collect();
+        writer.put_sorted(&self.db, &pairs)
+    }
+
+    /// Delete each key, in order, failing if one isn't there.
+    pub fn delete_many<T, K, I>(&self, writer: &mut Writer<T>, keys: I) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = K>,
+    {
+        for k in keys {
+            self.delete(writer, k)?;
+        }
+        Ok(())
+    }
+
+    /// Delete the keys of `range`, returning how many there were.
+    pub fn delete_range<T>(
+        &self,
+        writer: &mut Writer<T>,
+        range: &KeyRange,
+    ) -> Result<usize, StoreError>
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        writer.delete_range(&self.db, range)
+    }
+
     pub fn iter_start<'r, R, I, C>(&self, reader: &'r R) -> Result<Iter<'r, I>, StoreError>
     where
         R: Readable<'r, Database = D, RoCursor = C>,
@@ -91,6 +214,7 @@ where
 
         Ok(Iter {
             iter,
+            compression: self.compression,
             phantom: PhantomData,
         })
     }
@@ -107,10 +231,108 @@ where
 
         Ok(Iter {
             iter,
+            compression: self.compression,
             phantom: PhantomData,
         })
     }
 
+    /// Split the store's keys into at most `n` ranges holding about as many keys each, which
+    /// cover the whole key space in order, e.g. to scan them in parallel from the clones of
+    /// a `SharedReader`. This reads every key, but no values.
+    pub fn partition<'r, R, I, C>(
+        &self,
+        reader: &'r R,
+        n: usize,
+    ) -> Result<Vec<KeyRange>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        let mut count = 0;
+        let mut iter = reader.open_ro_cursor(&self.db)?.into_iter();
+        while let Some(result) = iter.next() {
+            result.map_err(|e| e.into())?;
+            count += 1;
+        }
+
+        // Each range but the last ends at the first key of the next one.
+        let n = n.max(1).min(count.max(1));
+        let mut ends = Vec::with_capacity(n);
+        let mut index = 0;
+        let mut iter = reader.open_ro_cursor(&self.db)?.into_iter();
+        while ends.len() + 1 < n {
+            let (key, _) = match iter.next() {
+                Some(result) => result.map_err(|e| e.into())?,
+                None => break,
+            };
+            if index == (ends.len() + 1) * count / n {
+                ends.push(key.to_vec());
+            }
+            index += 1;
+        }
+
+        let mut start = None;
+        let mut ranges = Vec::with_capacity(n);
+        for end in ends {
+            ranges.push(KeyRange {
+                start: start.replace(end.clone()),
+                end: Some(end),
+            });
+        }
+        ranges.push(KeyRange { start, end: None });
+        Ok(ranges)
+    }
+
+    /// Iterate over the keys of `range`, in order.
+    pub fn iter_range<'r, R, I, C>(
+        &self,
+        reader: &'r R,
+        range: &KeyRange,
+    ) -> Result<RangeIter<'r, I>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        let iter = match &range.start {
+            Some(start) => self.iter_from(reader, start.clone())?,
+            None => self.iter_start(reader)?,
+        };
+
+        Ok(RangeIter {
+            iter,
+            end: range.end.clone(),
+        })
+    }
+
+    /// Iterate over the JSON values whose field at `path`, a JSON Pointer as accepted by
+    /// `Value::json_path`, exists and satisfies `predicate`. Values that aren't JSON are
+    /// skipped, as are blobs that merely start with the JSON tag. Only the matching field
+    /// of each value is parsed into a `serde_json::Value`. Documents are borrowed from the
+    /// reader, unless they had to be decompressed.
+    pub fn filter_json<'r, R, I, C, F>(
+        &self,
+        reader: &'r R,
+        path: &str,
+        predicate: F,
+    ) -> Result<JsonFilter<'r, I, F>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        F: FnMut(&serde_json::Value) -> bool,
+    {
+        let tokens = json_pointer(path)?;
+        let iter = self.iter_start(reader)?;
+
+        Ok(JsonFilter {
+            iter,
+            tokens,
+            predicate,
+        })
+    }
+
     pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
     where
         D: BackendDatabase,
@@ -129,11 +351,89 @@ where
     fn next(&mut self) -> Option<Self::Item> {
         match self.iter.next() {
             None => None,
-            Some(Ok((key, bytes))) => match read_transform(Ok(bytes)) {
-                Ok(val) => Some(Ok((key, val))),
-                Err(err) => Some(Err(err)),
-            },
+            Some(Ok((key, bytes))) => {
+                match read_transform(Ok(bytes), Encoding::Bincode, self.compression) {
+                    Ok(val) => Some(Ok((key, val))),
+                    Err(err) => Some(Err(err)),
+                }
+            }
             Some(Err(err)) => Some(Err(err.into())),
         }
     }
 }
+
+impl<'i, I> Iterator for RangeIter<'i, I>
+where
+    I: BackendIter<'i>,
+{
+    type Item = Result<(&'i [u8], Value<'i>), StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.iter.next()? {
+            Ok((key, _)) if self.end.as_deref().map_or(false, |end| key >= end) => None,
+            result => Some(result),
+        }
+    }
+}
+
+/// The JSON document held by the given bytes, as read back from a store, if they hold one.
+fn json_doc(bytes: Cow<[u8]>) -> Option<Cow<str>> {
+    match bytes {
+        Cow::Borrowed(bytes) => tagged_json(bytes).map(Cow::Borrowed),
+        Cow::Owned(bytes) => tagged_json(&bytes).map(|doc| Cow::Owned(doc.to_owned())),
+    }
+}
+
+/// Blobs are held untagged, so they may start with the JSON tag. Only bytes that are
+/// exactly the encoding of a JSON value, length included, are taken for one.
+fn tagged_json(bytes: &[u8]) -> Option<&str> {
+    if bytes.first() != Some(&Type::Json.to_tag()) {
+        return None;
+    }
+    match Value::from_tagged_slice(bytes) {
+        Ok(Value::Json(Cow::Borrowed(doc))) => {
+            let size = Value::Json(Cow::Borrowed(doc)).serialized_size().ok()?;
+            if size == bytes.len() as u64 {
+                Some(doc)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+impl<'i, I, F> Iterator for JsonFilter<'i, I, F>
+where
+    I: BackendIter<'i>,
+    F: FnMut(&serde_json::Value) -> bool,
+{
+    type Item = Result<(&'i [u8], Cow<'i, str>), StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, bytes) = match self.iter.next()? {
+                Ok((key, Value::Blob(bytes))) => (key, bytes),
+                Ok(_) => continue,
+                Err(err) => return Some(Err(err)),
+            };
+            let doc = match json_doc(bytes) {
+                Some(doc) => doc,
+                None => continue,
+            };
+            let matched = match Value::Json(Cow::Borrowed(&doc)).json_field(&self.tokens) {
+                Ok(Some(Value::Json(field))) => match serde_json::from_str(&field) {
+                    Ok(field) => (self.predicate)(&field),
+                    Err(err) => return Some(Err(StoreError::DataError(err.into()))),
+                },
+                Ok(_) => false,
+                // Values put as JSON were checked, so this is a blob that looks like one.
+                Err(DataError::InvalidJson(_)) => continue,
+                Err(err) => return Some(Err(err.into())),
+            };
+            if matched {
+                return Some(Ok((key, doc)));
+            }
+        }
+    }
+}
@@ -0,0 +1,534 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Stores that hold a single serde type per database. Values are encoded with
+//! bincode, prefixed by the `Type::Serde` tag and a caller-chosen schema version
+//! byte, so that reading data written by an incompatible schema fails with a
+//! `DataError` instead of silently producing garbage.
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    backend::{BackendDatabase, BackendIter, BackendRoCursor, BackendRwTransaction},
+    error::{DataError, StoreError},
+    readwrite::{Readable, Writer},
+    store::{
+        single::{self, SingleStore},
+        Store,
+    },
+    value::{Compression, Type, Value},
+};
+
+#[cfg(feature = "db-dup-sort")]
+use crate::{
+    store::multi::{self, MultiStore},
+    value::Encoding,
+};
+
+type EmptyResult = Result<(), StoreError>;
+
+/// Encode `v` as `[Type::Serde tag, version, bincode payload...]`.
+pub(crate) fn encode<V>(version: u8, v: &V) -> Result<Vec<u8>, DataError>
+where
+    V: Serialize,
+{
+    let mut bytes = vec![Type::Serde.to_tag(), version];
+    bincode::serialize_into(&mut bytes, v)?;
+    Ok(bytes)
+}
+
+/// Decode bytes produced by `encode`, checking both the type tag and the version.
+pub(crate) fn decode<V>(version: u8, bytes: &[u8]) -> Result<V, DataError>
+where
+    V: DeserializeOwned,
+{
+    let (tag, data) = bytes.split_first().ok_or(DataError::Empty)?;
+    let t = Type::from_tag(*tag)?;
+    if t != Type::Serde {
+        return Err(DataError::UnexpectedType {
+            expected: Type::Serde,
+            actual: t,
+        });
+    }
+    let (actual, payload) = data.split_first().ok_or(DataError::Empty)?;
+    if *actual != version {
+        return Err(DataError::UnexpectedVersion {
+            expected: version,
+            actual: *actual,
+        });
+    }
+    bincode::deserialize(payload).map_err(|e| DataError::DecodingError {
+        value_type: Type::Serde,
+        err: e,
+    })
+}
+
+fn decode_value<V>(version: u8, value: Value) -> Result<V, StoreError>
+where
+    V: DeserializeOwned,
+{
+    match value {
+        Value::Blob(bytes) => decode(version, &bytes).map_err(StoreError::DataError),
+        value => Err(StoreError::DataError(DataError::UnexpectedType {
+            expected: Type::Serde,
+            actual: value.value_type(),
+        })),
+    }
+}
+
+pub struct TypedStore<D, K, V> {
+    inner: SingleStore<D>,
+    version: u8,
+    phantom: PhantomData<(K, V)>,
+}
+
+// Derives would also bound these on `K` and `V`, which the stores only name.
+macro_rules! impl_typed_store {
+    ($store:ident) => {
+        impl<D: fmt::Debug, K, V> fmt::Debug for $store<D, K, V> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.debug_struct(stringify!($store))
+                    .field("inner", &self.inner)
+                    .field("version", &self.version)
+                    .finish()
+            }
+        }
+
+        impl<D: Clone, K, V> Clone for $store<D, K, V> {
+            fn clone(&self) -> $store<D, K, V> {
+                $store {
+                    inner: self.inner.clone(),
+                    version: self.version,
+                    phantom: PhantomData,
+                }
+            }
+        }
+
+        impl<D: Copy, K, V> Copy for $store<D, K, V> {}
+
+        impl<D: PartialEq, K, V> PartialEq for $store<D, K, V> {
+            fn eq(&self, other: &$store<D, K, V>) -> bool {
+                self.inner == other.inner && self.version == other.version
+            }
+        }
+
+        impl<D: Eq, K, V> Eq for $store<D, K, V> {}
+    };
+}
+
+impl_typed_store!(TypedStore);
+
+#[cfg(feature = "db-dup-sort")]
+impl_typed_store!(TypedMultiStore);
+
+/// Iterates over `(key, value)` pairs, decoding each value into `V`.
+pub struct Iter<I, V> {
+    iter: I,
+    version: u8,
+    phantom: PhantomData<V>,
+}
+
+impl<D, K, V> Store for TypedStore<D, K, V>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> D {
+        self.inner.database()
+    }
+}
+
+impl<D, K, V> TypedStore<D, K, V>
+where
+    D: BackendDatabase,
+    K: AsRef<[u8]>,
+    V: Serialize + DeserializeOwned,
+{
+    pub(crate) fn new(db: D, version: u8, compression: Compression) -> TypedStore<D, K, V> {
+        TypedStore {
+            inner: SingleStore::new(db, compression),
+            version,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The schema version written with, and expected from, every value in this store.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn get<'r, R>(&self, reader: &'r R, k: K) -> Result<Option<V>, StoreError>
+    where
+        R: Readable<'r, Database = D>,
+    {
+        match self.inner.get(reader, k)? {
+            Some(value) => decode_value(self.version, value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put<T>(&self, writer: &mut Writer<T>, k: K, v: &V) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        let bytes = encode(self.version, v)?;
+        self.inner.put_encoded(writer, k, Type::Serde, bytes)
+    }
+
+    pub fn delete<T>(&self, writer: &mut Writer<T>, k: K) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        self.inner.delete(writer, k)
+    }
+
+    pub fn iter_start<'r, R, I, C>(
+        &self,
+        reader: &'r R,
+    ) -> Result<Iter<single::Iter<'r, I>, V>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        Ok(Iter::new(self.inner.iter_start(reader)?, self.version))
+    }
+
+    pub fn iter_from<'r, R, I, C>(
+        &self,
+        reader: &'r R,
+        k: K,
+    ) -> Result<Iter<single::Iter<'r, I>, V>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: 'r,
+    {
+        Ok(Iter::new(self.inner.iter_from(reader, k)?, self.version))
+    }
+
+    pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        self.inner.clear(writer)
+    }
+}
+
+#[cfg(feature = "db-dup-sort")]
+pub struct TypedMultiStore<D, K, V> {
+    inner: MultiStore<D>,
+    version: u8,
+    phantom: PhantomData<(K, V)>,
+}
+
+#[cfg(feature = "db-dup-sort")]
+impl<D, K, V> Store for TypedMultiStore<D, K, V>
+where
+    D: BackendDatabase,
+{
+    type Database = D;
+
+    fn database(&self) -> D {
+        self.inner.database()
+    }
+}
+
+#[cfg(feature = "db-dup-sort")]
+impl<D, K, V> TypedMultiStore<D, K, V>
+where
+    D: BackendDatabase,
+    K: AsRef<[u8]>,
+    V: Serialize + DeserializeOwned,
+{
+    pub(crate) fn new(db: D, version: u8, compression: Compression) -> TypedMultiStore<D, K, V> {
+        TypedMultiStore {
+            inner: MultiStore::with_encoding(db, Encoding::Bincode, compression),
+            version,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The schema version written with, and expected from, every value in this store.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Provides a cursor to all of the values for the duplicate entries that match this
+    /// key
+    pub fn get<'r, R, I, C>(
+        &self,
+        reader: &'r R,
+        k: K,
+    ) -> Result<Iter<multi::Iter<'r, I>, V>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+        K: 'r,
+    {
+        Ok(Iter::new(self.inner.get(reader, k)?, self.version))
+    }
+
+    /// Provides the first value that matches this key
+    pub fn get_first<'r, R>(&self, reader: &'r R, k: K) -> Result<Option<V>, StoreError>
+    where
+        R: Readable<'r, Database = D>,
+    {
+        match self.inner.get_first(reader, k)? {
+            Some(value) => decode_value(self.version, value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn iter_start<'r, R, I, C>(
+        &self,
+        reader: &'r R,
+    ) -> Result<Iter<multi::Iter<'r, I>, V>, StoreError>
+    where
+        R: Readable<'r, Database = D, RoCursor = C>,
+        I: BackendIter<'r>,
+        C: BackendRoCursor<'r, Iter = I>,
+    {
+        Ok(Iter::new(self.inner.iter_start(reader)?, self.version))
+    }
+
+    /// Insert a value at the specified key, next to any existing duplicates.
+    pub fn put<T>(&self, writer: &mut Writer<T>, k: K, v: &V) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        let bytes = encode(self.version, v)?;
+        self.inner.put_encoded(writer, k, Type::Serde, bytes)
+    }
+
+    pub fn delete_all<T>(&self, writer: &mut Writer<T>, k: K) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        self.inner.delete_all(writer, k)
+    }
+
+    /// Delete the single duplicate equal to `v` once encoded.
+    pub fn delete<T>(&self, writer: &mut Writer<T>, k: K, v: &V) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        let bytes = encode(self.version, v)?;
+        self.inner.delete_encoded(writer, k, Type::Serde, bytes)
+    }
+
+    pub fn clear<T>(&self, writer: &mut Writer<T>) -> EmptyResult
+    where
+        T: BackendRwTransaction<Database = D>,
+    {
+        self.inner.clear(writer)
+    }
+}
+
+impl<I, V> Iter<I, V> {
+    fn new(iter: I, version: u8) -> Iter<I, V> {
+        Iter {
+            iter,
+            version,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'i, I, V> Iterator for Iter<I, V>
+where
+    I: Iterator<Item = Result<(&'i [u8], Value<'i>), StoreError>>,
+    V: DeserializeOwned,
+{
+    type Item = Result<(&'i [u8], V), StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.iter.next() {
+            None => None,
+            Some(Ok((key, value))) => Some(decode_value(self.version, value).map(|v| (key, v))),
+            Some(Err(err)) => Some(Err(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    use std::{borrow::Cow, fs};
+
+    use serde_derive::{Deserialize, Serialize};
+    use tempfile::Builder;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        id: u64,
+        name: String,
+    }
+
+    #[test]
+    fn test_typed_round_trip() {
+        let root = Builder::new()
+            .prefix("test_typed_round_trip")
+            .tempdir()
+            .expect("tempdir");
+        fs::create_dir_all(root.path()).expect("dir created");
+
+        let k = Rkv::new::<backend::SafeMode>(root.path()).expect("new succeeded");
+        let s = k
+            .open_typed::<_, &str, Record>("s", StoreOptions::create(), 1)
+            .expect("open");
+        // Stores are `Copy` whatever their value type.
+        let copy = s;
+        assert_eq!(copy, s);
+
+        let foo = Record {
+            id: 1,
+            name: "foo".into(),
+        };
+        let bar = Record {
+            id: 2,
+            name: "bar".into(),
+        };
+
+        let mut writer = k.write().expect("writer");
+        s.put(&mut writer, "foo", &foo).expect("write");
+        s.put(&mut writer, "bar", &bar).expect("write");
+        assert_eq!(s.get(&writer, "foo").expect("read"), Some(foo));
+        writer.commit().expect("committed");
+
+        let reader = k.read().expect("reader");
+        assert_eq!(s.get(&reader, "bar").expect("read").map(|r| r.id), Some(2));
+        assert_eq!(s.get(&reader, "baz").expect("read"), None);
+        let keys: Vec<_> = s
+            .iter_start(&reader)
+            .expect("iter")
+            .map(|r| r.expect("decoded").0)
+            .collect();
+        assert_eq!(keys, vec![&b"bar"[..], &b"foo"[..]]);
+    }
+
+    #[test]
+    fn test_typed_version_mismatch() {
+        let root = Builder::new()
+            .prefix("test_typed_version_mismatch")
+            .tempdir()
+            .expect("tempdir");
+        fs::create_dir_all(root.path()).expect("dir created");
+
+        let k = Rkv::new::<backend::SafeMode>(root.path()).expect("new succeeded");
+        let v1 = k
+            .open_typed::<_, &str, u64>("s", StoreOptions::create(), 1)
+            .expect("open");
+        let v2 = k
+            .open_typed::<_, &str, u64>("s", StoreOptions::default(), 2)
+            .expect("open");
+        let raw = k.open_single("s", StoreOptions::default()).expect("open");
+
+        let mut writer = k.write().expect("writer");
+        v1.put(&mut writer, "n", &42).expect("write");
+        raw.put(
+            &mut writer,
+            "raw",
+            &Value::Blob(Cow::Borrowed(&[Type::Str.to_tag(), 1])),
+        )
+        .expect("write");
+        writer.commit().expect("committed");
+
+        let reader = k.read().expect("reader");
+        match v2.get(&reader, "n") {
+            Err(StoreError::DataError(DataError::UnexpectedVersion {
+                expected: 2,
+                actual: 1,
+            })) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match v1.get(&reader, "raw") {
+            Err(StoreError::DataError(DataError::UnexpectedType {
+                expected: Type::Serde,
+                actual: Type::Str,
+            })) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_typed_compression() {
+        let root = Builder::new()
+            .prefix("test_typed_compression")
+            .tempdir()
+            .expect("tempdir");
+        fs::create_dir_all(root.path()).expect("dir created");
+
+        let k = Rkv::new::<backend::SafeMode>(root.path()).expect("new succeeded");
+        let opts = StoreOptions::create().with_compression(ValueCompression::Lz4 { threshold: 16 });
+        let s = k.open_typed::<_, &str, String>("s", opts, 1).expect("open");
+        let long = "x".repeat(100);
+
+        let mut writer = k.write().expect("writer");
+        s.put(&mut writer, "short", &"a".to_string())
+            .expect("write");
+        s.put(&mut writer, "long", &long).expect("write");
+        writer.commit().expect("committed");
+
+        // Values are held under their own tag, which compression keeps.
+        let reader = k.read().expect("reader");
+        let short = reader.get(&s.database(), &"short").expect("read");
+        let mut expected = vec![Type::Serde.to_tag(), 1];
+        bincode::serialize_into(&mut expected, "a").expect("encoded");
+        assert_eq!(short, Some(Value::Blob(Cow::Owned(expected))));
+        match reader.get(&s.database(), &"long").expect("read") {
+            Some(Value::Blob(bytes)) => {
+                assert_eq!(bytes[0], Type::Serde.to_tag() | 0x80);
+                assert!(bytes.len() < long.len());
+            }
+            other => panic!("unexpected value: {:?}", other),
+        }
+        assert_eq!(s.get(&reader, "long").expect("read"), Some(long));
+    }
+
+    #[cfg(feature = "db-dup-sort")]
+    #[test]
+    fn test_typed_multi() {
+        let root = Builder::new()
+            .prefix("test_typed_multi")
+            .tempdir()
+            .expect("tempdir");
+        fs::create_dir_all(root.path()).expect("dir created");
+
+        let k = Rkv::new::<backend::SafeMode>(root.path()).expect("new succeeded");
+        let s = k
+            .open_typed_multi::<_, &str, String>("s", StoreOptions::create(), 1)
+            .expect("open");
+
+        let mut writer = k.write().expect("writer");
+        s.put(&mut writer, "k", &"a".to_string()).expect("write");
+        s.put(&mut writer, "k", &"b".to_string()).expect("write");
+        s.put(&mut writer, "k", &"c".to_string()).expect("write");
+        s.delete(&mut writer, "k", &"b".to_string())
+            .expect("deleted");
+        writer.commit().expect("committed");
+
+        let reader = k.read().expect("reader");
+        let values: Vec<String> = s
+            .get(&reader, "k")
+            .expect("read")
+            .map(|r| r.expect("decoded").1)
+            .collect();
+        assert_eq!(values, vec!["a".to_string(), "c".to_string()]);
+        assert_eq!(s.get_first(&reader, "k").expect("read"), Some("a".into()));
+    }
+}
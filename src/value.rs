@@ -8,20 +8,91 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use std::fmt;
+use std::{
+    borrow::Cow,
+    cmp,
+    collections::BTreeMap,
+    convert::TryFrom,
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use arrayref::array_ref;
 use bincode::{deserialize, serialize, serialized_size};
+use lz4_flex::{compress_prepend_size, decompress_size_prepended};
 use ordered_float::OrderedFloat;
+use rust_decimal::Decimal;
+use serde_json::value::RawValue;
 use uuid::{Bytes, Uuid};
 
 use crate::error::DataError;
 
+/// Set in the tag byte of values written with `Encoding::Ordered`.
+const ORDERED_FLAG: u8 = 0b0100_0000;
+
+/// Set in the tag byte of values compressed by a store, see `Compression`.
+const COMPRESSED_FLAG: u8 = 0b1000_0000;
+
+const SIGN_BIT: u64 = 1 << 63;
+
+const WIDE_SIGN_BIT: u128 = 1 << 127;
+
+/// The first byte of an ordered decimal, see `ordered_decimal`.
+const DECIMAL_NEGATIVE: u8 = 0;
+const DECIMAL_ZERO: u8 = 1;
+const DECIMAL_POSITIVE: u8 = 2;
+
+/// How deeply arrays and maps may nest in a decoded value, so that decoding
+/// corrupt or hostile bytes can't overflow the stack.
+pub const MAX_NESTING: usize = 128;
+
+/// How a store lays out the bytes of the values it holds.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Encoding {
+    /// Values are bincode-encoded behind their type tag, except for blobs, which are
+    /// stored as-is. Since a blob's first byte can look like any tag, stores can't tell
+    /// blobs from tagged values, and hand back the stored bytes as a `Value::Blob`, which
+    /// `Value::from_tagged_slice` decodes for stores known to hold no blobs.
+    Bincode,
+    /// Values are tagged with `ORDERED_FLAG` and encoded so that their byte order equals
+    /// their semantic order: integers are big-endian (signed ones with a flipped sign
+    /// bit), floats use the IEEE 754 total order, decimals are laid out by sign, exponent
+    /// and digits, and strings and blobs are appended verbatim. This keeps duplicates of a
+    /// dup-sort store meaningfully sorted. Stores decode these values on read.
+    Ordered,
+}
+
+impl Default for Encoding {
+    fn default() -> Encoding {
+        Encoding::Bincode
+    }
+}
+
+/// Whether a store compresses the values it holds.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Compression {
+    /// Values are stored as encoded.
+    None,
+    /// Blobs, JSON values and the values of typed stores whose encoding takes at least
+    /// `threshold` bytes are
+    /// compressed with LZ4, and tagged with `COMPRESSED_FLAG`, as long as that makes them
+    /// smaller. Stores decompress them on read, handing back owned data where they'd
+    /// otherwise borrow it. Only `Encoding::Bincode` values are compressed, as compression
+    /// would break the order of `Encoding::Ordered` ones.
+    Lz4 { threshold: usize },
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression::None
+    }
+}
+
 /// We define a set of types, associated with simple integers, to annotate values stored
 /// in LMDB. This is to avoid an accidental 'cast' from a value of one type to another.
 /// For this reason we don't simply use `deserialize` from the `bincode` crate.
 #[repr(u8)]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Type {
     Bool = 1,
     U64 = 2,
@@ -32,6 +103,13 @@ pub enum Type {
     Str = 7,
     Json = 8,
     Blob = 9,
+    Serde = 10, // Bincode-encoded serde value, see `store::typed`.
+    Array = 11,
+    Map = 12,
+    I128 = 13,
+    U128 = 14,
+    Decimal = 15,
+    Timestamp = 16, // Nanosecond-precision timestamp.
 }
 
 /// We use manual tagging, because <https://github.com/serde-rs/serde/issues/610>.
@@ -56,6 +134,13 @@ impl Type {
             7 => Some(Type::Str),
             8 => Some(Type::Json),
             9 => Some(Type::Blob),
+            10 => Some(Type::Serde),
+            11 => Some(Type::Array),
+            12 => Some(Type::Map),
+            13 => Some(Type::I128),
+            14 => Some(Type::U128),
+            15 => Some(Type::Decimal),
+            16 => Some(Type::Timestamp),
             _ => None,
         }
     }
@@ -73,6 +158,13 @@ impl fmt::Display for Type {
             Type::Str => "str",
             Type::Json => "json",
             Type::Blob => "blob",
+            Type::Serde => "serde",
+            Type::Array => "array",
+            Type::Map => "map",
+            Type::I128 => "i128",
+            Type::U128 => "u128",
+            Type::Decimal => "decimal",
+            Type::Timestamp => "timestamp",
         })
     }
 }
@@ -86,8 +178,14 @@ pub enum Value<'v> {
     Instant(i64), // Millisecond-precision timestamp.
     Uuid(&'v Bytes),
     Str(&'v str),
-    Json(&'v str),
-    Blob(&'v [u8]),
+    Json(Cow<'v, str>),
+    Blob(Cow<'v, [u8]>),
+    Array(Vec<Value<'v>>),
+    Map(BTreeMap<&'v str, Value<'v>>),
+    I128(i128),
+    U128(u128),
+    Decimal(Decimal),
+    Timestamp(i128), // Nanoseconds since the Unix epoch.
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -99,8 +197,14 @@ pub enum OwnedValue {
     Instant(i64), // Millisecond-precision timestamp.
     Uuid(Uuid),
     Str(String),
-    Json(String), // TODO
+    Json(String),
     Blob(Vec<u8>),
+    Array(Vec<OwnedValue>),
+    Map(BTreeMap<String, OwnedValue>),
+    I128(i128),
+    U128(u128),
+    Decimal(Decimal),
+    Timestamp(i128), // Nanoseconds since the Unix epoch.
 }
 
 fn uuid(bytes: &[u8]) -> Result<Value, DataError> {
@@ -111,15 +215,189 @@ fn uuid(bytes: &[u8]) -> Result<Value, DataError> {
     }
 }
 
+fn ordered_u64(t: Type, data: &[u8]) -> Result<u64, DataError> {
+    if data.len() == 8 {
+        Ok(u64::from_be_bytes(*array_ref![data, 0, 8]))
+    } else {
+        Err(DataError::InvalidLength {
+            value_type: t,
+            expected: 8,
+            actual: data.len(),
+        })
+    }
+}
+
+fn ordered_u128(t: Type, data: &[u8]) -> Result<u128, DataError> {
+    if data.len() == 16 {
+        Ok(u128::from_be_bytes(*array_ref![data, 0, 16]))
+    } else {
+        Err(DataError::InvalidLength {
+            value_type: t,
+            expected: 16,
+            actual: data.len(),
+        })
+    }
+}
+
+/// Lay out a decimal so that byte order is numeric order: a sign byte, then for nonzero
+/// decimals, written as `0.d1d2...dn * 10^e` without trailing zeros, the biased exponent,
+/// the digits and a zero terminator, with all of these inverted for negative decimals so
+/// that greater magnitudes sort first. Equal decimals of different scales are laid out
+/// alike, so they're decoded with their smallest scale.
+fn ordered_decimal(v: &Decimal) -> Vec<u8> {
+    if v.is_zero() {
+        return vec![DECIMAL_ZERO];
+    }
+    let digits = v.mantissa().unsigned_abs().to_string();
+    let exponent = digits.len() as i32 - v.scale() as i32;
+    let digits = digits.trim_end_matches('0');
+    let mut bytes = Vec::with_capacity(digits.len() + 3);
+    bytes.push(DECIMAL_POSITIVE);
+    bytes.push((exponent + 128) as u8);
+    bytes.extend_from_slice(digits.as_bytes());
+    bytes.push(0);
+    if v.is_sign_negative() {
+        bytes[0] = DECIMAL_NEGATIVE;
+        bytes[1..].iter_mut().for_each(|b| *b = !*b);
+    }
+    bytes
+}
+
+fn decimal_from_ordered(data: &[u8]) -> Result<Decimal, DataError> {
+    let invalid = || DataError::DecodingError {
+        value_type: Type::Decimal,
+        err: Box::new(bincode::ErrorKind::Custom(
+            "invalid ordered decimal".to_string(),
+        )),
+    };
+    let (sign, rest) = data.split_first().ok_or_else(invalid)?;
+    let negative = match *sign {
+        DECIMAL_ZERO if rest.is_empty() => return Ok(Decimal::ZERO),
+        DECIMAL_NEGATIVE => true,
+        DECIMAL_POSITIVE => false,
+        _ => return Err(invalid()),
+    };
+    let rest: Vec<u8> = if negative {
+        rest.iter().map(|b| !b).collect()
+    } else {
+        rest.to_vec()
+    };
+    let (digits, exponent) = match rest.split_last() {
+        Some((0, [exponent, digits @ ..])) => (digits, i32::from(*exponent) - 128),
+        _ => return Err(invalid()),
+    };
+    if digits.is_empty() || digits.len() > 29 || !digits.iter().all(u8::is_ascii_digit) {
+        return Err(invalid());
+    }
+    let mut mantissa = digits
+        .iter()
+        .fold(0i128, |m, d| m * 10 + i128::from(d - b'0'));
+    let mut scale = digits.len() as i32 - exponent;
+    while scale < 0 {
+        mantissa = mantissa.checked_mul(10).ok_or_else(invalid)?;
+        scale += 1;
+    }
+    if negative {
+        mantissa = -mantissa;
+    }
+    Decimal::try_from_i128_with_scale(mantissa, scale as u32).map_err(|_| invalid())
+}
+
+fn utf8_str(t: Type, data: &[u8]) -> Result<&str, DataError> {
+    std::str::from_utf8(data).map_err(|e| DataError::DecodingError {
+        value_type: t,
+        err: Box::new(bincode::ErrorKind::InvalidUtf8Encoding(e)),
+    })
+}
+
 impl<'v> Value<'v> {
+    /// Decode a value written with either `Encoding`, which is told apart by the tag.
+    /// Compressed blobs and JSON values are decompressed into owned data.
     pub fn from_tagged_slice(slice: &'v [u8]) -> Result<Value<'v>, DataError> {
-        //let (tag, data) = slice.split_first().ok_or(DataError::Empty)?;
-        //let t = Type::from_tag(*tag)?;
-        //Value::from_type_and_data(t, data)
-        Ok(Value::Blob(slice))
+        let (tag, data) = slice.split_first().ok_or(DataError::Empty)?;
+        if tag & COMPRESSED_FLAG != 0 {
+            let t = Type::from_tag(tag & !COMPRESSED_FLAG)?;
+            let data = decompress_size_prepended(data)?;
+            match t {
+                // The version byte and payload are interpreted by `store::typed`.
+                Type::Blob | Type::Serde => Ok(Value::Blob(Cow::Owned(data))),
+                Type::Json => match Value::from_type_and_data(t, &data)? {
+                    Value::Json(v) => Ok(Value::Json(Cow::Owned(v.into_owned()))),
+                    _ => unreachable!(),
+                },
+                _ => Err(DataError::Compressed(t)),
+            }
+        } else if tag & ORDERED_FLAG != 0 {
+            let t = Type::from_tag(tag & !ORDERED_FLAG)?;
+            Value::from_type_and_ordered_data(t, data)
+        } else {
+            let t = Type::from_tag(*tag)?;
+            Value::from_type_and_data(t, data)
+        }
+    }
+
+    /// Undo the compression of bytes read from a store opened with `Compression::Lz4`,
+    /// yielding the bytes the store would have held without compression. Uncompressed
+    /// bytes are borrowed as-is. Stores do this on read, so only raw bytes, such as those
+    /// of a dump, need it. Bytes from stores that don't compress values must not go
+    /// through this, as blobs are stored untagged and could look compressed.
+    pub fn decompress(slice: &'v [u8]) -> Result<Cow<'v, [u8]>, DataError> {
+        let (tag, data) = match slice.split_first() {
+            Some((tag, data)) if tag & COMPRESSED_FLAG != 0 => (tag & !COMPRESSED_FLAG, data),
+            _ => return Ok(Cow::Borrowed(slice)),
+        };
+        let t = Type::from_tag(tag)?;
+        let data = decompress_size_prepended(data)?;
+        if t == Type::Blob {
+            return Ok(Cow::Owned(data));
+        }
+        let mut bytes = Vec::with_capacity(1 + data.len());
+        bytes.push(tag);
+        bytes.extend_from_slice(&data);
+        Ok(Cow::Owned(bytes))
+    }
+
+    fn from_type_and_ordered_data(t: Type, data: &'v [u8]) -> Result<Value<'v>, DataError> {
+        match t {
+            Type::Bool => match data {
+                [0] => Ok(Value::Bool(false)),
+                [1] => Ok(Value::Bool(true)),
+                [b] => Err(DataError::DecodingError {
+                    value_type: t,
+                    err: Box::new(bincode::ErrorKind::InvalidBoolEncoding(*b)),
+                }),
+                _ => Err(DataError::InvalidLength {
+                    value_type: t,
+                    expected: 1,
+                    actual: data.len(),
+                }),
+            },
+            Type::U64 => ordered_u64(t, data).map(Value::U64),
+            Type::I64 => ordered_u64(t, data).map(|v| Value::I64((v ^ SIGN_BIT) as i64)),
+            Type::Instant => ordered_u64(t, data).map(|v| Value::Instant((v ^ SIGN_BIT) as i64)),
+            Type::F64 => ordered_u64(t, data).map(|v| {
+                let bits = if v & SIGN_BIT != 0 { v ^ SIGN_BIT } else { !v };
+                Value::F64(OrderedFloat(f64::from_bits(bits)))
+            }),
+            Type::I128 => ordered_u128(t, data).map(|v| Value::I128((v ^ WIDE_SIGN_BIT) as i128)),
+            Type::U128 => ordered_u128(t, data).map(Value::U128),
+            Type::Timestamp => {
+                ordered_u128(t, data).map(|v| Value::Timestamp((v ^ WIDE_SIGN_BIT) as i128))
+            }
+            Type::Decimal => decimal_from_ordered(data).map(Value::Decimal),
+            Type::Uuid => uuid(data),
+            Type::Str => utf8_str(t, data).map(Value::Str),
+            Type::Json => utf8_str(t, data).map(|v| Value::Json(Cow::Borrowed(v))),
+            Type::Blob | Type::Serde => Ok(Value::Blob(Cow::Borrowed(data))),
+            // Composite values have no meaningful order, and share the compact layout.
+            Type::Array | Type::Map => Composite::decode(t, data),
+        }
     }
 
     fn from_type_and_data(t: Type, data: &'v [u8]) -> Result<Value<'v>, DataError> {
+        if t == Type::Array || t == Type::Map {
+            return Composite::decode(t, data);
+        }
         if t == Type::Uuid {
             return deserialize(data)
                 .map_err(|e| DataError::DecodingError {
@@ -135,10 +413,18 @@ impl<'v> Value<'v> {
             Type::I64 => deserialize(data).map(Value::I64),
             Type::F64 => deserialize(data).map(OrderedFloat).map(Value::F64),
             Type::Instant => deserialize(data).map(Value::Instant),
+            Type::I128 => deserialize(data).map(Value::I128),
+            Type::U128 => deserialize(data).map(Value::U128),
+            Type::Decimal => deserialize(data)
+                .map(Decimal::deserialize)
+                .map(Value::Decimal),
+            Type::Timestamp => deserialize(data).map(Value::Timestamp),
             Type::Str => deserialize(data).map(Value::Str),
-            Type::Json => deserialize(data).map(Value::Json),
-            Type::Blob => deserialize(data).map(Value::Blob),
-            Type::Uuid => {
+            Type::Json => deserialize(data).map(|v| Value::Json(Cow::Borrowed(v))),
+            Type::Blob => deserialize(data).map(|v| Value::Blob(Cow::Borrowed(v))),
+            // The version byte and payload are interpreted by `store::typed`.
+            Type::Serde => Ok(Value::Blob(Cow::Borrowed(data))),
+            Type::Uuid | Type::Array | Type::Map => {
                 // Processed above to avoid verbose duplication of error transforms.
                 unreachable!()
             }
@@ -149,6 +435,103 @@ impl<'v> Value<'v> {
         })
     }
 
+    /// Encode this value with the given `Encoding`. JSON values, including those nested
+    /// in arrays and maps, are validated first.
+    pub fn encode(&self, encoding: Encoding) -> Result<Vec<u8>, DataError> {
+        self.validate()?;
+        match encoding {
+            Encoding::Bincode => self.to_bytes(),
+            Encoding::Ordered => Ok(self.to_ordered_bytes()),
+        }
+    }
+
+    /// Encode this value as a store with the given `Encoding` and `Compression` holds it.
+    pub(crate) fn encode_compressed(
+        &self,
+        encoding: Encoding,
+        compression: Compression,
+    ) -> Result<Vec<u8>, DataError> {
+        let bytes = self.encode(encoding)?;
+        Ok(match (encoding, self) {
+            (Encoding::Bincode, Value::Blob(_)) => compress(Type::Blob, bytes, compression),
+            (Encoding::Bincode, Value::Json(_)) => compress(Type::Json, bytes, compression),
+            _ => bytes,
+        })
+    }
+
+    fn validate(&self) -> Result<(), DataError> {
+        match self {
+            Value::Json(v) => serde_json::from_str::<&RawValue>(v).map(|_| ())?,
+            Value::Array(values) => values.iter().try_for_each(Value::validate)?,
+            Value::Map(entries) => entries.values().try_for_each(Value::validate)?,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Look up the field of a JSON value at the given JSON Pointer (RFC 6901), such as
+    /// `/items/0/name`. The field is handed back as a `Value::Json` that borrows what this
+    /// value borrows, or is owned if this one is; only the objects and arrays along the
+    /// path are parsed, and only far enough to find the next field. The empty path points
+    /// at the whole document.
+    pub fn json_path(&self, path: &str) -> Result<Option<Value<'v>>, DataError> {
+        self.json_field(&json_pointer(path)?)
+    }
+
+    pub(crate) fn json_field(&self, tokens: &[String]) -> Result<Option<Value<'v>>, DataError> {
+        match self {
+            Value::Json(Cow::Borrowed(doc)) => {
+                Ok(json_field(doc, tokens)?.map(|field| Value::Json(Cow::Borrowed(field))))
+            }
+            Value::Json(Cow::Owned(doc)) => Ok(
+                json_field(doc, tokens)?.map(|field| Value::Json(Cow::Owned(field.to_string())))
+            ),
+            _ => Err(DataError::UnexpectedType {
+                expected: Type::Json,
+                actual: self.value_type(),
+            }),
+        }
+    }
+
+    fn to_ordered_bytes(&self) -> Vec<u8> {
+        let tagged = |t: Type, data: &[u8]| {
+            let mut bytes = Vec::with_capacity(1 + data.len());
+            bytes.push(t.to_tag() | ORDERED_FLAG);
+            bytes.extend_from_slice(data);
+            bytes
+        };
+        match self {
+            Value::Bool(v) => tagged(Type::Bool, &[*v as u8]),
+            Value::U64(v) => tagged(Type::U64, &v.to_be_bytes()),
+            Value::I64(v) => tagged(Type::I64, &(*v as u64 ^ SIGN_BIT).to_be_bytes()),
+            Value::Instant(v) => tagged(Type::Instant, &(*v as u64 ^ SIGN_BIT).to_be_bytes()),
+            Value::F64(v) => {
+                let bits = v.0.to_bits();
+                let bits = if bits & SIGN_BIT != 0 {
+                    !bits
+                } else {
+                    bits ^ SIGN_BIT
+                };
+                tagged(Type::F64, &bits.to_be_bytes())
+            }
+            Value::I128(v) => tagged(Type::I128, &(*v as u128 ^ WIDE_SIGN_BIT).to_be_bytes()),
+            Value::U128(v) => tagged(Type::U128, &v.to_be_bytes()),
+            Value::Decimal(v) => tagged(Type::Decimal, &ordered_decimal(v)),
+            Value::Timestamp(v) => {
+                tagged(Type::Timestamp, &(*v as u128 ^ WIDE_SIGN_BIT).to_be_bytes())
+            }
+            Value::Uuid(v) => tagged(Type::Uuid, &v[..]),
+            Value::Str(v) => tagged(Type::Str, v.as_bytes()),
+            Value::Json(v) => tagged(Type::Json, v.as_bytes()),
+            Value::Blob(v) => tagged(Type::Blob, v),
+            Value::Array(_) | Value::Map(_) => {
+                let mut bytes = vec![self.value_type().to_tag() | ORDERED_FLAG];
+                self.write_payload(&mut bytes);
+                bytes
+            }
+        }
+    }
+
     pub fn to_bytes(&self) -> Result<Vec<u8>, DataError> {
         match self {
             Value::Bool(v) => serialize(&(Type::Bool.to_tag(), *v)),
@@ -156,10 +539,15 @@ impl<'v> Value<'v> {
             Value::I64(v) => serialize(&(Type::I64.to_tag(), *v)),
             Value::F64(v) => serialize(&(Type::F64.to_tag(), v.0)),
             Value::Instant(v) => serialize(&(Type::Instant.to_tag(), *v)),
+            Value::I128(v) => serialize(&(Type::I128.to_tag(), *v)),
+            Value::U128(v) => serialize(&(Type::U128.to_tag(), *v)),
+            Value::Decimal(v) => serialize(&(Type::Decimal.to_tag(), v.serialize())),
+            Value::Timestamp(v) => serialize(&(Type::Timestamp.to_tag(), *v)),
             Value::Str(v) => serialize(&(Type::Str.to_tag(), v)),
             Value::Json(v) => serialize(&(Type::Json.to_tag(), v)),
             Value::Blob(v) => Ok(v.to_vec()),
             Value::Uuid(v) => serialize(&(Type::Uuid.to_tag(), v)),
+            Value::Array(_) | Value::Map(_) => Ok(self.to_compact_bytes()),
         }
         .map_err(DataError::EncodingError)
     }
@@ -171,13 +559,331 @@ impl<'v> Value<'v> {
             Value::I64(v) => serialized_size(&(Type::I64.to_tag(), *v)),
             Value::F64(v) => serialized_size(&(Type::F64.to_tag(), v.0)),
             Value::Instant(v) => serialized_size(&(Type::Instant.to_tag(), *v)),
+            Value::I128(v) => serialized_size(&(Type::I128.to_tag(), *v)),
+            Value::U128(v) => serialized_size(&(Type::U128.to_tag(), *v)),
+            Value::Decimal(v) => serialized_size(&(Type::Decimal.to_tag(), v.serialize())),
+            Value::Timestamp(v) => serialized_size(&(Type::Timestamp.to_tag(), *v)),
             Value::Str(v) => serialized_size(&(Type::Str.to_tag(), v)),
             Value::Json(v) => serialized_size(&(Type::Json.to_tag(), v)),
             Value::Blob(v) => serialized_size(&(Type::Blob.to_tag(), v)),
             Value::Uuid(v) => serialized_size(&(Type::Uuid.to_tag(), v)),
+            Value::Array(_) | Value::Map(_) => Ok(self.to_compact_bytes().len() as u64),
         }
         .map_err(DataError::EncodingError)
     }
+
+    pub fn value_type(&self) -> Type {
+        match self {
+            Value::Bool(_) => Type::Bool,
+            Value::U64(_) => Type::U64,
+            Value::I64(_) => Type::I64,
+            Value::F64(_) => Type::F64,
+            Value::Instant(_) => Type::Instant,
+            Value::Uuid(_) => Type::Uuid,
+            Value::Str(_) => Type::Str,
+            Value::Json(_) => Type::Json,
+            Value::Blob(_) => Type::Blob,
+            Value::Array(_) => Type::Array,
+            Value::Map(_) => Type::Map,
+            Value::I128(_) => Type::I128,
+            Value::U128(_) => Type::U128,
+            Value::Decimal(_) => Type::Decimal,
+            Value::Timestamp(_) => Type::Timestamp,
+        }
+    }
+
+    /// The point in time held by a `Timestamp` or an `Instant`.
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        let nanos = match self {
+            Value::Timestamp(v) => *v,
+            Value::Instant(v) => i128::from(*v) * 1_000_000,
+            _ => return None,
+        };
+        let offset = Duration::new(
+            u64::try_from(nanos.unsigned_abs() / 1_000_000_000).ok()?,
+            (nanos.unsigned_abs() % 1_000_000_000) as u32,
+        );
+        if nanos < 0 {
+            UNIX_EPOCH.checked_sub(offset)
+        } else {
+            UNIX_EPOCH.checked_add(offset)
+        }
+    }
+
+    /// The time elapsed since the Unix epoch for a `Timestamp` or an `Instant`,
+    /// or `None` if it predates the epoch.
+    pub fn to_duration(&self) -> Option<Duration> {
+        self.to_system_time()?.duration_since(UNIX_EPOCH).ok()
+    }
+
+    /// Arrays and maps aren't bincode-encoded, but use a compact layout in which
+    /// each element is its type tag followed by its payload, and variable-length
+    /// payloads (strings, blobs, nested arrays and maps) are prefixed with their
+    /// LEB128-encoded length or element count.
+    fn to_compact_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.write_element(&mut bytes);
+        bytes
+    }
+
+    fn write_element(&self, bytes: &mut Vec<u8>) {
+        bytes.push(self.value_type().to_tag());
+        self.write_payload(bytes);
+    }
+
+    fn write_payload(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Value::Bool(v) => bytes.push(*v as u8),
+            Value::U64(v) => bytes.extend_from_slice(&v.to_le_bytes()),
+            Value::U128(v) => bytes.extend_from_slice(&v.to_le_bytes()),
+            Value::I128(v) | Value::Timestamp(v) => bytes.extend_from_slice(&v.to_le_bytes()),
+            Value::Decimal(v) => bytes.extend_from_slice(&v.serialize()),
+            Value::I64(v) | Value::Instant(v) => bytes.extend_from_slice(&v.to_le_bytes()),
+            Value::F64(v) => bytes.extend_from_slice(&v.0.to_le_bytes()),
+            Value::Uuid(v) => bytes.extend_from_slice(&v[..]),
+            Value::Str(v) => {
+                write_varint(bytes, v.len() as u64);
+                bytes.extend_from_slice(v.as_bytes());
+            }
+            Value::Json(v) => {
+                write_varint(bytes, v.len() as u64);
+                bytes.extend_from_slice(v.as_bytes());
+            }
+            Value::Blob(v) => {
+                write_varint(bytes, v.len() as u64);
+                bytes.extend_from_slice(v);
+            }
+            Value::Array(values) => {
+                write_varint(bytes, values.len() as u64);
+                for value in values {
+                    value.write_element(bytes);
+                }
+            }
+            Value::Map(entries) => {
+                write_varint(bytes, entries.len() as u64);
+                for (key, value) in entries {
+                    write_varint(bytes, key.len() as u64);
+                    bytes.extend_from_slice(key.as_bytes());
+                    value.write_element(bytes);
+                }
+            }
+        }
+    }
+}
+
+/// Compress the bincode-encoded bytes of a value of type `t` as a store with the given
+/// `Compression` holds them, see `Value::encode_compressed`.
+pub(crate) fn compress(t: Type, bytes: Vec<u8>, compression: Compression) -> Vec<u8> {
+    let threshold = match compression {
+        Compression::Lz4 { threshold } => threshold,
+        Compression::None => return bytes,
+    };
+    // Blobs are written untagged, so they're compressed whole. Those that start with
+    // a byte that looks like a compressed tag are compressed regardless of their size,
+    // so that they're never mistaken for a compressed value.
+    let (payload, escaped) = match t {
+        Type::Blob => (
+            &bytes[..],
+            bytes.first().map_or(false, |b| b & COMPRESSED_FLAG != 0),
+        ),
+        _ => (&bytes[1..], false),
+    };
+    if bytes.len() < threshold && !escaped {
+        return bytes;
+    }
+    let mut compressed = vec![t.to_tag() | COMPRESSED_FLAG];
+    compressed.extend_from_slice(&compress_prepend_size(payload));
+    if compressed.len() < bytes.len() || escaped {
+        compressed
+    } else {
+        bytes
+    }
+}
+
+/// The field of a JSON document at the given reference tokens, see `Value::json_path`.
+fn json_field<'d>(doc: &'d str, tokens: &[String]) -> Result<Option<&'d str>, DataError> {
+    let mut field: &'d RawValue = serde_json::from_str(doc)?;
+    for token in tokens {
+        let next = match field.get().trim_start().as_bytes().first() {
+            Some(b'{') => {
+                let object: BTreeMap<String, &'d RawValue> = serde_json::from_str(field.get())?;
+                object.get(token).copied()
+            }
+            Some(b'[') => {
+                let array: Vec<&'d RawValue> = serde_json::from_str(field.get())?;
+                array_index(token).and_then(|i| array.get(i).copied())
+            }
+            _ => None,
+        };
+        match next {
+            Some(next) => field = next,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(field.get()))
+}
+
+/// The array index a reference token stands for. RFC 6901 only allows digits, without
+/// leading zeros, so tokens such as `01` or `+1` point at no element.
+fn array_index(token: &str) -> Option<usize> {
+    let digits = token.bytes().all(|b| b.is_ascii_digit());
+    if !digits || token.is_empty() || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+/// Split a JSON Pointer into its unescaped reference tokens.
+pub(crate) fn json_pointer(path: &str) -> Result<Vec<String>, DataError> {
+    if path.is_empty() {
+        return Ok(vec![]);
+    }
+    if !path.starts_with('/') {
+        return Err(DataError::InvalidJsonPath(path.to_string()));
+    }
+    Ok(path[1..]
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        bytes.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+}
+
+/// Decodes the compact layout of arrays and maps. Strings and blobs nested in them
+/// borrow from the decoded slice, only the containers themselves are allocated.
+struct Composite<'v> {
+    t: Type,
+    data: &'v [u8],
+    depth: usize,
+}
+
+impl<'v> Composite<'v> {
+    fn decode(t: Type, data: &'v [u8]) -> Result<Value<'v>, DataError> {
+        let mut composite = Composite { t, data, depth: 0 };
+        let value = composite.payload(t)?;
+        if !composite.data.is_empty() {
+            return Err(DataError::InvalidLength {
+                value_type: t,
+                expected: data.len() - composite.data.len(),
+                actual: data.len(),
+            });
+        }
+        Ok(value)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'v [u8], DataError> {
+        if n > self.data.len() {
+            return Err(DataError::InvalidLength {
+                value_type: self.t,
+                expected: n,
+                actual: self.data.len(),
+            });
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<usize, DataError> {
+        let mut n: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n as usize);
+            }
+        }
+        Err(DataError::DecodingError {
+            value_type: self.t,
+            err: Box::new(bincode::ErrorKind::Custom("varint overflow".into())),
+        })
+    }
+
+    fn element(&mut self) -> Result<Value<'v>, DataError> {
+        let tag = self.take(1)?[0];
+        let t = Type::from_tag(tag)?;
+        self.payload(t)
+    }
+
+    fn payload(&mut self, t: Type) -> Result<Value<'v>, DataError> {
+        Ok(match t {
+            Type::Bool => match self.take(1)?[0] {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                b => {
+                    return Err(DataError::DecodingError {
+                        value_type: t,
+                        err: Box::new(bincode::ErrorKind::InvalidBoolEncoding(b)),
+                    })
+                }
+            },
+            Type::U64 => Value::U64(u64::from_le_bytes(*array_ref![self.take(8)?, 0, 8])),
+            Type::I64 => Value::I64(i64::from_le_bytes(*array_ref![self.take(8)?, 0, 8])),
+            Type::Instant => Value::Instant(i64::from_le_bytes(*array_ref![self.take(8)?, 0, 8])),
+            Type::F64 => Value::F64(OrderedFloat(f64::from_le_bytes(*array_ref![
+                self.take(8)?,
+                0,
+                8
+            ]))),
+            Type::I128 => Value::I128(i128::from_le_bytes(*array_ref![self.take(16)?, 0, 16])),
+            Type::U128 => Value::U128(u128::from_le_bytes(*array_ref![self.take(16)?, 0, 16])),
+            Type::Decimal => {
+                Value::Decimal(Decimal::deserialize(*array_ref![self.take(16)?, 0, 16]))
+            }
+            Type::Timestamp => {
+                Value::Timestamp(i128::from_le_bytes(*array_ref![self.take(16)?, 0, 16]))
+            }
+            Type::Uuid => uuid(self.take(16)?)?,
+            Type::Str => {
+                let n = self.varint()?;
+                Value::Str(utf8_str(t, self.take(n)?)?)
+            }
+            Type::Json => {
+                let n = self.varint()?;
+                Value::Json(Cow::Borrowed(utf8_str(t, self.take(n)?)?))
+            }
+            Type::Blob => {
+                let n = self.varint()?;
+                Value::Blob(Cow::Borrowed(self.take(n)?))
+            }
+            Type::Array | Type::Map => return self.container(t),
+            // Serde values are only ever stored at the top level.
+            Type::Serde => return Err(DataError::UnknownType(t.to_tag())),
+        })
+    }
+
+    /// An array or a map, which may nest up to `MAX_NESTING` levels deep.
+    fn container(&mut self, t: Type) -> Result<Value<'v>, DataError> {
+        if self.depth == MAX_NESTING {
+            return Err(DataError::TooDeeplyNested(MAX_NESTING));
+        }
+        self.depth += 1;
+        let n = self.varint()?;
+        let value = if t == Type::Array {
+            // Don't trust the count for preallocation, each element takes a byte at least.
+            let mut values = Vec::with_capacity(cmp::min(n, self.data.len()));
+            for _ in 0..n {
+                values.push(self.element()?);
+            }
+            Value::Array(values)
+        } else {
+            let mut entries = BTreeMap::new();
+            for _ in 0..n {
+                let len = self.varint()?;
+                let key = utf8_str(t, self.take(len)?)?;
+                entries.insert(key, self.element()?);
+            }
+            Value::Map(entries)
+        };
+        self.depth -= 1;
+        Ok(value)
+    }
 }
 
 impl<'v> From<&'v Value<'v>> for OwnedValue {
@@ -190,8 +896,18 @@ impl<'v> From<&'v Value<'v>> for OwnedValue {
             Value::Instant(v) => OwnedValue::Instant(*v),
             Value::Uuid(v) => OwnedValue::Uuid(Uuid::from_bytes(**v)),
             Value::Str(v) => OwnedValue::Str((*v).to_string()),
-            Value::Json(v) => OwnedValue::Json((*v).to_string()),
+            Value::Json(v) => OwnedValue::Json(v.to_string()),
             Value::Blob(v) => OwnedValue::Blob(v.to_vec()),
+            Value::I128(v) => OwnedValue::I128(*v),
+            Value::U128(v) => OwnedValue::U128(*v),
+            Value::Decimal(v) => OwnedValue::Decimal(*v),
+            Value::Timestamp(v) => OwnedValue::Timestamp(*v),
+            Value::Array(v) => OwnedValue::Array(v.iter().map(OwnedValue::from).collect()),
+            Value::Map(v) => OwnedValue::Map(
+                v.iter()
+                    .map(|(k, v)| ((*k).to_string(), OwnedValue::from(v)))
+                    .collect(),
+            ),
         }
     }
 }
@@ -206,16 +922,498 @@ impl<'v> From<&'v OwnedValue> for Value<'v> {
             OwnedValue::Instant(v) => Value::Instant(*v),
             OwnedValue::Uuid(v) => Value::Uuid(v.as_bytes()),
             OwnedValue::Str(v) => Value::Str(v),
-            OwnedValue::Json(v) => Value::Json(v),
-            OwnedValue::Blob(v) => Value::Blob(v),
+            OwnedValue::Json(v) => Value::Json(Cow::Borrowed(v)),
+            OwnedValue::Blob(v) => Value::Blob(Cow::Borrowed(v)),
+            OwnedValue::I128(v) => Value::I128(*v),
+            OwnedValue::U128(v) => Value::U128(*v),
+            OwnedValue::Decimal(v) => Value::Decimal(*v),
+            OwnedValue::Timestamp(v) => Value::Timestamp(*v),
+            OwnedValue::Array(v) => Value::Array(v.iter().map(Value::from).collect()),
+            OwnedValue::Map(v) => Value::Map(
+                v.iter()
+                    .map(|(k, v)| (k.as_str(), Value::from(v)))
+                    .collect(),
+            ),
         }
     }
 }
 
+fn nanos_since_epoch(time: SystemTime) -> i128 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_nanos() as i128,
+        Err(e) => -(e.duration().as_nanos() as i128),
+    }
+}
+
+impl<'v> From<SystemTime> for Value<'v> {
+    fn from(time: SystemTime) -> Value<'v> {
+        Value::Timestamp(nanos_since_epoch(time))
+    }
+}
+
+/// A `Duration` is taken as the time elapsed since the Unix epoch.
+impl<'v> From<Duration> for Value<'v> {
+    fn from(elapsed: Duration) -> Value<'v> {
+        Value::Timestamp(elapsed.as_nanos() as i128)
+    }
+}
+
+impl From<SystemTime> for OwnedValue {
+    fn from(time: SystemTime) -> OwnedValue {
+        OwnedValue::Timestamp(nanos_since_epoch(time))
+    }
+}
+
+impl From<Duration> for OwnedValue {
+    fn from(elapsed: Duration) -> OwnedValue {
+        OwnedValue::Timestamp(elapsed.as_nanos() as i128)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_ordered_encoding_round_trip() {
+        let uuid =
+            Uuid::from_bytes(*b"\x9f\xe2\xc4\xe9\x3f\x65\x4f\xdb\xb2\x4c\x02\xb1\x52\x59\x71\x6c");
+        let values = vec![
+            Value::Bool(true),
+            Value::U64(u64::MAX),
+            Value::I64(i64::MIN),
+            Value::F64(OrderedFloat(-0.25)),
+            Value::Instant(1_558_020_865_224),
+            Value::Uuid(uuid.as_bytes()),
+            Value::Str("¡Hola"),
+            Value::Json(Cow::Borrowed(r#"{"foo":"bar"}"#)),
+            Value::Blob(Cow::Borrowed(b"blob")),
+        ];
+        for value in values {
+            let bytes = value.encode(Encoding::Ordered).unwrap();
+            assert_eq!(Value::from_tagged_slice(&bytes).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_ordered_encoding_sorts() {
+        fn sorted(values: Vec<Value>) -> bool {
+            let bytes: Vec<_> = values
+                .iter()
+                .map(|v| v.encode(Encoding::Ordered).unwrap())
+                .collect();
+            bytes.windows(2).all(|w| w[0] < w[1])
+        }
+
+        assert!(sorted(vec![
+            Value::I64(i64::MIN),
+            Value::I64(-256),
+            Value::I64(-1),
+            Value::I64(0),
+            Value::I64(1),
+            Value::I64(i64::MAX),
+        ]));
+        assert!(sorted(vec![
+            Value::U64(0),
+            Value::U64(255),
+            Value::U64(256),
+            Value::U64(u64::MAX),
+        ]));
+        assert!(sorted(
+            [
+                f64::NEG_INFINITY,
+                -1e10,
+                -1.5,
+                -0.0,
+                0.0,
+                f64::MIN_POSITIVE,
+                2.5,
+                f64::INFINITY,
+            ]
+            .iter()
+            .map(|v| Value::F64(OrderedFloat(*v)))
+            .collect()
+        ));
+        assert!(sorted(vec![
+            Value::Str("a"),
+            Value::Str("ab"),
+            Value::Str("b")
+        ]));
+        assert!(sorted(
+            [
+                Decimal::MIN,
+                Decimal::new(-12, 0),
+                Decimal::new(-11, 1),
+                Decimal::new(-1, 0),
+                Decimal::new(-99, 2),
+                Decimal::new(-1, 28),
+                Decimal::ZERO,
+                Decimal::new(1, 28),
+                Decimal::new(1, 1),
+                Decimal::new(101, 3),
+                Decimal::new(11, 2),
+                Decimal::new(1, 0),
+                Decimal::new(1_000, 0),
+                Decimal::MAX,
+            ]
+            .iter()
+            .map(|v| Value::Decimal(*v))
+            .collect()
+        ));
+    }
+
+    #[test]
+    fn test_ordered_encoding_errors() {
+        assert!(matches!(
+            Value::from_tagged_slice(&[]),
+            Err(DataError::Empty)
+        ));
+        assert!(matches!(
+            Value::from_tagged_slice(&[Type::U64.to_tag() | ORDERED_FLAG, 1, 2]),
+            Err(DataError::InvalidLength {
+                value_type: Type::U64,
+                expected: 8,
+                actual: 2,
+            })
+        ));
+        assert!(matches!(
+            Value::from_tagged_slice(&[0x3f | ORDERED_FLAG]),
+            Err(DataError::UnknownType(0x3f))
+        ));
+        let decimal = Type::Decimal.to_tag() | ORDERED_FLAG;
+        for bytes in [
+            &[decimal][..],
+            &[decimal, DECIMAL_ZERO, 0],
+            &[decimal, DECIMAL_POSITIVE, 129, b'1'],
+            &[decimal, DECIMAL_POSITIVE, 129, b'x', 0],
+            &[decimal, DECIMAL_POSITIVE, 250, b'1', 0],
+        ]
+        .iter()
+        {
+            assert!(matches!(
+                Value::from_tagged_slice(bytes),
+                Err(DataError::DecodingError {
+                    value_type: Type::Decimal,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_wide_values_round_trip() {
+        let values = vec![
+            Value::I128(i128::MIN),
+            Value::U128(u128::MAX),
+            Value::Decimal(Decimal::new(-1_234_567, 4)),
+            Value::Timestamp(1_558_020_865_224_123_456),
+        ];
+        for value in values {
+            let bytes = value.to_bytes().unwrap();
+            assert_eq!(bytes.len(), 17);
+            assert_eq!(value.serialized_size().unwrap(), 17);
+            assert_eq!(Value::from_tagged_slice(&bytes).unwrap(), value);
+
+            let ordered = value.encode(Encoding::Ordered).unwrap();
+            assert_eq!(Value::from_tagged_slice(&ordered).unwrap(), value);
+
+            let array = Value::Array(vec![value]);
+            let bytes = array.to_bytes().unwrap();
+            assert_eq!(Value::from_tagged_slice(&bytes).unwrap(), array);
+        }
+
+        for v in [
+            Decimal::MIN,
+            Decimal::new(-5, 28),
+            Decimal::new(1_500, 3),
+            Decimal::MAX,
+        ]
+        .iter()
+        {
+            let ordered = Value::Decimal(*v).encode(Encoding::Ordered).unwrap();
+            match Value::from_tagged_slice(&ordered).unwrap() {
+                Value::Decimal(decoded) => {
+                    assert_eq!(decoded, *v);
+                    assert_eq!(decoded.scale(), v.normalize().scale());
+                }
+                decoded => panic!("expected a decimal, got {:?}", decoded),
+            }
+        }
+
+        let i128s = [i128::MIN, -1, 0, 1, i128::MAX];
+        let bytes: Vec<_> = i128s
+            .iter()
+            .map(|v| Value::I128(*v).encode(Encoding::Ordered).unwrap())
+            .collect();
+        assert!(bytes.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_timestamp_conversions() {
+        let time = UNIX_EPOCH + Duration::new(1_558_020_865, 224_123_456);
+        let value = Value::from(time);
+        assert_eq!(value, Value::Timestamp(1_558_020_865_224_123_456));
+        assert_eq!(value.to_system_time(), Some(time));
+        assert_eq!(
+            value.to_duration(),
+            Some(Duration::new(1_558_020_865, 224_123_456))
+        );
+        assert_eq!(
+            Value::from(Duration::new(1_558_020_865, 224_123_456)),
+            value
+        );
+
+        let before = UNIX_EPOCH - Duration::new(1, 500);
+        let value = Value::from(before);
+        assert_eq!(value, Value::Timestamp(-1_000_000_500));
+        assert_eq!(value.to_system_time(), Some(before));
+        assert_eq!(value.to_duration(), None);
+
+        assert_eq!(
+            Value::Instant(1_500).to_system_time(),
+            Some(UNIX_EPOCH + Duration::from_millis(1_500))
+        );
+        assert_eq!(Value::I64(1_500).to_system_time(), None);
+    }
+
+    #[test]
+    fn test_json_validation() {
+        assert!(Value::Json(Cow::Borrowed(r#"{"foo": [1, 2]}"#))
+            .encode(Encoding::Bincode)
+            .is_ok());
+        assert!(matches!(
+            Value::Json(Cow::Borrowed(r#"{"foo": "#)).encode(Encoding::Bincode),
+            Err(DataError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            Value::Array(vec![Value::Json(Cow::Borrowed("nope"))]).encode(Encoding::Ordered),
+            Err(DataError::InvalidJson(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_path() {
+        let text = r#"{"name": "rkv", "a/b": {"~": [10, {"x": null}]}}"#;
+        let doc = Value::Json(Cow::Borrowed(text));
+        assert_eq!(
+            doc.json_path("/name").unwrap(),
+            Some(Value::Json(Cow::Borrowed(r#""rkv""#)))
+        );
+        assert_eq!(
+            doc.json_path("/a~1b/~0/0").unwrap(),
+            Some(Value::Json(Cow::Borrowed("10")))
+        );
+        assert_eq!(
+            doc.json_path("/a~1b/~0/1/x").unwrap(),
+            Some(Value::Json(Cow::Borrowed("null")))
+        );
+        assert_eq!(
+            doc.json_path("").unwrap(),
+            Some(Value::Json(Cow::Borrowed(text)))
+        );
+        assert_eq!(doc.json_path("/missing").unwrap(), None);
+        assert_eq!(doc.json_path("/a~1b/~0/2").unwrap(), None);
+        assert_eq!(doc.json_path("/a~1b/~0/01").unwrap(), None);
+        assert_eq!(doc.json_path("/a~1b/~0/+1").unwrap(), None);
+        assert_eq!(doc.json_path("/a~1b/~0/").unwrap(), None);
+        assert_eq!(doc.json_path("/name/0").unwrap(), None);
+        assert!(matches!(
+            doc.json_path("name"),
+            Err(DataError::InvalidJsonPath(_))
+        ));
+        assert!(matches!(
+            Value::Str("{}").json_path(""),
+            Err(DataError::UnexpectedType {
+                expected: Type::Json,
+                actual: Type::Str,
+            })
+        ));
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        let lz4 = Compression::Lz4 { threshold: 64 };
+        let json = format!("[{}]", vec!["\"rkv\""; 100].join(","));
+        let blob = vec![0x89; 1000];
+        for value in [
+            Value::Json(Cow::Borrowed(&json)),
+            Value::Blob(Cow::Borrowed(&blob)),
+        ]
+        .iter()
+        {
+            let plain = value.encode(Encoding::Bincode).unwrap();
+            let bytes = value.encode_compressed(Encoding::Bincode, lz4).unwrap();
+            assert!(bytes.len() < plain.len());
+            assert_eq!(bytes[0], value.value_type().to_tag() | COMPRESSED_FLAG);
+            let decompressed = Value::decompress(&bytes).unwrap();
+            assert!(matches!(decompressed, Cow::Owned(_)));
+            assert_eq!(decompressed.as_ref(), &plain[..]);
+        }
+        let bytes = Value::Json(Cow::Borrowed(&json))
+            .encode_compressed(Encoding::Bincode, lz4)
+            .unwrap();
+        match Value::from_tagged_slice(&bytes).unwrap() {
+            Value::Json(Cow::Owned(doc)) => assert_eq!(doc, json),
+            v => panic!("expected owned json, got {:?}", v),
+        }
+        let bytes = Value::Blob(Cow::Borrowed(&blob))
+            .encode_compressed(Encoding::Bincode, lz4)
+            .unwrap();
+        match Value::from_tagged_slice(&bytes).unwrap() {
+            Value::Blob(Cow::Owned(bytes)) => assert_eq!(bytes, blob),
+            v => panic!("expected an owned blob, got {:?}", v),
+        }
+
+        // Small values, incompressible ones, ordered ones and those of other types are left
+        // alone.
+        let small = Value::Str("rkv");
+        let incompressible: Vec<u8> = (0..=255).collect();
+        let ordered = Value::Json(Cow::Borrowed(&json));
+        for (value, encoding) in [
+            (small, Encoding::Bincode),
+            (Value::Str(&json), Encoding::Bincode),
+            (
+                Value::Blob(Cow::Borrowed(&incompressible[..128])),
+                Encoding::Bincode,
+            ),
+            (ordered, Encoding::Ordered),
+        ]
+        .iter()
+        {
+            let bytes = value.encode_compressed(*encoding, lz4).unwrap();
+            assert_eq!(bytes, value.encode(*encoding).unwrap());
+            assert!(matches!(Value::decompress(&bytes), Ok(Cow::Borrowed(_))));
+        }
+
+        // Blobs that could be mistaken for compressed values are compressed regardless.
+        let escaped = [0x89, 0];
+        let bytes = Value::Blob(Cow::Borrowed(&escaped))
+            .encode_compressed(Encoding::Bincode, lz4)
+            .unwrap();
+        assert_eq!(bytes[0], Type::Blob.to_tag() | COMPRESSED_FLAG);
+        assert_eq!(Value::decompress(&bytes).unwrap().as_ref(), &escaped[..]);
+    }
+
+    #[test]
+    fn test_decompression_errors() {
+        assert!(matches!(
+            Value::decompress(&[Type::Json.to_tag() | COMPRESSED_FLAG, 9, 0, 0, 0, 1]),
+            Err(DataError::DecompressionError(_))
+        ));
+        assert!(matches!(
+            Value::decompress(&[0x3f | COMPRESSED_FLAG]),
+            Err(DataError::UnknownType(0x3f))
+        ));
+
+        // Only blobs and JSON values are compressed.
+        let json = format!("[{}]", vec!["\"rkv\""; 100].join(","));
+        let mut bytes = Value::Json(Cow::Borrowed(&json))
+            .encode_compressed(Encoding::Bincode, Compression::Lz4 { threshold: 64 })
+            .unwrap();
+        bytes[0] = Type::Str.to_tag() | COMPRESSED_FLAG;
+        assert!(matches!(
+            Value::from_tagged_slice(&bytes),
+            Err(DataError::Compressed(Type::Str))
+        ));
+    }
+
+    fn composite() -> Value<'static> {
+        let mut map = BTreeMap::new();
+        map.insert("name", Value::Str("rkv"));
+        map.insert(
+            "tags",
+            Value::Array(vec![Value::Str("lmdb"), Value::Str("safe")]),
+        );
+        map.insert("empty", Value::Map(BTreeMap::new()));
+        Value::Array(vec![
+            Value::Bool(false),
+            Value::U64(300),
+            Value::I64(-1),
+            Value::F64(OrderedFloat(1.5)),
+            Value::Instant(1_558_020_865_224),
+            Value::Json(Cow::Borrowed(r#"{"foo":"bar"}"#)),
+            Value::Blob(Cow::Borrowed(&[0, 1, 2])),
+            Value::Map(map),
+        ])
+    }
+
+    #[test]
+    fn test_composite_round_trip() {
+        let value = composite();
+        let bytes = value.to_bytes().unwrap();
+        assert_eq!(value.serialized_size().unwrap(), bytes.len() as u64);
+        assert_eq!(Value::from_tagged_slice(&bytes).unwrap(), value);
+
+        let ordered = value.encode(Encoding::Ordered).unwrap();
+        assert_eq!(ordered[0], Type::Array.to_tag() | ORDERED_FLAG);
+        assert_eq!(Value::from_tagged_slice(&ordered).unwrap(), value);
+
+        let owned = OwnedValue::from(&value);
+        assert_eq!(Value::from(&owned), value);
+    }
+
+    #[test]
+    fn test_composite_errors() {
+        let bytes = composite().to_bytes().unwrap();
+        assert!(matches!(
+            Value::from_tagged_slice(&bytes[..bytes.len() - 1]),
+            Err(DataError::InvalidLength {
+                value_type: Type::Array,
+                ..
+            })
+        ));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            Value::from_tagged_slice(&trailing),
+            Err(DataError::InvalidLength {
+                value_type: Type::Array,
+                ..
+            })
+        ));
+
+        // A one-element array holding an unknown tag, then one holding a serde value.
+        assert!(matches!(
+            Value::from_tagged_slice(&[Type::Array.to_tag(), 1, 0x3f]),
+            Err(DataError::UnknownType(0x3f))
+        ));
+        assert!(matches!(
+            Value::from_tagged_slice(&[Type::Array.to_tag(), 1, Type::Serde.to_tag(), 1]),
+            Err(DataError::UnknownType(10))
+        ));
+    }
+
+    #[test]
+    fn test_composite_nesting() {
+        // Arrays holding one array each, down to an empty one, built as bytes since
+        // a value this deep would overflow the stack when dropped.
+        let nested = |depth: usize| {
+            let mut bytes = vec![Type::Array.to_tag()];
+            for _ in 1..depth {
+                bytes.extend_from_slice(&[1, Type::Array.to_tag()]);
+            }
+            bytes.push(0);
+            bytes
+        };
+        assert!(Value::from_tagged_slice(&nested(MAX_NESTING)).is_ok());
+        assert!(matches!(
+            Value::from_tagged_slice(&nested(MAX_NESTING + 1)),
+            Err(DataError::TooDeeplyNested(MAX_NESTING))
+        ));
+        assert!(matches!(
+            Value::from_tagged_slice(&nested(100_000)),
+            Err(DataError::TooDeeplyNested(MAX_NESTING))
+        ));
+
+        let mut ordered = nested(100_000);
+        ordered[0] |= ORDERED_FLAG;
+        assert!(matches!(
+            Value::from_tagged_slice(&ordered),
+            Err(DataError::TooDeeplyNested(MAX_NESTING))
+        ));
+    }
+
     #[test]
     fn test_value_serialized_size() {
         // | Value enum    | tag: 1 byte   |     value_payload        |
@@ -240,7 +1438,12 @@ mod tests {
         // );
         // assert_eq!(Value::Str("hello!").serialized_size().unwrap(), 15);
         // assert_eq!(Value::Str("¡Hola").serialized_size().unwrap(), 15);
-        assert_eq!(Value::Blob(b"hello!").serialized_size().unwrap(), 6);
+        assert_eq!(
+            Value::Blob(Cow::Borrowed(b"hello!"))
+                .serialized_size()
+                .unwrap(),
+            6
+        );
         // assert_eq!(
         //     uuid(b"\x9f\xe2\xc4\xe9\x3f\x65\x4f\xdb\xb2\x4c\x02\xb1\x52\x59\x71\x6c")
         //         .unwrap()
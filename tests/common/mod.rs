@@ -0,0 +1,20 @@
+// Copyright 2018-2019 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Helpers shared by the integration tests.
+
+use std::borrow::Cow;
+
+use rkv::Value;
+
+/// A value as stores hand it back, which is as the tagged blob they hold it as.
+pub fn stored(value: Value) -> Value<'static> {
+    Value::Blob(Cow::Owned(value.to_bytes().expect("encoded")))
+}
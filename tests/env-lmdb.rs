@@ -13,24 +13,36 @@
 #![allow(clippy::complexity)]
 
 use std::{
+    borrow::Cow,
     fs,
     path::Path,
     str,
     sync::{Arc, RwLock},
     thread,
+    time::{Duration, UNIX_EPOCH},
 };
 
 use byteorder::{ByteOrder, LittleEndian};
+use rust_decimal::Decimal;
 use tempfile::Builder;
 
 use rkv::{
     backend::{
         BackendEnvironmentBuilder, BackendInfo, BackendStat, Lmdb, LmdbDatabase, LmdbEnvironment,
-        LmdbRwTransaction,
+        LmdbRwTransaction, SafeMode,
     },
-    EnvironmentFlags, Rkv, SingleStore, StoreError, StoreOptions, Value, Writer,
+    hlc::Clock,
+    snapshot::{Keypair, PublicKey, SecretKey},
+    store::{lww::LwwEntry, merkle::EMPTY_HASH, orset::OrSetEntry},
+    Change, ChangeOp, ChangeSet, DataError, Difference, EnvironmentFlags, KeyRange, MerkleStore,
+    Mutation, RetryOptions, Rkv, SignedSnapshot, SingleStore, Store, StoreError, StoreOptions,
+    Value, ValueCompression, ValueEncoding, Writer,
 };
 
+mod common;
+
+use common::stored;
+
 fn check_rkv(k: &Rkv<LmdbEnvironment>) {
     let _ = k
         .open_single(None, StoreOptions::create())
@@ -916,7 +928,7 @@ fn test_concurrent_read_transactions_prohibited() {
             println!("Thread was {:?}", t);
         }
         Err(e) => {
-            println!("Got error {:?}", e);
+            panic!("Got error {:?}", e);
         }
         _ => {
             panic!("Expected error.");
@@ -924,6 +936,74 @@ fn test_concurrent_read_transactions_prohibited() {
     }
 }
 
+#[test]
+fn test_concurrent_read_transactions_no_tls() {
+    let root = Builder::new()
+        .prefix("test_concurrent_reads_no_tls")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let mut builder = Rkv::environment_builder::<Lmdb>();
+    builder.set_no_tls(true);
+    let k = Rkv::from_builder(root.path(), builder).expect("rkv");
+    let s = k.open_single(None, StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    s.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    writer.commit().expect("committed");
+
+    // Each reader has its own snapshot, taken when it began.
+    let first = k.read().expect("reader");
+    let mut writer = k.write().expect("writer");
+    s.put(&mut writer, "foo", &Value::I64(999)).expect("wrote");
+    writer.commit().expect("committed");
+    let second = k.read().expect("reader");
+
+    assert_eq!(
+        s.get(&first, "foo").expect("read"),
+        Some(stored(Value::I64(1234)))
+    );
+    assert_eq!(
+        s.get(&second, "foo").expect("read"),
+        Some(stored(Value::I64(999)))
+    );
+}
+
+#[test]
+fn test_reader_reset_renew() {
+    let root = Builder::new()
+        .prefix("test_reader_reset_renew")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let s = k.open_single("s", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    s.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    assert_eq!(
+        s.get(&reader, "foo").expect("read"),
+        Some(stored(Value::I64(1234)))
+    );
+    let inactive = reader.reset();
+
+    let mut writer = k.write().expect("writer");
+    s.put(&mut writer, "foo", &Value::I64(999)).expect("wrote");
+    writer.commit().expect("committed");
+
+    // Once renewed, the reader sees what was committed in the meantime.
+    let reader = inactive.renew().expect("renewed");
+    assert_eq!(
+        s.get(&reader, "foo").expect("read"),
+        Some(stored(Value::I64(999)))
+    );
+}
+
 #[test]
 fn test_isolation() {
     let root = Builder::new()
@@ -970,6 +1050,1023 @@ fn test_isolation() {
     assert_eq!(s.get(&reader, "foo").expect("read"), Some(Value::I64(999)));
 }
 
+#[test]
+fn test_wide_values() {
+    let root = Builder::new()
+        .prefix("test_wide_values")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let time = UNIX_EPOCH + Duration::new(1_558_020_865, 224_123_456);
+    let values = [
+        Value::I128(i128::MIN),
+        Value::U128(u128::MAX),
+        Value::Decimal(Decimal::new(-1_234_567, 4)),
+        Value::from(time),
+    ];
+
+    {
+        let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        for (i, value) in values.iter().enumerate() {
+            sk.put(&mut writer, [i as u8], value).expect("wrote");
+        }
+        writer.commit().expect("committed");
+    }
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k
+        .open_single("sk", StoreOptions::default())
+        .expect("opened");
+    let reader = k.read().expect("reader");
+    for (i, value) in values.iter().enumerate() {
+        match sk.get(&reader, [i as u8]).expect("read") {
+            Some(Value::Blob(bytes)) => {
+                assert_eq!(bytes.len() as u64, value.serialized_size().expect("sized"));
+                assert_eq!(&Value::from_tagged_slice(&bytes).expect("decoded"), value);
+            }
+            v => panic!("expected a blob, got {:?}", v),
+        }
+    }
+    assert_eq!(
+        Value::from_tagged_slice(&values[3].to_bytes().expect("encoded"))
+            .expect("decoded")
+            .to_system_time(),
+        Some(time)
+    );
+}
+
+#[test]
+fn test_filter_json() {
+    let root = Builder::new()
+        .prefix("test_filter_json")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    sk.put(
+        &mut writer,
+        "a",
+        &Value::Json(Cow::Borrowed(r#"{"size": 3, "tags": ["x"]}"#)),
+    )
+    .expect("wrote");
+    sk.put(
+        &mut writer,
+        "b",
+        &Value::Json(Cow::Borrowed(r#"{"size": 12}"#)),
+    )
+    .expect("wrote");
+    sk.put(
+        &mut writer,
+        "c",
+        &Value::Json(Cow::Borrowed(r#"{"tags": []}"#)),
+    )
+    .expect("wrote");
+    sk.put(&mut writer, "d", &Value::Str("not json"))
+        .expect("wrote");
+    // Blobs are held untagged, so these look like JSON values: one is followed by
+    // another byte, the other doesn't parse.
+    let mut trailing = Value::Json(Cow::Borrowed(r#"{"size": 99}"#))
+        .to_bytes()
+        .expect("encoded");
+    trailing.push(0);
+    sk.put(&mut writer, "f", &Value::Blob(Cow::Owned(trailing)))
+        .expect("wrote");
+    let invalid = Value::Json(Cow::Borrowed("{")).to_bytes().expect("encoded");
+    sk.put(&mut writer, "g", &Value::Blob(Cow::Owned(invalid)))
+        .expect("wrote");
+    match sk.put(&mut writer, "e", &Value::Json(Cow::Borrowed("{"))) {
+        Err(StoreError::DataError(DataError::InvalidJson(_))) => (),
+        v => panic!("expected invalid json error, got {:?}", v),
+    }
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let big: Vec<_> = sk
+        .filter_json(&reader, "/size", |size| size.as_u64() > Some(5))
+        .expect("filtered")
+        .map(|r| r.expect("matched"))
+        .collect();
+    assert_eq!(big, vec![(&b"b"[..], r#"{"size": 12}"#.into())]);
+
+    let tagged: Vec<_> = sk
+        .filter_json(&reader, "/tags/0", |_| true)
+        .expect("filtered")
+        .map(|r| r.expect("matched").0)
+        .collect();
+    assert_eq!(tagged, vec![&b"a"[..]]);
+
+    assert!(sk.filter_json(&reader, "size", |_| true).is_err());
+}
+
+#[test]
+fn test_compression() {
+    let root = Builder::new()
+        .prefix("test_compression")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k
+        .open_single(
+            "sk",
+            StoreOptions::create().with_compression(ValueCompression::Lz4 { threshold: 256 }),
+        )
+        .expect("opened");
+
+    let doc = format!(r#"{{"size": 12, "padding": "{}"}}"#, "x".repeat(10_000));
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "doc", &Value::Json(Cow::Borrowed(&doc)))
+        .expect("wrote");
+    sk.put(
+        &mut writer,
+        "small",
+        &Value::Json(Cow::Borrowed(r#"{"size": 3}"#)),
+    )
+    .expect("wrote");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    match sk.get(&reader, "doc").expect("read") {
+        Some(Value::Blob(bytes)) => {
+            assert!(matches!(bytes, Cow::Owned(_)));
+            assert_eq!(
+                Value::from_tagged_slice(&bytes).expect("decoded"),
+                Value::Json(Cow::Borrowed(&doc))
+            );
+        }
+        v => panic!("expected a blob, got {:?}", v),
+    }
+    match sk.get(&reader, "small").expect("read") {
+        Some(Value::Blob(bytes)) => assert_eq!(
+            Value::from_tagged_slice(&bytes).expect("decoded"),
+            Value::Json(Cow::Borrowed(r#"{"size": 3}"#))
+        ),
+        v => panic!("expected a blob, got {:?}", v),
+    }
+
+    let stat = sk.stat(&reader).expect("stat");
+    assert_eq!(stat.overflow_pages(), 0);
+
+    let docs: Vec<_> = sk
+        .filter_json(&reader, "/size", |_| true)
+        .expect("filtered")
+        .map(|r| r.expect("matched").1.into_owned())
+        .collect();
+    assert_eq!(docs, vec![doc, r#"{"size": 3}"#.to_string()]);
+
+    // Stores must be opened with the compression they were created with, whatever the
+    // threshold. Stores can't be opened while a transaction is alive.
+    drop(reader);
+    let lz4 = ValueCompression::Lz4 { threshold: 16 };
+    k.open_single("sk", StoreOptions::default().with_compression(lz4))
+        .expect("opened");
+    assert!(matches!(
+        k.open_single("sk", StoreOptions::default()),
+        Err(StoreError::CompressionMismatch)
+    ));
+    let plain = k
+        .open_single("plain", StoreOptions::create())
+        .expect("opened");
+    let mut writer = k.write().expect("writer");
+    plain
+        .put(&mut writer, "foo", &Value::Blob(Cow::Borrowed(&[0xff])))
+        .expect("wrote");
+    writer.commit().expect("committed");
+    assert!(matches!(
+        k.open_single("plain", StoreOptions::default().with_compression(lz4)),
+        Err(StoreError::CompressionMismatch)
+    ));
+}
+
+#[test]
+fn test_subscribe() {
+    let root = Builder::new()
+        .prefix("test_subscribe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let other = k
+        .open_single("other", StoreOptions::create())
+        .expect("opened");
+    let changes = k.subscribe(&sk, |change| !change.key.starts_with(b"skip"));
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    sk.put(&mut writer, "skip", &Value::I64(2)).expect("wrote");
+    other
+        .put(&mut writer, "foo", &Value::I64(3))
+        .expect("wrote");
+    sk.put(&mut writer, "bar", &Value::I64(4)).expect("wrote");
+    // Nothing is received until the writer commits.
+    assert!(changes.try_recv().is_err());
+    writer.commit().expect("committed");
+
+    let put = |key: &str| Change {
+        db: sk.database(),
+        key: key.as_bytes().to_vec(),
+        op: ChangeOp::Put,
+    };
+    assert_eq!(
+        changes.try_recv().expect("received"),
+        vec![put("foo"), put("bar")]
+    );
+    assert!(changes.try_recv().is_err());
+
+    // Aborted writers, and writers that only change other stores, are never received.
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "baz", &Value::I64(5)).expect("wrote");
+    writer.abort();
+    let mut writer = k.write().expect("writer");
+    other
+        .put(&mut writer, "baz", &Value::I64(6))
+        .expect("wrote");
+    sk.put(&mut writer, "skip", &Value::I64(7)).expect("wrote");
+    writer.commit().expect("committed");
+    assert!(changes.try_recv().is_err());
+
+    let mut writer = k.write().expect("writer");
+    sk.delete(&mut writer, "foo").expect("deleted");
+    sk.clear(&mut writer).expect("cleared");
+    writer.commit().expect("committed");
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(8)).expect("wrote");
+    writer.commit().expect("committed");
+
+    // Batches are received in commit order, on any thread.
+    let received = thread::spawn(move || changes.iter().take(2).collect::<Vec<_>>())
+        .join()
+        .expect("joined");
+    assert_eq!(
+        received,
+        vec![
+            vec![
+                Change {
+                    db: sk.database(),
+                    key: b"foo".to_vec(),
+                    op: ChangeOp::Delete,
+                },
+                Change {
+                    db: sk.database(),
+                    key: vec![],
+                    op: ChangeOp::Clear,
+                },
+            ],
+            vec![put("foo")],
+        ]
+    );
+
+    // Writers keep working once the receiver is dropped.
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(9)).expect("wrote");
+    writer.commit().expect("committed");
+}
+
+#[test]
+fn test_change_log() {
+    let root = Builder::new()
+        .prefix("test_change_log")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+    let replica_root = Builder::new()
+        .prefix("test_change_log_replica")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(replica_root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    k.enable_change_log().expect("enabled");
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    sk.put(&mut writer, "bar", &Value::I64(1)).expect("wrote");
+    writer.commit().expect("committed");
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "baz", &Value::I64(3)).expect("wrote");
+    writer.abort();
+    let mut writer = k.write().expect("writer");
+    sk.delete(&mut writer, "foo").expect("deleted");
+    sk.put(&mut writer, "baz", &Value::I64(4)).expect("wrote");
+    sk.put(&mut writer, "bar", &Value::I64(2)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let changes_since = |seq| {
+        let reader = k.read().expect("reader");
+        let sets: Result<Vec<_>, _> = k.changes_since(&reader, seq)?.collect();
+        sets
+    };
+    let sets = changes_since(0).expect("changes");
+    assert_eq!(
+        sets.iter().map(|set| set.seq).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    assert_eq!(
+        sets[1].mutations[0],
+        Mutation::Delete {
+            store: Some("sk".to_owned()),
+            key: b"foo".to_vec(),
+            value: None,
+        }
+    );
+    assert_eq!(changes_since(1).expect("changes"), sets[1..].to_vec());
+
+    // Replaying sets is idempotent, and skips those already replayed.
+    let replica = Rkv::new::<Lmdb>(replica_root.path()).expect("new succeeded");
+    assert_eq!(
+        replica.apply_changes(sets[..1].to_vec()).expect("applied"),
+        1
+    );
+    assert_eq!(replica.apply_changes(sets.clone()).expect("applied"), 2);
+    assert_eq!(replica.apply_changes(sets.clone()).expect("applied"), 2);
+    let replica_sk = replica
+        .open_single("sk", StoreOptions::default())
+        .expect("opened");
+    {
+        let reader = replica.read().expect("reader");
+        let get = |key| match replica_sk.get(&reader, key).expect("read") {
+            Some(Value::Blob(Cow::Borrowed(bytes))) => {
+                Some(Value::from_tagged_slice(bytes).expect("decoded"))
+            }
+            v => v,
+        };
+        assert_eq!(get("foo"), None);
+        assert_eq!(get("bar"), Some(Value::I64(2)));
+        assert_eq!(get("baz"), Some(Value::I64(4)));
+    }
+    assert!(matches!(
+        replica.changes_since(&replica.read().expect("reader"), 0),
+        Err(StoreError::ChangeLogDisabled)
+    ));
+
+    // Compacting drops the puts that a later delete or put of the same key undoes.
+    assert_eq!(k.compact_changes(2).expect("compacted"), 2);
+    let compacted = changes_since(0).expect("changes");
+    assert_eq!(compacted.len(), 1);
+    assert_eq!(compacted[0].seq, 2);
+    assert_eq!(compacted[0].mutations, sets[1].mutations);
+    assert_eq!(replica.apply_changes(compacted).expect("applied"), 2);
+
+    // Truncating makes the sets before it unavailable, but numbering carries on.
+    assert_eq!(k.truncate_changes(2).expect("truncated"), 1);
+    assert!(matches!(
+        changes_since(1),
+        Err(StoreError::ChangesTruncated(2))
+    ));
+    assert!(changes_since(2).expect("changes").is_empty());
+    let mut writer = k.write().expect("writer");
+    sk.clear(&mut writer).expect("cleared");
+    writer.commit().expect("committed");
+    let sets = changes_since(2).expect("changes");
+    assert_eq!(
+        sets,
+        vec![ChangeSet {
+            seq: 3,
+            mutations: vec![Mutation::Clear {
+                store: Some("sk".to_owned())
+            }],
+        }]
+    );
+}
+
+#[test]
+fn test_merkle() {
+    let roots: Vec<_> = (0..2)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_merkle")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let a = Rkv::new::<Lmdb>(roots[0].path()).expect("new succeeded");
+    let b = Rkv::new::<Lmdb>(roots[1].path()).expect("new succeeded");
+    let ma = a
+        .open_merkle("mk", StoreOptions::create(), 2)
+        .expect("opened");
+    let mb = b
+        .open_merkle("mk", StoreOptions::create(), 2)
+        .expect("opened");
+
+    let entries = [("apple", 1), ("apricot", 2), ("banana", 3), ("b", 4)];
+    let mut writer = a.write().expect("writer");
+    for (k, v) in entries.iter() {
+        ma.put(&mut writer, k, &Value::I64(*v)).expect("wrote");
+    }
+    writer.commit().expect("committed");
+    // The same entries, written in another order and overwritten along the way.
+    let mut writer = b.write().expect("writer");
+    mb.put(&mut writer, "banana", &Value::I64(0))
+        .expect("wrote");
+    for (k, v) in entries.iter().rev() {
+        mb.put(&mut writer, k, &Value::I64(*v)).expect("wrote");
+    }
+    writer.commit().expect("committed");
+
+    let root_hash = |k: &Rkv<LmdbEnvironment>, m: &MerkleStore<LmdbDatabase>| {
+        m.root_hash(&k.read().expect("reader")).expect("hashed")
+    };
+    let range_hashes =
+        |k: &Rkv<LmdbEnvironment>, m: &MerkleStore<LmdbDatabase>, prefix: &[u8], depth| {
+            m.subrange_hashes(&k.read().expect("reader"), prefix, depth)
+                .expect("hashed")
+        };
+    assert_ne!(root_hash(&a, &ma), EMPTY_HASH);
+    assert_eq!(root_hash(&a, &ma), root_hash(&b, &mb));
+
+    let mut writer = b.write().expect("writer");
+    mb.put(&mut writer, "apricot", &Value::I64(5))
+        .expect("wrote");
+    writer.commit().expect("committed");
+    assert_ne!(root_hash(&a, &ma), root_hash(&b, &mb));
+
+    // Only the ranges holding the differing entry differ.
+    let (ra, rb) = (range_hashes(&a, &ma, b"", 1), range_hashes(&b, &mb, b"", 1));
+    let prefixes = |r: &[(Vec<u8>, [u8; 32])]| r.iter().map(|(p, _)| p.clone()).collect::<Vec<_>>();
+    assert_eq!(prefixes(&ra), vec![b"a".to_vec(), b"b".to_vec()]);
+    assert_eq!(prefixes(&ra), prefixes(&rb));
+    assert_ne!(ra[0], rb[0]);
+    assert_eq!(ra[1], rb[1]);
+    let (ra, rb) = (
+        range_hashes(&a, &ma, b"a", 2),
+        range_hashes(&b, &mb, b"a", 2),
+    );
+    assert_eq!(prefixes(&ra), vec![b"ap".to_vec()]);
+    assert_ne!(ra, rb);
+    let (ra, rb) = (
+        range_hashes(&a, &ma, b"b", 2),
+        range_hashes(&b, &mb, b"b", 2),
+    );
+    assert_eq!(prefixes(&ra), vec![b"b".to_vec(), b"ba".to_vec()]);
+    assert_eq!(ra, rb);
+
+    // Deleting the entry and writing it back heals the difference.
+    let mut writer = b.write().expect("writer");
+    mb.delete(&mut writer, "apricot").expect("deleted");
+    assert!(mb.delete(&mut writer, "apricot").is_err());
+    mb.put(&mut writer, "apricot", &Value::I64(2))
+        .expect("wrote");
+    writer.commit().expect("committed");
+    assert_eq!(root_hash(&a, &ma), root_hash(&b, &mb));
+    assert_eq!(
+        range_hashes(&a, &ma, b"", 2),
+        mb.range_hashes(&b.read().expect("reader"), 2)
+            .expect("hashed")
+    );
+
+    let mut writer = a.write().expect("writer");
+    ma.clear(&mut writer).expect("cleared");
+    writer.commit().expect("committed");
+    assert_eq!(root_hash(&a, &ma), EMPTY_HASH);
+    assert!(range_hashes(&a, &ma, b"", 0).is_empty());
+    assert!(range_hashes(&a, &ma, b"", 2).is_empty());
+    assert!(matches!(
+        ma.range_hashes(&a.read().expect("reader"), 3),
+        Err(StoreError::MerkleDepthMismatch {
+            expected: 2,
+            actual: 3
+        })
+    ));
+
+    // The tree keeps the depth it was created with.
+    a.open_merkle("mk", StoreOptions::default(), 2)
+        .expect("opened");
+    match a.open_merkle("mk", StoreOptions::default(), 3) {
+        Err(StoreError::MerkleDepthMismatch { expected, actual }) => {
+            assert_eq!((expected, actual), (3, 2))
+        }
+        result => panic!("expected a depth mismatch, got {:?}", result),
+    }
+}
+
+/// A xorshift generator, so that randomized tests replay the same interleavings.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+#[test]
+fn test_lww() {
+    let roots: Vec<_> = (0..4)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_lww")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let envs: Vec<_> = roots
+        .iter()
+        .map(|root| Rkv::new::<Lmdb>(root.path()).expect("new succeeded"))
+        .collect();
+    let stores: Vec<_> = envs
+        .iter()
+        .map(|k| k.open_lww("lww", StoreOptions::create()).expect("opened"))
+        .collect();
+    let clocks: Vec<_> = (0..envs.len()).map(|i| Clock::new(i as u64)).collect();
+    let entries = |i: usize| {
+        stores[i]
+            .entries(&envs[i].read().expect("reader"))
+            .expect("listed")
+    };
+    let merge = |i: usize, remote: Vec<LwwEntry>| {
+        for entry in &remote {
+            clocks[i].observe(&entry.timestamp);
+        }
+        let mut writer = envs[i].write().expect("writer");
+        stores[i].merge(&mut writer, remote).expect("merged");
+        writer.commit().expect("committed");
+    };
+
+    // Later writes win, deletes included.
+    let mut writer = envs[0].write().expect("writer");
+    let (earlier, later) = (clocks[0].now(), clocks[0].now());
+    assert!(stores[0]
+        .put(&mut writer, "a", &Value::I64(1), later)
+        .expect("wrote"));
+    assert!(!stores[0]
+        .put(&mut writer, "a", &Value::I64(2), earlier)
+        .expect("wrote"));
+    assert_eq!(
+        stores[0].get(&writer, "a").expect("read"),
+        Some(Value::Blob(Cow::Borrowed(
+            &Value::I64(1).to_bytes().expect("encoded")
+        )))
+    );
+    assert!(stores[0]
+        .delete(&mut writer, "a", clocks[0].now())
+        .expect("deleted"));
+    assert_eq!(stores[0].get(&writer, "a").expect("read"), None);
+    assert!(stores[0].timestamp(&writer, "a").expect("read") > Some(later));
+    writer.commit().expect("committed");
+
+    // Replicas write and sync in a random interleaving, and converge once they all
+    // merged everything.
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let keys = ["a", "b", "c", "d"];
+    for _ in 0..300 {
+        let i = rng.below(3);
+        match rng.below(4) {
+            0 => {
+                let mut remote = entries(rng.below(3));
+                rng.shuffle(&mut remote);
+                merge(i, remote);
+            }
+            1 => {
+                let mut writer = envs[i].write().expect("writer");
+                let k = keys[rng.below(keys.len())];
+                stores[i]
+                    .delete(&mut writer, k, clocks[i].now())
+                    .expect("deleted");
+                writer.commit().expect("committed");
+            }
+            _ => {
+                let mut writer = envs[i].write().expect("writer");
+                let k = keys[rng.below(keys.len())];
+                let v = Value::U64(rng.next());
+                stores[i]
+                    .put(&mut writer, k, &v, clocks[i].now())
+                    .expect("wrote");
+                writer.commit().expect("committed");
+            }
+        }
+    }
+    let all: Vec<_> = (0..3).flat_map(entries).collect();
+    for i in 0..3 {
+        let mut remote = all.clone();
+        rng.shuffle(&mut remote);
+        merge(i, remote);
+    }
+    assert!(!entries(0).is_empty());
+    assert_eq!(entries(0), entries(1));
+    assert_eq!(entries(0), entries(2));
+
+    // Merging is idempotent, whatever the batches the entries come in.
+    let mut remote = all.clone();
+    remote.extend(all);
+    rng.shuffle(&mut remote);
+    while !remote.is_empty() {
+        let batch = remote.split_off(remote.len() - rng.below(remote.len()) - 1);
+        merge(3, batch);
+    }
+    assert_eq!(entries(3), entries(0));
+    let reader = envs[3].read().expect("reader");
+    for k in keys.iter() {
+        assert_eq!(
+            stores[3].get(&reader, k).expect("read"),
+            stores[0]
+                .get(&envs[0].read().expect("reader"), k)
+                .expect("read")
+        );
+    }
+}
+
+#[test]
+fn test_or_set() {
+    let roots: Vec<_> = (0..4)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_or_set")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let envs: Vec<_> = roots
+        .iter()
+        .map(|root| Rkv::new::<Lmdb>(root.path()).expect("new succeeded"))
+        .collect();
+    let stores: Vec<_> = envs
+        .iter()
+        .map(|k| {
+            k.open_or_set("set", StoreOptions::create())
+                .expect("opened")
+        })
+        .collect();
+    let clocks: Vec<_> = (0..envs.len()).map(|i| Clock::new(i as u64)).collect();
+    let entries = |i: usize| {
+        stores[i]
+            .entries(&envs[i].read().expect("reader"))
+            .expect("listed")
+    };
+    let merge = |i: usize, remote: Vec<OrSetEntry>| {
+        for entry in &remote {
+            clocks[i].observe(&entry.tag);
+        }
+        let mut writer = envs[i].write().expect("writer");
+        stores[i].merge(&mut writer, remote).expect("merged");
+        writer.commit().expect("committed");
+    };
+    let remove = |i: usize, k: &str, v: &Value| {
+        let mut writer = envs[i].write().expect("writer");
+        let observed = stores[i].observe(&writer, k, v).expect("observed");
+        stores[i]
+            .remove(&mut writer, k, v, &observed)
+            .expect("removed");
+        writer.commit().expect("committed");
+    };
+    let contains = |i: usize, k: &str, v: &Value| {
+        stores[i]
+            .contains(&envs[i].read().expect("reader"), k, v)
+            .expect("read")
+    };
+
+    // An add made concurrently with a remove survives it, but not the adds it observed.
+    let (x, y) = (Value::Str("x"), Value::Str("y"));
+    let mut writer = envs[0].write().expect("writer");
+    stores[0]
+        .add(&mut writer, "k", &x, clocks[0].now())
+        .expect("added");
+    stores[0]
+        .add(&mut writer, "k", &y, clocks[0].now())
+        .expect("added");
+    writer.commit().expect("committed");
+    merge(1, entries(0));
+    remove(1, "k", &x);
+    remove(1, "k", &y);
+    let mut writer = envs[0].write().expect("writer");
+    stores[0]
+        .add(&mut writer, "k", &x, clocks[0].now())
+        .expect("added");
+    writer.commit().expect("committed");
+    merge(0, entries(1));
+    merge(1, entries(0));
+    for i in 0..2 {
+        assert!(contains(i, "k", &x));
+        assert!(!contains(i, "k", &y));
+        assert_eq!(
+            stores[i]
+                .get(&envs[i].read().expect("reader"), "k")
+                .expect("read"),
+            vec![Value::Blob(Cow::Borrowed(&x.to_bytes().expect("encoded")))]
+        );
+    }
+
+    // Replicas add, remove and sync in a random interleaving, and converge once they
+    // all merged everything.
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let keys = ["a", "b", "c"];
+    let values = [Value::I64(1), Value::I64(2), Value::I64(3)];
+    for _ in 0..300 {
+        let i = rng.below(3);
+        let k = keys[rng.below(keys.len())];
+        let v = &values[rng.below(values.len())];
+        match rng.below(4) {
+            0 => {
+                let mut remote = entries(rng.below(3));
+                rng.shuffle(&mut remote);
+                merge(i, remote);
+            }
+            1 => remove(i, k, v),
+            _ => {
+                let mut writer = envs[i].write().expect("writer");
+                stores[i]
+                    .add(&mut writer, k, v, clocks[i].now())
+                    .expect("added");
+                writer.commit().expect("committed");
+            }
+        }
+    }
+    let all: Vec<_> = (0..3).flat_map(entries).collect();
+    for i in 0..3 {
+        let mut remote = all.clone();
+        rng.shuffle(&mut remote);
+        merge(i, remote);
+    }
+    assert!(entries(0).iter().any(|entry| entry.removed));
+    assert_eq!(entries(0), entries(1));
+    assert_eq!(entries(0), entries(2));
+
+    // Merging is idempotent, whatever the batches the entries come in.
+    let mut remote = all.clone();
+    remote.extend(all);
+    rng.shuffle(&mut remote);
+    while !remote.is_empty() {
+        let batch = remote.split_off(remote.len() - rng.below(remote.len()) - 1);
+        merge(3, batch);
+    }
+    assert_eq!(entries(3), entries(0));
+    for k in keys.iter() {
+        for v in values.iter() {
+            assert_eq!(contains(3, k, v), contains(0, k, v));
+        }
+    }
+
+    let mut writer = envs[3].write().expect("writer");
+    stores[3].clear(&mut writer).expect("cleared");
+    writer.commit().expect("committed");
+    assert!(entries(3).is_empty());
+}
+
+#[test]
+fn test_snapshot() {
+    let roots: Vec<_> = (0..2)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_snapshot")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let a = Rkv::new::<Lmdb>(roots[0].path()).expect("new succeeded");
+    let b = Rkv::new::<Lmdb>(roots[1].path()).expect("new succeeded");
+    let secret = SecretKey::from_bytes(&[7; 32]).expect("secret key");
+    let keypair = Keypair {
+        public: PublicKey::from(&secret),
+        secret,
+    };
+    let other = PublicKey::from(&SecretKey::from_bytes(&[8; 32]).expect("secret key"));
+
+    let sa = a.open_single("s", StoreOptions::create()).expect("opened");
+    let ma = a.open_multi("m", StoreOptions::create()).expect("opened");
+    let mut writer = a.write().expect("writer");
+    sa.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    sa.put(&mut writer, "bar", &Value::Str("baz"))
+        .expect("wrote");
+    ma.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    ma.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    writer.commit().expect("committed");
+    let snapshot = a
+        .export_snapshot(&a.read().expect("reader"), &[&sa, &ma], &keypair)
+        .expect("exported");
+    let (hash, bytes) = (snapshot.hash(), snapshot.to_bytes());
+
+    // The destination's stores keep what they held unless the snapshot verifies.
+    let sb = b.open_single("s", StoreOptions::create()).expect("opened");
+    let mb = b.open_multi("m", StoreOptions::create()).expect("opened");
+    let mut writer = b.write().expect("writer");
+    sb.put(&mut writer, "old", &Value::Bool(true))
+        .expect("wrote");
+    writer.commit().expect("committed");
+    let mut tampered = bytes.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    let tampered = SignedSnapshot::from_bytes(&tampered).expect("decoded");
+    match b.import_snapshot(&tampered, &keypair.public) {
+        Err(StoreError::SnapshotInvalid) => (),
+        result => panic!("imported a tampered snapshot: {:?}", result),
+    }
+    let snapshot = SignedSnapshot::from_bytes(&bytes).expect("decoded");
+    assert_eq!(snapshot.hash(), hash);
+    match b.import_snapshot(&snapshot, &other) {
+        Err(StoreError::SnapshotSignatureInvalid) => (),
+        result => panic!("imported a snapshot signed by another key: {:?}", result),
+    }
+    {
+        let reader = b.read().expect("reader");
+        assert!(sb.get(&reader, "old").expect("read").is_some());
+        assert_eq!(sb.get(&reader, "foo").expect("read"), None);
+    }
+
+    b.import_snapshot(&snapshot, &keypair.public)
+        .expect("imported");
+    let (ra, rb) = (a.read().expect("reader"), b.read().expect("reader"));
+    assert_eq!(sb.get(&rb, "old").expect("read"), None);
+    for k in ["foo", "bar"].iter() {
+        assert_eq!(sa.get(&ra, k).expect("read"), sb.get(&rb, k).expect("read"));
+    }
+    let va: Vec<_> = ma
+        .get(&ra, "foo")
+        .expect("read")
+        .map(|result| result.expect("value").1.to_bytes().expect("encoded"))
+        .collect();
+    let vb: Vec<_> = mb
+        .get(&rb, "foo")
+        .expect("read")
+        .map(|result| result.expect("value").1.to_bytes().expect("encoded"))
+        .collect();
+    assert_eq!(va.len(), 2);
+    assert_eq!(va, vb);
+}
+
+#[test]
+fn test_diff() {
+    let roots: Vec<_> = (0..2)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_diff")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let a = Rkv::new::<Lmdb>(roots[0].path()).expect("new succeeded");
+    let b = Rkv::new::<SafeMode>(roots[1].path()).expect("new succeeded");
+
+    let sa = a.open_single("s", StoreOptions::create()).expect("opened");
+    let ma = a.open_multi("m", StoreOptions::create()).expect("opened");
+    let mut writer = a.write().expect("writer");
+    sa.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    sa.put(&mut writer, "bar", &Value::Str("baz"))
+        .expect("wrote");
+    sa.put(&mut writer, "ours", &Value::Bool(true))
+        .expect("wrote");
+    ma.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    ma.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let sb = b.open_single("s", StoreOptions::create()).expect("opened");
+    let mb = b.open_multi("m", StoreOptions::create()).expect("opened");
+    let mut writer = b.write().expect("writer");
+    sb.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    sb.put(&mut writer, "bar", &Value::Str("baz"))
+        .expect("wrote");
+    sb.put(&mut writer, "theirs", &Value::Bool(false))
+        .expect("wrote");
+    mb.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    mb.put(&mut writer, "foo", &Value::I64(3)).expect("wrote");
+    writer.commit().expect("committed");
+
+    // The environments are of different backends, which encode entries the same way.
+    let (ra, rb) = (a.read().expect("reader"), b.read().expect("reader"));
+    let differences: Vec<_> = a
+        .diff(&ra, &b, &rb, &[Some("s"), Some("m")])
+        .expect("diffed")
+        .map(|result| result.expect("difference"))
+        .collect();
+    assert_eq!(
+        differences,
+        vec![
+            (
+                Some("s"),
+                Difference::Changed {
+                    key: b"foo",
+                    old: Value::I64(1),
+                    new: Value::I64(2)
+                }
+            ),
+            (
+                Some("s"),
+                Difference::Removed {
+                    key: b"ours",
+                    value: Value::Bool(true)
+                }
+            ),
+            (
+                Some("s"),
+                Difference::Added {
+                    key: b"theirs",
+                    value: Value::Bool(false)
+                }
+            ),
+            (
+                Some("m"),
+                Difference::Removed {
+                    key: b"foo",
+                    value: Value::I64(1)
+                }
+            ),
+            (
+                Some("m"),
+                Difference::Added {
+                    key: b"foo",
+                    value: Value::I64(3)
+                }
+            ),
+        ]
+    );
+    assert_eq!(
+        a.diff(&ra, &a, &ra, &[Some("s"), Some("m")])
+            .expect("diffed")
+            .count(),
+        0
+    );
+
+    // Both environments must have opened the stores.
+    match a.diff(&ra, &b, &rb, &[Some("missing")]) {
+        Err(StoreError::ForeignStore) => (),
+        _ => panic!("diffed a store that wasn't opened"),
+    };
+}
+
+#[test]
+fn test_dump_load() {
+    let roots: Vec<_> = (0..2)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_dump_load")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let a = Rkv::new::<Lmdb>(roots[0].path()).expect("new succeeded");
+    let b = Rkv::new::<SafeMode>(roots[1].path()).expect("new succeeded");
+
+    let s = a.open_single("s", StoreOptions::create()).expect("opened");
+    let m = a
+        .open_multi(
+            "m",
+            StoreOptions::create().with_encoding(ValueEncoding::Ordered),
+        )
+        .expect("opened");
+    let i = a.open_integer("i", StoreOptions::create()).expect("opened");
+    let c = a
+        .open_single(
+            "c",
+            StoreOptions::create().with_compression(ValueCompression::Lz4 { threshold: 16 }),
+        )
+        .expect("opened");
+    let mut writer = a.write().expect("writer");
+    s.put(&mut writer, "foo", &Value::Str("bar"))
+        .expect("wrote");
+    m.put(&mut writer, "foo", &Value::I64(-1)).expect("wrote");
+    m.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    i.put(&mut writer, 7u32, &Value::U64(7)).expect("wrote");
+    c.put(&mut writer, "long", &Value::Str(&"x".repeat(64)))
+        .expect("wrote");
+    writer.commit().expect("committed");
+
+    // A dump of one backend loads into the other.
+    let stores = [Some("s"), Some("m"), Some("i"), Some("c")];
+    let mut dumped = vec![];
+    a.dump(&a.read().expect("reader"), &stores, &mut dumped)
+        .expect("dumped");
+    b.load(&dumped[..]).expect("loaded");
+
+    let (ra, rb) = (a.read().expect("reader"), b.read().expect("reader"));
+    assert_eq!(a.diff(&ra, &b, &rb, &stores).expect("diffed").count(), 0);
+    let mut redumped = vec![];
+    b.dump(&rb, &stores, &mut redumped).expect("dumped");
+    assert_eq!(dumped, redumped);
+}
+
 #[test]
 fn test_blob() {
     let root = Builder::new()
@@ -983,11 +2080,15 @@ fn test_blob() {
 
     let mut writer = k.write().expect("writer");
     assert_eq!(sk.get(&writer, "foo").expect("read"), None);
-    sk.put(&mut writer, "foo", &Value::Blob(&[1, 2, 3, 4]))
-        .expect("wrote");
+    sk.put(
+        &mut writer,
+        "foo",
+        &Value::Blob(Cow::Borrowed(&[1, 2, 3, 4])),
+    )
+    .expect("wrote");
     assert_eq!(
         sk.get(&writer, "foo").expect("read"),
-        Some(Value::Blob(&[1, 2, 3, 4]))
+        Some(Value::Blob(Cow::Borrowed(&[1, 2, 3, 4])))
     );
 
     fn u16_to_u8(src: &[u16]) -> Vec<u8> {
@@ -1007,10 +2108,14 @@ fn test_blob() {
     // reading, and converting back works as expected.
     let u16_array = [1000, 10000, 54321, 65535];
     assert_eq!(sk.get(&writer, "bar").expect("read"), None);
-    sk.put(&mut writer, "bar", &Value::Blob(&u16_to_u8(&u16_array)))
-        .expect("wrote");
+    sk.put(
+        &mut writer,
+        "bar",
+        &Value::Blob(Cow::Borrowed(&u16_to_u8(&u16_array))),
+    )
+    .expect("wrote");
     let u8_array = match sk.get(&writer, "bar").expect("read") {
-        Some(Value::Blob(val)) => val,
+        Some(Value::Blob(Cow::Borrowed(val))) => val,
         _ => &[],
     };
     assert_eq!(u8_to_u16(u8_array), u16_array);
@@ -1174,6 +2279,51 @@ fn test_set_map_size() {
     assert_eq!(k.info().expect("info").map_size(), 2 * DEFAULT_SIZE);
 }
 
+#[test]
+fn test_transact_with_retry() {
+    let root = Builder::new()
+        .prefix("test_transact_with_retry")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let val = "x".repeat(get_larger_than_default_map_size_value());
+
+    // Without growing the map, the transaction keeps failing.
+    let options = RetryOptions {
+        map_growth: None,
+        ..RetryOptions::default()
+    };
+    let mut attempts = 0;
+    match k.transact_with_retry(options, |writer| {
+        attempts += 1;
+        sk.put(writer, "foo", &Value::Str(&val))
+    }) {
+        Err(StoreError::MapFull) => (),
+        result => panic!("expected MapFull, got {:?}", result),
+    }
+    assert_eq!(attempts, 1);
+    assert_eq!(k.info().expect("info").map_size(), DEFAULT_SIZE);
+
+    // By default, the map is grown and the transaction retried.
+    let mut attempts = 0;
+    k.transact_with_retry(RetryOptions::default(), |writer| {
+        attempts += 1;
+        sk.put(writer, "foo", &Value::Str(&val))
+    })
+    .expect("transacted");
+    assert_eq!(attempts, 2);
+    assert_eq!(k.info().expect("info").map_size(), 2 * DEFAULT_SIZE);
+
+    let reader = k.read().expect("reader");
+    assert_eq!(
+        sk.get(&reader, "foo").expect("read"),
+        Some(stored(Value::Str(&val)))
+    );
+}
+
 #[test]
 fn test_multi_get_key_value() {
     let root = Builder::new()
@@ -1193,7 +2343,7 @@ fn test_multi_get_key_value() {
     }
 
     let mut writer = k.write().expect("writer");
-    sk.put(&mut writer, "foo", &Value::Blob(b"1234"))
+    sk.put(&mut writer, "foo", &Value::Blob(Cow::Borrowed(b"1234")))
         .expect("blob 1234");
 
     writer.commit().expect("committed");
@@ -1201,17 +2351,17 @@ fn test_multi_get_key_value() {
     let reader = k.read().unwrap();
 
     let yes = sk
-        .get_key_value(&reader, "foo", &Value::Blob(b"1234"))
+        .get_key_value(&reader, "foo", &Value::Blob(Cow::Borrowed(b"1234")))
         .unwrap();
     assert!(yes);
 
     let yes = sk
-        .get_key_value(&reader, "foo", &Value::Blob(b"12345"))
+        .get_key_value(&reader, "foo", &Value::Blob(Cow::Borrowed(b"12345")))
         .unwrap();
     assert!(!yes);
 
     let yes = sk
-        .get_key_value(&reader, "foo2", &Value::Blob(b"1234"))
+        .get_key_value(&reader, "foo2", &Value::Blob(Cow::Borrowed(b"1234")))
         .unwrap();
     assert!(!yes);
 }
@@ -1587,7 +2737,298 @@ fn test_store_multiple_thread() {
         .into_iter()
         .map(|handle| handle.join().expect("value"))
         .sum();
-    assert_eq!(thread_sum, (0..num_threads).sum());
+    assert_eq!(thread_sum, (0..num_threads).sum::<u64>());
+}
+
+#[test]
+fn test_shared_reader() {
+    let root = Builder::new()
+        .prefix("test_shared_reader")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    // Shared readers need read transactions that aren't bound to threads.
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    match k.read_shared() {
+        Err(StoreError::NoTlsRequired) => (),
+        result => panic!("expected NoTlsRequired, got {:?}", result.map(|_| ())),
+    }
+    drop(k);
+
+    // Leaked so that shared readers, which borrow it, can be sent to any thread.
+    let mut builder = Rkv::environment_builder::<Lmdb>();
+    builder.set_no_tls(true);
+    let k: &'static Rkv<LmdbEnvironment> = Box::leak(Box::new(
+        Rkv::from_builder(root.path(), builder).expect("rkv"),
+    ));
+    let sk = k.open_single(None, StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for i in 0..100u64 {
+        sk.put(&mut writer, format!("key-{:03}", i), &Value::U64(i))
+            .expect("wrote");
+    }
+    writer.commit().expect("committed");
+
+    let reader = k.read_shared().expect("reader");
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "key-100", &Value::U64(100))
+        .expect("wrote");
+    writer.commit().expect("committed");
+
+    let handles: Vec<_> = sk
+        .partition(&reader, 4)
+        .expect("partitioned")
+        .into_iter()
+        .map(|range| {
+            let reader = reader.clone();
+            thread::spawn(move || {
+                sk.iter_range(&reader, &range)
+                    .expect("iter")
+                    .map(|result| match result.expect("read") {
+                        (_, Value::U64(value)) => value,
+                        _ => panic!("value type unexpected"),
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let mut values = vec![];
+    for handle in handles {
+        let part = handle.join().expect("joined");
+        assert_eq!(part.len(), 25);
+        values.extend(part);
+    }
+    assert_eq!(values, (0..100).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_batch_writes() {
+    let root = Builder::new()
+        .prefix("test_batch_writes")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+    let ik = k
+        .open_integer("ik", StoreOptions::create())
+        .expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    sk.put_many(
+        &mut writer,
+        vec![("b", Value::Str("2")), ("a", Value::Str("1"))],
+    )
+    .expect("put many");
+    // Keys must come after those already in the store.
+    match sk.put_sorted(
+        &mut writer,
+        vec![("a", Value::Str("x")), ("c", Value::Str("3"))],
+    ) {
+        Err(StoreError::KeysNotSorted) => (),
+        result => panic!("expected KeysNotSorted, got {:?}", result),
+    }
+    // And be sorted.
+    match sk.put_sorted(
+        &mut writer,
+        vec![("d", Value::Str("4")), ("c", Value::Str("3"))],
+    ) {
+        Err(StoreError::KeysNotSorted) => (),
+        result => panic!("expected KeysNotSorted, got {:?}", result),
+    }
+    assert_eq!(
+        sk.get(&writer, "a").expect("read"),
+        Some(stored(Value::Str("1")))
+    );
+    assert_eq!(sk.get(&writer, "c").expect("read"), None);
+    let sorted: Vec<_> = (0..100u32)
+        .map(|i| (format!("c-{:03}", i), Value::Str("sorted")))
+        .collect();
+    sk.put_sorted(&mut writer, sorted).expect("put sorted");
+    sk.put_sorted(&mut writer, Vec::<(&str, Value)>::new())
+        .expect("put nothing");
+
+    mk.put_sorted(
+        &mut writer,
+        vec![
+            ("a", Value::Str("1")),
+            ("a", Value::Str("2")),
+            ("b", Value::Str("1")),
+        ],
+    )
+    .expect("put sorted");
+    // A value put twice isn't strictly ascending.
+    match mk.put_sorted(
+        &mut writer,
+        vec![("c", Value::Str("1")), ("c", Value::Str("1"))],
+    ) {
+        Err(StoreError::KeysNotSorted) => (),
+        result => panic!("expected KeysNotSorted, got {:?}", result),
+    }
+    mk.put_many(
+        &mut writer,
+        vec![("a", Value::Str("3")), ("c", Value::Str("1"))],
+    )
+    .expect("put many");
+
+    ik.put_many(&mut writer, (1..=10u32).map(|i| (i, Value::Str("int"))))
+        .expect("put many");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let keys: Vec<_> = sk
+        .iter_start(&reader)
+        .expect("iter")
+        .map(|result| result.expect("read").0.to_vec())
+        .collect();
+    assert_eq!(keys.len(), 102);
+    assert_eq!(keys[0], b"a");
+    assert_eq!(keys[101], b"c-099");
+    assert_eq!(
+        sk.get(&reader, "c-042").expect("read"),
+        Some(stored(Value::Str("sorted")))
+    );
+    let values: Vec<_> = mk
+        .get(&reader, "a")
+        .expect("read")
+        .map(|result| result.expect("read").1)
+        .collect();
+    assert_eq!(
+        values,
+        vec![
+            stored(Value::Str("1")),
+            stored(Value::Str("2")),
+            stored(Value::Str("3"))
+        ]
+    );
+    drop(reader);
+
+    let mut writer = k.write().expect("writer");
+    sk.delete_many(&mut writer, vec!["a", "b"])
+        .expect("deleted");
+    match sk.delete_many(&mut writer, vec!["a"]) {
+        Err(StoreError::KeyValuePairNotFound) => (),
+        result => panic!("expected KeyValuePairNotFound, got {:?}", result),
+    }
+    let range = KeyRange {
+        start: Some(b"c-010".to_vec()),
+        end: Some(b"c-090".to_vec()),
+    };
+    assert_eq!(sk.delete_range(&mut writer, &range).expect("deleted"), 80);
+    assert_eq!(sk.delete_range(&mut writer, &range).expect("deleted"), 0);
+    let backwards = KeyRange {
+        start: range.end.clone(),
+        end: range.start.clone(),
+    };
+    assert_eq!(
+        sk.delete_range(&mut writer, &backwards).expect("deleted"),
+        0
+    );
+
+    mk.delete_many(
+        &mut writer,
+        vec![("a", Value::Str("1")), ("c", Value::Str("1"))],
+    )
+    .expect("deleted");
+    let to_b = KeyRange {
+        start: None,
+        end: Some(b"c".to_vec()),
+    };
+    assert_eq!(mk.delete_range(&mut writer, &to_b).expect("deleted"), 2);
+
+    ik.delete_many(&mut writer, 1..=5u32).expect("deleted");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let keys: Vec<_> = sk
+        .iter_start(&reader)
+        .expect("iter")
+        .map(|result| result.expect("read").0.to_vec())
+        .collect();
+    let expected: Vec<_> = (0..10)
+        .chain(90..100)
+        .map(|i| format!("c-{:03}", i).into_bytes())
+        .collect();
+    assert_eq!(keys, expected);
+    assert_eq!(mk.iter_start(&reader).expect("iter").count(), 0);
+    assert_eq!(ik.get(&reader, 5).expect("read"), None);
+    assert_eq!(
+        ik.get(&reader, 6).expect("read"),
+        Some(stored(Value::Str("int")))
+    );
+}
+
+#[test]
+fn test_batch_writes_integer() {
+    let root = Builder::new()
+        .prefix("test_batch_writes_integer")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let ik = k
+        .open_integer("ik", StoreOptions::create())
+        .expect("opened");
+    let mik = k
+        .open_multi_integer("mik", StoreOptions::create())
+        .expect("opened");
+
+    // Keys are sorted as integers, although 256 is encoded as bytes sorting before 255's.
+    let mut writer = k.write().expect("writer");
+    ik.put_sorted(&mut writer, (1..=300u32).map(|i| (i, Value::U64(i.into()))))
+        .expect("put sorted");
+    match ik.put_sorted(&mut writer, vec![(256u32, Value::U64(0))]) {
+        Err(StoreError::KeysNotSorted) => (),
+        result => panic!("expected KeysNotSorted, got {:?}", result),
+    }
+    let pairs = (1..=300u32).flat_map(|i| vec![(i, Value::Str("a")), (i, Value::Str("b"))]);
+    mik.put_sorted(&mut writer, pairs).expect("put sorted");
+    writer.commit().expect("committed");
+
+    let mut writer = k.write().expect("writer");
+    assert_eq!(
+        ik.delete_range(&mut writer, Some(250), Some(260))
+            .expect("deleted"),
+        10
+    );
+    assert_eq!(
+        ik.delete_range(&mut writer, Some(290), None)
+            .expect("deleted"),
+        11
+    );
+    assert_eq!(
+        mik.delete_range(&mut writer, None, Some(101))
+            .expect("deleted"),
+        100
+    );
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let kept: Vec<u32> = (1..=300u32)
+        .filter(|&i| ik.get(&reader, i).expect("read").is_some())
+        .collect();
+    let expected: Vec<u32> = (1..250).chain(260..290).collect();
+    assert_eq!(kept, expected);
+    assert_eq!(
+        ik.get(&reader, 260).expect("read"),
+        Some(stored(Value::U64(260)))
+    );
+    assert_eq!(mik.get_first(&reader, 100).expect("read"), None);
+    let values: Vec<_> = mik
+        .get(&reader, 101)
+        .expect("read")
+        .map(|result| result.expect("read").1)
+        .collect();
+    assert_eq!(
+        values,
+        vec![stored(Value::Str("a")), stored(Value::Str("b"))]
+    );
+    assert_eq!(mik.iter_start(&reader).expect("iter").count(), 400);
 }
 
 #[test]
@@ -8,15 +8,20 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
-use std::{fs, path::Path};
+use std::{cell::RefCell, fs, path::Path};
 
 use tempfile::Builder;
 
 use rkv::{
-    backend::{Lmdb, LmdbEnvironment, SafeMode, SafeModeEnvironment},
-    Manager, Migrator, Rkv, StoreOptions, Value,
+    backend::{BackendEnvironmentBuilder, Lmdb, LmdbEnvironment, SafeMode, SafeModeEnvironment},
+    migrator::{Existing, MigrateOptions, Progress},
+    EnvironmentFlags, Key, Manager, MigrateError, Migrator, Rkv, StoreOptions, Value,
 };
 
+mod common;
+
+use common::stored;
+
 macro_rules! populate_store {
     ($env:expr) => {
         let store = $env
@@ -36,6 +41,60 @@ macro_rules! populate_store {
     };
 }
 
+macro_rules! populate_flagged_stores {
+    ($env:expr) => {
+        let multi = $env
+            .open_multi("multi", StoreOptions::create())
+            .expect("opened");
+        let integer = $env
+            .open_integer("integer", StoreOptions::create())
+            .expect("opened");
+        let mut writer = $env.write().expect("writer");
+        multi
+            .put(&mut writer, "foo", &Value::I64(1234))
+            .expect("wrote");
+        multi
+            .put(&mut writer, "foo", &Value::Bool(true))
+            .expect("wrote");
+        integer
+            .put(&mut writer, 300u32, &Value::Str("héllo, yöu"))
+            .expect("wrote");
+        integer
+            .put(&mut writer, 2u32, &Value::I64(2))
+            .expect("wrote");
+        writer.commit().expect("committed");
+    };
+}
+
+macro_rules! check_flagged_stores {
+    ($env:expr) => {
+        let multi = $env
+            .open_multi("multi", StoreOptions::default())
+            .expect("opened");
+        let integer = $env
+            .open_integer::<_, u32>("integer", StoreOptions::default())
+            .expect("opened");
+        let reader = $env.read().expect("reader");
+        let values: Vec<_> = multi
+            .get(&reader, "foo")
+            .expect("read")
+            .map(|result| result.expect("value").1)
+            .collect();
+        assert_eq!(
+            values,
+            vec![stored(Value::Bool(true)), stored(Value::I64(1234))]
+        );
+        assert_eq!(
+            integer.get(&reader, 300).expect("read"),
+            Some(stored(Value::Str("héllo, yöu")))
+        );
+        assert_eq!(
+            integer.get(&reader, 2).expect("read"),
+            Some(stored(Value::I64(2)))
+        );
+    };
+}
+
 #[test]
 fn test_open_migrator_lmdb_to_safe() {
     let root = Builder::new()
@@ -474,6 +533,291 @@ fn test_migrator_safe_to_lmdb_3() {
     );
 }
 
+#[test]
+fn test_migrator_lmdb_to_safe_flags() {
+    let root = Builder::new()
+        .prefix("test_migrate_lmdb_to_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let src_env = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    populate_flagged_stores!(&src_env);
+    let dst_env = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    Migrator::migrate_lmdb_to_safe_mode(&src_env, &dst_env).expect("migrated");
+    check_flagged_stores!(&dst_env);
+}
+
+#[test]
+fn test_migrator_safe_to_lmdb_flags() {
+    let root = Builder::new()
+        .prefix("test_migrate_safe_to_lmdb")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let src_env = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    populate_flagged_stores!(&src_env);
+    let dst_env = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    Migrator::migrate_safe_mode_to_lmdb(&src_env, &dst_env).expect("migrated");
+    check_flagged_stores!(&dst_env);
+}
+
+#[test]
+fn test_migrator_lmdb_to_safe_options() {
+    let root = Builder::new()
+        .prefix("test_migrate_lmdb_to_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let src_env = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    populate_store!(&src_env);
+    populate_flagged_stores!(&src_env);
+    let dst_env = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let dst_store = dst_env
+        .open_single("renamed", StoreOptions::create())
+        .expect("opened");
+    let mut writer = dst_env.write().expect("writer");
+    dst_store
+        .put(&mut writer, "foo", &Value::I64(1))
+        .expect("wrote");
+    dst_store
+        .put(&mut writer, "ours", &Value::I64(2))
+        .expect("wrote");
+    writer.commit().expect("committed");
+
+    // Existing keys are kept, so the stores don't match anymore.
+    let reports = RefCell::new(vec![]);
+    let options = MigrateOptions::default()
+        .with_existing(Existing::Skip)
+        .with_renamed_store("store", "renamed")
+        .with_progress(|progress| {
+            reports.borrow_mut().push(
+                *progress
+                    == Progress {
+                        store: Some("store"),
+                        entries: 3,
+                        done: true,
+                        stores_done: 0,
+                        stores: 1,
+                    },
+            )
+        })
+        .with_verification(true);
+    match Migrator::migrate_lmdb_to_safe_mode_with_options(&src_env, &dst_env, &options) {
+        Err(MigrateError::VerificationFailed(Some(name))) => assert_eq!(name, "renamed"),
+        result => panic!("unexpected result: {:?}", result),
+    };
+    drop(options);
+    assert_eq!(reports.into_inner(), vec![true]);
+    {
+        let reader = dst_env.read().expect("reader");
+        assert_eq!(
+            dst_store.get(&reader, "foo").expect("read"),
+            Some(Value::I64(1))
+        );
+        assert_eq!(
+            dst_store.get(&reader, "bar").expect("read"),
+            Some(Value::Bool(true))
+        );
+    }
+
+    // Keys the source holds are overwritten, but the others are kept.
+    let options = MigrateOptions::default()
+        .with_existing(Existing::Overwrite)
+        .with_renamed_store("store", "renamed");
+    Migrator::migrate_lmdb_to_safe_mode_with_options(&src_env, &dst_env, &options)
+        .expect("migrated");
+    {
+        let reader = dst_env.read().expect("reader");
+        assert_eq!(
+            dst_store.get(&reader, "foo").expect("read"),
+            Some(Value::I64(1234))
+        );
+        assert_eq!(
+            dst_store.get(&reader, "ours").expect("read"),
+            Some(Value::I64(2))
+        );
+    }
+
+    // The other stores weren't migrated, until now.
+    assert!(dst_env
+        .open_multi("multi", StoreOptions::default())
+        .is_err());
+    let options = MigrateOptions::default()
+        .with_store("multi")
+        .with_store("integer")
+        .with_verification(true);
+    Migrator::migrate_lmdb_to_safe_mode_with_options(&src_env, &dst_env, &options)
+        .expect_err("destination isn't empty");
+    let options = options.with_existing(Existing::Overwrite);
+    Migrator::migrate_lmdb_to_safe_mode_with_options(&src_env, &dst_env, &options)
+        .expect("migrated");
+    check_flagged_stores!(&dst_env);
+}
+
+#[test]
+fn test_migrator_safe_to_lmdb_merge() {
+    let root = Builder::new()
+        .prefix("test_migrate_safe_to_lmdb")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let src_env = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    populate_store!(&src_env);
+    let dst_env = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+    let dst_store = dst_env
+        .open_single("store", StoreOptions::create())
+        .expect("opened");
+    let mut writer = dst_env.write().expect("writer");
+    dst_store
+        .put(&mut writer, "foo", &Value::I64(1))
+        .expect("wrote");
+    writer.commit().expect("committed");
+
+    // Keep the larger of both values.
+    let resolve = |name: Option<&str>, key: &[u8], ours: &[u8], theirs: &[u8]| {
+        assert_eq!((name, key), (Some("store"), &b"foo"[..]));
+        match (
+            Value::from_tagged_slice(ours),
+            Value::from_tagged_slice(theirs),
+        ) {
+            (Ok(Value::I64(a)), Ok(Value::I64(b))) if a > b => ours.to_vec(),
+            _ => theirs.to_vec(),
+        }
+    };
+    let options = MigrateOptions::default().with_existing(Existing::Merge(Box::new(resolve)));
+    Migrator::migrate_safe_mode_to_lmdb_with_options(&src_env, &dst_env, &options)
+        .expect("migrated");
+
+    let reader = dst_env.read().expect("reader");
+    assert_eq!(
+        dst_store.get(&reader, "foo").expect("read"),
+        Some(Value::I64(1234))
+    );
+    assert_eq!(
+        dst_store.get(&reader, "baz").expect("read"),
+        Some(Value::Str("héllo, yöu"))
+    );
+}
+
+#[test]
+fn test_open_migrator_lmdb_to_safe_no_sub_dir() {
+    let root = Builder::new()
+        .prefix("test_open_migrator_lmdb_to_safe_no_sub_dir")
+        .tempdir()
+        .expect("tempdir");
+    let src_dir = root.path().join("src");
+    let dst_dir = root.path().join("dst");
+    fs::create_dir_all(&src_dir).expect("dir created");
+    fs::create_dir_all(&dst_dir).expect("dir created");
+
+    let mut datamdb = src_dir.clone();
+    datamdb.push("data.mdb");
+    // Populate source environment, then open its data file as a NO_SUB_DIR one.
+    {
+        let src_env = Rkv::new::<Lmdb>(&src_dir).expect("new succeeded");
+        populate_store!(&src_env);
+        populate_flagged_stores!(&src_env);
+        src_env.sync(true).expect("synced");
+    }
+    {
+        let dst_env = Rkv::new::<SafeMode>(&dst_dir).expect("new succeeded");
+        Migrator::open_and_migrate_lmdb_to_safe_mode(&datamdb, |builder| builder, &dst_env)
+            .expect("migrated");
+        check_flagged_stores!(&dst_env);
+    }
+    // Check if the old data file was deleted from disk.
+    assert!(!datamdb.exists());
+}
+
+#[test]
+fn test_open_migrator_safe_to_lmdb_no_sub_dir() {
+    let root = Builder::new()
+        .prefix("test_open_migrator_safe_to_lmdb_no_sub_dir")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let mut safebin = root.path().to_path_buf();
+    safebin.push("profile.safe.bin");
+    {
+        let mut builder = Rkv::environment_builder::<SafeMode>();
+        builder.set_flags(EnvironmentFlags::NO_SUB_DIR);
+        builder.set_max_dbs(10);
+        let src_env = Rkv::from_builder(&safebin, builder).expect("new succeeded");
+        populate_flagged_stores!(&src_env);
+    }
+    assert!(safebin.is_file());
+    {
+        let dst_env = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+        Migrator::open_and_migrate_safe_mode_to_lmdb(&safebin, |builder| builder, &dst_env)
+            .expect("migrated");
+        check_flagged_stores!(&dst_env);
+    }
+    // Check if the old data file was deleted from disk.
+    assert!(!safebin.exists());
+}
+
+#[test]
+fn test_rekey_lmdb() {
+    let root = Builder::new()
+        .prefix("test_rekey_lmdb")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let mut datamdb = root.path().to_path_buf();
+    datamdb.push("data.mdb");
+    let plaintext = "héllo, yöu".as_bytes();
+    let contains = |bytes: &[u8], part: &[u8]| bytes.windows(part.len()).any(|w| w == part);
+    let key: Key = [7; 32];
+    {
+        let src_env = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+        populate_store!(&src_env);
+        src_env.sync(true).expect("synced");
+    }
+    assert!(contains(&fs::read(&datamdb).expect("read"), plaintext));
+
+    // Encrypt the environment.
+    Migrator::rekey_lmdb(root.path(), None, Some(key)).expect("migrated");
+    assert!(!contains(&fs::read(&datamdb).expect("read"), plaintext));
+    {
+        let env = Rkv::with_encryption_key_and_mapsize::<Lmdb>(root.path(), key, 1024 * 1024)
+            .expect("new succeeded");
+        let store = env
+            .open_single("store", StoreOptions::default())
+            .expect("opened");
+        let reader = env.read().expect("reader");
+        assert_eq!(
+            store.get(&reader, "baz").expect("read"),
+            Some(Value::Str("héllo, yöu"))
+        );
+    }
+
+    // And decrypt it back.
+    Migrator::rekey_lmdb(root.path(), Some(key), None).expect("migrated");
+    assert!(contains(&fs::read(&datamdb).expect("read"), plaintext));
+    {
+        let env = Rkv::new::<Lmdb>(root.path()).expect("new succeeded");
+        let store = env
+            .open_single("store", StoreOptions::default())
+            .expect("opened");
+        let reader = env.read().expect("reader");
+        assert_eq!(
+            store.get(&reader, "foo").expect("read"),
+            Some(Value::I64(1234))
+        );
+    }
+
+    // Nothing is left behind next to the environment.
+    let mut rekey = root.path().as_os_str().to_owned();
+    rekey.push(".rekey");
+    assert!(!Path::new(&rekey).exists());
+}
+
 #[test]
 fn test_easy_migrator_failed_migration_1() {
     let root = Builder::new()
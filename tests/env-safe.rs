@@ -13,14 +13,17 @@
 #![allow(clippy::complexity)]
 
 use std::{
+    borrow::Cow,
     fs,
     path::Path,
     str,
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, RwLock},
     thread,
+    time::{Duration, UNIX_EPOCH},
 };
 
 use byteorder::{ByteOrder, LittleEndian};
+use rust_decimal::Decimal;
 use tempfile::Builder;
 
 use rkv::{
@@ -28,9 +31,19 @@ use rkv::{
         BackendEnvironmentBuilder, SafeMode, SafeModeDatabase, SafeModeEnvironment,
         SafeModeRwTransaction,
     },
-    Rkv, SingleStore, StoreError, StoreOptions, Value, Writer,
+    group_commit::Options as GroupCommitOptions,
+    hlc::Clock,
+    snapshot::{Keypair, PublicKey, SecretKey},
+    store::{lww::LwwEntry, merkle::EMPTY_HASH, orset::OrSetEntry},
+    Change, ChangeOp, ChangeSet, DataError, Difference, EnvironmentFlags, KeyRange, MerkleStore,
+    Mutation, OwnedValue, RetryOptions, Rkv, SignedSnapshot, SingleStore, Store, StoreError,
+    StoreOptions, Value, ValueCompression, ValueEncoding, Writer,
 };
 
+mod common;
+
+use common::stored;
+
 fn check_rkv(k: &Rkv<SafeModeEnvironment>) {
     let _ = k
         .open_single(None, StoreOptions::create())
@@ -125,6 +138,102 @@ fn test_open_from_builder_with_dir_safe_2() {
     check_rkv(&k);
 }
 
+#[test]
+fn test_open_from_builder_with_no_subdir_safe() {
+    let root = Builder::new()
+        .prefix("test_open_from_builder_safe")
+        .tempdir()
+        .expect("tempdir");
+    println!("Root path: {:?}", root.path());
+    fs::create_dir_all(root.path()).expect("dir created");
+    assert!(root.path().is_dir());
+
+    let mut databin = root.path().to_path_buf();
+    databin.push("profile.safe.bin");
+    {
+        let mut builder = Rkv::environment_builder::<SafeMode>();
+        builder.set_flags(EnvironmentFlags::NO_SUB_DIR);
+        builder.set_max_dbs(2);
+
+        let k = Rkv::from_builder(&databin, builder).expect("rkv");
+        check_rkv(&k);
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(1234))
+            .expect("wrote");
+        writer.commit().expect("committed");
+    }
+    assert!(databin.is_file());
+    {
+        let mut builder = Rkv::environment_builder::<SafeMode>();
+        builder.set_flags(EnvironmentFlags::NO_SUB_DIR);
+        builder.set_max_dbs(2);
+
+        let k = Rkv::from_builder(&databin, builder).expect("rkv");
+        let sk = k
+            .open_single("sk", StoreOptions::default())
+            .expect("opened");
+        let reader = k.read().expect("reader");
+        assert_eq!(
+            sk.get(&reader, "foo").expect("read"),
+            Some(stored(Value::I64(1234)))
+        );
+    }
+
+    // The path of an environment created with NO_SUB_DIR can't be a directory.
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_flags(EnvironmentFlags::NO_SUB_DIR);
+    match Rkv::from_builder(root.path(), builder) {
+        Err(StoreError::UnsuitableEnvironmentPath(_)) => (),
+        _ => panic!("opened a directory with NO_SUB_DIR"),
+    };
+}
+
+#[test]
+fn test_open_url_safe() {
+    let root = Builder::new()
+        .prefix("test_open_url_safe")
+        .tempdir()
+        .expect("tempdir");
+    let dir = root.path().join("env");
+    let databin = root.path().join("profile.safe.bin");
+
+    {
+        let url = format!("rkv+safe://{}?max_dbs=2&mkdir=1", dir.display());
+        let k = Rkv::open_url(&url).expect("rkv");
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(1234))
+            .expect("wrote");
+        writer.commit().expect("committed");
+    }
+    assert!(dir.join("data.safe.bin").is_file());
+
+    {
+        let url = format!("rkv+safe://{}?flags=no_sub_dir", databin.display());
+        let k = Rkv::open_url(&url).expect("rkv");
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+        let mut writer = k.write().expect("writer");
+        sk.put(&mut writer, "foo", &Value::I64(1234))
+            .expect("wrote");
+        writer.commit().expect("committed");
+    }
+    assert!(databin.is_file());
+
+    // The directory isn't made without `mkdir`.
+    let url = format!("rkv+safe://{}", root.path().join("missing").display());
+    match Rkv::open_url(&url) {
+        Err(StoreError::UnsuitableEnvironmentPath(_)) => (),
+        result => panic!("expected an unsuitable path, got {:?}", result.map(|_| ())),
+    }
+
+    let url = format!("rkv+safe://{}?max_dbs=2&mkdirs=1", dir.display());
+    match Rkv::open_url(&url) {
+        Err(StoreError::UrlParametersUnknown(names)) => assert_eq!(names, vec!["mkdirs"]),
+        result => panic!("expected unknown parameters, got {:?}", result.map(|_| ())),
+    }
+}
+
 #[test]
 #[should_panic(expected = "opened: DbsFull")]
 fn test_create_with_capacity_safe_1() {
@@ -522,6 +631,65 @@ fn test_multi_put_get_del_safe() {
     writer.commit().unwrap();
 }
 
+#[test]
+#[cfg(feature = "db-dup-sort")]
+fn test_multi_ordered_encoding_safe() {
+    let root = Builder::new()
+        .prefix("test_multi_ordered_encoding_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let multistore = k
+        .open_multi(
+            "multistore",
+            StoreOptions::create().with_encoding(ValueEncoding::Ordered),
+        )
+        .unwrap();
+
+    let mut writer = k.write().unwrap();
+    for v in &[256, -1, 1, i64::MIN, 0, i64::MAX] {
+        multistore.put(&mut writer, "i64", &Value::I64(*v)).unwrap();
+    }
+    for v in &[2.5, -0.5, f64::INFINITY, -1e10, 0.0] {
+        multistore
+            .put(&mut writer, "f64", &Value::F64((*v).into()))
+            .unwrap();
+    }
+    writer.commit().unwrap();
+
+    let reader = k.read().unwrap();
+    let ints: Vec<Value> = multistore
+        .get(&reader, "i64")
+        .unwrap()
+        .map(|r| r.unwrap().1)
+        .collect();
+    assert_eq!(
+        ints,
+        [i64::MIN, -1, 0, 1, 256, i64::MAX]
+            .iter()
+            .map(|v| Value::I64(*v))
+            .collect::<Vec<_>>()
+    );
+    let floats: Vec<Value> = multistore
+        .get(&reader, "f64")
+        .unwrap()
+        .map(|r| r.unwrap().1)
+        .collect();
+    assert_eq!(
+        floats,
+        [-1e10, -0.5, 0.0, 2.5, f64::INFINITY]
+            .iter()
+            .map(|v| Value::F64((*v).into()))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        multistore.get_first(&reader, "i64").unwrap(),
+        Some(Value::I64(i64::MIN))
+    );
+}
+
 #[test]
 #[cfg(feature = "db-dup-sort")]
 fn test_multiple_store_clear_safe() {
@@ -803,87 +971,133 @@ fn test_isolation_safe() {
 }
 
 #[test]
-fn test_blob_safe() {
+fn test_concurrent_read_transactions_safe() {
     let root = Builder::new()
-        .prefix("test_round_trip_blob_safe")
+        .prefix("test_concurrent_reads_safe")
         .tempdir()
         .expect("tempdir");
     fs::create_dir_all(root.path()).expect("dir created");
 
     let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
-    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let s = k.open_single("s", StoreOptions::create()).expect("opened");
 
     let mut writer = k.write().expect("writer");
-    assert_eq!(sk.get(&writer, "foo").expect("read"), None);
-    sk.put(&mut writer, "foo", &Value::Blob(&[1, 2, 3, 4]))
-        .expect("wrote");
+    s.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    writer.commit().expect("committed");
+
+    // A thread can have several readers, with or without `NO_TLS`, each with its own snapshot.
+    let first = k.read().expect("reader");
+    let mut writer = k.write().expect("writer");
+    s.put(&mut writer, "foo", &Value::I64(999)).expect("wrote");
+    writer.commit().expect("committed");
+    let second = k.read().expect("reader");
+
     assert_eq!(
-        sk.get(&writer, "foo").expect("read"),
-        Some(Value::Blob(&[1, 2, 3, 4]))
+        s.get(&first, "foo").expect("read"),
+        Some(stored(Value::I64(1234)))
+    );
+    assert_eq!(
+        s.get(&second, "foo").expect("read"),
+        Some(stored(Value::I64(999)))
     );
+}
 
-    fn u16_to_u8(src: &[u16]) -> Vec<u8> {
-        let mut dst = vec![0; 2 * src.len()];
-        LittleEndian::write_u16_into(src, &mut dst);
-        dst
-    }
+#[test]
+fn test_reader_reset_renew_safe() {
+    let root = Builder::new()
+        .prefix("test_reader_reset_renew_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
 
-    fn u8_to_u16(src: &[u8]) -> Vec<u16> {
-        let mut dst = vec![0; src.len() / 2];
-        LittleEndian::read_u16_into(src, &mut dst);
-        dst
-    }
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_no_tls(true);
+    let k = Rkv::from_builder(root.path(), builder).expect("rkv");
+    let s = k.open_single("s", StoreOptions::create()).expect("opened");
 
-    // When storing UTF-16 strings as blobs, we'll need to convert
-    // their [u16] backing storage to [u8].  Test that converting, writing,
-    // reading, and converting back works as expected.
-    let u16_array = [1000, 10000, 54321, 65535];
-    assert_eq!(sk.get(&writer, "bar").expect("read"), None);
-    sk.put(&mut writer, "bar", &Value::Blob(&u16_to_u8(&u16_array)))
+    let mut writer = k.write().expect("writer");
+    s.put(&mut writer, "foo", &Value::I64(1234)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    assert_eq!(
+        s.get(&reader, "foo").expect("read"),
+        Some(stored(Value::I64(1234)))
+    );
+    let inactive = reader.reset();
+
+    // An inactive reader doesn't keep stores from being opened.
+    let t = k.open_single("t", StoreOptions::create()).expect("opened");
+    let mut writer = k.write().expect("writer");
+    s.put(&mut writer, "foo", &Value::I64(999)).expect("wrote");
+    t.put(&mut writer, "bar", &Value::Bool(true))
         .expect("wrote");
-    let u8_array = match sk.get(&writer, "bar").expect("read") {
-        Some(Value::Blob(val)) => val,
-        _ => &[],
-    };
-    assert_eq!(u8_to_u16(u8_array), u16_array);
+    writer.commit().expect("committed");
+
+    // Once renewed, it sees what was committed in the meantime.
+    let reader = inactive.renew().expect("renewed");
+    assert_eq!(
+        s.get(&reader, "foo").expect("read"),
+        Some(stored(Value::I64(999)))
+    );
+    assert_eq!(
+        t.get(&reader, "bar").expect("read"),
+        Some(stored(Value::Bool(true)))
+    );
 }
 
 #[test]
-fn test_sync_safe() {
+fn test_wide_values_safe() {
     let root = Builder::new()
-        .prefix("test_sync_safe")
+        .prefix("test_wide_values_safe")
         .tempdir()
         .expect("tempdir");
     fs::create_dir_all(root.path()).expect("dir created");
 
-    let mut builder = Rkv::environment_builder::<SafeMode>();
-    builder.set_max_dbs(1);
+    let time = UNIX_EPOCH + Duration::new(1_558_020_865, 224_123_456);
+    let values = [
+        Value::I128(i128::MIN),
+        Value::U128(u128::MAX),
+        Value::Decimal(Decimal::new(-1_234_567, 4)),
+        Value::from(time),
+    ];
+
     {
-        let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+        let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
         let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
-        {
-            let mut writer = k.write().expect("writer");
-            sk.put(&mut writer, "foo", &Value::I64(1234))
-                .expect("wrote");
-            writer.commit().expect("committed");
-            k.sync(true).expect("synced");
+        let mut writer = k.write().expect("writer");
+        for (i, value) in values.iter().enumerate() {
+            sk.put(&mut writer, [i as u8], value).expect("wrote");
         }
+        writer.commit().expect("committed");
     }
-    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
     let sk = k
         .open_single("sk", StoreOptions::default())
         .expect("opened");
     let reader = k.read().expect("reader");
+    for (i, value) in values.iter().enumerate() {
+        match sk.get(&reader, [i as u8]).expect("read") {
+            Some(Value::Blob(bytes)) => {
+                assert_eq!(bytes.len() as u64, value.serialized_size().expect("sized"));
+                assert_eq!(&Value::from_tagged_slice(&bytes).expect("decoded"), value);
+            }
+            v => panic!("expected a blob, got {:?}", v),
+        }
+    }
     assert_eq!(
-        sk.get(&reader, "foo").expect("read"),
-        Some(Value::I64(1234))
+        Value::from_tagged_slice(&values[3].to_bytes().expect("encoded"))
+            .expect("decoded")
+            .to_system_time(),
+        Some(time)
     );
 }
 
 #[test]
-fn test_iter_safe() {
+fn test_filter_json_safe() {
     let root = Builder::new()
-        .prefix("test_iter_safe")
+        .prefix("test_filter_json_safe")
         .tempdir()
         .expect("tempdir");
     fs::create_dir_all(root.path()).expect("dir created");
@@ -891,105 +1105,1300 @@ fn test_iter_safe() {
     let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
     let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
 
-    // An iterator over an empty store returns no values.
-    {
-        let reader = k.read().unwrap();
-        let mut iter = sk.iter_start(&reader).unwrap();
-        assert!(iter.next().is_none());
-    }
-
     let mut writer = k.write().expect("writer");
-    sk.put(&mut writer, "foo", &Value::I64(1234))
+    sk.put(
+        &mut writer,
+        "a",
+        &Value::Json(Cow::Borrowed(r#"{"size": 3, "tags": ["x"]}"#)),
+    )
+    .expect("wrote");
+    sk.put(
+        &mut writer,
+        "b",
+        &Value::Json(Cow::Borrowed(r#"{"size": 12}"#)),
+    )
+    .expect("wrote");
+    sk.put(
+        &mut writer,
+        "c",
+        &Value::Json(Cow::Borrowed(r#"{"tags": []}"#)),
+    )
+    .expect("wrote");
+    sk.put(&mut writer, "d", &Value::Str("not json"))
         .expect("wrote");
-    sk.put(&mut writer, "noo", &Value::F64(1234.0.into()))
-        .expect("wrote");
-    sk.put(&mut writer, "bar", &Value::Bool(true))
-        .expect("wrote");
-    sk.put(&mut writer, "baz", &Value::Str("héllo, yöu"))
-        .expect("wrote");
-    sk.put(&mut writer, "héllò, töűrîst", &Value::Str("Emil.RuleZ!"))
+    // Blobs are held untagged, so these look like JSON values: one is followed by
+    // another byte, the other doesn't parse.
+    let mut trailing = Value::Json(Cow::Borrowed(r#"{"size": 99}"#))
+        .to_bytes()
+        .expect("encoded");
+    trailing.push(0);
+    sk.put(&mut writer, "f", &Value::Blob(Cow::Owned(trailing)))
         .expect("wrote");
-    sk.put(&mut writer, "你好，遊客", &Value::Str("米克規則"))
+    let invalid = Value::Json(Cow::Borrowed("{")).to_bytes().expect("encoded");
+    sk.put(&mut writer, "g", &Value::Blob(Cow::Owned(invalid)))
         .expect("wrote");
+    match sk.put(&mut writer, "e", &Value::Json(Cow::Borrowed("{"))) {
+        Err(StoreError::DataError(DataError::InvalidJson(_))) => (),
+        v => panic!("expected invalid json error, got {:?}", v),
+    }
     writer.commit().expect("committed");
 
-    let reader = k.read().unwrap();
-
-    // Reader.iter() returns (key, value) tuples ordered by key.
-    let mut iter = sk.iter_start(&reader).unwrap();
-    let (key, val) = iter.next().unwrap().unwrap();
-    assert_eq!(str::from_utf8(key).expect("key"), "bar");
-    assert_eq!(val, Value::Bool(true));
-    let (key, val) = iter.next().unwrap().unwrap();
-    assert_eq!(str::from_utf8(key).expect("key"), "baz");
-    assert_eq!(val, Value::Str("héllo, yöu"));
-    let (key, val) = iter.next().unwrap().unwrap();
-    assert_eq!(str::from_utf8(key).expect("key"), "foo");
-    assert_eq!(val, Value::I64(1234));
-    let (key, val) = iter.next().unwrap().unwrap();
-    assert_eq!(str::from_utf8(key).expect("key"), "héllò, töűrîst");
-    assert_eq!(val, Value::Str("Emil.RuleZ!"));
-    let (key, val) = iter.next().unwrap().unwrap();
-    assert_eq!(str::from_utf8(key).expect("key"), "noo");
-    assert_eq!(val, Value::F64(1234.0.into()));
-    let (key, val) = iter.next().unwrap().unwrap();
-    assert_eq!(str::from_utf8(key).expect("key"), "你好，遊客");
-    assert_eq!(val, Value::Str("米克規則"));
-    assert!(iter.next().is_none());
-
-    // Iterators don't loop.  Once one returns None, additional calls
-    // to its next() method will always return None.
-    assert!(iter.next().is_none());
-
-    // Reader.iter_from() begins iteration at the first key equal to
-    // or greater than the given key.
-    let mut iter = sk.iter_from(&reader, "moo").unwrap();
-    let (key, val) = iter.next().unwrap().unwrap();
-    assert_eq!(str::from_utf8(key).expect("key"), "noo");
-    assert_eq!(val, Value::F64(1234.0.into()));
-    let (key, val) = iter.next().unwrap().unwrap();
-    assert_eq!(str::from_utf8(key).expect("key"), "你好，遊客");
-    assert_eq!(val, Value::Str("米克規則"));
-    assert!(iter.next().is_none());
-
-    // Reader.iter_from() works as expected when the given key is a prefix
-    // of a key in the store.
-    let mut iter = sk.iter_from(&reader, "no").unwrap();
-    let (key, val) = iter.next().unwrap().unwrap();
-    assert_eq!(str::from_utf8(key).expect("key"), "noo");
-    assert_eq!(val, Value::F64(1234.0.into()));
-    let (key, val) = iter.next().unwrap().unwrap();
-    assert_eq!(str::from_utf8(key).expect("key"), "你好，遊客");
-    assert_eq!(val, Value::Str("米克規則"));
-    assert!(iter.next().is_none());
+    let reader = k.read().expect("reader");
+    let big: Vec<_> = sk
+        .filter_json(&reader, "/size", |size| size.as_u64() > Some(5))
+        .expect("filtered")
+        .map(|r| r.expect("matched"))
+        .collect();
+    assert_eq!(big, vec![(&b"b"[..], r#"{"size": 12}"#.into())]);
+
+    let tagged: Vec<_> = sk
+        .filter_json(&reader, "/tags/0", |_| true)
+        .expect("filtered")
+        .map(|r| r.expect("matched").0)
+        .collect();
+    assert_eq!(tagged, vec![&b"a"[..]]);
+
+    assert!(sk.filter_json(&reader, "size", |_| true).is_err());
 }
 
 #[test]
-fn test_iter_from_key_greater_than_existing_safe() {
+fn test_compression_safe() {
     let root = Builder::new()
-        .prefix("test_iter_from_key_greater_than_existing_safe")
+        .prefix("test_compression_safe")
         .tempdir()
         .expect("tempdir");
     fs::create_dir_all(root.path()).expect("dir created");
 
     let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
-    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let sk = k
+        .open_single(
+            "sk",
+            StoreOptions::create().with_compression(ValueCompression::Lz4 { threshold: 256 }),
+        )
+        .expect("opened");
 
+    let doc = format!(r#"{{"size": 12, "padding": "{}"}}"#, "x".repeat(10_000));
     let mut writer = k.write().expect("writer");
-    sk.put(&mut writer, "foo", &Value::I64(1234))
-        .expect("wrote");
-    sk.put(&mut writer, "noo", &Value::F64(1234.0.into()))
-        .expect("wrote");
-    sk.put(&mut writer, "bar", &Value::Bool(true))
-        .expect("wrote");
-    sk.put(&mut writer, "baz", &Value::Str("héllo, yöu"))
+    sk.put(&mut writer, "doc", &Value::Json(Cow::Borrowed(&doc)))
         .expect("wrote");
+    sk.put(
+        &mut writer,
+        "small",
+        &Value::Json(Cow::Borrowed(r#"{"size": 3}"#)),
+    )
+    .expect("wrote");
     writer.commit().expect("committed");
 
-    let reader = k.read().unwrap();
-    let mut iter = sk.iter_from(&reader, "nuu").unwrap();
-    assert!(iter.next().is_none());
-}
+    let reader = k.read().expect("reader");
+    match sk.get(&reader, "doc").expect("read") {
+        Some(Value::Blob(bytes)) => {
+            assert!(matches!(bytes, Cow::Owned(_)));
+            assert_eq!(
+                Value::from_tagged_slice(&bytes).expect("decoded"),
+                Value::Json(Cow::Borrowed(&doc))
+            );
+        }
+        v => panic!("expected a blob, got {:?}", v),
+    }
+    match sk.get(&reader, "small").expect("read") {
+        Some(Value::Blob(bytes)) => assert_eq!(
+            Value::from_tagged_slice(&bytes).expect("decoded"),
+            Value::Json(Cow::Borrowed(r#"{"size": 3}"#))
+        ),
+        v => panic!("expected a blob, got {:?}", v),
+    }
+
+    let docs: Vec<_> = sk
+        .filter_json(&reader, "/size", |_| true)
+        .expect("filtered")
+        .map(|r| r.expect("matched").1.into_owned())
+        .collect();
+    assert_eq!(docs, vec![doc, r#"{"size": 3}"#.to_string()]);
+
+    // Stores must be opened with the compression they were created with, whatever the
+    // threshold. Stores can't be opened while a transaction is alive.
+    drop(reader);
+    let lz4 = ValueCompression::Lz4 { threshold: 16 };
+    k.open_single("sk", StoreOptions::default().with_compression(lz4))
+        .expect("opened");
+    assert!(matches!(
+        k.open_single("sk", StoreOptions::default()),
+        Err(StoreError::CompressionMismatch)
+    ));
+    let plain = k
+        .open_single("plain", StoreOptions::create())
+        .expect("opened");
+    let mut writer = k.write().expect("writer");
+    plain
+        .put(&mut writer, "foo", &Value::Blob(Cow::Borrowed(&[0xff])))
+        .expect("wrote");
+    writer.commit().expect("committed");
+    assert!(matches!(
+        k.open_single("plain", StoreOptions::default().with_compression(lz4)),
+        Err(StoreError::CompressionMismatch)
+    ));
+}
+
+#[test]
+fn test_subscribe_safe() {
+    let root = Builder::new()
+        .prefix("test_subscribe_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let other = k
+        .open_single("other", StoreOptions::create())
+        .expect("opened");
+    let changes = k.subscribe(&sk, |change| !change.key.starts_with(b"skip"));
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    sk.put(&mut writer, "skip", &Value::I64(2)).expect("wrote");
+    other
+        .put(&mut writer, "foo", &Value::I64(3))
+        .expect("wrote");
+    sk.put(&mut writer, "bar", &Value::I64(4)).expect("wrote");
+    // Nothing is received until the writer commits.
+    assert!(changes.try_recv().is_err());
+    writer.commit().expect("committed");
+
+    let put = |key: &str| Change {
+        db: sk.database(),
+        key: key.as_bytes().to_vec(),
+        op: ChangeOp::Put,
+    };
+    assert_eq!(
+        changes.try_recv().expect("received"),
+        vec![put("foo"), put("bar")]
+    );
+    assert!(changes.try_recv().is_err());
+
+    // Aborted writers, and writers that only change other stores, are never received.
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "baz", &Value::I64(5)).expect("wrote");
+    writer.abort();
+    let mut writer = k.write().expect("writer");
+    other
+        .put(&mut writer, "baz", &Value::I64(6))
+        .expect("wrote");
+    sk.put(&mut writer, "skip", &Value::I64(7)).expect("wrote");
+    writer.commit().expect("committed");
+    assert!(changes.try_recv().is_err());
+
+    let mut writer = k.write().expect("writer");
+    sk.delete(&mut writer, "foo").expect("deleted");
+    sk.clear(&mut writer).expect("cleared");
+    writer.commit().expect("committed");
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(8)).expect("wrote");
+    writer.commit().expect("committed");
+
+    // Batches are received in commit order, on any thread.
+    let received = thread::spawn(move || changes.iter().take(2).collect::<Vec<_>>())
+        .join()
+        .expect("joined");
+    assert_eq!(
+        received,
+        vec![
+            vec![
+                Change {
+                    db: sk.database(),
+                    key: b"foo".to_vec(),
+                    op: ChangeOp::Delete,
+                },
+                Change {
+                    db: sk.database(),
+                    key: vec![],
+                    op: ChangeOp::Clear,
+                },
+            ],
+            vec![put("foo")],
+        ]
+    );
+
+    // Writers keep working once the receiver is dropped.
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(9)).expect("wrote");
+    writer.commit().expect("committed");
+}
+
+#[test]
+fn test_subscribe_within_filter_safe() {
+    let root = Builder::new()
+        .prefix("test_subscribe_within_filter_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Arc::new(Rkv::new::<SafeMode>(root.path()).expect("new succeeded"));
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    // Filters run without holding the feed's locks, so they can subscribe.
+    let subscribed = Arc::new(Mutex::new(vec![]));
+    let changes = {
+        let (k, subscribed) = (k.clone(), subscribed.clone());
+        k.clone().subscribe(&sk, move |_| {
+            let inner = k.subscribe(&sk, |_| true);
+            subscribed.lock().unwrap().push(inner);
+            true
+        })
+    };
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    writer.commit().expect("committed");
+    assert_eq!(changes.try_recv().expect("received").len(), 1);
+    assert_eq!(subscribed.lock().unwrap().len(), 1);
+
+    // Writers can be committed in another order than they were created in.
+    let mut first = k.write().expect("writer");
+    let mut second = k.write().expect("writer");
+    sk.put(&mut first, "bar", &Value::I64(2)).expect("wrote");
+    sk.put(&mut second, "baz", &Value::I64(3)).expect("wrote");
+    second.commit().expect("committed");
+    first.commit().expect("committed");
+    let keys: Vec<_> = changes
+        .try_iter()
+        .map(|batch| batch[0].key.clone())
+        .collect();
+    assert_eq!(keys, vec![b"baz".to_vec(), b"bar".to_vec()]);
+}
+
+#[test]
+fn test_change_log_safe() {
+    let root = Builder::new()
+        .prefix("test_change_log_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+    let replica_root = Builder::new()
+        .prefix("test_change_log_safe_replica")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(replica_root.path()).expect("dir created");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    k.enable_change_log().expect("enabled");
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    sk.put(&mut writer, "bar", &Value::I64(1)).expect("wrote");
+    writer.commit().expect("committed");
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "baz", &Value::I64(3)).expect("wrote");
+    writer.abort();
+    let mut writer = k.write().expect("writer");
+    sk.delete(&mut writer, "foo").expect("deleted");
+    sk.put(&mut writer, "baz", &Value::I64(4)).expect("wrote");
+    sk.put(&mut writer, "bar", &Value::I64(2)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let changes_since = |seq| {
+        let reader = k.read().expect("reader");
+        let sets: Result<Vec<_>, _> = k.changes_since(&reader, seq)?.collect();
+        sets
+    };
+    let sets = changes_since(0).expect("changes");
+    assert_eq!(
+        sets.iter().map(|set| set.seq).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    assert_eq!(
+        sets[1].mutations[0],
+        Mutation::Delete {
+            store: Some("sk".to_owned()),
+            key: b"foo".to_vec(),
+            value: None,
+        }
+    );
+    assert_eq!(changes_since(1).expect("changes"), sets[1..].to_vec());
+
+    // Replaying sets is idempotent, and skips those already replayed.
+    let replica = Rkv::new::<SafeMode>(replica_root.path()).expect("new succeeded");
+    assert_eq!(
+        replica.apply_changes(sets[..1].to_vec()).expect("applied"),
+        1
+    );
+    assert_eq!(replica.apply_changes(sets.clone()).expect("applied"), 2);
+    assert_eq!(replica.apply_changes(sets.clone()).expect("applied"), 2);
+    let replica_sk = replica
+        .open_single("sk", StoreOptions::default())
+        .expect("opened");
+    {
+        let reader = replica.read().expect("reader");
+        let get = |key| match replica_sk.get(&reader, key).expect("read") {
+            Some(Value::Blob(Cow::Borrowed(bytes))) => {
+                Some(Value::from_tagged_slice(bytes).expect("decoded"))
+            }
+            v => v,
+        };
+        assert_eq!(get("foo"), None);
+        assert_eq!(get("bar"), Some(Value::I64(2)));
+        assert_eq!(get("baz"), Some(Value::I64(4)));
+    }
+    assert!(matches!(
+        replica.changes_since(&replica.read().expect("reader"), 0),
+        Err(StoreError::ChangeLogDisabled)
+    ));
+
+    // Compacting drops the puts that a later delete or put of the same key undoes.
+    assert_eq!(k.compact_changes(2).expect("compacted"), 2);
+    let compacted = changes_since(0).expect("changes");
+    assert_eq!(compacted.len(), 1);
+    assert_eq!(compacted[0].seq, 2);
+    assert_eq!(compacted[0].mutations, sets[1].mutations);
+    assert_eq!(replica.apply_changes(compacted).expect("applied"), 2);
+
+    // Truncating makes the sets before it unavailable, but numbering carries on.
+    assert_eq!(k.truncate_changes(2).expect("truncated"), 1);
+    assert!(matches!(
+        changes_since(1),
+        Err(StoreError::ChangesTruncated(2))
+    ));
+    assert!(changes_since(2).expect("changes").is_empty());
+    let mut writer = k.write().expect("writer");
+    sk.clear(&mut writer).expect("cleared");
+    writer.commit().expect("committed");
+    let sets = changes_since(2).expect("changes");
+    assert_eq!(
+        sets,
+        vec![ChangeSet {
+            seq: 3,
+            mutations: vec![Mutation::Clear {
+                store: Some("sk".to_owned())
+            }],
+        }]
+    );
+}
+
+#[test]
+fn test_merkle_safe() {
+    let roots: Vec<_> = (0..2)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_merkle_safe")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let a = Rkv::new::<SafeMode>(roots[0].path()).expect("new succeeded");
+    let b = Rkv::new::<SafeMode>(roots[1].path()).expect("new succeeded");
+    let ma = a
+        .open_merkle("mk", StoreOptions::create(), 2)
+        .expect("opened");
+    let mb = b
+        .open_merkle("mk", StoreOptions::create(), 2)
+        .expect("opened");
+
+    let entries = [("apple", 1), ("apricot", 2), ("banana", 3), ("b", 4)];
+    let mut writer = a.write().expect("writer");
+    for (k, v) in entries.iter() {
+        ma.put(&mut writer, k, &Value::I64(*v)).expect("wrote");
+    }
+    writer.commit().expect("committed");
+    // The same entries, written in another order and overwritten along the way.
+    let mut writer = b.write().expect("writer");
+    mb.put(&mut writer, "banana", &Value::I64(0))
+        .expect("wrote");
+    for (k, v) in entries.iter().rev() {
+        mb.put(&mut writer, k, &Value::I64(*v)).expect("wrote");
+    }
+    writer.commit().expect("committed");
+
+    let root_hash = |k: &Rkv<SafeModeEnvironment>, m: &MerkleStore<SafeModeDatabase>| {
+        m.root_hash(&k.read().expect("reader")).expect("hashed")
+    };
+    let range_hashes =
+        |k: &Rkv<SafeModeEnvironment>, m: &MerkleStore<SafeModeDatabase>, prefix: &[u8], depth| {
+            m.subrange_hashes(&k.read().expect("reader"), prefix, depth)
+                .expect("hashed")
+        };
+    assert_ne!(root_hash(&a, &ma), EMPTY_HASH);
+    assert_eq!(root_hash(&a, &ma), root_hash(&b, &mb));
+
+    let mut writer = b.write().expect("writer");
+    mb.put(&mut writer, "apricot", &Value::I64(5))
+        .expect("wrote");
+    writer.commit().expect("committed");
+    assert_ne!(root_hash(&a, &ma), root_hash(&b, &mb));
+
+    // Only the ranges holding the differing entry differ.
+    let (ra, rb) = (range_hashes(&a, &ma, b"", 1), range_hashes(&b, &mb, b"", 1));
+    let prefixes = |r: &[(Vec<u8>, [u8; 32])]| r.iter().map(|(p, _)| p.clone()).collect::<Vec<_>>();
+    assert_eq!(prefixes(&ra), vec![b"a".to_vec(), b"b".to_vec()]);
+    assert_eq!(prefixes(&ra), prefixes(&rb));
+    assert_ne!(ra[0], rb[0]);
+    assert_eq!(ra[1], rb[1]);
+    let (ra, rb) = (
+        range_hashes(&a, &ma, b"a", 2),
+        range_hashes(&b, &mb, b"a", 2),
+    );
+    assert_eq!(prefixes(&ra), vec![b"ap".to_vec()]);
+    assert_ne!(ra, rb);
+    let (ra, rb) = (
+        range_hashes(&a, &ma, b"b", 2),
+        range_hashes(&b, &mb, b"b", 2),
+    );
+    assert_eq!(prefixes(&ra), vec![b"b".to_vec(), b"ba".to_vec()]);
+    assert_eq!(ra, rb);
+
+    // Deleting the entry and writing it back heals the difference.
+    let mut writer = b.write().expect("writer");
+    mb.delete(&mut writer, "apricot").expect("deleted");
+    assert!(mb.delete(&mut writer, "apricot").is_err());
+    mb.put(&mut writer, "apricot", &Value::I64(2))
+        .expect("wrote");
+    writer.commit().expect("committed");
+    assert_eq!(root_hash(&a, &ma), root_hash(&b, &mb));
+    assert_eq!(
+        range_hashes(&a, &ma, b"", 2),
+        mb.range_hashes(&b.read().expect("reader"), 2)
+            .expect("hashed")
+    );
+
+    let mut writer = a.write().expect("writer");
+    ma.clear(&mut writer).expect("cleared");
+    writer.commit().expect("committed");
+    assert_eq!(root_hash(&a, &ma), EMPTY_HASH);
+    assert!(range_hashes(&a, &ma, b"", 0).is_empty());
+    assert!(range_hashes(&a, &ma, b"", 2).is_empty());
+    assert!(matches!(
+        ma.range_hashes(&a.read().expect("reader"), 3),
+        Err(StoreError::MerkleDepthMismatch {
+            expected: 2,
+            actual: 3
+        })
+    ));
+
+    // The tree keeps the depth it was created with.
+    a.open_merkle("mk", StoreOptions::default(), 2)
+        .expect("opened");
+    match a.open_merkle("mk", StoreOptions::default(), 3) {
+        Err(StoreError::MerkleDepthMismatch { expected, actual }) => {
+            assert_eq!((expected, actual), (3, 2))
+        }
+        result => panic!("expected a depth mismatch, got {:?}", result),
+    }
+}
+
+/// A xorshift generator, so that randomized tests replay the same interleavings.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+#[test]
+fn test_lww_safe() {
+    let roots: Vec<_> = (0..4)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_lww_safe")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let envs: Vec<_> = roots
+        .iter()
+        .map(|root| Rkv::new::<SafeMode>(root.path()).expect("new succeeded"))
+        .collect();
+    let stores: Vec<_> = envs
+        .iter()
+        .map(|k| k.open_lww("lww", StoreOptions::create()).expect("opened"))
+        .collect();
+    let clocks: Vec<_> = (0..envs.len()).map(|i| Clock::new(i as u64)).collect();
+    let entries = |i: usize| {
+        stores[i]
+            .entries(&envs[i].read().expect("reader"))
+            .expect("listed")
+    };
+    let merge = |i: usize, remote: Vec<LwwEntry>| {
+        for entry in &remote {
+            clocks[i].observe(&entry.timestamp);
+        }
+        let mut writer = envs[i].write().expect("writer");
+        stores[i].merge(&mut writer, remote).expect("merged");
+        writer.commit().expect("committed");
+    };
+
+    // Later writes win, deletes included.
+    let mut writer = envs[0].write().expect("writer");
+    let (earlier, later) = (clocks[0].now(), clocks[0].now());
+    assert!(stores[0]
+        .put(&mut writer, "a", &Value::I64(1), later)
+        .expect("wrote"));
+    assert!(!stores[0]
+        .put(&mut writer, "a", &Value::I64(2), earlier)
+        .expect("wrote"));
+    assert_eq!(
+        stores[0].get(&writer, "a").expect("read"),
+        Some(Value::Blob(Cow::Borrowed(
+            &Value::I64(1).to_bytes().expect("encoded")
+        )))
+    );
+    assert!(stores[0]
+        .delete(&mut writer, "a", clocks[0].now())
+        .expect("deleted"));
+    assert_eq!(stores[0].get(&writer, "a").expect("read"), None);
+    assert!(stores[0].timestamp(&writer, "a").expect("read") > Some(later));
+    writer.commit().expect("committed");
+
+    // Replicas write and sync in a random interleaving, and converge once they all
+    // merged everything.
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let keys = ["a", "b", "c", "d"];
+    for _ in 0..300 {
+        let i = rng.below(3);
+        match rng.below(4) {
+            0 => {
+                let mut remote = entries(rng.below(3));
+                rng.shuffle(&mut remote);
+                merge(i, remote);
+            }
+            1 => {
+                let mut writer = envs[i].write().expect("writer");
+                let k = keys[rng.below(keys.len())];
+                stores[i]
+                    .delete(&mut writer, k, clocks[i].now())
+                    .expect("deleted");
+                writer.commit().expect("committed");
+            }
+            _ => {
+                let mut writer = envs[i].write().expect("writer");
+                let k = keys[rng.below(keys.len())];
+                let v = Value::U64(rng.next());
+                stores[i]
+                    .put(&mut writer, k, &v, clocks[i].now())
+                    .expect("wrote");
+                writer.commit().expect("committed");
+            }
+        }
+    }
+    let all: Vec<_> = (0..3).flat_map(entries).collect();
+    for i in 0..3 {
+        let mut remote = all.clone();
+        rng.shuffle(&mut remote);
+        merge(i, remote);
+    }
+    assert!(!entries(0).is_empty());
+    assert_eq!(entries(0), entries(1));
+    assert_eq!(entries(0), entries(2));
+
+    // Merging is idempotent, whatever the batches the entries come in.
+    let mut remote = all.clone();
+    remote.extend(all);
+    rng.shuffle(&mut remote);
+    while !remote.is_empty() {
+        let batch = remote.split_off(remote.len() - rng.below(remote.len()) - 1);
+        merge(3, batch);
+    }
+    assert_eq!(entries(3), entries(0));
+    let reader = envs[3].read().expect("reader");
+    for k in keys.iter() {
+        assert_eq!(
+            stores[3].get(&reader, k).expect("read"),
+            stores[0]
+                .get(&envs[0].read().expect("reader"), k)
+                .expect("read")
+        );
+    }
+}
+
+#[test]
+fn test_or_set_safe() {
+    let roots: Vec<_> = (0..4)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_or_set_safe")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let envs: Vec<_> = roots
+        .iter()
+        .map(|root| Rkv::new::<SafeMode>(root.path()).expect("new succeeded"))
+        .collect();
+    let stores: Vec<_> = envs
+        .iter()
+        .map(|k| {
+            k.open_or_set("set", StoreOptions::create())
+                .expect("opened")
+        })
+        .collect();
+    let clocks: Vec<_> = (0..envs.len()).map(|i| Clock::new(i as u64)).collect();
+    let entries = |i: usize| {
+        stores[i]
+            .entries(&envs[i].read().expect("reader"))
+            .expect("listed")
+    };
+    let merge = |i: usize, remote: Vec<OrSetEntry>| {
+        for entry in &remote {
+            clocks[i].observe(&entry.tag);
+        }
+        let mut writer = envs[i].write().expect("writer");
+        stores[i].merge(&mut writer, remote).expect("merged");
+        writer.commit().expect("committed");
+    };
+    let remove = |i: usize, k: &str, v: &Value| {
+        let mut writer = envs[i].write().expect("writer");
+        let observed = stores[i].observe(&writer, k, v).expect("observed");
+        stores[i]
+            .remove(&mut writer, k, v, &observed)
+            .expect("removed");
+        writer.commit().expect("committed");
+    };
+    let contains = |i: usize, k: &str, v: &Value| {
+        stores[i]
+            .contains(&envs[i].read().expect("reader"), k, v)
+            .expect("read")
+    };
+
+    // An add made concurrently with a remove survives it, but not the adds it observed.
+    let (x, y) = (Value::Str("x"), Value::Str("y"));
+    let mut writer = envs[0].write().expect("writer");
+    stores[0]
+        .add(&mut writer, "k", &x, clocks[0].now())
+        .expect("added");
+    stores[0]
+        .add(&mut writer, "k", &y, clocks[0].now())
+        .expect("added");
+    writer.commit().expect("committed");
+    merge(1, entries(0));
+    remove(1, "k", &x);
+    remove(1, "k", &y);
+    let mut writer = envs[0].write().expect("writer");
+    stores[0]
+        .add(&mut writer, "k", &x, clocks[0].now())
+        .expect("added");
+    writer.commit().expect("committed");
+    merge(0, entries(1));
+    merge(1, entries(0));
+    for i in 0..2 {
+        assert!(contains(i, "k", &x));
+        assert!(!contains(i, "k", &y));
+        assert_eq!(
+            stores[i]
+                .get(&envs[i].read().expect("reader"), "k")
+                .expect("read"),
+            vec![Value::Blob(Cow::Borrowed(&x.to_bytes().expect("encoded")))]
+        );
+    }
+
+    // Replicas add, remove and sync in a random interleaving, and converge once they
+    // all merged everything.
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let keys = ["a", "b", "c"];
+    let values = [Value::I64(1), Value::I64(2), Value::I64(3)];
+    for _ in 0..300 {
+        let i = rng.below(3);
+        let k = keys[rng.below(keys.len())];
+        let v = &values[rng.below(values.len())];
+        match rng.below(4) {
+            0 => {
+                let mut remote = entries(rng.below(3));
+                rng.shuffle(&mut remote);
+                merge(i, remote);
+            }
+            1 => remove(i, k, v),
+            _ => {
+                let mut writer = envs[i].write().expect("writer");
+                stores[i]
+                    .add(&mut writer, k, v, clocks[i].now())
+                    .expect("added");
+                writer.commit().expect("committed");
+            }
+        }
+    }
+    let all: Vec<_> = (0..3).flat_map(entries).collect();
+    for i in 0..3 {
+        let mut remote = all.clone();
+        rng.shuffle(&mut remote);
+        merge(i, remote);
+    }
+    assert!(entries(0).iter().any(|entry| entry.removed));
+    assert_eq!(entries(0), entries(1));
+    assert_eq!(entries(0), entries(2));
+
+    // Merging is idempotent, whatever the batches the entries come in.
+    let mut remote = all.clone();
+    remote.extend(all);
+    rng.shuffle(&mut remote);
+    while !remote.is_empty() {
+        let batch = remote.split_off(remote.len() - rng.below(remote.len()) - 1);
+        merge(3, batch);
+    }
+    assert_eq!(entries(3), entries(0));
+    for k in keys.iter() {
+        for v in values.iter() {
+            assert_eq!(contains(3, k, v), contains(0, k, v));
+        }
+    }
+
+    let mut writer = envs[3].write().expect("writer");
+    stores[3].clear(&mut writer).expect("cleared");
+    writer.commit().expect("committed");
+    assert!(entries(3).is_empty());
+}
+
+#[test]
+fn test_snapshot_safe() {
+    let roots: Vec<_> = (0..2)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_snapshot_safe")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let a = Rkv::new::<SafeMode>(roots[0].path()).expect("new succeeded");
+    let b = Rkv::new::<SafeMode>(roots[1].path()).expect("new succeeded");
+    let secret = SecretKey::from_bytes(&[7; 32]).expect("secret key");
+    let keypair = Keypair {
+        public: PublicKey::from(&secret),
+        secret,
+    };
+    let other = PublicKey::from(&SecretKey::from_bytes(&[8; 32]).expect("secret key"));
+
+    let sa = a.open_single("s", StoreOptions::create()).expect("opened");
+    let ma = a.open_multi("m", StoreOptions::create()).expect("opened");
+    let mut writer = a.write().expect("writer");
+    sa.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    sa.put(&mut writer, "bar", &Value::Str("baz"))
+        .expect("wrote");
+    ma.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    ma.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    writer.commit().expect("committed");
+    let snapshot = a
+        .export_snapshot(&a.read().expect("reader"), &[&sa, &ma], &keypair)
+        .expect("exported");
+    let (hash, bytes) = (snapshot.hash(), snapshot.to_bytes());
+
+    // The destination's stores keep what they held unless the snapshot verifies.
+    let sb = b.open_single("s", StoreOptions::create()).expect("opened");
+    let mb = b.open_multi("m", StoreOptions::create()).expect("opened");
+    let mut writer = b.write().expect("writer");
+    sb.put(&mut writer, "old", &Value::Bool(true))
+        .expect("wrote");
+    writer.commit().expect("committed");
+    let mut tampered = bytes.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    let tampered = SignedSnapshot::from_bytes(&tampered).expect("decoded");
+    match b.import_snapshot(&tampered, &keypair.public) {
+        Err(StoreError::SnapshotInvalid) => (),
+        result => panic!("imported a tampered snapshot: {:?}", result),
+    }
+    let snapshot = SignedSnapshot::from_bytes(&bytes).expect("decoded");
+    assert_eq!(snapshot.hash(), hash);
+    match b.import_snapshot(&snapshot, &other) {
+        Err(StoreError::SnapshotSignatureInvalid) => (),
+        result => panic!("imported a snapshot signed by another key: {:?}", result),
+    }
+    {
+        let reader = b.read().expect("reader");
+        assert!(sb.get(&reader, "old").expect("read").is_some());
+        assert_eq!(sb.get(&reader, "foo").expect("read"), None);
+    }
+
+    b.import_snapshot(&snapshot, &keypair.public)
+        .expect("imported");
+    let (ra, rb) = (a.read().expect("reader"), b.read().expect("reader"));
+    assert_eq!(sb.get(&rb, "old").expect("read"), None);
+    for k in ["foo", "bar"].iter() {
+        assert_eq!(sa.get(&ra, k).expect("read"), sb.get(&rb, k).expect("read"));
+    }
+    let va: Vec<_> = ma
+        .get(&ra, "foo")
+        .expect("read")
+        .map(|result| result.expect("value").1.to_bytes().expect("encoded"))
+        .collect();
+    let vb: Vec<_> = mb
+        .get(&rb, "foo")
+        .expect("read")
+        .map(|result| result.expect("value").1.to_bytes().expect("encoded"))
+        .collect();
+    assert_eq!(va.len(), 2);
+    assert_eq!(va, vb);
+}
+
+#[test]
+fn test_diff_safe() {
+    let roots: Vec<_> = (0..2)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_diff_safe")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let a = Rkv::new::<SafeMode>(roots[0].path()).expect("new succeeded");
+    let b = Rkv::new::<SafeMode>(roots[1].path()).expect("new succeeded");
+
+    let sa = a.open_single("s", StoreOptions::create()).expect("opened");
+    let ma = a.open_multi("m", StoreOptions::create()).expect("opened");
+    let mut writer = a.write().expect("writer");
+    sa.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    sa.put(&mut writer, "bar", &Value::Str("baz"))
+        .expect("wrote");
+    sa.put(&mut writer, "ours", &Value::Bool(true))
+        .expect("wrote");
+    ma.put(&mut writer, "foo", &Value::I64(1)).expect("wrote");
+    ma.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let sb = b.open_single("s", StoreOptions::create()).expect("opened");
+    let mb = b.open_multi("m", StoreOptions::create()).expect("opened");
+    let mut writer = b.write().expect("writer");
+    sb.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    sb.put(&mut writer, "bar", &Value::Str("baz"))
+        .expect("wrote");
+    sb.put(&mut writer, "theirs", &Value::Bool(false))
+        .expect("wrote");
+    mb.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    mb.put(&mut writer, "foo", &Value::I64(3)).expect("wrote");
+    writer.commit().expect("committed");
+
+    let (ra, rb) = (a.read().expect("reader"), b.read().expect("reader"));
+    let differences: Vec<_> = a
+        .diff(&ra, &b, &rb, &[Some("s"), Some("m")])
+        .expect("diffed")
+        .map(|result| result.expect("difference"))
+        .collect();
+    assert_eq!(
+        differences,
+        vec![
+            (
+                Some("s"),
+                Difference::Changed {
+                    key: b"foo",
+                    old: Value::I64(1),
+                    new: Value::I64(2)
+                }
+            ),
+            (
+                Some("s"),
+                Difference::Removed {
+                    key: b"ours",
+                    value: Value::Bool(true)
+                }
+            ),
+            (
+                Some("s"),
+                Difference::Added {
+                    key: b"theirs",
+                    value: Value::Bool(false)
+                }
+            ),
+            (
+                Some("m"),
+                Difference::Removed {
+                    key: b"foo",
+                    value: Value::I64(1)
+                }
+            ),
+            (
+                Some("m"),
+                Difference::Added {
+                    key: b"foo",
+                    value: Value::I64(3)
+                }
+            ),
+        ]
+    );
+    assert_eq!(
+        a.diff(&ra, &a, &ra, &[Some("s"), Some("m")])
+            .expect("diffed")
+            .count(),
+        0
+    );
+
+    // Both environments must have opened the stores.
+    match a.diff(&ra, &b, &rb, &[Some("missing")]) {
+        Err(StoreError::ForeignStore) => (),
+        _ => panic!("diffed a store that wasn't opened"),
+    };
+}
+
+#[test]
+fn test_dump_load_safe() {
+    let roots: Vec<_> = (0..2)
+        .map(|_| {
+            let root = Builder::new()
+                .prefix("test_dump_load_safe")
+                .tempdir()
+                .expect("tempdir");
+            fs::create_dir_all(root.path()).expect("dir created");
+            root
+        })
+        .collect();
+    let a = Rkv::new::<SafeMode>(roots[0].path()).expect("new succeeded");
+    let b = Rkv::new::<SafeMode>(roots[1].path()).expect("new succeeded");
+
+    let s = a.open_single("s", StoreOptions::create()).expect("opened");
+    let m = a
+        .open_multi(
+            "m",
+            StoreOptions::create().with_encoding(ValueEncoding::Ordered),
+        )
+        .expect("opened");
+    let i = a.open_integer("i", StoreOptions::create()).expect("opened");
+    let c = a
+        .open_single(
+            "c",
+            StoreOptions::create().with_compression(ValueCompression::Lz4 { threshold: 16 }),
+        )
+        .expect("opened");
+    let mut writer = a.write().expect("writer");
+    s.put(&mut writer, "foo", &Value::Str("bar"))
+        .expect("wrote");
+    s.put(&mut writer, "blob", &Value::Blob(Cow::Borrowed(&[0, 1, 2])))
+        .expect("wrote");
+    s.put(&mut writer, "float", &Value::F64(1.5.into()))
+        .expect("wrote");
+    m.put(&mut writer, "foo", &Value::I64(-1)).expect("wrote");
+    m.put(&mut writer, "foo", &Value::I64(2)).expect("wrote");
+    i.put(&mut writer, 7u32, &Value::U64(7)).expect("wrote");
+    c.put(&mut writer, "long", &Value::Str(&"x".repeat(64)))
+        .expect("wrote");
+    writer.commit().expect("committed");
+
+    let stores = [Some("s"), Some("m"), Some("i"), Some("c")];
+    let mut dumped = vec![];
+    a.dump(&a.read().expect("reader"), &stores, &mut dumped)
+        .expect("dumped");
+    let loaded = b.load(&dumped[..]).expect("loaded");
+    assert_eq!(
+        loaded,
+        stores
+            .iter()
+            .map(|name| name.map(str::to_owned))
+            .collect::<Vec<_>>()
+    );
+
+    let (ra, rb) = (a.read().expect("reader"), b.read().expect("reader"));
+    assert_eq!(a.diff(&ra, &b, &rb, &stores).expect("diffed").count(), 0);
+    let mut redumped = vec![];
+    b.dump(&rb, &stores, &mut redumped).expect("dumped");
+    assert_eq!(dumped, redumped);
+
+    // Loading replaces what the stores held. Stores can't be opened while a
+    // transaction is alive, so readers are dropped first.
+    drop((ra, rb));
+    b.load(&dumped[..]).expect("loaded");
+    let (ra, rb) = (a.read().expect("reader"), b.read().expect("reader"));
+    assert_eq!(a.diff(&ra, &b, &rb, &stores).expect("diffed").count(), 0);
+
+    drop((ra, rb));
+
+    match b.load(&b"{\"header\":{\"version\":2}}\n"[..]) {
+        Err(StoreError::DumpVersionUnsupported(2)) => (),
+        _ => panic!("loaded a dump of an unknown version"),
+    };
+    let mut malformed = dumped.clone();
+    malformed.extend(b"{\"entry\":{}}\n");
+    match b.load(&malformed[..]) {
+        Err(StoreError::DumpInvalid { line, .. }) => {
+            assert_eq!(line, dumped.iter().filter(|&&b| b == b'\n').count() + 1)
+        }
+        _ => panic!("loaded a malformed dump"),
+    };
+}
+
+#[test]
+fn test_blob_safe() {
+    let root = Builder::new()
+        .prefix("test_round_trip_blob_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    assert_eq!(sk.get(&writer, "foo").expect("read"), None);
+    sk.put(
+        &mut writer,
+        "foo",
+        &Value::Blob(Cow::Borrowed(&[1, 2, 3, 4])),
+    )
+    .expect("wrote");
+    assert_eq!(
+        sk.get(&writer, "foo").expect("read"),
+        Some(Value::Blob(Cow::Borrowed(&[1, 2, 3, 4])))
+    );
+
+    fn u16_to_u8(src: &[u16]) -> Vec<u8> {
+        let mut dst = vec![0; 2 * src.len()];
+        LittleEndian::write_u16_into(src, &mut dst);
+        dst
+    }
+
+    fn u8_to_u16(src: &[u8]) -> Vec<u16> {
+        let mut dst = vec![0; src.len() / 2];
+        LittleEndian::read_u16_into(src, &mut dst);
+        dst
+    }
+
+    // When storing UTF-16 strings as blobs, we'll need to convert
+    // their [u16] backing storage to [u8].  Test that converting, writing,
+    // reading, and converting back works as expected.
+    let u16_array = [1000, 10000, 54321, 65535];
+    assert_eq!(sk.get(&writer, "bar").expect("read"), None);
+    sk.put(
+        &mut writer,
+        "bar",
+        &Value::Blob(Cow::Borrowed(&u16_to_u8(&u16_array))),
+    )
+    .expect("wrote");
+    let u8_array = match sk.get(&writer, "bar").expect("read") {
+        Some(Value::Blob(Cow::Borrowed(val))) => val,
+        _ => &[],
+    };
+    assert_eq!(u8_to_u16(u8_array), u16_array);
+}
+
+#[test]
+fn test_sync_safe() {
+    let root = Builder::new()
+        .prefix("test_sync_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let mut builder = Rkv::environment_builder::<SafeMode>();
+    builder.set_max_dbs(1);
+    {
+        let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+        let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+        {
+            let mut writer = k.write().expect("writer");
+            sk.put(&mut writer, "foo", &Value::I64(1234))
+                .expect("wrote");
+            writer.commit().expect("committed");
+            k.sync(true).expect("synced");
+        }
+    }
+    let k = Rkv::from_builder(root.path(), builder).expect("new succeeded");
+    let sk = k
+        .open_single("sk", StoreOptions::default())
+        .expect("opened");
+    let reader = k.read().expect("reader");
+    assert_eq!(
+        sk.get(&reader, "foo").expect("read"),
+        Some(Value::I64(1234))
+    );
+}
+
+#[derive(Debug)]
+enum AppError {
+    Store(StoreError),
+    Rejected,
+}
+
+impl From<StoreError> for AppError {
+    fn from(e: StoreError) -> AppError {
+        AppError::Store(e)
+    }
+}
+
+#[test]
+fn test_transact_safe() {
+    let root = Builder::new()
+        .prefix("test_transact_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    // The transaction is committed when the closure returns `Ok`.
+    let read = k
+        .transact(|writer| {
+            sk.put(writer, "foo", &Value::I64(1234))?;
+            sk.get(&*writer, "foo")
+                .map(|v| v.as_ref().map(OwnedValue::from))
+        })
+        .expect("transacted");
+    assert_eq!(read, Some(OwnedValue::from(&stored(Value::I64(1234)))));
+
+    // And aborted when it returns `Err`.
+    match k.transact(|writer| {
+        sk.put(writer, "foo", &Value::I64(5678))?;
+        sk.put(writer, "bar", &Value::Bool(true))?;
+        Err::<(), _>(AppError::Rejected)
+    }) {
+        Err(AppError::Rejected) => (),
+        Err(AppError::Store(e)) => panic!("unexpected store error: {}", e),
+        Ok(()) => panic!("expected a rejection"),
+    }
+
+    let read = k
+        .view(|reader| -> Result<_, StoreError> {
+            let foo = sk.get(reader, "foo")?.as_ref().map(OwnedValue::from);
+            let bar = sk.get(reader, "bar")?.as_ref().map(OwnedValue::from);
+            Ok((foo, bar))
+        })
+        .expect("viewed");
+    assert_eq!(
+        read,
+        (Some(OwnedValue::from(&stored(Value::I64(1234)))), None)
+    );
+
+    // Transactions which succeed aren't retried.
+    let mut attempts = 0;
+    k.transact_with_retry(RetryOptions::default(), |writer| {
+        attempts += 1;
+        sk.put(writer, "bar", &Value::Bool(true))
+    })
+    .expect("transacted");
+    assert_eq!(attempts, 1);
+    let reader = k.read().expect("reader");
+    assert_eq!(
+        sk.get(&reader, "bar").expect("read"),
+        Some(stored(Value::Bool(true)))
+    );
+}
+
+#[test]
+fn test_iter_safe() {
+    let root = Builder::new()
+        .prefix("test_iter_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    // An iterator over an empty store returns no values.
+    {
+        let reader = k.read().unwrap();
+        let mut iter = sk.iter_start(&reader).unwrap();
+        assert!(iter.next().is_none());
+    }
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1234))
+        .expect("wrote");
+    sk.put(&mut writer, "noo", &Value::F64(1234.0.into()))
+        .expect("wrote");
+    sk.put(&mut writer, "bar", &Value::Bool(true))
+        .expect("wrote");
+    sk.put(&mut writer, "baz", &Value::Str("héllo, yöu"))
+        .expect("wrote");
+    sk.put(&mut writer, "héllò, töűrîst", &Value::Str("Emil.RuleZ!"))
+        .expect("wrote");
+    sk.put(&mut writer, "你好，遊客", &Value::Str("米克規則"))
+        .expect("wrote");
+    writer.commit().expect("committed");
+
+    let reader = k.read().unwrap();
+
+    // Reader.iter() returns (key, value) tuples ordered by key.
+    let mut iter = sk.iter_start(&reader).unwrap();
+    let (key, val) = iter.next().unwrap().unwrap();
+    assert_eq!(str::from_utf8(key).expect("key"), "bar");
+    assert_eq!(val, Value::Bool(true));
+    let (key, val) = iter.next().unwrap().unwrap();
+    assert_eq!(str::from_utf8(key).expect("key"), "baz");
+    assert_eq!(val, Value::Str("héllo, yöu"));
+    let (key, val) = iter.next().unwrap().unwrap();
+    assert_eq!(str::from_utf8(key).expect("key"), "foo");
+    assert_eq!(val, Value::I64(1234));
+    let (key, val) = iter.next().unwrap().unwrap();
+    assert_eq!(str::from_utf8(key).expect("key"), "héllò, töűrîst");
+    assert_eq!(val, Value::Str("Emil.RuleZ!"));
+    let (key, val) = iter.next().unwrap().unwrap();
+    assert_eq!(str::from_utf8(key).expect("key"), "noo");
+    assert_eq!(val, Value::F64(1234.0.into()));
+    let (key, val) = iter.next().unwrap().unwrap();
+    assert_eq!(str::from_utf8(key).expect("key"), "你好，遊客");
+    assert_eq!(val, Value::Str("米克規則"));
+    assert!(iter.next().is_none());
+
+    // Iterators don't loop.  Once one returns None, additional calls
+    // to its next() method will always return None.
+    assert!(iter.next().is_none());
+
+    // Reader.iter_from() begins iteration at the first key equal to
+    // or greater than the given key.
+    let mut iter = sk.iter_from(&reader, "moo").unwrap();
+    let (key, val) = iter.next().unwrap().unwrap();
+    assert_eq!(str::from_utf8(key).expect("key"), "noo");
+    assert_eq!(val, Value::F64(1234.0.into()));
+    let (key, val) = iter.next().unwrap().unwrap();
+    assert_eq!(str::from_utf8(key).expect("key"), "你好，遊客");
+    assert_eq!(val, Value::Str("米克規則"));
+    assert!(iter.next().is_none());
+
+    // Reader.iter_from() works as expected when the given key is a prefix
+    // of a key in the store.
+    let mut iter = sk.iter_from(&reader, "no").unwrap();
+    let (key, val) = iter.next().unwrap().unwrap();
+    assert_eq!(str::from_utf8(key).expect("key"), "noo");
+    assert_eq!(val, Value::F64(1234.0.into()));
+    let (key, val) = iter.next().unwrap().unwrap();
+    assert_eq!(str::from_utf8(key).expect("key"), "你好，遊客");
+    assert_eq!(val, Value::Str("米克規則"));
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_iter_from_key_greater_than_existing_safe() {
+    let root = Builder::new()
+        .prefix("test_iter_from_key_greater_than_existing_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "foo", &Value::I64(1234))
+        .expect("wrote");
+    sk.put(&mut writer, "noo", &Value::F64(1234.0.into()))
+        .expect("wrote");
+    sk.put(&mut writer, "bar", &Value::Bool(true))
+        .expect("wrote");
+    sk.put(&mut writer, "baz", &Value::Str("héllo, yöu"))
+        .expect("wrote");
+    writer.commit().expect("committed");
+
+    let reader = k.read().unwrap();
+    let mut iter = sk.iter_from(&reader, "nuu").unwrap();
+    assert!(iter.next().is_none());
+}
 
 #[test]
 fn test_multiple_store_read_write_safe() {
@@ -1252,7 +2661,387 @@ fn test_store_multiple_thread_safe() {
         .into_iter()
         .map(|handle| handle.join().expect("value"))
         .sum();
-    assert_eq!(thread_sum, (0..num_threads).sum());
+    assert_eq!(thread_sum, (0..num_threads).sum::<u64>());
+}
+
+#[test]
+fn test_shared_reader_safe() {
+    let root = Builder::new()
+        .prefix("test_shared_reader_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    // Leaked so that shared readers, which borrow it, can be sent to any thread.
+    let k: &'static Rkv<SafeModeEnvironment> = Box::leak(Box::new(
+        Rkv::new::<SafeMode>(root.path()).expect("new succeeded"),
+    ));
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    for i in 0..100u64 {
+        sk.put(&mut writer, format!("key-{:03}", i), &Value::U64(i))
+            .expect("wrote");
+    }
+    writer.commit().expect("committed");
+
+    let reader = k.read_shared().expect("reader");
+
+    // Changes committed afterwards aren't part of the reader's snapshot.
+    let mut writer = k.write().expect("writer");
+    sk.put(&mut writer, "key-100", &Value::U64(100))
+        .expect("wrote");
+    writer.commit().expect("committed");
+
+    let ranges = sk.partition(&reader, 4).expect("partitioned");
+    assert_eq!(ranges.len(), 4);
+    assert_eq!(ranges[0].start, None);
+    assert_eq!(ranges[3].end, None);
+    for pair in ranges.windows(2) {
+        assert_eq!(pair[0].end, pair[1].start);
+    }
+
+    let handles: Vec<_> = ranges
+        .into_iter()
+        .map(|range| {
+            let reader = reader.clone();
+            thread::spawn(move || {
+                sk.iter_range(&reader, &range)
+                    .expect("iter")
+                    .map(|result| {
+                        let (key, _) = result.expect("read");
+                        assert!(range.contains(key));
+                        key.to_vec()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let mut keys = vec![];
+    for handle in handles {
+        let part = handle.join().expect("joined");
+        assert_eq!(part.len(), 25);
+        keys.extend(part);
+    }
+    let expected: Vec<_> = (0..100)
+        .map(|i| format!("key-{:03}", i).into_bytes())
+        .collect();
+    assert_eq!(keys, expected);
+
+    // There are never more ranges than keys, nor less than one.
+    let mut writer = k.write().expect("writer");
+    sk.clear(&mut writer).expect("cleared");
+    sk.put(&mut writer, "foo", &Value::Bool(true))
+        .expect("wrote");
+    writer.commit().expect("committed");
+    let reader = k.read_shared().expect("reader");
+    assert_eq!(
+        sk.partition(&reader, 4).expect("partitioned"),
+        vec![KeyRange::default()]
+    );
+    assert_eq!(
+        sk.partition(&reader, 0).expect("partitioned"),
+        vec![KeyRange::default()]
+    );
+}
+
+#[test]
+fn test_group_commit_safe() {
+    let root = Builder::new()
+        .prefix("test_group_commit_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let rkv_arc = Arc::new(RwLock::new(
+        Rkv::new::<SafeMode>(root.path()).expect("new succeeded"),
+    ));
+    let store = rkv_arc
+        .read()
+        .unwrap()
+        .open_single("test", StoreOptions::create())
+        .expect("opened");
+
+    // A long interval, so that all the submissions end up in one batch.
+    let committer = Arc::new(Rkv::group_commit(
+        rkv_arc.clone(),
+        GroupCommitOptions {
+            interval: Duration::from_secs(1),
+            max_batch: 10,
+        },
+    ));
+    let subscription = rkv_arc.read().unwrap().subscribe(&store, |_| true);
+
+    let num_threads = 10;
+    let handles: Vec<_> = (0..num_threads)
+        .map(|i: u64| {
+            let committer = committer.clone();
+            thread::spawn(move || {
+                committer
+                    .submit(move |writer| {
+                        // The last submission fails, which doesn't affect the others.
+                        if i == num_threads - 1 {
+                            return Err(StoreError::KeyValuePairNotFound);
+                        }
+                        store.put(writer, i.to_string(), &Value::U64(i))?;
+                        Ok(i)
+                    })
+                    .wait()
+            })
+        })
+        .collect();
+    for (i, handle) in handles.into_iter().enumerate() {
+        match handle.join().expect("joined") {
+            Ok(value) => assert_eq!(value, i as u64),
+            Err(StoreError::KeyValuePairNotFound) => assert_eq!(i as u64, num_threads - 1),
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+
+    // The successful submissions were committed together.
+    let batch = subscription.recv().expect("batch");
+    assert_eq!(batch.len() as u64, num_threads - 1);
+    assert!(subscription.try_recv().is_err());
+
+    let rkv = rkv_arc.read().unwrap();
+    let reader = rkv.read().expect("reader");
+    for i in 0..num_threads - 1 {
+        assert!(store.get(&reader, i.to_string()).expect("read").is_some());
+    }
+    assert_eq!(
+        store
+            .get(&reader, (num_threads - 1).to_string())
+            .expect("read"),
+        None
+    );
+}
+
+#[test]
+fn test_batch_writes_safe() {
+    let root = Builder::new()
+        .prefix("test_batch_writes_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let sk = k.open_single("sk", StoreOptions::create()).expect("opened");
+    let mk = k.open_multi("mk", StoreOptions::create()).expect("opened");
+    let ik = k
+        .open_integer("ik", StoreOptions::create())
+        .expect("opened");
+
+    let mut writer = k.write().expect("writer");
+    sk.put_many(
+        &mut writer,
+        vec![("b", Value::Str("2")), ("a", Value::Str("1"))],
+    )
+    .expect("put many");
+    // Keys must come after those already in the store.
+    match sk.put_sorted(
+        &mut writer,
+        vec![("a", Value::Str("x")), ("c", Value::Str("3"))],
+    ) {
+        Err(StoreError::KeysNotSorted) => (),
+        result => panic!("expected KeysNotSorted, got {:?}", result),
+    }
+    // And be sorted.
+    match sk.put_sorted(
+        &mut writer,
+        vec![("d", Value::Str("4")), ("c", Value::Str("3"))],
+    ) {
+        Err(StoreError::KeysNotSorted) => (),
+        result => panic!("expected KeysNotSorted, got {:?}", result),
+    }
+    assert_eq!(
+        sk.get(&writer, "a").expect("read"),
+        Some(stored(Value::Str("1")))
+    );
+    assert_eq!(sk.get(&writer, "c").expect("read"), None);
+    let sorted: Vec<_> = (0..100u32)
+        .map(|i| (format!("c-{:03}", i), Value::Str("sorted")))
+        .collect();
+    sk.put_sorted(&mut writer, sorted).expect("put sorted");
+    sk.put_sorted(&mut writer, Vec::<(&str, Value)>::new())
+        .expect("put nothing");
+
+    mk.put_sorted(
+        &mut writer,
+        vec![
+            ("a", Value::Str("1")),
+            ("a", Value::Str("2")),
+            ("b", Value::Str("1")),
+        ],
+    )
+    .expect("put sorted");
+    // A value put twice isn't strictly ascending.
+    match mk.put_sorted(
+        &mut writer,
+        vec![("c", Value::Str("1")), ("c", Value::Str("1"))],
+    ) {
+        Err(StoreError::KeysNotSorted) => (),
+        result => panic!("expected KeysNotSorted, got {:?}", result),
+    }
+    mk.put_many(
+        &mut writer,
+        vec![("a", Value::Str("3")), ("c", Value::Str("1"))],
+    )
+    .expect("put many");
+
+    ik.put_many(&mut writer, (1..=10u32).map(|i| (i, Value::Str("int"))))
+        .expect("put many");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let keys: Vec<_> = sk
+        .iter_start(&reader)
+        .expect("iter")
+        .map(|result| result.expect("read").0.to_vec())
+        .collect();
+    assert_eq!(keys.len(), 102);
+    assert_eq!(keys[0], b"a");
+    assert_eq!(keys[101], b"c-099");
+    assert_eq!(
+        sk.get(&reader, "c-042").expect("read"),
+        Some(stored(Value::Str("sorted")))
+    );
+    let values: Vec<_> = mk
+        .get(&reader, "a")
+        .expect("read")
+        .map(|result| result.expect("read").1)
+        .collect();
+    assert_eq!(
+        values,
+        vec![
+            stored(Value::Str("1")),
+            stored(Value::Str("2")),
+            stored(Value::Str("3"))
+        ]
+    );
+    drop(reader);
+
+    let mut writer = k.write().expect("writer");
+    sk.delete_many(&mut writer, vec!["a", "b"])
+        .expect("deleted");
+    match sk.delete_many(&mut writer, vec!["a"]) {
+        Err(StoreError::KeyValuePairNotFound) => (),
+        result => panic!("expected KeyValuePairNotFound, got {:?}", result),
+    }
+    let range = KeyRange {
+        start: Some(b"c-010".to_vec()),
+        end: Some(b"c-090".to_vec()),
+    };
+    assert_eq!(sk.delete_range(&mut writer, &range).expect("deleted"), 80);
+    assert_eq!(sk.delete_range(&mut writer, &range).expect("deleted"), 0);
+    let backwards = KeyRange {
+        start: range.end.clone(),
+        end: range.start.clone(),
+    };
+    assert_eq!(
+        sk.delete_range(&mut writer, &backwards).expect("deleted"),
+        0
+    );
+
+    mk.delete_many(
+        &mut writer,
+        vec![("a", Value::Str("1")), ("c", Value::Str("1"))],
+    )
+    .expect("deleted");
+    let to_b = KeyRange {
+        start: None,
+        end: Some(b"c".to_vec()),
+    };
+    assert_eq!(mk.delete_range(&mut writer, &to_b).expect("deleted"), 2);
+
+    ik.delete_many(&mut writer, 1..=5u32).expect("deleted");
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let keys: Vec<_> = sk
+        .iter_start(&reader)
+        .expect("iter")
+        .map(|result| result.expect("read").0.to_vec())
+        .collect();
+    let expected: Vec<_> = (0..10)
+        .chain(90..100)
+        .map(|i| format!("c-{:03}", i).into_bytes())
+        .collect();
+    assert_eq!(keys, expected);
+    assert_eq!(mk.iter_start(&reader).expect("iter").count(), 0);
+    assert_eq!(ik.get(&reader, 5).expect("read"), None);
+    assert_eq!(
+        ik.get(&reader, 6).expect("read"),
+        Some(stored(Value::Str("int")))
+    );
+}
+
+#[test]
+fn test_batch_writes_integer_safe() {
+    let root = Builder::new()
+        .prefix("test_batch_writes_integer_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let k = Rkv::new::<SafeMode>(root.path()).expect("new succeeded");
+    let ik = k
+        .open_integer("ik", StoreOptions::create())
+        .expect("opened");
+    let mik = k
+        .open_multi_integer("mik", StoreOptions::create())
+        .expect("opened");
+
+    // Keys are sorted as integers, although 256 is encoded as bytes sorting before 255's.
+    let mut writer = k.write().expect("writer");
+    ik.put_sorted(&mut writer, (1..=300u32).map(|i| (i, Value::U64(i.into()))))
+        .expect("put sorted");
+    match ik.put_sorted(&mut writer, vec![(256u32, Value::U64(0))]) {
+        Err(StoreError::KeysNotSorted) => (),
+        result => panic!("expected KeysNotSorted, got {:?}", result),
+    }
+    let pairs = (1..=300u32).flat_map(|i| vec![(i, Value::Str("a")), (i, Value::Str("b"))]);
+    mik.put_sorted(&mut writer, pairs).expect("put sorted");
+    writer.commit().expect("committed");
+
+    let mut writer = k.write().expect("writer");
+    assert_eq!(
+        ik.delete_range(&mut writer, Some(250), Some(260))
+            .expect("deleted"),
+        10
+    );
+    assert_eq!(
+        ik.delete_range(&mut writer, Some(290), None)
+            .expect("deleted"),
+        11
+    );
+    assert_eq!(
+        mik.delete_range(&mut writer, None, Some(101))
+            .expect("deleted"),
+        100
+    );
+    writer.commit().expect("committed");
+
+    let reader = k.read().expect("reader");
+    let kept: Vec<u32> = (1..=300u32)
+        .filter(|&i| ik.get(&reader, i).expect("read").is_some())
+        .collect();
+    let expected: Vec<u32> = (1..250).chain(260..290).collect();
+    assert_eq!(kept, expected);
+    assert_eq!(
+        ik.get(&reader, 260).expect("read"),
+        Some(stored(Value::U64(260)))
+    );
+    assert_eq!(mik.get_first(&reader, 100).expect("read"), None);
+    let values: Vec<_> = mik
+        .get(&reader, 101)
+        .expect("read")
+        .map(|result| result.expect("read").1)
+        .collect();
+    assert_eq!(
+        values,
+        vec![stored(Value::Str("a")), stored(Value::Str("b"))]
+    );
+    assert_eq!(mik.iter_start(&reader).expect("iter").count(), 400);
 }
 
 #[test]
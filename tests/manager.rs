@@ -12,12 +12,22 @@ use std::{fs, sync::Arc};
 
 use tempfile::Builder;
 
-#[cfg(feature = "lmdb")]
-use rkv::backend::{Lmdb, LmdbEnvironment};
 use rkv::{
-    backend::{BackendEnvironmentBuilder, SafeMode, SafeModeEnvironment},
+    backend::{
+        Any, AnyEnvironment, AnyReader, AnyStore, AnyWriter, BackendEnvironmentBuilder, SafeMode,
+        SafeModeEnvironment,
+    },
     CloseOptions, Rkv, StoreOptions, Value,
 };
+#[cfg(feature = "lmdb")]
+use rkv::{
+    backend::{Lmdb, LmdbEnvironment},
+    StoreError,
+};
+
+mod common;
+
+use common::stored;
 
 /// Test that a manager can be created with simple type inference.
 #[cfg(feature = "lmdb")]
@@ -378,3 +388,89 @@ fn test_safe_mode_corrupt_while_open_2() {
         Some(Value::Str("byé, yöu"))
     );
 }
+
+fn put_and_get_any(k: &Rkv<AnyEnvironment>) -> AnyStore {
+    let store: AnyStore = k
+        .open_single("store", StoreOptions::create())
+        .expect("opened");
+    let mut writer: AnyWriter = k.write().expect("writer");
+    store
+        .put(&mut writer, "foo", &Value::I64(1234))
+        .expect("wrote");
+    writer.commit().expect("committed");
+
+    let reader: AnyReader = k.read().expect("reader");
+    assert_eq!(
+        store.get(&reader, "foo").expect("read"),
+        Some(stored(Value::I64(1234)))
+    );
+    store
+}
+
+/// Test that a manager of `AnyEnvironment` manages environments picked at runtime.
+#[test]
+fn test_any_safe() {
+    type Manager = rkv::Manager<AnyEnvironment>;
+
+    let root = Builder::new()
+        .prefix("test_any_safe")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root.path()).expect("dir created");
+
+    let mut builder = Any::safe_mode();
+    builder.set_max_dbs(1);
+
+    let mut manager = Manager::singleton().write().unwrap();
+    let shared_env = manager
+        .get_or_create_from_builder(root.path(), builder, Rkv::from_builder::<Any>)
+        .expect("created");
+    put_and_get_any(&shared_env.read().unwrap());
+    assert!(root.path().join("data.safe.bin").exists());
+    drop(shared_env);
+
+    manager
+        .try_close(root.path(), CloseOptions::default())
+        .expect("closed");
+    assert!(manager.get(root.path()).expect("success").is_none());
+}
+
+/// Test that a manager of `AnyEnvironment` manages environments of both backends together.
+#[cfg(feature = "lmdb")]
+#[test]
+fn test_any() {
+    type Manager = rkv::Manager<AnyEnvironment>;
+
+    let root_lmdb = Builder::new()
+        .prefix("test_any_lmdb")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root_lmdb.path()).expect("dir created");
+    let root_safe = Builder::new()
+        .prefix("test_any_safe_mode")
+        .tempdir()
+        .expect("tempdir");
+    fs::create_dir_all(root_safe.path()).expect("dir created");
+
+    let mut manager = Manager::singleton().write().unwrap();
+    let shared_lmdb = manager
+        .get_or_create_from_builder(root_lmdb.path(), Any::lmdb(), Rkv::from_builder::<Any>)
+        .expect("created");
+    let shared_safe = manager
+        .get_or_create_from_builder(root_safe.path(), Any::safe_mode(), Rkv::from_builder::<Any>)
+        .expect("created");
+
+    let lmdb = shared_lmdb.read().unwrap();
+    let safe = shared_safe.read().unwrap();
+    let lmdb_store = put_and_get_any(&lmdb);
+    put_and_get_any(&safe);
+    assert!(root_lmdb.path().join("data.mdb").exists());
+    assert!(root_safe.path().join("data.safe.bin").exists());
+
+    // Stores of one backend can't be used with transactions of the other one.
+    let mut writer = safe.write().expect("writer");
+    match lmdb_store.put(&mut writer, "bar", &Value::Bool(true)) {
+        Err(StoreError::ForeignStore) => (),
+        result => panic!("expected a foreign store error, got {:?}", result),
+    }
+}